@@ -0,0 +1,57 @@
+//! Aligned (comfy_table) vs unaligned rendering of a large result
+//!
+//! Run with `cargo bench --bench render`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use helix_dadbod::output::{RenderOptions, ResultDocument};
+
+const ROWS: usize = 10_000;
+const COLUMNS: usize = 10;
+
+/// Synthetic 100k-cell result with values of varying width
+fn synthetic_result() -> (Vec<String>, Vec<Vec<String>>) {
+    let header = (0..COLUMNS).map(|c| format!("column_{}", c)).collect();
+    let rows = (0..ROWS)
+        .map(|r| {
+            (0..COLUMNS)
+                .map(|c| "x".repeat((r * 7 + c * 13) % 24 + 1))
+                .collect()
+        })
+        .collect();
+    (header, rows)
+}
+
+fn render(header: &[String], rows: &[Vec<String>], options: RenderOptions) -> String {
+    let mut doc = ResultDocument::with_options(options);
+    doc.table(header.to_vec(), rows.to_vec());
+    doc.render()
+}
+
+fn bench_render(c: &mut Criterion) {
+    let (header, rows) = synthetic_result();
+    let mut group = c.benchmark_group("render_100k_cells");
+    group.sample_size(10);
+
+    group.bench_function("aligned", |b| {
+        let options = RenderOptions {
+            force_align: true,
+            ..RenderOptions::default()
+        };
+        b.iter(|| render(black_box(&header), black_box(&rows), options.clone()))
+    });
+
+    group.bench_function("unaligned", |b| {
+        b.iter(|| {
+            render(
+                black_box(&header),
+                black_box(&rows),
+                RenderOptions::default(),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
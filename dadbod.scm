@@ -9,15 +9,50 @@
 (#%require-dylib "libhelix_dadbod"
     (only-in
         Dadbod::list_connections
+        Dadbod::list_connections_detailed
         Dadbod::connect
+        Dadbod::connect_with_overrides
         Dadbod::test_connection
         Dadbod::execute_query
+        Dadbod::execute_query_buffer
+        Dadbod::execute_file
+        Dadbod::execute_query_async
+        Dadbod::query_status
+        Dadbod::execute_sql
+        Dadbod::peek
         Dadbod::close_connection
         Dadbod::get_workspace_path
+        Dadbod::list_buffers
+        Dadbod::workspace_dir
+        Dadbod::query_history
+        Dadbod::save_snippet
+        Dadbod::load_snippet
+        Dadbod::list_snippets
+        Dadbod::last_objects
+        Dadbod::query_running
+        Dadbod::tunnel_status
+        Dadbod::get_tunnel_status
+        Dadbod::pending_host_keys
+        Dadbod::accept_host_key
+        Dadbod::reject_host_key
+        Dadbod::cancel_query
+        Dadbod::last_error
+        Dadbod::last_run
+        Dadbod::pending_prompts
+        Dadbod::provide_prompt
+        Dadbod::store_password
+        Dadbod::get_connect_timings
         Dadbod::get_init_error
+        Dadbod::version
         WorkspaceInfo-path
         WorkspaceInfo-sql_file
-        WorkspaceInfo-dbout_file))
+        WorkspaceInfo-dbout_file
+        WorkspaceInfo-buffers
+        TunnelStatus-ssh_host
+        TunnelStatus-ssh_port
+        TunnelStatus-remote
+        TunnelStatus-healthy
+        TunnelStatus-summary))
 
 
 ;;; Database Connection Picker for Helix
@@ -27,23 +62,57 @@
 ;;; Auto-Execute on Save
 ;;; ============================================================================
 
-;; Helper: Extract connection name from SQL file path
-;; Path format: /tmp/helix-dadbod/{connection_name}.sql
+;; Helper: Workspace directory path followed by "/"
+;; (workspace_dir, default $XDG_RUNTIME_DIR/helix-dadbod)
+(define (workspace-prefix)
+  (string-append (Dadbod::workspace_dir) "/"))
+
+;; Helper: Extract the SQL file name without ".sql" from a workspace path
+;; Path format: {workspace_dir}/{connection_name}.sql, or
+;; {workspace_dir}/{connection_name}.{n}.sql for numbered buffer n
 (define (extract-connection-name path)
-  (define prefix "/tmp/helix-dadbod/")
+  (define prefix (workspace-prefix))
   (define suffix ".sql")
-  (if (and (starts-with? path prefix)
+  ;; "/" alone means the workspace directory is unknown
+  (if (and (not (equal? prefix "/"))
+           (starts-with? path prefix)
            (ends-with? path suffix))
       (let* ([after-prefix (substring path (string-length prefix))]
              [conn-name (substring after-prefix 0 (- (string-length after-prefix) (string-length suffix)))])
         conn-name)
       #f))
 
+;; Helper: (connection-name buffer-index) of a name from extract-connection-name
+;; "prod" is buffer 1 of prod and "prod.3" buffer 3, unless a connection is
+;; itself called "prod.3"
+(define (split-buffer-name file-name)
+  (define (find-dot i)
+    (cond
+      [(< i 1) #f]
+      [(equal? (string-ref file-name i) #\.) i]
+      [else (find-dot (- i 1))]))
+  (define dot (find-dot (- (string-length file-name) 1)))
+  (define index
+    (and dot (string->number (substring file-name (+ dot 1) (string-length file-name)))))
+  (if (and index
+           (integer? index)
+           (> index 1)
+           (not (member file-name (Dadbod::list_connections))))
+      (list (substring file-name 0 dot) index)
+      (list file-name 1)))
+
+;; Helper: SQL file of numbered buffer `index` of a connection
+(define (buffer-path connection-name index)
+  (if (= index 1)
+      (string-append (workspace-prefix) connection-name ".sql")
+      (string-append (workspace-prefix) connection-name "." (number->string index) ".sql")))
+
 ;; Helper: Find and reload the shared dbout file
 ;; This function switches to the dbout buffer, reloads it, then switches back
-(define (reload-dbout-file connection-name)
-  (define dbout-path "/tmp/helix-dadbod/results.dbout")
-  (define sql-path (string-append "/tmp/helix-dadbod/" connection-name ".sql"))
+;; to the SQL file of buffer `index` (default 1)
+(define (reload-dbout-file connection-name . index)
+  (define dbout-path (string-append (workspace-prefix) "results.dbout"))
+  (define sql-path (buffer-path connection-name (if (null? index) 1 (car index))))
 
   ;; Check if dbout file is open in any buffer
   (define all-docs (editor-all-documents))
@@ -65,6 +134,51 @@
     ;; Switch back to the SQL file
     (helix.open sql-path)))
 
+;; Helper: Ask for each pending \prompt value in turn, then execute again
+;; `pending` is a list of (variable label) pairs from Dadbod::pending_prompts
+(define (ask-prompts conn-name pending)
+  (if (null? pending)
+      (maybe-execute-query)
+      (let ([variable (list-ref (car pending) 0)]
+            [label (list-ref (car pending) 1)])
+        (push-component!
+          (prompt (string-append label ": ")
+                  (lambda (value)
+                    (let ([result (Dadbod::provide_prompt conn-name variable value)])
+                      (if (starts-with? result "Error:")
+                          (set-error! result)
+                          (ask-prompts conn-name (cdr pending))))))))))
+
+;; Helper: Ask whether to trust each SSH host key awaiting confirmation
+;; (host_key_policy = "ask"), then run `retry` if one was accepted
+;; `pending` is a list of (host port key-type fingerprint) lists from
+;; Dadbod::pending_host_keys
+(define (ask-host-keys pending retry accepted?)
+  (if (null? pending)
+      (when accepted? (retry))
+      (let* ([key (car pending)]
+             [fingerprint (list-ref key 3)])
+        (push-component!
+          (prompt (string-append "Trust " (list-ref key 2) " host key " fingerprint
+                                 " of " (list-ref key 0) ":" (list-ref key 1) "? (yes/no): ")
+                  (lambda (answer)
+                    (let* ([accept? (equal? answer "yes")]
+                           [result (if accept?
+                                       (Dadbod::accept_host_key fingerprint)
+                                       (Dadbod::reject_host_key fingerprint))])
+                      (if (starts-with? result "Error:")
+                          (set-error! result)
+                          (ask-host-keys (cdr pending) retry (or accepted? accept?))))))))))
+
+;; Helper: Report a failed connect, first asking about unknown host keys that
+;; stopped it; `retry` connects again once one is trusted
+(define (connect-failed name retry)
+  (define pending (Dadbod::pending_host_keys))
+  (if (null? pending)
+      (set-error! (string-append "Failed to connect to: " name
+                                 ". Check ~/.config/helix-dadbod/dadbod.log for details"))
+      (ask-host-keys pending retry #f)))
+
 ;; Helper: Check if current file is a SQL file and execute if so
 (define (maybe-execute-query)
   (define focus (editor-focus))
@@ -75,16 +189,25 @@
       (begin
         (set-status! "No path found for current document")
         void)
-      (let ([conn-name (extract-connection-name path)])
-        (if (not conn-name)
+      (let ([file-name (extract-connection-name path)])
+        (if (not file-name)
             (begin
               (set-status! (string-append "Not a SQL file: " path))
               void)
             ;; This is a SQL file - execute the query
-            (begin
-              (Dadbod::execute_query conn-name)
-              (reload-dbout-file conn-name)
-              (set-status! (string-append "Query executed: " conn-name))
+            (let* ([buffer (split-buffer-name file-name)]
+                   [conn-name (car buffer)]
+                   [index (cadr buffer)]
+                   [result (if (= index 1)
+                               (Dadbod::execute_query conn-name)
+                               (Dadbod::execute_query_buffer conn-name index))])
+              (reload-dbout-file conn-name index)
+              (cond
+                [(starts-with? result "Error:") (set-error! result)]
+                ;; \prompt lines stop execution until their values are given
+                [(not (null? (Dadbod::pending_prompts conn-name)))
+                 (ask-prompts conn-name (Dadbod::pending_prompts conn-name))]
+                [else (set-status! (string-append "Query executed: " conn-name))])
               void)))))
 
 ;;@doc
@@ -92,6 +215,23 @@
 (define (db-execute)
   (maybe-execute-query))
 
+;;@doc
+;; Execute the file open in the current buffer, wherever it is on disk, on the
+;; current connection; results go to its results.dbout
+(define (db-execute-file)
+  (define conn-name (db-get-connection))
+  (define path (editor-document->path (editor->doc-id (editor-focus))))
+  (cond
+    [(not conn-name) (set-error! "No connection selected. Use :db-open-picker first")]
+    [(not path) (set-error! "The current buffer has no file")]
+    [else
+     (let ([result (Dadbod::execute_file conn-name path)])
+       (reload-dbout-file conn-name)
+       (helix.open path)
+       (if (starts-with? result "Error:")
+           (set-error! result)
+           (set-status! (string-append "Executed " path " on " conn-name))))]))
+
 ;;; ============================================================================
 ;;; Connection Data Functions
 ;;; ============================================================================
@@ -208,6 +348,18 @@
 ;;; Event Handler
 ;;; ============================================================================
 
+;; Helper: Connect to `name` and open its workspace files
+(define (connect-and-open name)
+  (define workspace (Dadbod::connect name))
+  (if workspace
+      (begin
+        (set-selected-connection! name)
+        (set-status! (string-append "Connected: " name
+                                    " → SQL: " (WorkspaceInfo-sql_file workspace)))
+        ;; Open workspace files in hsplit
+        (open-workspace-files workspace))
+      (connect-failed name (lambda () (connect-and-open name)))))
+
 (define (handle-dadbod-event state event)
   (let* ([connections (DadbodState-connections state)]
          [num-connections (length connections)]
@@ -239,40 +391,17 @@
 
       ;; Select with Enter
       [(key-event-enter? event)
-       (let ([selected-name (list-ref connections current-idx)])
-         (define workspace (Dadbod::connect selected-name))
-         (if workspace
-             (begin
-               (set-selected-connection! selected-name)
-               (set-status! (string-append "Connected: " selected-name
-                                           " → SQL: " (WorkspaceInfo-sql_file workspace)))
-               ;; Open workspace files in hsplit
-               (open-workspace-files workspace)
-               event-result/close)
-             (begin
-               (set-error! (string-append "Failed to connect to: " selected-name
-                                          ". Check ~/.config/helix-dadbod/dadbod.log for details"))
-               event-result/close)))]
+       (connect-and-open (list-ref connections current-idx))
+       event-result/close]
 
       ;; Select by number (1-9)
       [(and char (char-digit? char))
        (let* ([num (char->number char)]
               [idx (- num 1)])
          (if (and (>= idx 0) (< idx num-connections))
-             (let ([selected-name (list-ref connections idx)])
-               (define workspace (Dadbod::connect selected-name))
-               (if workspace
-                   (begin
-                     (set-selected-connection! selected-name)
-                     (set-status! (string-append "Connected: " selected-name
-                                                 " → SQL: " (WorkspaceInfo-sql_file workspace)))
-                     ;; Open workspace files in hsplit
-                     (open-workspace-files workspace)
-                     event-result/close)
-                   (begin
-                     (set-error! (string-append "Failed to connect to: " selected-name
-                                                ". Check ~/.config/helix-dadbod/dadbod.log for details"))
-                     event-result/close)))
+             (begin
+               (connect-and-open (list-ref connections idx))
+               event-result/close)
              event-result/consume))]
 
       ;; Ignore other events
@@ -293,7 +422,7 @@
       ;; Initialization succeeded - show picker
       (let* ([connections (get-connection-names)])
         (if (null? connections)
-            (set-error! "No connections found in ~/.config/helix-dadbod/config.toml")
+            (set-error! "No connections found in ~/.config/helix-dadbod/config.toml or PG* environment variables")
             (let ([component (new-component!
                               "dadbod-picker"
                               (DadbodState connections 0)
@@ -301,6 +430,260 @@
                               (hash "handle_event" handle-dadbod-event))])
               (push-component! component))))))
 
+;;@doc
+;; Connect with temporary SSH tunnel overrides (this session only, never saved)
+;; Keys: host, port, user, key_path and proxy_jump ("gw1,ops@gw2:2200" or "none")
+;; Example: (db-connect-with-overrides "prod" '(("port" "2222") ("key_path" "~/.ssh/debug_key")))
+(define (db-connect-with-overrides name overrides)
+  (define workspace (Dadbod::connect_with_overrides name overrides))
+  (if workspace
+      (begin
+        (set-selected-connection! name)
+        (set-status! (string-append "Connected (ephemeral tunnel overrides): " name))
+        (open-workspace-files workspace))
+      (connect-failed name (lambda () (db-connect-with-overrides name overrides)))))
+
+;;@doc
+;; Show the row of a table with the given primary key value(s) in results.dbout
+;; Composite keys take one value per key column, e.g. (db-peek "order_items" "7" "2")
+(define (db-peek table . pk-values)
+  (define conn-name (db-get-connection))
+  (if (not conn-name)
+      (set-error! "No active connection. Open one with :db-open-picker")
+      (let ([result (Dadbod::peek conn-name table pk-values)])
+        (if (starts-with? result "Error:")
+            (set-error! result)
+            (begin
+              (reload-dbout-file conn-name)
+              (set-status! (string-append "Peek: " table)))))))
+
+;;@doc
+;; Meta-command describing the object on result row `row-index` (0-based) of the
+;; last \dt, \dv or \df, e.g. "\\sf public.add(a integer)", or #f if there is none
+(define (db-last-object row-index)
+  (define conn-name (db-get-connection))
+  (if (not conn-name)
+      #f
+      (let ([objects (Dadbod::last_objects conn-name)])
+        (if (and (>= row-index 0) (< row-index (length objects)))
+            (list-ref (list-ref objects row-index) 2)
+            #f))))
+
+;;@doc
+;; Error of the last execution on the current connection (e.g. results.dbout
+;; could not be written), or #f if it succeeded. Useful for flagging in a statusline
+(define (db-last-error)
+  (define conn-name (db-get-connection))
+  (and conn-name
+       (let ([err (Dadbod::last_error conn-name)])
+         (if (equal? err "") #f err))))
+
+;;@doc
+;; Outcome of the last execution on the current connection as a JSON string
+;; (the contents of last_run.json in the workspace), or #f if nothing ran yet
+(define (db-last-run)
+  (define conn-name (db-get-connection))
+  (and conn-name
+       (let ([report (Dadbod::last_run conn-name)])
+         (if (equal? report "") #f report))))
+
+;;@doc
+;; Recent queries executed on the current connection, newest first, as
+;; (started-at summary statement results-file) lists, for building a picker;
+;; results-file is "" unless keep_result_history = true
+(define (db-query-history limit)
+  (define conn-name (db-get-connection))
+  (if conn-name
+      (Dadbod::query_history conn-name limit)
+      '()))
+
+;;@doc
+;; Save the current connection's SQL file as snippet `snippet-name`, replacing
+;; a snippet of that name
+(define (db-save-snippet snippet-name)
+  (define conn-name (db-get-connection))
+  (when conn-name
+    (let ([result (Dadbod::save_snippet conn-name snippet-name)])
+      (if (starts-with? result "Error:")
+          (set-error! result)
+          (set-status! (string-append "Saved snippet " snippet-name))))))
+
+;;@doc
+;; Replace the current connection's SQL file with snippet `snippet-name`.
+;; SQL not saved as a snippet is kept unless the second argument is "force"
+(define (db-load-snippet snippet-name . force)
+  (define conn-name (db-get-connection))
+  (when conn-name
+    (let ([result (Dadbod::load_snippet conn-name snippet-name
+                                        (equal? force '("force")))])
+      (if (starts-with? result "Error:")
+          (set-error! result)
+          (begin
+            (helix.open (string-append (workspace-prefix) conn-name ".sql"))
+            (helix.reload)
+            (set-status! (string-append "Loaded snippet " snippet-name)))))))
+
+;;@doc
+;; Names of the current connection's saved snippets, for building a picker
+(define (db-list-snippets)
+  (define conn-name (db-get-connection))
+  (if conn-name
+      (Dadbod::list_snippets conn-name)
+      '()))
+
+;;@doc
+;; Open numbered query buffer `index` of the current connection; buffer 1 is
+;; its usual SQL file, further ones are created when first executed or saved
+;; Example: (db-open-buffer 2)
+(define (db-open-buffer index)
+  (define conn-name (db-get-connection))
+  (when conn-name
+    (helix.open (buffer-path conn-name index))))
+
+;;@doc
+;; SQL files of the current connection's existing buffers, buffer 1 first, for
+;; building a picker
+(define (db-list-buffers)
+  (define conn-name (db-get-connection))
+  (if conn-name
+      (Dadbod::list_buffers conn-name)
+      '()))
+
+;;@doc
+;; Open the query history log (history/history.sql in the workspace)
+(define (db-history)
+  (define dir (Dadbod::workspace_dir))
+  (if (equal? dir "")
+      (set-error! "Workspace directory unknown. Check ~/.config/helix-dadbod/dadbod.log for details")
+      (helix.open (string-append dir "/history/history.sql"))))
+
+;;@doc
+;; SSH tunnel of the current connection as a statusline segment, e.g.
+;; "ssh bastion:22 → db.internal:5432 up, 1.5 kB sent, 3.0 MB received",
+;; flagged with "!" while the session is not healthy; #f without a tunnel
+(define (db-tunnel-status)
+  (define conn-name (db-get-connection))
+  (and conn-name
+       (let ([tunnel (Dadbod::get_tunnel_status conn-name)])
+         (and tunnel
+              (string-append (if (TunnelStatus-healthy tunnel) "ssh " "ssh! ")
+                             (TunnelStatus-ssh_host tunnel) ":"
+                             (number->string (TunnelStatus-ssh_port tunnel))
+                             " → " (TunnelStatus-remote tunnel) " "
+                             (TunnelStatus-summary tunnel))))))
+
+;;@doc
+;; Whether a query is still executing on the current connection
+(define (db-query-running?)
+  (define conn-name (db-get-connection))
+  (and conn-name (Dadbod::query_running conn-name)))
+
+;; How often a background execution is polled, in milliseconds
+(define poll-interval-ms 200)
+
+;; Poll the background execution job-id until it finishes, then reload the results
+(define (poll-query-job conn-name job-id)
+  (enqueue-thread-local-callback-with-delay
+   poll-interval-ms
+   (lambda ()
+     (let ([status (Dadbod::query_status job-id)])
+       (cond
+         [(equal? (car status) "pending")
+          (let ([tunnel (Dadbod::tunnel_status conn-name)])
+            (when (starts-with? tunnel "reconnecting")
+              (set-status! (string-append "SSH tunnel of " conn-name " " tunnel "..."))))
+          (poll-query-job conn-name job-id)]
+         [(equal? (car status) "failed")
+          (reload-dbout-file conn-name)
+          (set-error! (list-ref status 2))]
+         [(equal? (car status) "succeeded")
+          (reload-dbout-file conn-name)
+          (if (not (null? (Dadbod::pending_prompts conn-name)))
+              (ask-prompts conn-name (Dadbod::pending_prompts conn-name))
+              (set-status! (string-append "Query executed: " conn-name
+                                          " (" (list-ref status 1) "ms)")))]
+         [else void])))))
+
+;;@doc
+;; Execute the current SQL query in the background: Helix stays responsive and the
+;; results pane reloads when the query finishes
+(define (db-execute-async)
+  (define conn-name (db-get-connection))
+  (when conn-name
+    (let ([job-id (Dadbod::execute_query_async conn-name)])
+      (if (starts-with? job-id "Error:")
+          (set-error! job-id)
+          (begin
+            (set-status! (string-append "Running query on " conn-name "..."))
+            (poll-query-job conn-name job-id))))))
+
+;; State of a masked prompt: its label, the text typed so far and what to do
+;; with it on Enter
+(struct SecretPromptState (label text on-submit) #:mutable)
+
+;; Helper: Draw the prompt on the bottom line, one * per typed character
+(define (render-secret-prompt state rect buffer)
+  (let* ([y (- (area-height rect) 1)]
+         [line (area 0 y (area-width rect) 1)]
+         [masked (make-string (string-length (SecretPromptState-text state)) #\*)])
+    (buffer/clear buffer line)
+    (frame-set-string! buffer 0 y
+                       (string-append (SecretPromptState-label state) masked)
+                       (theme-scope "ui.text"))))
+
+(define (handle-secret-prompt-event state event)
+  (let ([text (SecretPromptState-text state)]
+        [char (key-event-char event)])
+    (cond
+      [(key-event-escape? event) event-result/close]
+      [(key-event-enter? event)
+       ((SecretPromptState-on-submit state) text)
+       event-result/close]
+      [(key-event-backspace? event)
+       (when (> (string-length text) 0)
+         (set-SecretPromptState-text! state (substring text 0 (- (string-length text) 1))))
+       event-result/consume]
+      [char
+       (set-SecretPromptState-text! state (string-append text (string char)))
+       event-result/consume]
+      [else event-result/consume])))
+
+;; Helper: Ask for a secret without echoing it; `on-submit` gets the text
+(define (ask-secret label on-submit)
+  (push-component!
+    (new-component! "dadbod-secret-prompt"
+                    (SecretPromptState label "" on-submit)
+                    render-secret-prompt
+                    (hash "handle_event" handle-secret-prompt-event))))
+
+;;@doc
+;; Cancel the query running on the current connection; the cancel error is
+;; written to the results pane
+(define (db-cancel)
+  (define conn-name (db-get-connection))
+  (when conn-name
+    (let ([result (Dadbod::cancel_query conn-name)])
+      (if (starts-with? result "Error:")
+          (set-error! result)
+          (set-status! result)))))
+
+;;@doc
+;; Store the password of a connection with password_keyring = true in the OS keyring
+;; The password is asked for in a masked prompt, so it never enters the command history
+;; Example: (db-store-password "prod")
+(define (db-store-password name)
+  (ask-secret (string-append "Password for " name ": ")
+              (lambda (secret)
+                (define result (Dadbod::store_password name secret))
+                (if (equal? result "")
+                    (set-status! (string-append "Stored password for " name " in the keyring"))
+                    (set-error! result)))))
+
+;;@doc
+;; Show the helix-dadbod version, enabled features and loaded config (for bug reports)
+(define (db-version)
+  (set-status! (Dadbod::version)))
+
 ;;; ============================================================================
 ;;; Command Aliases
 ;;; ============================================================================
@@ -314,4 +697,4 @@
 ;;; Exports
 ;;; ============================================================================
 
-(provide db-open-picker db-get-connection db-execute dbe)
+(provide db-open-picker db-get-connection db-execute dbe db-execute-async db-connect-with-overrides db-peek db-last-object db-query-running? db-cancel db-last-error db-last-run db-tunnel-status db-store-password db-version db-query-history db-history db-save-snippet db-load-snippet db-list-snippets db-open-buffer db-list-buffers db-execute-file)
@@ -0,0 +1,656 @@
+//! Database clients behind a common trait
+//!
+//! `ActiveConnection` runs queries through `DatabaseClient`, so the execution
+//! path is shared by every backend and each backend renders its own values to
+//! strings. Features built on PostgreSQL catalogs reach the tokio_postgres
+//! client through `postgres()`.
+
+use crate::mysql;
+use crate::notice::ServerNotice;
+use crate::run_report::{RunError, RunStatus};
+use crate::sqlite::{self, SqliteDb};
+use crate::value::CellValue;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mysql_async::prelude::Queryable;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::AbortHandle;
+use tokio_postgres::types::{FromSql, ToSql, Type};
+use tokio_postgres::Client;
+
+/// Column names and values of a query's result
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryRows {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<CellValue>>,
+    /// Rows beyond the `max_rows` the query ran with were left unread
+    pub truncated: bool,
+    /// Rows the statement inserted, updated or deleted, when the server says
+    pub affected: Option<u64>,
+}
+
+/// What `stream_rows` sends while a result arrives: the column names, then
+/// each row
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamedRow {
+    Header(Vec<String>),
+    Row(Vec<CellValue>),
+}
+
+/// How `stream_rows` ended
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StreamEnd {
+    /// Rows beyond `max_rows` were left unread
+    pub truncated: bool,
+    /// Rows the statement inserted, updated or deleted, when the server says
+    pub affected: Option<u64>,
+}
+
+/// A failed statement, as shown in results.dbout and last_run.json
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub status: RunStatus,
+    pub error: RunError,
+}
+
+impl QueryError {
+    /// An error raised by the client rather than the database
+    pub fn client(message: impl Into<String>) -> Self {
+        Self {
+            status: RunStatus::ClientError,
+            error: RunError::client(message.into()),
+        }
+    }
+}
+
+impl From<(RunStatus, RunError)> for QueryError {
+    fn from((status, error): (RunStatus, RunError)) -> Self {
+        Self { status, error }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Client of an active connection
+#[async_trait]
+pub trait DatabaseClient: Send + Sync {
+    /// Backend name used in messages, e.g. `PostgreSQL`
+    fn backend(&self) -> &'static str;
+
+    /// Run `sql` and return its rows, at most `max_rows` of them if set
+    async fn query_rows(&self, sql: &str, max_rows: Option<usize>)
+        -> Result<QueryRows, QueryError>;
+
+    /// Run `sql` and send its column names, then each of its rows (at most
+    /// `max_rows` if set), to `rows`; returns whether rows past `max_rows` were
+    /// left unread and how many rows the statement affected
+    ///
+    /// Sending stops when `rows` is closed. Backends that can't stream send the
+    /// rows of `query_rows` once they have them all.
+    async fn stream_rows(
+        &self,
+        sql: &str,
+        max_rows: Option<usize>,
+        rows: mpsc::Sender<StreamedRow>,
+    ) -> Result<StreamEnd, QueryError> {
+        let result = self.query_rows(sql, max_rows).await?;
+        if rows.send(StreamedRow::Header(result.header)).await.is_ok() {
+            for row in result.rows {
+                if rows.send(StreamedRow::Row(row)).await.is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(StreamEnd {
+            truncated: result.truncated,
+            affected: result.affected,
+        })
+    }
+
+    /// Run `sql` and return the number of rows it affected
+    async fn execute(&self, sql: &str) -> Result<u64, QueryError>;
+
+    /// Server version string, as shown by :db-test-connection
+    async fn server_version(&self) -> Result<String>;
+
+    /// Close the connection; later queries fail
+    async fn close(&self) -> Result<()>;
+
+    /// Messages the server sent since the last call (NOTICE, WARNING, ...)
+    fn take_notices(&self) -> Vec<ServerNotice> {
+        Vec::new()
+    }
+
+    /// The tokio_postgres client, if this is a PostgreSQL connection
+    fn as_postgres(&self) -> Option<&Client> {
+        None
+    }
+
+    /// The PostgreSQL client, for features that only exist there
+    fn postgres(&self) -> Result<&Client> {
+        match self.as_postgres() {
+            Some(client) => Ok(client),
+            None => anyhow::bail!("Only supported on PostgreSQL connections"),
+        }
+    }
+}
+
+/// PostgreSQL connection through tokio_postgres
+pub struct PostgresClient {
+    client: Client,
+    /// Notices forwarded by the connection task
+    notices: std::sync::Mutex<mpsc::UnboundedReceiver<ServerNotice>>,
+    /// Task driving the connection's socket; aborting it closes the connection
+    connection: AbortHandle,
+}
+
+impl PostgresClient {
+    pub fn new(
+        client: Client,
+        notices: mpsc::UnboundedReceiver<ServerNotice>,
+        connection: AbortHandle,
+    ) -> Self {
+        Self {
+            client,
+            notices: std::sync::Mutex::new(notices),
+            connection,
+        }
+    }
+
+    /// Split rows into column names and values
+    pub fn rows_to_cells(rows: &[tokio_postgres::Row]) -> QueryRows {
+        let header = rows
+            .first()
+            .map(|row| {
+                row.columns()
+                    .iter()
+                    .map(|col| col.name().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let rows = rows.iter().map(Self::row_to_cells).collect();
+
+        QueryRows {
+            header,
+            rows,
+            truncated: false,
+            affected: None,
+        }
+    }
+
+    fn row_to_cells(row: &tokio_postgres::Row) -> Vec<CellValue> {
+        row.columns()
+            .iter()
+            .enumerate()
+            .map(|(idx, col)| Self::value_to_cell(row, idx, col.type_()))
+            .collect()
+    }
+
+    /// How a failed statement is reported: errors raised by the server carry
+    /// its SQLSTATE and position, anything else is a client error
+    pub fn query_error(e: &tokio_postgres::Error) -> QueryError {
+        match e.as_db_error() {
+            Some(db_err) => QueryError {
+                status: RunStatus::DbError,
+                error: RunError::from_db_error(db_err),
+            },
+            None => QueryError::client(e.to_string()),
+        }
+    }
+
+    /// Convert a PostgreSQL value to a `CellValue` based on its type
+    fn value_to_cell(row: &tokio_postgres::Row, idx: usize, col_type: &Type) -> CellValue {
+        // Values that fail to decode are shown as NULL
+        fn get<'a, T: tokio_postgres::types::FromSql<'a>>(
+            row: &'a tokio_postgres::Row,
+            idx: usize,
+        ) -> Option<T> {
+            row.try_get::<_, Option<T>>(idx).ok().flatten()
+        }
+        let text = |v: Option<String>| v.map(CellValue::Text).unwrap_or(CellValue::Null);
+
+        // Check type by name since Type doesn't implement PartialEq for constants
+        if *col_type == Type::BOOL {
+            return get(row, idx)
+                .map(CellValue::Bool)
+                .unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::INT2 {
+            return get::<i16>(row, idx)
+                .map(|v| CellValue::Int(v.into()))
+                .unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::INT4 {
+            return get::<i32>(row, idx)
+                .map(|v| CellValue::Int(v.into()))
+                .unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::INT8 {
+            return get(row, idx).map(CellValue::Int).unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::FLOAT4 {
+            // Through the f32's shortest decimal form, so 1.1 stays 1.1
+            return get::<f32>(row, idx)
+                .and_then(|v| v.to_string().parse().ok())
+                .map(CellValue::Float)
+                .unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::FLOAT8 {
+            return get(row, idx)
+                .map(CellValue::Float)
+                .unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::UUID {
+            return text(get::<uuid::Uuid>(row, idx).map(|v| v.to_string()));
+        }
+
+        if *col_type == Type::TIMESTAMP {
+            return text(get::<chrono::NaiveDateTime>(row, idx).map(|v| v.to_string()));
+        }
+
+        if *col_type == Type::TIMESTAMPTZ {
+            return text(get::<chrono::DateTime<chrono::Utc>>(row, idx).map(|v| v.to_string()));
+        }
+
+        if *col_type == Type::DATE {
+            return text(get::<chrono::NaiveDate>(row, idx).map(|v| v.to_string()));
+        }
+
+        if *col_type == Type::TIME {
+            return text(get::<chrono::NaiveTime>(row, idx).map(|v| v.to_string()));
+        }
+
+        if *col_type == Type::JSON || *col_type == Type::JSONB {
+            return get(row, idx)
+                .map(|RawJson(text)| CellValue::Json(text))
+                .unwrap_or(CellValue::Null);
+        }
+
+        if *col_type == Type::BYTEA {
+            return text(get::<Vec<u8>>(row, idx).map(|v| format!("\\x{}", hex::encode(v))));
+        }
+
+        // NUMERIC/DECIMAL types - handle as string to preserve precision
+        if *col_type == Type::NUMERIC {
+            return get(row, idx)
+                .map(CellValue::Numeric)
+                .unwrap_or(CellValue::Null);
+        }
+
+        // Fallback: try as string for text types and all other types
+        text(get(row, idx))
+    }
+}
+
+/// json or jsonb value as the server's text, unparsed
+struct RawJson(String);
+
+impl<'a> FromSql<'a> for RawJson {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        // jsonb's binary format is a version byte before the text
+        let text = if *ty == Type::JSONB {
+            match raw.split_first() {
+                Some((1, text)) => text,
+                _ => return Err("unsupported jsonb version".into()),
+            }
+        } else {
+            raw
+        };
+        Ok(RawJson(std::str::from_utf8(text)?.to_string()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::JSON || *ty == Type::JSONB
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for PostgresClient {
+    fn backend(&self) -> &'static str {
+        "PostgreSQL"
+    }
+
+    /// Rows are streamed, so those past `max_rows` are never kept in memory
+    async fn query_rows(
+        &self,
+        sql: &str,
+        max_rows: Option<usize>,
+    ) -> Result<QueryRows, QueryError> {
+        let stream = self
+            .client
+            .query_raw(sql, std::iter::empty::<&(dyn ToSql + Sync)>())
+            .await
+            .map_err(|e| Self::query_error(&e))?;
+        let mut stream = std::pin::pin!(stream);
+
+        let mut rows = Vec::new();
+        let mut truncated = false;
+        while let Some(row) = stream.try_next().await.map_err(|e| Self::query_error(&e))? {
+            if max_rows.is_some_and(|max_rows| rows.len() >= max_rows) {
+                truncated = true;
+                break;
+            }
+            rows.push(row);
+        }
+
+        Ok(QueryRows {
+            truncated,
+            affected: stream.rows_affected(),
+            ..Self::rows_to_cells(&rows)
+        })
+    }
+
+    async fn stream_rows(
+        &self,
+        sql: &str,
+        max_rows: Option<usize>,
+        rows: mpsc::Sender<StreamedRow>,
+    ) -> Result<StreamEnd, QueryError> {
+        let stream = self
+            .client
+            .query_raw(sql, std::iter::empty::<&(dyn ToSql + Sync)>())
+            .await
+            .map_err(|e| Self::query_error(&e))?;
+        let mut stream = std::pin::pin!(stream);
+
+        let mut sent = 0;
+        while let Some(row) = stream.try_next().await.map_err(|e| Self::query_error(&e))? {
+            if max_rows.is_some_and(|max_rows| sent >= max_rows) {
+                return Ok(StreamEnd {
+                    truncated: true,
+                    affected: None,
+                });
+            }
+            if sent == 0 {
+                let header = row.columns().iter().map(|c| c.name().to_string()).collect();
+                if rows.send(StreamedRow::Header(header)).await.is_err() {
+                    break;
+                }
+            }
+            if rows
+                .send(StreamedRow::Row(Self::row_to_cells(&row)))
+                .await
+                .is_err()
+            {
+                break;
+            }
+            sent += 1;
+        }
+        Ok(StreamEnd {
+            truncated: false,
+            affected: stream.rows_affected(),
+        })
+    }
+
+    async fn execute(&self, sql: &str) -> Result<u64, QueryError> {
+        self.client
+            .execute(sql, &[])
+            .await
+            .map_err(|e| Self::query_error(&e))
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        let row = self
+            .client
+            .query_one("SELECT version()", &[])
+            .await
+            .context("Failed to execute test query")?;
+        Ok(row.get(0))
+    }
+
+    async fn close(&self) -> Result<()> {
+        // Also fails a query still running on the connection, which dropping
+        // the client wouldn't
+        self.connection.abort();
+        Ok(())
+    }
+
+    fn take_notices(&self) -> Vec<ServerNotice> {
+        let mut notices = self.notices.lock().unwrap_or_else(|e| e.into_inner());
+        std::iter::from_fn(|| notices.try_recv().ok()).collect()
+    }
+
+    fn as_postgres(&self) -> Option<&Client> {
+        Some(&self.client)
+    }
+}
+
+/// MySQL connection through mysql_async
+pub struct MysqlClient {
+    /// mysql_async connections take `&mut self` to query; None once closed
+    conn: Mutex<Option<mysql_async::Conn>>,
+}
+
+impl MysqlClient {
+    pub fn new(conn: mysql_async::Conn) -> Self {
+        Self {
+            conn: Mutex::new(Some(conn)),
+        }
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for MysqlClient {
+    fn backend(&self) -> &'static str {
+        mysql::BACKEND
+    }
+
+    async fn query_rows(
+        &self,
+        sql: &str,
+        max_rows: Option<usize>,
+    ) -> Result<QueryRows, QueryError> {
+        let mut conn = self.conn.lock().await;
+        let conn = conn
+            .as_mut()
+            .ok_or_else(|| QueryError::client("Connection is closed"))?;
+        mysql::query(conn, sql, max_rows)
+            .await
+            .map_err(|e| QueryError::from(mysql::run_error(&e)))
+    }
+
+    async fn execute(&self, sql: &str) -> Result<u64, QueryError> {
+        let mut conn = self.conn.lock().await;
+        let conn = conn
+            .as_mut()
+            .ok_or_else(|| QueryError::client("Connection is closed"))?;
+        conn.query_drop(sql)
+            .await
+            .map_err(|e| QueryError::from(mysql::run_error(&e)))?;
+        Ok(conn.affected_rows())
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        let mut conn = self.conn.lock().await;
+        let conn = conn.as_mut().context("Connection is closed")?;
+        mysql::server_version(conn).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        if let Some(conn) = self.conn.lock().await.take() {
+            conn.disconnect()
+                .await
+                .context("Failed to close MySQL connection")?;
+        }
+        Ok(())
+    }
+}
+
+/// SQLite database file through rusqlite
+pub struct SqliteClient {
+    /// rusqlite is blocking, so queries run on the blocking thread pool
+    db: Arc<std::sync::Mutex<SqliteDb>>,
+}
+
+impl SqliteClient {
+    pub fn new(db: SqliteDb) -> Self {
+        Self {
+            db: Arc::new(std::sync::Mutex::new(db)),
+        }
+    }
+
+    /// Run `f` on the database on the blocking thread pool
+    async fn with_db<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut SqliteDb) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || f(&mut db.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .context("SQLite call panicked")?
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for SqliteClient {
+    fn backend(&self) -> &'static str {
+        "SQLite"
+    }
+
+    async fn query_rows(
+        &self,
+        sql: &str,
+        max_rows: Option<usize>,
+    ) -> Result<QueryRows, QueryError> {
+        let sql = sql.to_string();
+        self.with_db(move |db| db.query(&sql, max_rows))
+            .await
+            .map_err(|e| QueryError::from(sqlite::run_error(&e)))
+    }
+
+    async fn execute(&self, sql: &str) -> Result<u64, QueryError> {
+        let sql = sql.to_string();
+        self.with_db(move |db| db.execute(&sql))
+            .await
+            .map_err(|e| QueryError::from(sqlite::run_error(&e)))
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        self.with_db(|db| db.version()).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.with_db(|db| db.close()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_query_error_display() {
+        let err = QueryError::client("connection reset");
+        assert_eq!(err.status, RunStatus::ClientError);
+        assert_eq!(err.to_string(), "connection reset");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_client() {
+        let dir = std::env::temp_dir().join("helix-dadbod-client-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("client.db");
+        let _ = fs::remove_file(&path);
+
+        let client: Arc<dyn DatabaseClient> =
+            Arc::new(SqliteClient::new(SqliteDb::new(path.clone(), true)));
+        assert_eq!(client.backend(), "SQLite");
+        assert!(client.postgres().is_err());
+
+        client
+            .execute("CREATE TABLE t (id INTEGER, name TEXT)")
+            .await
+            .unwrap();
+        let affected = client
+            .execute("INSERT INTO t VALUES (1, 'a'), (2, NULL)")
+            .await
+            .unwrap();
+        assert_eq!(affected, 2);
+
+        let result = client
+            .query_rows("SELECT * FROM t ORDER BY id", None)
+            .await
+            .unwrap();
+        assert!(!result.truncated);
+        assert_eq!(result.header, vec!["id", "name"]);
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![CellValue::Int(1), CellValue::Text("a".to_string())],
+                vec![CellValue::Int(2), CellValue::Null],
+            ]
+        );
+
+        let limited = client
+            .query_rows("SELECT * FROM t ORDER BY id", Some(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            limited.rows,
+            vec![vec![CellValue::Int(1), CellValue::Text("a".to_string())]]
+        );
+        assert!(limited.truncated);
+        let exact = client
+            .query_rows("SELECT * FROM t ORDER BY id", Some(2))
+            .await
+            .unwrap();
+        assert!(!exact.truncated);
+
+        let updated = client
+            .query_rows("UPDATE t SET name = 'b' WHERE id IN (1, 2)", None)
+            .await
+            .unwrap();
+        assert!(updated.header.is_empty());
+        assert_eq!(updated.affected, Some(2));
+        let deleted = client
+            .query_rows("DELETE FROM t WHERE id = 99", None)
+            .await
+            .unwrap();
+        assert_eq!(deleted.affected, Some(0));
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let end = client
+            .stream_rows("SELECT id FROM t ORDER BY id", Some(1), tx)
+            .await
+            .unwrap();
+        assert!(end.truncated);
+        assert_eq!(
+            rx.recv().await,
+            Some(StreamedRow::Header(vec!["id".to_string()]))
+        );
+        assert_eq!(
+            rx.recv().await,
+            Some(StreamedRow::Row(vec![CellValue::Int(1)]))
+        );
+        assert_eq!(rx.recv().await, None);
+
+        let err = client
+            .query_rows("SELECT * FROM nope", None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, RunStatus::DbError);
+
+        client.close().await.unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
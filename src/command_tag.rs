@@ -0,0 +1,143 @@
+//! psql-style command tags for statements that don't return rows
+//!
+//! tokio_postgres keeps the server's command tag to itself and only reports the
+//! affected row count, so the tag is rebuilt from the statement's leading
+//! keywords: `UPDATE 3`, `INSERT 0 1`, `CREATE TABLE`. Queries (SELECT, WITH,
+//! VALUES, SHOW, EXPLAIN, ...) have no tag; their rows are the result.
+
+/// Words between CREATE/ALTER/DROP and the object type that psql leaves out of
+/// the tag, e.g. `CREATE OR REPLACE FUNCTION` -> `CREATE FUNCTION`
+const MODIFIERS: &[&str] = &[
+    "OR",
+    "REPLACE",
+    "UNIQUE",
+    "TEMP",
+    "TEMPORARY",
+    "UNLOGGED",
+    "GLOBAL",
+    "LOCAL",
+    "RECURSIVE",
+    "TRUSTED",
+    "PROCEDURAL",
+];
+
+/// Object types named by two words, e.g. `CREATE MATERIALIZED VIEW`
+const TWO_WORD_OBJECTS: &[&str] = &["MATERIALIZED", "FOREIGN", "EVENT"];
+
+/// Statements whose tag carries the affected row count
+const COUNTED: &[&str] = &["UPDATE", "DELETE", "MERGE", "COPY", "MOVE", "FETCH"];
+
+/// Statements that are queries: their rows are shown and they have no tag
+const QUERIES: &[&str] = &[
+    "SELECT", "WITH", "VALUES", "TABLE", "SHOW", "EXPLAIN", "DESCRIBE", "DESC", "PRAGMA",
+];
+
+/// Tag psql prints for `sql` after it affected `affected` rows, or None for
+/// queries and for counted statements whose count isn't known
+pub fn command_tag(sql: &str, affected: Option<u64>) -> Option<String> {
+    let words = leading_words(sql);
+    let first = words.first()?;
+    match first.as_str() {
+        "INSERT" => Some(format!("INSERT 0 {}", affected?)),
+        word if COUNTED.contains(&word) => Some(format!("{} {}", word, affected?)),
+        word if QUERIES.contains(&word) => None,
+        "CREATE" | "ALTER" | "DROP" => {
+            let mut object = words[1..]
+                .iter()
+                .skip_while(|word| MODIFIERS.contains(&word.as_str()));
+            let mut tag = first.clone();
+            if let Some(kind) = object.next() {
+                tag = format!("{} {}", tag, kind);
+                if TWO_WORD_OBJECTS.contains(&kind.as_str()) {
+                    if let Some(kind) = object.next() {
+                        tag = format!("{} {}", tag, kind);
+                    }
+                }
+            }
+            Some(tag)
+        }
+        "TRUNCATE" => Some("TRUNCATE TABLE".to_string()),
+        word => Some(word.to_string()),
+    }
+}
+
+/// The first few keywords of `sql` in upper case, after any comments
+pub fn leading_words(sql: &str) -> Vec<String> {
+    let mut rest = sql;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else {
+            break;
+        }
+    }
+
+    rest.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .take(5)
+        .map(str::to_uppercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(sql: &str, affected: u64) -> Option<String> {
+        command_tag(sql, Some(affected))
+    }
+
+    #[test]
+    fn test_dml_tags_carry_the_count() {
+        assert_eq!(
+            tag("UPDATE users SET name = 'x' WHERE id = 5", 3).as_deref(),
+            Some("UPDATE 3")
+        );
+        assert_eq!(
+            tag("delete from users where false", 0).as_deref(),
+            Some("DELETE 0")
+        );
+        assert_eq!(
+            tag("-- add one\nINSERT INTO users (name) VALUES ('a')", 1).as_deref(),
+            Some("INSERT 0 1")
+        );
+        assert_eq!(command_tag("UPDATE users SET x = 1", None), None);
+    }
+
+    #[test]
+    fn test_ddl_tags_name_the_object() {
+        assert_eq!(
+            tag("CREATE TABLE t (id int)", 0).as_deref(),
+            Some("CREATE TABLE")
+        );
+        assert_eq!(
+            tag("create or replace function f() returns int", 0).as_deref(),
+            Some("CREATE FUNCTION")
+        );
+        assert_eq!(
+            tag("CREATE UNIQUE INDEX i ON t (id)", 0).as_deref(),
+            Some("CREATE INDEX")
+        );
+        assert_eq!(
+            tag("/* cache */ CREATE MATERIALIZED VIEW v AS SELECT 1", 0).as_deref(),
+            Some("CREATE MATERIALIZED VIEW")
+        );
+        assert_eq!(
+            tag("DROP TABLE IF EXISTS t", 0).as_deref(),
+            Some("DROP TABLE")
+        );
+        assert_eq!(tag("truncate t", 0).as_deref(), Some("TRUNCATE TABLE"));
+        assert_eq!(tag("BEGIN;", 0).as_deref(), Some("BEGIN"));
+    }
+
+    #[test]
+    fn test_queries_have_no_tag() {
+        assert_eq!(tag("SELECT 1", 1), None);
+        assert_eq!(tag("(SELECT 1) UNION (SELECT 2)", 2), None);
+        assert_eq!(tag("WITH x AS (SELECT 1) SELECT * FROM x", 1), None);
+        assert_eq!(tag("-- nothing", 0), None);
+    }
+}
@@ -1,5 +1,19 @@
+pub mod editor;
+
+use crate::deadline::{
+    DEFAULT_FFI_CALL_TIMEOUT_MS, DEFAULT_FFI_CONNECT_TIMEOUT_MS, DEFAULT_FFI_EXECUTE_TIMEOUT_MS,
+};
+use crate::display::{ExpandedMode, OutputFormat};
+use crate::known_hosts::HostKeyPolicy;
+use crate::meta_commands::Dialect;
+use crate::output::{DEFAULT_CELL_BUDGET, DEFAULT_MAX_OUTPUT_BYTES};
+use crate::sql;
+use crate::tunnel::{
+    DEFAULT_SSH_CONNECT_TIMEOUT_SECS, DEFAULT_SSH_KEEPALIVE_SECS, DEFAULT_SSH_RECONNECT_ATTEMPTS,
+};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -11,24 +25,292 @@ pub struct SqlConfig {
     /// Skip SSH host key verification (INSECURE - only for testing/dev environments)
     #[serde(default)]
     pub skip_host_key_verification: bool,
+    /// What to do with SSH host keys missing from ~/.ssh/known_hosts: reject
+    /// them ("strict"), trust and append them ("accept-new"), or append them
+    /// once the user accepts their fingerprint ("ask"). A changed key is
+    /// always rejected
+    #[serde(default)]
+    pub host_key_policy: HostKeyPolicy,
+    /// Seconds between SSH keepalives on tunnels, so idle sessions aren't
+    /// dropped by firewalls and dead ones are noticed; 0 disables them
+    #[serde(default = "default_ssh_keepalive_secs")]
+    pub ssh_keepalive_secs: u64,
+    /// Attempts to re-establish a tunnel's dropped SSH session, waiting 1s,
+    /// 2s, 4s, ... between them, before the tunnel is closed
+    #[serde(default = "default_ssh_reconnect_attempts")]
+    pub ssh_reconnect_attempts: u32,
+    /// Seconds connecting to an SSH server may take, handshake and
+    /// authentication included, before it fails as unreachable
+    #[serde(default = "default_ssh_connect_timeout_secs")]
+    pub ssh_connect_timeout_secs: u64,
+    /// Append per-phase connect timings to the results.dbout connection banner
+    #[serde(default)]
+    pub verbose_connect: bool,
+    /// Queue executions started while a query is running on the same connection;
+    /// when false they fail with a busy error instead
+    #[serde(default = "default_queue_queries")]
+    pub queue_queries: bool,
+    /// Where results go when results.dbout can't be written
+    /// (default: ~/.local/state/helix-dadbod/results_fallback.dbout)
+    #[serde(default)]
+    pub results_fallback_file: Option<String>,
+    /// Directory of the SQL files and results.dbout; `~` and `$VAR` are
+    /// expanded (default: $XDG_RUNTIME_DIR/helix-dadbod, else /tmp/helix-dadbod)
+    #[serde(default)]
+    pub workspace_dir: Option<String>,
+    /// What new SQL files start with, instead of a short header: the template
+    /// itself, or a template file when it's one line starting with `/` or `~`.
+    /// `{connection}`, `{database}` and `{host}` are filled in
+    #[serde(default)]
+    pub query_template: Option<String>,
+    /// Also copy results.dbout of every execution to the workspace's history
+    /// directory, next to the history.sql log of executed statements
+    #[serde(default)]
+    pub keep_result_history: bool,
+    /// Query history entries and result copies older than this many days are
+    /// pruned; 0 keeps them
+    #[serde(default = "default_history_max_age_days")]
+    pub history_max_age_days: u64,
+    /// The oldest query history is pruned once the history directory grows
+    /// past this many megabytes, result copies first; 0 for no limit
+    #[serde(default = "default_history_max_mb")]
+    pub history_max_mb: u64,
+    /// Split result tables longer than this many rows into pages in results.dbout
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    /// Tables with more cells (rows × columns) are written unaligned instead of
+    /// through comfy_table; 0 always aligns
+    #[serde(default = "default_table_cell_budget")]
+    pub table_cell_budget: usize,
+    /// Longest a blocking call from Helix may take, in milliseconds; connects and
+    /// executions still running then continue in the background
+    #[serde(default = "default_ffi_call_timeout_ms")]
+    pub ffi_call_timeout_ms: u64,
+    /// Deadline of connects from Helix, in milliseconds, longer than other calls
+    /// for SSH tunnels through a slow bastion; 0 waits for the connect
+    #[serde(default = "default_ffi_connect_timeout_ms")]
+    pub ffi_connect_timeout_ms: u64,
+    /// Deadline of executions from Helix, in milliseconds; 0 (the default)
+    /// waits for the query however long it runs
+    #[serde(default = "default_ffi_execute_timeout_ms")]
+    pub ffi_execute_timeout_ms: u64,
+    /// Text shown for NULL values on every connection (default: NULL); a
+    /// connection's `output.null` and `\pset null` override it
+    #[serde(default)]
+    pub null_display: Option<String>,
+    /// Most rows read from a query's result; the rest are never fetched into
+    /// memory and a footer notes the cut. 0 reads every row
+    #[serde(default = "default_max_rows")]
+    pub max_rows: usize,
+    /// Rows stop being written to results.dbout once it reaches this many
+    /// bytes, and a footer notes how many were shown; 0 disables the cap
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    /// Largest file `Dadbod::execute_file` runs; bigger ones are more likely a
+    /// dump than a query. 0 runs any size
+    #[serde(default = "default_max_execute_file_bytes")]
+    pub max_execute_file_bytes: u64,
+    /// Let `\copy ... TO` replace an existing file without FORCE in its options
+    #[serde(default)]
+    pub copy_overwrite: bool,
+    /// File this config was read from; None when built from the environment
+    #[serde(skip)]
+    pub loaded_from: Option<PathBuf>,
 }
 
 fn default_log_level() -> String {
     "info".to_string()
 }
 
+fn default_max_rows() -> usize {
+    1000
+}
+
+fn default_max_output_bytes() -> usize {
+    DEFAULT_MAX_OUTPUT_BYTES
+}
+
+fn default_max_execute_file_bytes() -> u64 {
+    1024 * 1024
+}
+
+fn default_history_max_age_days() -> u64 {
+    30
+}
+
+fn default_history_max_mb() -> u64 {
+    50
+}
+
+fn default_ssh_keepalive_secs() -> u64 {
+    DEFAULT_SSH_KEEPALIVE_SECS
+}
+
+fn default_ssh_reconnect_attempts() -> u32 {
+    DEFAULT_SSH_RECONNECT_ATTEMPTS
+}
+
+fn default_ssh_connect_timeout_secs() -> u64 {
+    DEFAULT_SSH_CONNECT_TIMEOUT_SECS
+}
+
+fn default_queue_queries() -> bool {
+    true
+}
+
+fn default_table_cell_budget() -> usize {
+    DEFAULT_CELL_BUDGET
+}
+
+fn default_ffi_call_timeout_ms() -> u64 {
+    DEFAULT_FFI_CALL_TIMEOUT_MS
+}
+
+fn default_ffi_connect_timeout_ms() -> u64 {
+    DEFAULT_FFI_CONNECT_TIMEOUT_MS
+}
+
+fn default_ffi_execute_timeout_ms() -> u64 {
+    DEFAULT_FFI_EXECUTE_TIMEOUT_MS
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Connection {
     pub name: String,
     #[serde(rename = "type")]
     pub db_type: String,
+    /// Not used for SQLite
+    #[serde(default)]
     pub host: String,
-    #[serde(default = "default_postgres_port")]
-    pub port: u16,
+    /// Unset means the default port of the type; read it through `port()`
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Database name, or the file path for SQLite (`~` is expanded)
+    #[serde(default)]
     pub database: String,
+    /// Not used for SQLite
+    #[serde(default)]
     pub username: String,
     pub password: Option<String>,
+    /// Command printing the password, run when the connection is established;
+    /// its output is never logged
+    #[serde(default)]
+    pub password_cmd: Option<String>,
+    /// Read the password from the OS keyring at connect time
+    #[serde(default)]
+    pub password_keyring: bool,
+    /// Keyring service of the password (default "helix-dadbod"); setting it
+    /// implies password_keyring
+    #[serde(default)]
+    pub keyring_service: Option<String>,
+    /// Keyring user of the password (default: the connection name); setting
+    /// it implies password_keyring
+    #[serde(default)]
+    pub keyring_user: Option<String>,
+    /// postgres:// URL supplying host, port, database, username, password and
+    /// query parameters like sslmode; fields it sets must not be contradicted
+    #[serde(default)]
+    pub url: Option<String>,
     pub ssh_tunnel: Option<SshTunnel>,
+    /// UNIX socket the database listens on at the SSH server, e.g.
+    /// `/var/run/postgresql/.s.PGSQL.5432`; replaces host, and needs ssh_tunnel
+    #[serde(default)]
+    pub remote_socket: Option<String>,
+    /// Execute the workspace SQL file automatically whenever it is saved
+    #[serde(default)]
+    pub auto_execute_on_save: bool,
+    /// libpq sslmode passed through to the connection string (e.g. "disable", "prefer")
+    #[serde(default)]
+    pub sslmode: Option<String>,
+    /// Client certificate (PEM) for TLS client authentication (`~` is expanded)
+    #[serde(default)]
+    pub ssl_cert: Option<String>,
+    /// Private key (PEM) of `ssl_cert`
+    #[serde(default)]
+    pub ssl_key: Option<String>,
+    /// Passphrase of an encrypted `ssl_key`
+    #[serde(default)]
+    pub ssl_key_password: Option<String>,
+    /// Command printing the passphrase of an encrypted `ssl_key`
+    #[serde(default)]
+    pub ssl_key_password_cmd: Option<String>,
+    /// CA certificates (PEM) that verify-ca and verify-full trust instead of
+    /// the system's (`~` is expanded)
+    #[serde(default)]
+    pub ssl_root_cert: Option<String>,
+    /// Display settings the connection starts each session with
+    #[serde(default)]
+    pub output: Option<OutputConfig>,
+    /// Interpret leading-backslash lines (\dt, \prompt, ...) as meta-commands;
+    /// when false the SQL file is always sent to the server as written
+    #[serde(default = "default_meta_commands")]
+    pub meta_commands: bool,
+    /// SQL dialect for meta-commands ("postgres" or "cockroach"); detected from
+    /// `version()` at connect when unset
+    #[serde(default)]
+    pub dialect: Option<Dialect>,
+    /// PostgreSQL only: schemas the session's search_path is set to after
+    /// connecting, e.g. ["app", "public"]
+    #[serde(default)]
+    pub search_path: Option<Vec<String>>,
+    /// PostgreSQL only: run-time parameters set for the session after
+    /// connecting, e.g. { work_mem = "256MB", timezone = "UTC" }
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub session_settings: BTreeMap<String, String>,
+    /// PostgreSQL only: name the session shows in pg_stat_activity
+    #[serde(default = "default_application_name")]
+    pub application_name: String,
+    /// PostgreSQL only: seconds to wait for the database to accept the
+    /// connection, through the tunnel if there is one; 0 waits as long as the OS
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// PostgreSQL only: seconds of idleness before TCP keepalive probes are
+    /// sent; 0 turns keepalives off (default: tokio-postgres's, 2 hours)
+    #[serde(default)]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// SQLite only: create the database file when it doesn't exist instead of
+    /// reporting an error
+    #[serde(default)]
+    pub create_if_missing: bool,
+    /// Synthesized from PG* environment variables rather than read from config.toml
+    #[serde(skip)]
+    pub from_env: bool,
+}
+
+/// Per-connection `output = { ... }` block; unset options use the defaults
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub format: Option<OutputFormat>,
+    /// "on", "off" or "auto"
+    pub expanded: Option<ExpandedMode>,
+    /// Text shown for NULL values
+    pub null: Option<String>,
+    /// Widest table shown as-is with expanded = "auto"
+    pub max_output_width: Option<usize>,
+    /// Column count above which expanded = "auto" switches to records
+    pub auto_expand_columns: Option<usize>,
+    /// Values longer than this many characters are cut short in tables; 0
+    /// shows them in full
+    pub max_column_width: Option<usize>,
+    /// Separator between fields with format = "unaligned"
+    pub fieldsep: Option<String>,
+    /// Leave out the row count, and the header row of unaligned output
+    pub tuples_only: Option<bool>,
+}
+
+/// Connection details for display, e.g. in the connection picker
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionSummary {
+    pub name: String,
+    pub db_type: String,
+    /// Database host, or the remote_socket path
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub username: String,
+    pub uses_tunnel: bool,
+    pub from_env: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,13 +324,132 @@ pub enum SshTunnel {
         user: String,
         /// Optional private key path, defaults to ~/.ssh/id_rsa or ~/.ssh/id_ed25519
         key_path: Option<PathBuf>,
+        /// Command printing the passphrase of an encrypted private key
+        key_passphrase_cmd: Option<String>,
+        /// Environment variable holding the passphrase of an encrypted private key
+        key_passphrase_env: Option<String>,
+        /// known_hosts file searched first for the server's host key
+        known_hosts_file: Option<String>,
+        /// Verify the server's host key; overrides skip_host_key_verification
+        /// for this tunnel
+        strict_host_key_checking: Option<bool>,
     },
     /// Reference to SSH config entry
-    ConfigRef { ssh_config: String },
+    ConfigRef {
+        ssh_config: String,
+        /// Command printing the passphrase of the entry's encrypted private key
+        key_passphrase_cmd: Option<String>,
+        /// Environment variable holding the passphrase of the entry's key
+        key_passphrase_env: Option<String>,
+        /// known_hosts file searched first, before the entry's UserKnownHostsFile
+        known_hosts_file: Option<String>,
+        /// Verify the server's host key; overrides skip_host_key_verification
+        /// for this tunnel
+        strict_host_key_checking: Option<bool>,
+    },
+}
+
+impl SshTunnel {
+    /// Whether this tunnel skips host key verification: as its
+    /// strict_host_key_checking says, else as the global `skip_global`
+    pub fn skip_host_key_verification(&self, skip_global: bool) -> bool {
+        let strict = match self {
+            SshTunnel::Explicit {
+                strict_host_key_checking,
+                ..
+            }
+            | SshTunnel::ConfigRef {
+                strict_host_key_checking,
+                ..
+            } => strict_host_key_checking,
+        };
+        strict.map_or(skip_global, |strict| !strict)
+    }
 }
 
-fn default_postgres_port() -> u16 {
-    5432
+/// Session-only replacements for a connection's SSH tunnel settings
+///
+/// Lets a tunnel be retried with a different bastion, port, key or jump host
+/// without editing config.toml or ~/.ssh/config. Overrides are never written to
+/// disk and are dropped when the connection closes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TunnelOverrides {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub key_path: Option<PathBuf>,
+    /// ProxyJump hops replacing the configured ones, `[user@]host[:port]` each;
+    /// empty to connect directly
+    pub proxy_jump: Option<Vec<String>>,
+}
+
+impl TunnelOverrides {
+    /// Build overrides from key/value pairs
+    /// Supported keys: host, port, user, key_path, proxy_jump (comma-separated
+    /// hops as in `ssh -J`, or "none")
+    pub fn from_pairs<K, V>(pairs: impl IntoIterator<Item = (K, V)>) -> Result<Self>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut overrides = Self::default();
+
+        for (key, value) in pairs {
+            let value = value.as_ref().trim();
+            match key.as_ref() {
+                "host" => overrides.host = Some(value.to_string()),
+                "port" => {
+                    let port = value
+                        .parse()
+                        .with_context(|| format!("Invalid port override: '{}'", value))?;
+                    overrides.port = Some(port);
+                }
+                "user" => overrides.user = Some(value.to_string()),
+                "key_path" => overrides.key_path = Some(crate::ssh_config::expand_tilde(value)),
+                "proxy_jump" => {
+                    let hops: Vec<String> = match value {
+                        "" | "none" => Vec::new(),
+                        hops => hops.split(',').map(|hop| hop.trim().to_string()).collect(),
+                    };
+                    for hop in &hops {
+                        crate::ssh_config::JumpHost::parse(hop)
+                            .context("Invalid proxy_jump override")?;
+                    }
+                    overrides.proxy_jump = Some(hops);
+                }
+                other => anyhow::bail!(
+                    "Unknown tunnel override '{}' (expected host, port, user, key_path or proxy_jump)",
+                    other
+                ),
+            }
+        }
+
+        Ok(overrides)
+    }
+}
+
+/// Port of PostgreSQL connections that don't set one
+pub const DEFAULT_POSTGRES_PORT: u16 = 5432;
+
+/// Port of MySQL connections that don't set one
+pub const DEFAULT_MYSQL_PORT: u16 = 3306;
+
+fn default_meta_commands() -> bool {
+    true
+}
+
+/// application_name PostgreSQL sessions report in pg_stat_activity by default
+pub const DEFAULT_APPLICATION_NAME: &str = "helix-dadbod";
+
+/// Seconds a PostgreSQL connect may take by default
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+fn default_application_name() -> String {
+    DEFAULT_APPLICATION_NAME.to_string()
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    DEFAULT_CONNECT_TIMEOUT_SECS
 }
 
 fn default_ssh_port() -> u16 {
@@ -61,38 +462,191 @@ impl SqlConfig {
         let contents = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-        let config: SqlConfig = toml::from_str(&contents)
+        let mut config: SqlConfig = toml::from_str(&contents)
             .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        config.loaded_from = Some(path.clone());
+        config
+            .resolve_urls()
+            .and_then(|_| config.validate())
+            .with_context(|| format!("Invalid config file: {}", path.display()))?;
 
         Ok(config)
     }
 
     /// Load from default location (./config.toml or ~/.config/helix-dadbod/config.toml)
+    /// Falls back to a connection built from PGHOST/PGDATABASE etc. when neither exists
     pub fn from_default_location() -> Result<Self> {
-        // Try current directory first
-        let local_path = PathBuf::from("config.toml");
-        if local_path.exists() {
-            return Self::from_file(&local_path);
+        // Try current directory first, then Unix-style ~/.config/helix-dadbod/config.toml
+        let mut candidates = vec![PathBuf::from("config.toml")];
+        if let Some(home) = dirs::home_dir() {
+            candidates.push(
+                home.join(".config")
+                    .join("helix-dadbod")
+                    .join("config.toml"),
+            );
         }
 
-        // Try Unix-style ~/.config/helix-dadbod/config.toml
-        if let Some(home) = dirs::home_dir() {
-            let unix_config = home
-                .join(".config")
-                .join("helix-dadbod")
-                .join("config.toml");
-            if unix_config.exists() {
-                return Self::from_file(&unix_config);
-            }
+        Self::from_candidates(&candidates, |key| std::env::var(key).ok())
+    }
+
+    /// Load the first existing config file, or synthesize one from libpq variables
+    fn from_candidates<F>(candidates: &[PathBuf], env: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(path) = candidates.iter().find(|path| path.exists()) {
+            return Self::from_file(path);
+        }
+
+        if let Some(conn) = crate::pg_env::connection_from_env(env) {
+            return Ok(Self::from_env_connection(conn));
         }
 
         anyhow::bail!(
             "No config.toml found in:\n  \
              - ./config.toml\n  \
-             - ~/.config/helix-dadbod/config.toml"
+             - ~/.config/helix-dadbod/config.toml\n\
+             and neither PGHOST nor PGDATABASE is set"
         )
     }
 
+    /// Fill in the fields of connections given as a `url`
+    pub(crate) fn resolve_urls(&mut self) -> Result<()> {
+        self.connections
+            .iter_mut()
+            .try_for_each(crate::pg_url::apply)
+    }
+
+    /// Check what serde can't: network databases need a host and username,
+    /// SQLite files can't be reached through an SSH tunnel, client
+    /// certificates need their key, and passwords come from one source
+    pub fn validate(&self) -> Result<()> {
+        for conn in &self.connections {
+            if crate::sqlite::SQLITE_DB_TYPES.contains(&conn.db_type.as_str()) {
+                if conn.ssh_tunnel.is_some() {
+                    anyhow::bail!(
+                        "Connection '{}': SQLite databases are local files and can't use ssh_tunnel",
+                        conn.name
+                    );
+                }
+            } else if conn.remote_socket.is_some() {
+                if conn.ssh_tunnel.is_none() {
+                    anyhow::bail!(
+                        "Connection '{}': remote_socket is reached through ssh_tunnel, which is not set",
+                        conn.name
+                    );
+                }
+                if !conn.host.is_empty() {
+                    anyhow::bail!(
+                        "Connection '{}': set host or remote_socket, not both",
+                        conn.name
+                    );
+                }
+                if conn.username.is_empty() {
+                    anyhow::bail!("Connection '{}' needs a username", conn.name);
+                }
+            } else if conn.host.is_empty() || conn.username.is_empty() {
+                anyhow::bail!("Connection '{}' needs a host and username", conn.name);
+            }
+            if conn.password.is_some() && conn.password_cmd.is_some() {
+                anyhow::bail!(
+                    "Connection '{}': set password or password_cmd, not both",
+                    conn.name
+                );
+            }
+            if conn.uses_keyring() && (conn.password.is_some() || conn.password_cmd.is_some()) {
+                anyhow::bail!(
+                    "Connection '{}': the keyring password can't be combined with password or password_cmd",
+                    conn.name
+                );
+            }
+            if conn.ssl_cert.is_some() != conn.ssl_key.is_some() {
+                anyhow::bail!(
+                    "Connection '{}': ssl_cert and ssl_key must be set together",
+                    conn.name
+                );
+            }
+            if conn.ssl_key_password.is_some() && conn.ssl_key_password_cmd.is_some() {
+                anyhow::bail!(
+                    "Connection '{}': set ssl_key_password or ssl_key_password_cmd, not both",
+                    conn.name
+                );
+            }
+            if conn.search_path.is_some()
+                && conn
+                    .session_settings
+                    .keys()
+                    .any(|name| name.eq_ignore_ascii_case("search_path"))
+            {
+                anyhow::bail!(
+                    "Connection '{}': set search_path or session_settings.search_path, not both",
+                    conn.name
+                );
+            }
+            let other_db = [crate::mysql::MYSQL_DB_TYPES, crate::sqlite::SQLITE_DB_TYPES]
+                .concat()
+                .contains(&conn.db_type.as_str());
+            if other_db && !conn.session_parameters().is_empty() {
+                anyhow::bail!(
+                    "Connection '{}': search_path and session_settings are PostgreSQL only",
+                    conn.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn from_env_connection(conn: Connection) -> Self {
+        Self {
+            connections: vec![conn],
+            log_level: default_log_level(),
+            skip_host_key_verification: false,
+            host_key_policy: HostKeyPolicy::default(),
+            ssh_keepalive_secs: default_ssh_keepalive_secs(),
+            ssh_reconnect_attempts: default_ssh_reconnect_attempts(),
+            ssh_connect_timeout_secs: default_ssh_connect_timeout_secs(),
+            verbose_connect: false,
+            queue_queries: default_queue_queries(),
+            results_fallback_file: None,
+            workspace_dir: None,
+            query_template: None,
+            keep_result_history: false,
+            history_max_age_days: default_history_max_age_days(),
+            history_max_mb: default_history_max_mb(),
+            page_size: None,
+            table_cell_budget: default_table_cell_budget(),
+            ffi_call_timeout_ms: default_ffi_call_timeout_ms(),
+            ffi_connect_timeout_ms: default_ffi_connect_timeout_ms(),
+            ffi_execute_timeout_ms: default_ffi_execute_timeout_ms(),
+            null_display: None,
+            max_rows: default_max_rows(),
+            max_output_bytes: default_max_output_bytes(),
+            max_execute_file_bytes: default_max_execute_file_bytes(),
+            copy_overwrite: false,
+            loaded_from: None,
+        }
+    }
+
+    /// Directory workspaces are created in, `workspace_dir` expanded
+    pub fn workspace_dir(&self) -> Result<PathBuf> {
+        crate::workspace::resolve_dir(self.workspace_dir.as_deref(), |key| std::env::var(key).ok())
+    }
+
+    /// Template of new SQL files: `query_template`, read from its file when it
+    /// names one, else workspace::DEFAULT_QUERY_TEMPLATE
+    pub fn query_template(&self) -> Result<String> {
+        let Some(template) = self.query_template.as_deref() else {
+            return Ok(crate::workspace::DEFAULT_QUERY_TEMPLATE.to_string());
+        };
+        let line = template.trim();
+        if template.contains('\n') || !(line.starts_with('/') || line.starts_with('~')) {
+            return Ok(template.to_string());
+        }
+        let path = crate::ssh_config::expand_tilde(line);
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read query_template {}", path.display()))
+    }
+
     /// Get connection by name
     pub fn get_connection(&self, name: &str) -> Option<&Connection> {
         self.connections.iter().find(|c| c.name == name)
@@ -102,13 +656,63 @@ impl SqlConfig {
     pub fn list_connections(&self) -> Vec<&str> {
         self.connections.iter().map(|c| c.name.as_str()).collect()
     }
+
+    /// List all connections with their target details
+    pub fn list_connections_detailed(&self) -> Vec<ConnectionSummary> {
+        self.connections
+            .iter()
+            .map(|c| ConnectionSummary {
+                name: c.name.clone(),
+                db_type: c.db_type.clone(),
+                host: c.remote_socket.clone().unwrap_or_else(|| c.host.clone()),
+                port: c.port(),
+                database: c.database.clone(),
+                username: c.username.clone(),
+                uses_tunnel: c.needs_tunnel(),
+                from_env: c.from_env,
+            })
+            .collect()
+    }
 }
 
 impl Connection {
+    /// The configured port, or the default one of the connection's type
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(
+            if crate::mysql::MYSQL_DB_TYPES.contains(&self.db_type.as_str()) {
+                DEFAULT_MYSQL_PORT
+            } else {
+                DEFAULT_POSTGRES_PORT
+            },
+        )
+    }
+
     /// Check if this connection requires an SSH tunnel
     pub fn needs_tunnel(&self) -> bool {
         self.ssh_tunnel.is_some()
     }
+
+    /// Whether the password is read from the OS keyring
+    pub fn uses_keyring(&self) -> bool {
+        self.password_keyring || self.keyring_service.is_some() || self.keyring_user.is_some()
+    }
+
+    /// Parameters set for the session after connecting: `search_path`, each
+    /// schema quoted as an identifier, then `session_settings`
+    pub fn session_parameters(&self) -> Vec<(String, String)> {
+        let search_path = self.search_path.as_ref().map(|schemas| {
+            let quoted: Vec<String> = schemas.iter().map(|schema| sql::ident(schema)).collect();
+            ("search_path".to_string(), quoted.join(", "))
+        });
+        search_path
+            .into_iter()
+            .chain(
+                self.session_settings
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            )
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -155,6 +759,259 @@ mod tests {
         assert!(config.connections[0].needs_tunnel());
     }
 
+    #[test]
+    fn test_parse_output_block() {
+        let toml = r#"
+            [[connections]]
+            name = "warehouse"
+            type = "postgres"
+            host = "localhost"
+            database = "dw"
+            username = "user"
+            output = { format = "table", expanded = "auto", null = "∅" }
+
+            [[connections]]
+            name = "app"
+            type = "postgres"
+            host = "localhost"
+            database = "app"
+            username = "user"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        let output = config.connections[0].output.as_ref().unwrap();
+        assert_eq!(output.format, Some(OutputFormat::Table));
+        assert_eq!(output.expanded, Some(ExpandedMode::Auto));
+        assert_eq!(output.null.as_deref(), Some("∅"));
+        assert_eq!(output.max_output_width, None);
+        assert!(config.connections[1].output.is_none());
+
+        let invalid = toml.replace("\"auto\"", "\"sometimes\"");
+        assert!(toml::from_str::<SqlConfig>(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_parse_meta_commands_flag() {
+        let toml = r#"
+            [[connections]]
+            name = "cockroach"
+            type = "postgres"
+            host = "localhost"
+            database = "defaultdb"
+            username = "root"
+            meta_commands = false
+
+            [[connections]]
+            name = "app"
+            type = "postgres"
+            host = "localhost"
+            database = "app"
+            username = "user"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        assert!(!config.get_connection("cockroach").unwrap().meta_commands);
+        assert!(config.get_connection("app").unwrap().meta_commands);
+    }
+
+    #[test]
+    fn test_parse_dialect() {
+        let toml = r#"
+            [[connections]]
+            name = "cockroach"
+            type = "postgres"
+            host = "localhost"
+            port = 26257
+            database = "defaultdb"
+            username = "root"
+            dialect = "cockroach"
+
+            [[connections]]
+            name = "app"
+            type = "postgres"
+            host = "localhost"
+            database = "app"
+            username = "user"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        assert_eq!(
+            config.get_connection("cockroach").unwrap().dialect,
+            Some(Dialect::Cockroach)
+        );
+        assert_eq!(config.get_connection("app").unwrap().dialect, None);
+
+        let invalid = toml.replace("\"cockroach\"\n", "\"oracle\"\n");
+        assert!(toml::from_str::<SqlConfig>(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_default_port_follows_the_type() {
+        let toml = r#"
+            [[connections]]
+            name = "app"
+            type = "postgres"
+            host = "localhost"
+            database = "app"
+            username = "user"
+
+            [[connections]]
+            name = "shop"
+            type = "mysql"
+            host = "localhost"
+            database = "shop"
+            username = "user"
+
+            [[connections]]
+            name = "legacy"
+            type = "mariadb"
+            host = "localhost"
+            port = 3307
+            database = "legacy"
+            username = "user"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.get_connection("app").unwrap().port(), 5432);
+        assert_eq!(config.get_connection("shop").unwrap().port(), 3306);
+        assert_eq!(config.get_connection("legacy").unwrap().port(), 3307);
+    }
+
+    #[test]
+    fn test_sqlite_needs_no_host() {
+        let toml = r#"
+            [[connections]]
+            name = "notes"
+            type = "sqlite"
+            database = "~/notes.db"
+            create_if_missing = true
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        let conn = config.get_connection("notes").unwrap();
+        assert!(conn.host.is_empty());
+        assert!(conn.create_if_missing);
+
+        let postgres = toml.replace("\"sqlite\"", "\"postgres\"");
+        let config: SqlConfig = toml::from_str(&postgres).unwrap();
+        let err = config.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Connection 'notes' needs a host and username"
+        );
+
+        let tunneled = format!(
+            "{}\n[connections.ssh_tunnel]\nssh_config = \"bastion\"\n",
+            toml
+        );
+        let config: SqlConfig = toml::from_str(&tunneled).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_password_and_password_cmd_conflict() {
+        let toml = r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+            password_cmd = "pass show prod/db"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.connections[0].password_cmd.as_deref(),
+            Some("pass show prod/db")
+        );
+
+        let config: SqlConfig =
+            toml::from_str(&format!("{}password = \"hunter2\"\n", toml)).unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Connection 'prod': set password or password_cmd, not both"
+        );
+
+        let config: SqlConfig =
+            toml::from_str(&format!("{}password_keyring = true\n", toml)).unwrap();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("keyring password can't be combined"));
+    }
+
+    #[test]
+    fn test_client_cert_validation() {
+        let toml = r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+            sslmode = "verify-full"
+            ssl_cert = "~/.postgresql/postgresql.crt"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Connection 'prod': ssl_cert and ssl_key must be set together"
+        );
+
+        let with_key = format!("{}ssl_key = \"~/.postgresql/postgresql.key\"\n", toml);
+        let config: SqlConfig = toml::from_str(&with_key).unwrap();
+        config.validate().unwrap();
+
+        let both_passwords = format!(
+            "{}ssl_key_password = \"x\"\nssl_key_password_cmd = \"pass show pg\"\n",
+            with_key
+        );
+        let config: SqlConfig = toml::from_str(&both_passwords).unwrap();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("not both"));
+    }
+
+    #[test]
+    fn test_remote_socket_validation() {
+        let toml = r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            database = "app"
+            username = "app"
+            remote_socket = "/var/run/postgresql/.s.PGSQL.5432"
+        "#;
+
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Connection 'prod': remote_socket is reached through ssh_tunnel, which is not set"
+        );
+
+        let tunneled = format!("{}ssh_tunnel = {{ ssh_config = \"bastion\" }}\n", toml);
+        let config: SqlConfig = toml::from_str(&tunneled).unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.list_connections_detailed()[0].host,
+            "/var/run/postgresql/.s.PGSQL.5432"
+        );
+
+        let with_host = format!("{}host = \"db.internal\"\n", tunneled);
+        let config: SqlConfig = toml::from_str(&with_host).unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Connection 'prod': set host or remote_socket, not both"
+        );
+    }
+
     #[test]
     fn test_skip_host_key_verification_defaults_to_false() {
         let toml = r#"
@@ -168,6 +1025,10 @@ mod tests {
 
         let config: SqlConfig = toml::from_str(toml).unwrap();
         assert_eq!(config.skip_host_key_verification, false);
+        assert_eq!(config.host_key_policy, HostKeyPolicy::Strict);
+        assert_eq!(config.ssh_keepalive_secs, 30);
+        assert_eq!(config.ssh_reconnect_attempts, 5);
+        assert_eq!(config.ssh_connect_timeout_secs, 10);
     }
 
     #[test]
@@ -186,4 +1047,316 @@ mod tests {
         let config: SqlConfig = toml::from_str(toml).unwrap();
         assert_eq!(config.skip_host_key_verification, true);
     }
+
+    #[test]
+    fn test_strict_host_key_checking_overrides_the_global_flag() {
+        let config = |global: bool| {
+            let toml = format!(
+                r#"
+                skip_host_key_verification = {}
+
+                [[connections]]
+                name = "dev-vm"
+                type = "postgres"
+                host = "localhost"
+                database = "app"
+                username = "app"
+                ssh_tunnel = {{ host = "vm.local", user = "dev", strict_host_key_checking = false }}
+
+                [[connections]]
+                name = "prod"
+                type = "postgres"
+                host = "localhost"
+                database = "app"
+                username = "app"
+                ssh_tunnel = {{ ssh_config = "prod-bastion", strict_host_key_checking = true }}
+
+                [[connections]]
+                name = "staging"
+                type = "postgres"
+                host = "localhost"
+                database = "app"
+                username = "app"
+                ssh_tunnel = {{ ssh_config = "staging-bastion" }}
+            "#,
+                global
+            );
+            toml::from_str::<SqlConfig>(&toml).unwrap()
+        };
+        let skips = |config: &SqlConfig| -> Vec<bool> {
+            config
+                .connections
+                .iter()
+                .map(|conn| {
+                    conn.ssh_tunnel
+                        .as_ref()
+                        .unwrap()
+                        .skip_host_key_verification(config.skip_host_key_verification)
+                })
+                .collect()
+        };
+
+        // Global off, per-connection off for the dev VM only
+        assert_eq!(skips(&config(false)), vec![true, false, false]);
+        // Global on, per-connection on for prod
+        assert_eq!(skips(&config(true)), vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_session_parameters() {
+        let toml = r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+            search_path = ["billing", "$user", "public", "Odd\"Name"]
+            session_settings = { work_mem = "256MB", timezone = "UTC" }
+        "#;
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.connections[0].session_parameters(),
+            vec![
+                (
+                    "search_path".to_string(),
+                    r#""billing", "$user", "public", "Odd""Name""#.to_string()
+                ),
+                ("timezone".to_string(), "UTC".to_string()),
+                ("work_mem".to_string(), "256MB".to_string()),
+            ]
+        );
+
+        let both = toml.replace("timezone", "search_path");
+        let config: SqlConfig = toml::from_str(&both).unwrap();
+        assert!(config.validate().is_err());
+
+        let mysql = toml.replace("postgres", "mysql");
+        let config: SqlConfig = toml::from_str(&mysql).unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Connection 'prod': search_path and session_settings are PostgreSQL only"
+        );
+    }
+
+    #[test]
+    fn test_postgres_session_options() {
+        let toml = r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+
+            [[connections]]
+            name = "tuned"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+            application_name = "ops console"
+            connect_timeout_secs = 3
+            tcp_keepalive_secs = 60
+        "#;
+        let config: SqlConfig = toml::from_str(toml).unwrap();
+
+        let prod = config.get_connection("prod").unwrap();
+        assert_eq!(prod.application_name, DEFAULT_APPLICATION_NAME);
+        assert_eq!(prod.connect_timeout_secs, DEFAULT_CONNECT_TIMEOUT_SECS);
+        assert_eq!(prod.tcp_keepalive_secs, None);
+
+        let tuned = config.get_connection("tuned").unwrap();
+        assert_eq!(tuned.application_name, "ops console");
+        assert_eq!(tuned.connect_timeout_secs, 3);
+        assert_eq!(tuned.tcp_keepalive_secs, Some(60));
+    }
+
+    #[test]
+    fn test_query_template() {
+        let config: SqlConfig = toml::from_str("").unwrap();
+        assert_eq!(
+            config.query_template().unwrap(),
+            crate::workspace::DEFAULT_QUERY_TEMPLATE
+        );
+
+        let inline = "-- {connection} on {host}\nSELECT 1;\n";
+        let config: SqlConfig = toml::from_str(&format!("query_template = {:?}", inline)).unwrap();
+        assert_eq!(config.query_template().unwrap(), inline);
+
+        let path = std::env::temp_dir().join("helix-dadbod-query-template.sql");
+        std::fs::write(&path, "-- from {database}\n").unwrap();
+        let config: SqlConfig = toml::from_str(&format!(
+            "query_template = {:?}",
+            path.display().to_string()
+        ))
+        .unwrap();
+        assert_eq!(config.query_template().unwrap(), "-- from {database}\n");
+        std::fs::remove_file(&path).unwrap();
+        assert!(config.query_template().is_err());
+    }
+
+    #[test]
+    fn test_host_key_policy() {
+        let config: SqlConfig = toml::from_str(r#"host_key_policy = "accept-new""#).unwrap();
+        assert_eq!(config.host_key_policy, HostKeyPolicy::AcceptNew);
+        let config: SqlConfig = toml::from_str(r#"host_key_policy = "ask""#).unwrap();
+        assert_eq!(config.host_key_policy, HostKeyPolicy::Ask);
+        assert!(toml::from_str::<SqlConfig>(r#"host_key_policy = "yes""#).is_err());
+    }
+
+    #[test]
+    fn test_tunnel_overrides_from_pairs() {
+        let overrides = TunnelOverrides::from_pairs([
+            ("host", "bastion-2.example.com"),
+            ("port", "2222"),
+            ("user", "debug"),
+            ("key_path", "/keys/debug_key"),
+            ("proxy_jump", "gateway, ops@10.0.0.7:2200"),
+        ])
+        .unwrap();
+
+        assert_eq!(overrides.host.as_deref(), Some("bastion-2.example.com"));
+        assert_eq!(overrides.port, Some(2222));
+        assert_eq!(overrides.user.as_deref(), Some("debug"));
+        assert_eq!(overrides.key_path, Some(PathBuf::from("/keys/debug_key")));
+        assert_eq!(
+            overrides.proxy_jump,
+            Some(vec!["gateway".to_string(), "ops@10.0.0.7:2200".to_string()])
+        );
+
+        let direct = TunnelOverrides::from_pairs([("proxy_jump", "none")]).unwrap();
+        assert_eq!(direct.proxy_jump, Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_tunnel_overrides_rejects_bad_input() {
+        assert!(TunnelOverrides::from_pairs([("port", "not-a-port")]).is_err());
+        assert!(TunnelOverrides::from_pairs([("port", "70000")]).is_err());
+        assert!(TunnelOverrides::from_pairs([("password", "secret")]).is_err());
+        assert!(TunnelOverrides::from_pairs([("proxy_jump", "gateway:port")]).is_err());
+    }
+
+    /// Serializes tests that modify process-wide PG* variables
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    const PG_VARS: [&str; 7] = [
+        "PGHOST",
+        "PGPORT",
+        "PGUSER",
+        "PGDATABASE",
+        "PGPASSWORD",
+        "PGPASSFILE",
+        "PGSSLMODE",
+    ];
+
+    /// Sets PG* variables for the duration of a test and restores them on drop
+    struct ScopedPgEnv {
+        saved: Vec<(&'static str, Option<String>)>,
+        _lock: std::sync::MutexGuard<'static, ()>,
+    }
+
+    impl ScopedPgEnv {
+        fn set(vars: &[(&str, &str)]) -> Self {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let saved = PG_VARS
+                .iter()
+                .map(|key| (*key, std::env::var(key).ok()))
+                .collect();
+            for key in PG_VARS {
+                std::env::remove_var(key);
+            }
+            for (key, value) in vars {
+                std::env::set_var(key, value);
+            }
+            Self { saved, _lock: lock }
+        }
+    }
+
+    impl Drop for ScopedPgEnv {
+        fn drop(&mut self) {
+            for (key, value) in &self.saved {
+                match value {
+                    Some(value) => std::env::set_var(key, value),
+                    None => std::env::remove_var(key),
+                }
+            }
+        }
+    }
+
+    fn load(candidates: &[PathBuf]) -> Result<SqlConfig> {
+        SqlConfig::from_candidates(candidates, |key| std::env::var(key).ok())
+    }
+
+    fn missing_config() -> PathBuf {
+        std::env::temp_dir().join("helix-dadbod-no-such-dir/config.toml")
+    }
+
+    #[test]
+    fn test_env_connection_when_no_config_file() {
+        let _env = ScopedPgEnv::set(&[
+            ("PGHOST", "db.internal"),
+            ("PGUSER", "alice"),
+            ("PGDATABASE", "app"),
+            ("PGPASSWORD", "secret"),
+        ]);
+
+        let config = load(&[missing_config()]).unwrap();
+        assert_eq!(config.list_connections(), vec!["env"]);
+
+        let conn = config.get_connection("env").unwrap();
+        assert_eq!(conn.host, "db.internal");
+        assert_eq!(conn.username, "alice");
+        assert_eq!(conn.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_config_file_wins_over_env() {
+        let _env = ScopedPgEnv::set(&[("PGHOST", "db.internal"), ("PGDATABASE", "app")]);
+
+        let dir = std::env::temp_dir().join("helix-dadbod-config-precedence");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [[connections]]
+            name = "from-file"
+            type = "postgres"
+            host = "localhost"
+            database = "mydb"
+            username = "user"
+            "#,
+        )
+        .unwrap();
+
+        let config = load(&[missing_config(), path]).unwrap();
+        assert_eq!(config.list_connections(), vec!["from-file"]);
+        assert!(!config.connections[0].from_env);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_error_without_config_or_env() {
+        let _env = ScopedPgEnv::set(&[("PGUSER", "alice")]);
+
+        let err = load(&[missing_config()]).unwrap_err();
+        assert!(err.to_string().contains("PGHOST"));
+    }
+
+    #[test]
+    fn test_detailed_listing_flags_env_connection() {
+        let _env = ScopedPgEnv::set(&[("PGDATABASE", "app"), ("PGPORT", "6543")]);
+
+        let details = load(&[missing_config()])
+            .unwrap()
+            .list_connections_detailed();
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].name, "env");
+        assert_eq!(details[0].port, 6543);
+        assert!(details[0].from_env);
+        assert!(!details[0].uses_tunnel);
+    }
 }
@@ -0,0 +1,401 @@
+//! Targeted edits to config.toml that keep the user's formatting
+//!
+//! Rewriting the file from a `SqlConfig` would drop comments and reorder
+//! sections, so edits go through a toml_edit document instead: only the
+//! touched connection table changes, everything else is written back as it
+//! was read. The result is validated as a `SqlConfig` before it replaces the
+//! file, and written to a temporary file that is renamed over the original.
+
+use super::{Connection, SqlConfig};
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// A config.toml document loaded for editing
+pub struct ConfigEditor {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigEditor {
+    /// Load `path` for editing; a missing file starts an empty document
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read config file: {}", path.display()))
+            }
+        };
+        Self::parse(path, &contents)
+    }
+
+    /// Edit `contents` as if it had been read from `path`
+    pub fn parse(path: &Path, contents: &str) -> Result<Self> {
+        let doc = contents
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            doc,
+        })
+    }
+
+    /// Append `conn` as a new `[[connections]]` table at the end of the list
+    pub fn append_connection(&mut self, conn: &Connection) -> Result<()> {
+        if self.connection_index(&conn.name).is_some() {
+            anyhow::bail!("Connection '{}' already exists in config", conn.name);
+        }
+
+        let serialized = toml::to_string(conn)
+            .with_context(|| format!("Failed to serialize connection '{}'", conn.name))?;
+        let table = serialized
+            .parse::<DocumentMut>()
+            .context("Failed to convert serialized connection")?
+            .as_table()
+            .clone();
+
+        self.connections_mut()?.push(table);
+        Ok(())
+    }
+
+    /// Set one field of the connection `name`, e.g. `port` or `ssh_tunnel.host`
+    ///
+    /// Intermediate tables of a dotted field are created when missing. The
+    /// field's existing comments and position are kept.
+    pub fn update_connection_field(
+        &mut self,
+        name: &str,
+        field: &str,
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        let index = self
+            .connection_index(name)
+            .with_context(|| format!("Connection '{}' not found in config", name))?;
+        let mut table = self
+            .connections_mut()?
+            .get_mut(index)
+            .expect("index of an existing connection");
+
+        let mut keys: Vec<&str> = field.split('.').collect();
+        let last = keys.pop().filter(|key| !key.is_empty());
+        let Some(last) = last else {
+            anyhow::bail!("Invalid config field '{}'", field);
+        };
+        for key in keys {
+            table = table
+                .entry(key)
+                .or_insert_with(|| Item::Table(Table::new()))
+                .as_table_mut()
+                .with_context(|| format!("'{}' in '{}' is not a table", key, field))?;
+        }
+
+        let value = value.into();
+        match table.get_mut(last).and_then(Item::as_value_mut) {
+            // Keep the comments around the old value
+            Some(existing) => {
+                let decor = existing.decor().clone();
+                *existing = value;
+                *existing.decor_mut() = decor;
+            }
+            None => {
+                table.insert(last, Item::Value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the connection `name` with its comments
+    pub fn remove_connection(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .connection_index(name)
+            .with_context(|| format!("Connection '{}' not found in config", name))?;
+        self.connections_mut()?.remove(index);
+        Ok(())
+    }
+
+    /// Validate the edited document and atomically replace the config file
+    pub fn save(&self) -> Result<()> {
+        let contents = self.to_string();
+        toml::from_str::<SqlConfig>(&contents)
+            .context("Edited config would no longer be valid; not saved")?;
+
+        let file_name = self
+            .path
+            .file_name()
+            .with_context(|| format!("Invalid config path: {}", self.path.display()))?;
+        let tmp = self
+            .path
+            .with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+        // Configs often hold passwords: the temporary file is private from the
+        // start, then gets the original's permissions
+        let _ = std::fs::remove_file(&tmp);
+        Self::create_private(&tmp)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        if let Ok(metadata) = std::fs::metadata(&self.path) {
+            let _ = std::fs::set_permissions(&tmp, metadata.permissions());
+        }
+        std::fs::rename(&tmp, &self.path).with_context(|| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("Failed to replace config file: {}", self.path.display())
+        })?;
+
+        log::info!("Saved config to {}", self.path.display());
+        Ok(())
+    }
+
+    /// Create a new file readable only by its owner
+    fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)
+    }
+
+    fn connections(&self) -> Option<&ArrayOfTables> {
+        self.doc
+            .get("connections")
+            .and_then(Item::as_array_of_tables)
+    }
+
+    fn connections_mut(&mut self) -> Result<&mut ArrayOfTables> {
+        self.doc
+            .entry("connections")
+            .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .context("'connections' in config is not a list of [[connections]] tables")
+    }
+
+    fn connection_index(&self, name: &str) -> Option<usize> {
+        self.connections()?
+            .iter()
+            .position(|table| table.get("name").and_then(Item::as_str) == Some(name))
+    }
+}
+
+impl std::fmt::Display for ConfigEditor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+impl SqlConfig {
+    /// Set one field of connection `name` in the config file at `path`
+    pub fn persist_connection_update(
+        path: &Path,
+        name: &str,
+        field: &str,
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        let mut editor = ConfigEditor::load(path)?;
+        editor.update_connection_field(name, field, value)?;
+        editor.save()
+    }
+
+    /// Append `conn` to the config file at `path`
+    pub fn persist_new_connection(path: &Path, conn: &Connection) -> Result<()> {
+        let mut editor = ConfigEditor::load(path)?;
+        editor.append_connection(conn)?;
+        editor.save()
+    }
+
+    /// Remove connection `name` from the config file at `path`
+    pub fn persist_connection_removal(path: &Path, name: &str) -> Result<()> {
+        let mut editor = ConfigEditor::load(path)?;
+        editor.remove_connection(name)?;
+        editor.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SshTunnel;
+
+    const CONFIG: &str = r#"# helix-dadbod config
+log_level = "debug"  # chatty while testing
+
+# Local database
+[[connections]]
+name = "local"
+type = "postgres"
+host = "localhost"
+port = 5432  # default port
+database = "app"
+username = "postgres"
+
+# Production, through the bastion
+[[connections]]
+name = "prod"
+type = "postgres"
+host = "db.internal"
+database = "app"
+username = "readonly"
+
+[connections.ssh_tunnel]
+host = "bastion.example.com"  # jump host
+user = "deploy"
+"#;
+
+    fn editor() -> ConfigEditor {
+        ConfigEditor::parse(Path::new("config.toml"), CONFIG).unwrap()
+    }
+
+    fn parsed(editor: &ConfigEditor) -> SqlConfig {
+        toml::from_str(&editor.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_unedited_document_round_trips() {
+        assert_eq!(editor().to_string(), CONFIG);
+    }
+
+    #[test]
+    fn test_update_field_keeps_comments() {
+        let mut editor = editor();
+        editor
+            .update_connection_field("local", "port", 5433)
+            .unwrap();
+
+        assert_eq!(
+            editor.to_string(),
+            CONFIG.replace("port = 5432  # default port", "port = 5433  # default port")
+        );
+        assert_eq!(
+            parsed(&editor).get_connection("local").unwrap().port(),
+            5433
+        );
+    }
+
+    #[test]
+    fn test_update_adds_missing_and_nested_fields() {
+        let mut editor = editor();
+        editor
+            .update_connection_field("prod", "password", "s3cret")
+            .unwrap();
+        editor
+            .update_connection_field("prod", "ssh_tunnel.port", 2222)
+            .unwrap();
+
+        let rendered = editor.to_string();
+        assert!(rendered.contains("host = \"bastion.example.com\"  # jump host\n"));
+        assert!(rendered.starts_with("# helix-dadbod config\nlog_level = \"debug\"  # chatty"));
+
+        let config = parsed(&editor);
+        let prod = config.get_connection("prod").unwrap();
+        assert_eq!(prod.password.as_deref(), Some("s3cret"));
+        match &prod.ssh_tunnel {
+            Some(SshTunnel::Explicit { port, .. }) => assert_eq!(*port, 2222),
+            other => panic!("unexpected tunnel: {:?}", other),
+        }
+        assert_eq!(config.get_connection("local").unwrap().password, None);
+    }
+
+    #[test]
+    fn test_update_unknown_connection_fails() {
+        let mut editor = editor();
+        assert!(editor
+            .update_connection_field("staging", "port", 1)
+            .is_err());
+        assert!(editor.update_connection_field("local", "", 1).is_err());
+        assert_eq!(editor.to_string(), CONFIG);
+    }
+
+    #[test]
+    fn test_append_connection_keeps_existing_content() {
+        let mut editor = editor();
+        let mut conn = parsed(&editor).get_connection("local").unwrap().clone();
+        conn.name = "replica".to_string();
+        conn.host = "replica.internal".to_string();
+        editor.append_connection(&conn).unwrap();
+
+        let rendered = editor.to_string();
+        assert!(rendered.starts_with(CONFIG));
+        assert!(rendered[CONFIG.len()..].contains("[[connections]]\nname = \"replica\"\n"));
+
+        let config = parsed(&editor);
+        assert_eq!(config.list_connections(), vec!["local", "prod", "replica"]);
+        assert_eq!(
+            config.get_connection("replica").unwrap().host,
+            "replica.internal"
+        );
+
+        // Names must stay unique
+        assert!(editor.append_connection(&conn).is_err());
+    }
+
+    #[test]
+    fn test_remove_connection_keeps_other_sections() {
+        let mut editor = editor();
+        editor.remove_connection("local").unwrap();
+
+        let rendered = editor.to_string();
+        assert!(rendered.starts_with("# helix-dadbod config\nlog_level = \"debug\"  # chatty"));
+        assert!(!rendered.contains("name = \"local\""));
+        assert!(rendered.contains("# Production, through the bastion\n"));
+        assert!(rendered.contains("host = \"bastion.example.com\"  # jump host\n"));
+        assert_eq!(parsed(&editor).list_connections(), vec!["prod"]);
+
+        assert!(editor.remove_connection("local").is_err());
+    }
+
+    #[test]
+    fn test_persist_writes_atomically() {
+        let dir = std::env::temp_dir().join("helix-dadbod-config-editor");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, CONFIG).unwrap();
+
+        SqlConfig::persist_connection_update(&path, "prod", "username", "admin").unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            CONFIG.replace("username = \"readonly\"", "username = \"admin\"")
+        );
+
+        // An edit that breaks the config is rejected and leaves the file alone
+        assert!(SqlConfig::persist_connection_update(&path, "prod", "port", "ssh").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), written);
+
+        // No temporary file is left behind
+        let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_saved_config_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("helix-dadbod-config-editor-mode");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        // A new config is never readable by others, whatever the umask
+        let path = dir.join("config.toml");
+        let mut editor = ConfigEditor::load(&path).unwrap();
+        editor
+            .append_connection(&parsed(&self::editor()).connections[0])
+            .unwrap();
+        editor.save().unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // An existing one keeps its permissions, also over a stale temporary file
+        std::fs::write(&path, CONFIG).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        std::fs::write(dir.join(".config.toml.tmp"), "stale").unwrap();
+        SqlConfig::persist_connection_update(&path, "prod", "username", "admin").unwrap();
+        assert_eq!(mode(&path), 0o640);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
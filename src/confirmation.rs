@@ -0,0 +1,94 @@
+//! Confirmation gate for destructive commands
+//!
+//! Queries are executed from the editor without an interactive prompt, so a
+//! destructive command only runs when the exact same command is executed a
+//! second time within a short window. The first execution just reports what
+//! would happen.
+
+use std::time::{Duration, Instant};
+
+/// How long a pending confirmation stays valid
+pub const CONFIRMATION_WINDOW: Duration = Duration::from_secs(30);
+
+/// Tracks the destructive command awaiting confirmation for a connection
+#[derive(Debug)]
+pub struct ConfirmationGate {
+    pending: Option<(String, Instant)>,
+    window: Duration,
+}
+
+impl ConfirmationGate {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            pending: None,
+            window,
+        }
+    }
+
+    /// Returns true if `command` was requested before and is now confirmed,
+    /// otherwise records it as pending and returns false
+    pub fn confirm(&mut self, command: &str) -> bool {
+        self.confirm_at(command, Instant::now())
+    }
+
+    fn confirm_at(&mut self, command: &str, now: Instant) -> bool {
+        if let Some((pending, requested_at)) = self.pending.take() {
+            if pending == command && now.duration_since(requested_at) <= self.window {
+                return true;
+            }
+        }
+
+        self.pending = Some((command.to_string(), now));
+        false
+    }
+
+    /// Forget any pending confirmation (e.g. when another query is executed)
+    pub fn reset(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl Default for ConfirmationGate {
+    fn default() -> Self {
+        Self::new(CONFIRMATION_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_second_execution() {
+        let mut gate = ConfirmationGate::default();
+        assert!(!gate.confirm("\\kill 42"));
+        assert!(gate.confirm("\\kill 42"));
+        // Confirmation is consumed
+        assert!(!gate.confirm("\\kill 42"));
+    }
+
+    #[test]
+    fn test_different_command_restarts_confirmation() {
+        let mut gate = ConfirmationGate::default();
+        assert!(!gate.confirm("\\kill 42"));
+        assert!(!gate.confirm("\\kill 43"));
+        assert!(gate.confirm("\\kill 43"));
+    }
+
+    #[test]
+    fn test_reset_clears_pending() {
+        let mut gate = ConfirmationGate::default();
+        assert!(!gate.confirm("\\kill 42"));
+        gate.reset();
+        assert!(!gate.confirm("\\kill 42"));
+    }
+
+    #[test]
+    fn test_confirmation_expires() {
+        let mut gate = ConfirmationGate::new(Duration::from_secs(30));
+        let start = Instant::now();
+        assert!(!gate.confirm_at("\\kill 42", start));
+        assert!(!gate.confirm_at("\\kill 42", start + Duration::from_secs(31)));
+        assert!(gate.confirm_at("\\kill 42", start + Duration::from_secs(40)));
+    }
+}
@@ -0,0 +1,301 @@
+//! \copy: move data between a local file and the server through COPY
+//!
+//! `\copy (SELECT ...) TO 'orders.csv' WITH CSV HEADER` runs `COPY ... TO
+//! STDOUT` on the server and writes the data to the file as it arrives;
+//! `\copy orders FROM 'orders.csv' WITH CSV HEADER` sends the file to `COPY
+//! ... FROM STDIN` a chunk at a time. Neither holds the data in memory, so
+//! files of any size work. A relative path is resolved against the workspace.
+//! An existing file is kept unless the options end with FORCE or
+//! `copy_overwrite` is set.
+
+use crate::client::{PostgresClient, QueryError};
+use futures_util::{SinkExt, TryStreamExt};
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio_postgres::Client;
+
+/// Option word allowing an existing file to be overwritten; never sent to the
+/// server
+const FORCE: &str = "FORCE";
+
+/// Size of the chunks a file is sent to the server in
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// An import logs its progress every time this much more of the file was sent
+const PROGRESS_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Which way a \copy moves data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    To,
+    From,
+}
+
+/// What a copy moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Copied {
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+/// `options` without FORCE, and whether it was there
+pub fn split_force(options: &str) -> (String, bool) {
+    let (force, rest): (Vec<&str>, Vec<&str>) = options
+        .split_whitespace()
+        .partition(|word| word.eq_ignore_ascii_case(FORCE));
+    (rest.join(" "), !force.is_empty())
+}
+
+/// The server-side COPY of `source` (a table, or a parenthesized query to
+/// export)
+pub fn sql(direction: Direction, source: &str, options: &str) -> String {
+    let endpoint = match direction {
+        Direction::To => "TO STDOUT",
+        Direction::From => "FROM STDIN",
+    };
+    if options.is_empty() {
+        format!("COPY {} {}", source, endpoint)
+    } else {
+        format!("COPY {} {} {}", source, endpoint, options)
+    }
+}
+
+/// Whether `options` ask for a header line, which COPY sends as a row of its own
+fn has_header(options: &str) -> bool {
+    let words: Vec<String> = options
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect();
+    words.iter().enumerate().any(|(i, word)| {
+        word == "HEADER"
+            && !matches!(
+                words.get(i + 1).map(String::as_str),
+                Some("FALSE" | "OFF" | "0")
+            )
+    })
+}
+
+/// Refuse to replace an existing `path` unless `overwrite`
+pub fn check_target(path: &Path, overwrite: bool) -> anyhow::Result<()> {
+    if path.exists() && !overwrite {
+        anyhow::bail!(
+            "{} already exists; add FORCE to the options (or set copy_overwrite) to replace it",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Run `COPY ... TO STDOUT` with `options` and write its data to `path` as it
+/// arrives
+pub async fn export(
+    client: &Client,
+    source: &str,
+    options: &str,
+    path: &Path,
+) -> Result<Copied, QueryError> {
+    let file_error = |e: std::io::Error| {
+        QueryError::client(format!("Failed to write {}: {}", path.display(), e))
+    };
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| {
+            QueryError::client(format!(
+                "Failed to create directory {}: {}",
+                parent.display(),
+                e
+            ))
+        })?;
+    }
+
+    let stream = client
+        .copy_out(&sql(Direction::To, source, options))
+        .await
+        .map_err(|e| PostgresClient::query_error(&e))?;
+    let mut stream = std::pin::pin!(stream);
+    let mut file = BufWriter::new(tokio::fs::File::create(path).await.map_err(file_error)?);
+
+    // COPY sends one message per row
+    let mut chunks = 0;
+    let mut bytes = 0;
+    while let Some(chunk) = stream
+        .try_next()
+        .await
+        .map_err(|e| PostgresClient::query_error(&e))?
+    {
+        file.write_all(&chunk).await.map_err(file_error)?;
+        chunks += 1;
+        bytes += chunk.len() as u64;
+    }
+    file.flush().await.map_err(file_error)?;
+
+    let header = u64::from(chunks > 0 && has_header(options));
+    Ok(Copied {
+        rows: chunks - header,
+        bytes,
+    })
+}
+
+/// Send the file at `path` to `COPY table FROM STDIN` with `options`, a chunk
+/// at a time
+pub async fn import(
+    client: &Client,
+    table: &str,
+    options: &str,
+    path: &Path,
+) -> Result<Copied, QueryError> {
+    let read_error =
+        |e: std::io::Error| QueryError::client(format!("Failed to read {}: {}", path.display(), e));
+    let mut file = tokio::fs::File::open(path).await.map_err(read_error)?;
+
+    let sink = client
+        .copy_in(&sql(Direction::From, table, options))
+        .await
+        .map_err(|e| PostgresClient::query_error(&e))?;
+    let mut sink = std::pin::pin!(sink);
+
+    let mut bytes = 0;
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_BYTES);
+        if (&mut file)
+            .take(CHUNK_BYTES as u64)
+            .read_to_end(&mut chunk)
+            .await
+            .map_err(read_error)?
+            == 0
+        {
+            break;
+        }
+        let sent = bytes;
+        bytes += chunk.len() as u64;
+        sink.send(Cursor::new(chunk))
+            .await
+            .map_err(|e| PostgresClient::query_error(&e))?;
+        if bytes / PROGRESS_BYTES > sent / PROGRESS_BYTES {
+            log::info!("Sent {} of {} to COPY", format_size(bytes), path.display());
+        }
+    }
+
+    let rows = sink
+        .as_mut()
+        .finish()
+        .await
+        .map_err(|e| PostgresClient::query_error(&e))?;
+    Ok(Copied { rows, bytes })
+}
+
+/// Line of the file a failed import stopped at, from the server's context,
+/// e.g. `COPY orders, line 3, column total: "abc"`
+pub fn failed_line(context: &str) -> Option<u64> {
+    let (_, rest) = context.split_once(", line ")?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Summary line, e.g. `COPY 152344 rows to /tmp/orders.csv, 12.3 MB, 4.2s`
+pub fn summary(direction: Direction, copied: Copied, path: &Path, duration: Duration) -> String {
+    let preposition = match direction {
+        Direction::To => "to",
+        Direction::From => "from",
+    };
+    format!(
+        "COPY {} rows {} {}, {}, {:.1}s\n",
+        copied.rows,
+        preposition,
+        path.display(),
+        format_size(copied.bytes),
+        duration.as_secs_f64()
+    )
+}
+
+/// `bytes` in the largest unit that keeps it above 1, e.g. `1.5 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_force() {
+        assert_eq!(
+            split_force("WITH CSV HEADER FORCE"),
+            ("WITH CSV HEADER".to_string(), true)
+        );
+        assert_eq!(
+            split_force("WITH (FORMAT csv, FORCE_QUOTE *)"),
+            ("WITH (FORMAT csv, FORCE_QUOTE *)".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn test_sql_and_header() {
+        assert_eq!(
+            sql(Direction::To, "(SELECT * FROM orders)", "WITH CSV HEADER"),
+            "COPY (SELECT * FROM orders) TO STDOUT WITH CSV HEADER"
+        );
+        assert_eq!(sql(Direction::To, "orders", ""), "COPY orders TO STDOUT");
+        assert_eq!(
+            sql(Direction::From, "orders (id, total)", "WITH CSV"),
+            "COPY orders (id, total) FROM STDIN WITH CSV"
+        );
+        assert!(has_header("WITH CSV HEADER"));
+        assert!(has_header("WITH (FORMAT csv, header true)"));
+        assert!(!has_header("WITH (FORMAT csv, HEADER false)"));
+        assert!(!has_header("WITH CSV"));
+    }
+
+    #[test]
+    fn test_check_target() {
+        let path = std::env::temp_dir().join("helix-dadbod-copy-to-test.csv");
+        std::fs::write(&path, "id\n").unwrap();
+        assert!(check_target(&path, false).is_err());
+        assert!(check_target(&path, true).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        assert!(check_target(&path, false).is_ok());
+    }
+
+    #[test]
+    fn test_summary() {
+        let copied = Copied {
+            rows: 152344,
+            bytes: 12_897_485,
+        };
+        let path = Path::new("/tmp/orders.csv");
+        let duration = Duration::from_millis(4210);
+        assert_eq!(
+            summary(Direction::To, copied, path, duration),
+            "COPY 152344 rows to /tmp/orders.csv, 12.3 MB, 4.2s\n"
+        );
+        assert_eq!(
+            summary(Direction::From, copied, path, duration),
+            "COPY 152344 rows from /tmp/orders.csv, 12.3 MB, 4.2s\n"
+        );
+        assert_eq!(format_size(512), "512 bytes");
+    }
+
+    #[test]
+    fn test_failed_line() {
+        assert_eq!(
+            failed_line("COPY orders, line 3, column total: \"abc\""),
+            Some(3)
+        );
+        assert_eq!(failed_line("COPY orders, line 12"), Some(12));
+        assert_eq!(failed_line("SQL function \"f\""), None);
+    }
+}
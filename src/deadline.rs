@@ -0,0 +1,310 @@
+//! Deadlines for blocking FFI calls
+//!
+//! The blocking wrappers run on Helix's main thread, so no call may wait longer
+//! than `ffi_call_timeout_ms` (or `ffi_connect_timeout_ms` and
+//! `ffi_execute_timeout_ms` for connects and executions; a deadline of 0 waits
+//! as long as the call takes, which is the default for executions so a healthy
+//! long query isn't cut loose from the editor). Operations whose
+//! effect outlives the call (connecting, executing) keep running in a spawned
+//! task when the deadline passes; reads are simply dropped. A connect finishing
+//! after its deadline is stored and used by the next connect to it.
+
+use crate::config::SqlConfig;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+/// Default for `ffi_call_timeout_ms`
+pub const DEFAULT_FFI_CALL_TIMEOUT_MS: u64 = 15_000;
+
+/// Default for `ffi_connect_timeout_ms`: SSH tunnels through a bastion take longer
+/// than other calls
+pub const DEFAULT_FFI_CONNECT_TIMEOUT_MS: u64 = 30_000;
+
+/// Default for `ffi_execute_timeout_ms`: executions wait for their query
+pub const DEFAULT_FFI_EXECUTE_TIMEOUT_MS: u64 = 0;
+
+/// How long each blocking call may take; `Duration::ZERO` for no limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadlines {
+    pub default: Duration,
+    pub connect: Duration,
+    pub execute: Duration,
+}
+
+impl Deadlines {
+    /// `ffi_call_timeout_ms`, with the connect and execute deadlines
+    pub fn from_config(config: &SqlConfig) -> Self {
+        Self {
+            default: Duration::from_millis(config.ffi_call_timeout_ms),
+            connect: Duration::from_millis(config.ffi_connect_timeout_ms),
+            execute: Duration::from_millis(config.ffi_execute_timeout_ms),
+        }
+    }
+
+    pub fn for_call(&self, call: FfiCall) -> Duration {
+        match call {
+            FfiCall::Connect | FfiCall::TestConnection => self.connect,
+            FfiCall::Execute | FfiCall::Peek => self.execute,
+            _ => self.default,
+        }
+    }
+}
+
+/// What happens to an operation that is still running at the deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnTimeout {
+    /// Keep running in the background; its result lands in the connection map
+    /// or results.dbout
+    Detach,
+    /// Drop the future; nothing is left to observe its result
+    Cancel,
+}
+
+/// Blocking FFI operations that run under a deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FfiCall {
+    Connect,
+    Execute,
+    Peek,
+    TestConnection,
+    CloseConnection,
+    Cancel,
+    LastObjects,
+    LastRun,
+    PendingPrompts,
+    ProvidePrompt,
+    StorePassword,
+    Snippet,
+}
+
+impl FfiCall {
+    pub fn on_timeout(&self) -> OnTimeout {
+        match self {
+            // The connection is stored once established, and results are
+            // written to results.dbout, so the work is not wasted
+            FfiCall::Connect | FfiCall::Execute | FfiCall::Peek => OnTimeout::Detach,
+            // A half-closed connection would linger in the map, and a cancel
+            // request already sent should still reach the server
+            FfiCall::CloseConnection | FfiCall::Cancel => OnTimeout::Detach,
+            FfiCall::TestConnection
+            | FfiCall::LastObjects
+            | FfiCall::LastRun
+            | FfiCall::PendingPrompts
+            | FfiCall::ProvidePrompt
+            | FfiCall::StorePassword
+            | FfiCall::Snippet => OnTimeout::Cancel,
+        }
+    }
+
+    /// Error shown in Helix when the call runs past `timeout`
+    pub fn timeout_message(&self, timeout: Duration) -> String {
+        match self.on_timeout() {
+            OnTimeout::Detach => format!(
+                "{} timed out after {}; it continues in the background — check :db-query-running?",
+                self,
+                format_timeout(timeout)
+            ),
+            OnTimeout::Cancel => format!(
+                "{} timed out after {} and was cancelled",
+                self,
+                format_timeout(timeout)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for FfiCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FfiCall::Connect => "connect",
+            FfiCall::Execute => "execute",
+            FfiCall::Peek => "peek",
+            FfiCall::TestConnection => "test connection",
+            FfiCall::CloseConnection => "close connection",
+            FfiCall::Cancel => "cancel",
+            FfiCall::LastObjects => "last objects",
+            FfiCall::LastRun => "last run",
+            FfiCall::PendingPrompts => "pending prompts",
+            FfiCall::ProvidePrompt => "provide prompt",
+            FfiCall::StorePassword => "store password",
+            FfiCall::Snippet => "snippet",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// `15s` for whole seconds, `250ms` otherwise
+fn format_timeout(timeout: Duration) -> String {
+    if timeout.subsec_millis() == 0 {
+        format!("{}s", timeout.as_secs())
+    } else {
+        format!("{}ms", timeout.as_millis())
+    }
+}
+
+/// Await `future` for at most `timeout` (no limit when zero), then detach or
+/// cancel it as `call` requires and fail with its timeout message
+pub async fn run<T, F>(call: FfiCall, timeout: Duration, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    if timeout.is_zero() {
+        return future.await;
+    }
+    let finished = match call.on_timeout() {
+        OnTimeout::Detach => {
+            // Dropping the JoinHandle at the deadline leaves the task running
+            let handle = tokio::spawn(future);
+            match tokio::time::timeout(timeout, handle).await {
+                Ok(joined) => Some(joined.with_context(|| format!("{} panicked", call))?),
+                Err(_) => None,
+            }
+        }
+        OnTimeout::Cancel => tokio::time::timeout(timeout, future).await.ok(),
+    };
+
+    match finished {
+        Some(result) => result,
+        None => {
+            log::warn!(
+                "FFI call {} exceeded {:?} ({:?})",
+                call,
+                timeout,
+                call.on_timeout()
+            );
+            anyhow::bail!(call.timeout_message(timeout))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Stand-in for a stalled operation: sets `done` after `delay`
+    async fn slow_operation(done: Arc<AtomicBool>, delay: Duration) -> Result<&'static str> {
+        tokio::time::sleep(delay).await;
+        done.store(true, Ordering::SeqCst);
+        Ok("done")
+    }
+
+    #[test]
+    fn test_on_timeout_per_call() {
+        assert_eq!(FfiCall::Connect.on_timeout(), OnTimeout::Detach);
+        assert_eq!(FfiCall::Execute.on_timeout(), OnTimeout::Detach);
+        assert_eq!(FfiCall::CloseConnection.on_timeout(), OnTimeout::Detach);
+        assert_eq!(FfiCall::Cancel.on_timeout(), OnTimeout::Detach);
+        assert_eq!(FfiCall::TestConnection.on_timeout(), OnTimeout::Cancel);
+        assert_eq!(FfiCall::LastRun.on_timeout(), OnTimeout::Cancel);
+    }
+
+    #[test]
+    fn test_deadlines_per_call() {
+        let mut config: SqlConfig = toml::from_str("ffi_call_timeout_ms = 5000").unwrap();
+        let deadlines = Deadlines::from_config(&config);
+        assert_eq!(
+            deadlines.for_call(FfiCall::Connect),
+            Duration::from_secs(30)
+        );
+        assert_eq!(deadlines.for_call(FfiCall::Execute), Duration::ZERO);
+        assert_eq!(deadlines.for_call(FfiCall::Cancel), Duration::from_secs(5));
+
+        config.ffi_connect_timeout_ms = 60_000;
+        config.ffi_execute_timeout_ms = 120_000;
+        let deadlines = Deadlines::from_config(&config);
+        assert_eq!(
+            deadlines.for_call(FfiCall::Connect),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            deadlines.for_call(FfiCall::TestConnection),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            deadlines.for_call(FfiCall::Execute),
+            Duration::from_secs(120)
+        );
+        assert_eq!(deadlines.for_call(FfiCall::LastRun), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_timeout_message() {
+        assert_eq!(
+            FfiCall::Execute.timeout_message(Duration::from_secs(15)),
+            "execute timed out after 15s; it continues in the background — check :db-query-running?"
+        );
+        assert_eq!(
+            FfiCall::TestConnection.timeout_message(Duration::from_millis(250)),
+            "test connection timed out after 250ms and was cancelled"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fast_operation_returns_its_result() {
+        let done = Arc::new(AtomicBool::new(false));
+        let result = run(
+            FfiCall::Execute,
+            Duration::from_secs(1),
+            slow_operation(Arc::clone(&done), Duration::from_millis(10)),
+        )
+        .await;
+        assert_eq!(result.unwrap(), "done");
+
+        let failed: Result<()> = run(FfiCall::LastRun, Duration::from_secs(1), async {
+            anyhow::bail!("no such connection")
+        })
+        .await;
+        assert_eq!(failed.unwrap_err().to_string(), "no such connection");
+    }
+
+    #[tokio::test]
+    async fn test_zero_timeout_waits_for_the_operation() {
+        let done = Arc::new(AtomicBool::new(false));
+        let result = run(
+            FfiCall::Execute,
+            Duration::ZERO,
+            slow_operation(Arc::clone(&done), Duration::from_millis(50)),
+        )
+        .await;
+        assert_eq!(result.unwrap(), "done");
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_detached_operation_keeps_running() {
+        let done = Arc::new(AtomicBool::new(false));
+        let result = run(
+            FfiCall::Connect,
+            Duration::from_millis(20),
+            slow_operation(Arc::clone(&done), Duration::from_millis(100)),
+        )
+        .await;
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.starts_with("connect timed out after 20ms"), "{}", err);
+        assert!(!done.load(Ordering::SeqCst));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_operation_stops() {
+        let done = Arc::new(AtomicBool::new(false));
+        let result = run(
+            FfiCall::TestConnection,
+            Duration::from_millis(20),
+            slow_operation(Arc::clone(&done), Duration::from_millis(100)),
+        )
+        .await;
+
+        assert!(result.unwrap_err().to_string().contains("was cancelled"));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!done.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,632 @@
+//! Per-connection display settings for query results
+//!
+//! Settings are seeded from the connection's `output` config block when it
+//! connects; \pset changes them for the rest of the session.
+
+use crate::config::OutputConfig;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Widest table (in characters) shown as-is when `expanded = "auto"`
+pub const DEFAULT_MAX_OUTPUT_WIDTH: usize = 160;
+/// Column count above which `expanded = "auto"` switches to records
+pub const DEFAULT_AUTO_EXPAND_COLUMNS: usize = 20;
+/// Field separator of unaligned output, as in psql
+pub const DEFAULT_FIELDSEP: &str = "|";
+/// Longest value shown in full in tables; 0 never truncates
+pub const DEFAULT_MAX_COLUMN_WIDTH: usize = 1000;
+
+/// How result rows are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Aligned comfy_table grid
+    #[default]
+    Table,
+    /// One JSON array of objects keyed by column name
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// Fields joined by `fieldsep`, one row per line
+    Unaligned,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "unaligned" => Ok(OutputFormat::Unaligned),
+            _ => anyhow::bail!(
+                "Unknown output format '{}': expected table, json, jsonl or unaligned",
+                value
+            ),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Unaligned => "unaligned",
+        }
+    }
+}
+
+/// Whether rows are shown as `-[ RECORD n ]` blocks instead of a table
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpandedMode {
+    #[default]
+    Off,
+    On,
+    /// Expanded only when the table would be too wide or have too many columns
+    Auto,
+}
+
+impl ExpandedMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "off" => Ok(ExpandedMode::Off),
+            "on" => Ok(ExpandedMode::On),
+            "auto" => Ok(ExpandedMode::Auto),
+            _ => anyhow::bail!(
+                "Unknown expanded mode '{}': expected on, off or auto",
+                value
+            ),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpandedMode::Off => "off",
+            ExpandedMode::On => "on",
+            ExpandedMode::Auto => "auto",
+        }
+    }
+}
+
+/// Whether tables over the cell budget fall back to unaligned output
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlignMode {
+    /// Aligned unless the table exceeds the cell budget
+    #[default]
+    Auto,
+    /// Always aligned, however large the table
+    Force,
+}
+
+impl AlignMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(AlignMode::Auto),
+            "force" => Ok(AlignMode::Force),
+            _ => anyhow::bail!("Unknown align mode '{}': expected auto or force", value),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlignMode::Auto => "auto",
+            AlignMode::Force => "force",
+        }
+    }
+}
+
+/// Display settings in effect for one connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplaySettings {
+    pub format: OutputFormat,
+    pub expanded: ExpandedMode,
+    /// Text shown for NULL values
+    pub null_display: String,
+    pub max_output_width: usize,
+    pub auto_expand_columns: usize,
+    /// Values longer than this many characters are cut short in tables; 0
+    /// shows them in full
+    pub max_column_width: usize,
+    pub align: AlignMode,
+    /// Whether results show when the statement ran and how long it took
+    pub timing: bool,
+    /// Separator between fields in unaligned output
+    pub fieldsep: String,
+    /// Leave out the row count, and the header row of unaligned output, like
+    /// psql's \t
+    pub tuples_only: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Table,
+            expanded: ExpandedMode::Off,
+            null_display: "NULL".to_string(),
+            max_output_width: DEFAULT_MAX_OUTPUT_WIDTH,
+            auto_expand_columns: DEFAULT_AUTO_EXPAND_COLUMNS,
+            max_column_width: DEFAULT_MAX_COLUMN_WIDTH,
+            align: AlignMode::Auto,
+            timing: true,
+            fieldsep: DEFAULT_FIELDSEP.to_string(),
+            tuples_only: false,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Settings a connection starts with: its `output` block over the top-level
+    /// `null_display` over the defaults
+    pub fn from_config(config: Option<&OutputConfig>, null_display: Option<&str>) -> Self {
+        let mut defaults = Self::default();
+        if let Some(null_display) = null_display {
+            defaults.null_display = null_display.to_string();
+        }
+        let Some(config) = config else {
+            return defaults;
+        };
+
+        Self {
+            format: config.format.unwrap_or(defaults.format),
+            expanded: config.expanded.unwrap_or(defaults.expanded),
+            null_display: config.null.clone().unwrap_or(defaults.null_display),
+            max_output_width: config.max_output_width.unwrap_or(defaults.max_output_width),
+            auto_expand_columns: config
+                .auto_expand_columns
+                .unwrap_or(defaults.auto_expand_columns),
+            max_column_width: config.max_column_width.unwrap_or(defaults.max_column_width),
+            align: defaults.align,
+            timing: defaults.timing,
+            fieldsep: config.fieldsep.clone().unwrap_or(defaults.fieldsep),
+            tuples_only: config.tuples_only.unwrap_or(defaults.tuples_only),
+        }
+    }
+
+    /// Change one setting for the session, as `\pset option value`
+    /// `value` may be single-quoted, e.g. `\pset null '∅'`
+    pub fn set(&mut self, option: &str, value: &str) -> Result<()> {
+        let value = unquote(value);
+        match option {
+            "format" => self.format = OutputFormat::parse(value)?,
+            "expanded" => self.expanded = ExpandedMode::parse(value)?,
+            "null" => self.null_display = value.to_string(),
+            "max_output_width" => self.max_output_width = parse_count(option, value)?,
+            "auto_expand_columns" => self.auto_expand_columns = parse_count(option, value)?,
+            "columnwidth" => self.max_column_width = parse_count(option, value)?,
+            "align" => self.align = AlignMode::parse(value)?,
+            "fieldsep" => self.fieldsep = unescape(value),
+            "tuples_only" => {
+                self.tuples_only = toggle("tuples_only", self.tuples_only, Some(value))?
+            }
+            _ => anyhow::bail!(
+                "Unknown \\pset option '{}': expected format, expanded, null, \
+                 max_output_width, auto_expand_columns, columnwidth, align, fieldsep or \
+                 tuples_only",
+                option
+            ),
+        }
+        Ok(())
+    }
+
+    /// Bare `\x`: expanded output on if it was off, otherwise off (as in psql,
+    /// auto turns off)
+    pub fn toggle_expanded(&mut self) {
+        self.expanded = match self.expanded {
+            ExpandedMode::Off => ExpandedMode::On,
+            ExpandedMode::On | ExpandedMode::Auto => ExpandedMode::Off,
+        };
+    }
+
+    /// `\timing [on|off]`: set timing, or toggle it without an argument
+    pub fn set_timing(&mut self, value: Option<&str>) -> Result<()> {
+        self.timing = toggle("timing", self.timing, value)?;
+        Ok(())
+    }
+
+    /// `\tuples_only [on|off]`: set tuples-only output, or toggle it without an
+    /// argument
+    pub fn set_tuples_only(&mut self, value: Option<&str>) -> Result<()> {
+        self.tuples_only = toggle("tuples_only", self.tuples_only, value)?;
+        Ok(())
+    }
+
+    /// `option = value` lines for every setting, or just `option` if given
+    pub fn describe(&self, option: Option<&str>) -> Result<Vec<String>> {
+        let all = [
+            ("format", self.format.as_str().to_string()),
+            ("expanded", self.expanded.as_str().to_string()),
+            ("null", format!("'{}'", self.null_display)),
+            ("max_output_width", self.max_output_width.to_string()),
+            ("auto_expand_columns", self.auto_expand_columns.to_string()),
+            ("columnwidth", self.max_column_width.to_string()),
+            ("align", self.align.as_str().to_string()),
+            ("fieldsep", format!("'{}'", escape(&self.fieldsep))),
+            ("tuples_only", on_off(self.tuples_only).to_string()),
+        ];
+
+        let lines: Vec<String> = all
+            .iter()
+            .filter(|(name, _)| match option {
+                Some(option) => option == *name,
+                None => true,
+            })
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        if lines.is_empty() {
+            anyhow::bail!("Unknown \\pset option '{}'", option.unwrap_or_default());
+        }
+        Ok(lines)
+    }
+
+    /// Whether a result with these columns and rows is shown in expanded form
+    pub fn expands(&self, header: &[String], rows: &[Vec<String>]) -> bool {
+        match self.expanded {
+            ExpandedMode::Off => false,
+            ExpandedMode::On => true,
+            ExpandedMode::Auto => {
+                header.len() > self.auto_expand_columns
+                    || table_width(header, rows) > self.max_output_width
+            }
+        }
+    }
+}
+
+/// Width in characters of the widest line of the rendered table
+///
+/// Each column takes its widest value or header line plus one padding space
+/// and one border; the table adds one more border on the left.
+pub fn table_width(header: &[String], rows: &[Vec<String>]) -> usize {
+    let line_width = |value: &String| value.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+
+    let content: usize = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(line_width)
+                .max()
+                .unwrap_or(0)
+                .max(line_width(name))
+                + 2
+        })
+        .sum();
+    content + 1
+}
+
+/// Cut every value longer than `width` characters to `width` characters and
+/// an `…`, returning how many were cut; 0 leaves them all
+pub fn truncate_cells(rows: &mut [Vec<String>], width: usize) -> usize {
+    if width == 0 {
+        return 0;
+    }
+    let mut truncated = 0;
+    for value in rows.iter_mut().flatten() {
+        if let Some((end, _)) = value.char_indices().nth(width) {
+            value.truncate(end);
+            value.push('…');
+            truncated += 1;
+        }
+    }
+    truncated
+}
+
+/// Header line saying how many values `truncate_cells` cut to `width`
+pub fn truncation_note(truncated: usize, width: usize) -> String {
+    format!(
+        "{} value(s) truncated to {} characters (\\pset columnwidth 0 shows them in full)",
+        truncated, width
+    )
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(value)
+}
+
+/// `on`/`off` sets `setting`, no value flips it
+fn toggle(setting: &str, current: bool, value: Option<&str>) -> Result<bool> {
+    match value {
+        None => Ok(!current),
+        Some("on") => Ok(true),
+        Some("off") => Ok(false),
+        Some(other) => anyhow::bail!(
+            "Unrecognized value '{}' for \\{}: expected on or off",
+            other,
+            setting
+        ),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Replace `\t`, `\n`, `\r` and `\\` with the characters they stand for;
+/// other backslashes are kept
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Inverse of `unescape`, for showing a separator
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn parse_count(option: &str, value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("\\pset {} expects a number, got '{}'", option, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{self, CellValue};
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn warehouse_config() -> OutputConfig {
+        OutputConfig {
+            format: Some(OutputFormat::Table),
+            expanded: Some(ExpandedMode::Auto),
+            null: Some("∅".to_string()),
+            ..OutputConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_seeded_from_config() {
+        let settings = DisplaySettings::from_config(Some(&warehouse_config()), None);
+        assert_eq!(settings.expanded, ExpandedMode::Auto);
+        assert_eq!(settings.null_display, "∅");
+        // Unset options keep their defaults
+        assert_eq!(settings.max_output_width, DEFAULT_MAX_OUTPUT_WIDTH);
+
+        assert_eq!(
+            DisplaySettings::from_config(None, None),
+            DisplaySettings::default()
+        );
+    }
+
+    #[test]
+    fn test_null_display_config() {
+        // Out of the box NULL reads "NULL", like a text value of "NULL" would
+        let settings = DisplaySettings::from_config(None, None);
+        assert_eq!(settings.null_display, "NULL");
+
+        // The top-level null_display applies to every connection ...
+        let settings = DisplaySettings::from_config(None, Some("∅"));
+        assert_eq!(settings.null_display, "∅");
+        let values = vec![vec![CellValue::Text("NULL".to_string()), CellValue::Null]];
+        assert_eq!(
+            value::display_rows(&values, &settings.null_display),
+            vec![vec!["NULL", "∅"]]
+        );
+
+        // ... unless the connection's output block sets its own
+        let config = OutputConfig {
+            null: Some("(null)".to_string()),
+            ..OutputConfig::default()
+        };
+        let settings = DisplaySettings::from_config(Some(&config), Some("∅"));
+        assert_eq!(settings.null_display, "(null)");
+        let settings = DisplaySettings::from_config(Some(&OutputConfig::default()), Some("∅"));
+        assert_eq!(settings.null_display, "∅");
+    }
+
+    #[test]
+    fn test_runtime_changes_override_config() {
+        let config = warehouse_config();
+        let mut settings = DisplaySettings::from_config(Some(&config), None);
+
+        settings.set("expanded", "off").unwrap();
+        settings.set("null", "'(null)'").unwrap();
+        assert_eq!(settings.expanded, ExpandedMode::Off);
+        assert_eq!(settings.null_display, "(null)");
+
+        // A new session starts from the config again
+        let reconnected = DisplaySettings::from_config(Some(&config), None);
+        assert_eq!(reconnected.expanded, ExpandedMode::Auto);
+        assert_eq!(reconnected.null_display, "∅");
+    }
+
+    #[test]
+    fn test_set_align() {
+        let mut settings = DisplaySettings::default();
+        settings.set("align", "force").unwrap();
+        assert_eq!(settings.align, AlignMode::Force);
+        settings.set("align", "auto").unwrap();
+        assert_eq!(settings.align, AlignMode::Auto);
+    }
+
+    #[test]
+    fn test_truncate_cells() {
+        let mut rows = vec![
+            strings(&["short", "ééééé"]),
+            strings(&["exactly", "日本語のテキスト"]),
+        ];
+        assert_eq!(truncate_cells(&mut rows, 5), 2);
+        assert_eq!(rows[0], strings(&["short", "ééééé"]));
+        assert_eq!(rows[1], strings(&["exact…", "日本語のテ…"]));
+
+        let mut rows = vec![strings(&["x".repeat(5000).as_str()])];
+        assert_eq!(truncate_cells(&mut rows, 0), 0);
+        assert_eq!(rows[0][0].len(), 5000);
+    }
+
+    #[test]
+    fn test_set_columnwidth() {
+        let mut settings = DisplaySettings::default();
+        assert_eq!(settings.max_column_width, DEFAULT_MAX_COLUMN_WIDTH);
+        settings.set("columnwidth", "40").unwrap();
+        assert_eq!(settings.max_column_width, 40);
+        assert_eq!(
+            settings.describe(Some("columnwidth")).unwrap(),
+            vec!["columnwidth = 40"]
+        );
+        assert!(settings.set("columnwidth", "wide").is_err());
+
+        let config = OutputConfig {
+            max_column_width: Some(0),
+            ..OutputConfig::default()
+        };
+        assert_eq!(
+            DisplaySettings::from_config(Some(&config), None).max_column_width,
+            0
+        );
+    }
+
+    #[test]
+    fn test_fieldsep_and_tuples_only() {
+        let mut settings = DisplaySettings::default();
+        assert_eq!(settings.fieldsep, "|");
+        settings.set("fieldsep", "'\\t'").unwrap();
+        assert_eq!(settings.fieldsep, "\t");
+        settings.set("fieldsep", ";").unwrap();
+        assert_eq!(settings.fieldsep, ";");
+        settings.set("fieldsep", "'\\\\'").unwrap();
+        assert_eq!(settings.fieldsep, "\\");
+        assert_eq!(
+            settings.describe(Some("fieldsep")).unwrap(),
+            vec!["fieldsep = '\\\\'"]
+        );
+
+        settings.set_tuples_only(None).unwrap();
+        assert!(settings.tuples_only);
+        settings.set("tuples_only", "off").unwrap();
+        assert!(!settings.tuples_only);
+        assert!(settings.set_tuples_only(Some("maybe")).is_err());
+    }
+
+    #[test]
+    fn test_set_rejects_invalid_values() {
+        let mut settings = DisplaySettings::default();
+        assert!(settings.set("expanded", "sometimes").is_err());
+        assert!(settings.set("format", "csv").is_err());
+        assert!(settings.set("max_output_width", "wide").is_err());
+        assert!(settings.set("align", "left").is_err());
+        assert!(settings.set("border", "2").is_err());
+        assert_eq!(settings, DisplaySettings::default());
+    }
+
+    #[test]
+    fn test_toggle_expanded() {
+        let mut settings = DisplaySettings::default();
+        settings.toggle_expanded();
+        assert_eq!(settings.expanded, ExpandedMode::On);
+        settings.toggle_expanded();
+        assert_eq!(settings.expanded, ExpandedMode::Off);
+
+        settings.set("expanded", "auto").unwrap();
+        settings.toggle_expanded();
+        assert_eq!(settings.expanded, ExpandedMode::Off);
+    }
+
+    #[test]
+    fn test_set_timing() {
+        let mut settings = DisplaySettings::default();
+        assert!(settings.timing);
+        settings.set_timing(None).unwrap();
+        assert!(!settings.timing);
+        settings.set_timing(Some("off")).unwrap();
+        assert!(!settings.timing);
+        settings.set_timing(Some("on")).unwrap();
+        assert!(settings.timing);
+
+        let err = settings.set_timing(Some("maybe")).unwrap_err();
+        assert!(err.to_string().contains("expected on or off"), "{}", err);
+        assert!(settings.timing);
+    }
+
+    #[test]
+    fn test_describe() {
+        let settings = DisplaySettings::from_config(Some(&warehouse_config()), None);
+        let lines = settings.describe(None).unwrap();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[1], "expanded = auto");
+        assert_eq!(settings.describe(Some("null")).unwrap(), vec!["null = '∅'"]);
+        assert!(settings.describe(Some("border")).is_err());
+    }
+
+    #[test]
+    fn test_auto_expands_many_columns() {
+        let settings = DisplaySettings {
+            expanded: ExpandedMode::Auto,
+            auto_expand_columns: 3,
+            ..DisplaySettings::default()
+        };
+        let narrow = strings(&["a", "b", "c"]);
+        let wide = strings(&["a", "b", "c", "d"]);
+
+        assert!(!settings.expands(&narrow, &[strings(&["1", "2", "3"])]));
+        assert!(settings.expands(&wide, &[strings(&["1", "2", "3", "4"])]));
+    }
+
+    #[test]
+    fn test_auto_expands_wide_rows() {
+        let settings = DisplaySettings {
+            expanded: ExpandedMode::Auto,
+            max_output_width: 20,
+            ..DisplaySettings::default()
+        };
+        let header = strings(&["id", "note"]);
+
+        // │id ┆note      │ is 17 characters wide
+        let fits = vec![strings(&["1", "short"]), strings(&["2", "0123456789"])];
+        assert_eq!(table_width(&header, &fits), 17);
+        assert!(!settings.expands(&header, &fits));
+
+        let too_wide = vec![strings(&["1", "0123456789abcd"])];
+        assert_eq!(table_width(&header, &too_wide), 21);
+        assert!(settings.expands(&header, &too_wide));
+    }
+
+    #[test]
+    fn test_fixed_modes_ignore_shape() {
+        let header = strings(&["id"]);
+        let rows = vec![strings(&["1"])];
+        let on = DisplaySettings {
+            expanded: ExpandedMode::On,
+            ..DisplaySettings::default()
+        };
+        assert!(on.expands(&header, &rows));
+
+        let off = DisplaySettings {
+            auto_expand_columns: 0,
+            ..DisplaySettings::default()
+        };
+        assert!(!off.expands(&header, &rows));
+    }
+}
@@ -0,0 +1,372 @@
+//! Per-connection execution guard
+//!
+//! Only one query runs on a connection at a time. A second execution either
+//! waits its turn (executions run in the order they were started, so the last
+//! one started writes results.dbout last) or, with `queue_queries = false`,
+//! fails immediately with a busy error. While a transaction is open,
+//! executions always wait their turn so its statements run one after another.
+//!
+//! Closing a connection while a query runs cancels it server-side first, so
+//! the backend stops executing and releases its locks. A query that keeps
+//! running anyway has the connection closed under it after `CLOSE_WAIT`.
+
+use anyhow::Result;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// How long closing a connection waits for the server to accept a cancel request
+pub const CANCEL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long closing a connection then waits for the cancelled query to stop
+/// before closing the connection under it
+pub const CLOSE_WAIT: Duration = Duration::from_secs(2);
+
+/// Result of trying to cancel the running query when a connection closes
+#[derive(Debug, Clone, PartialEq)]
+pub enum CancelOutcome {
+    /// No query was running
+    Idle,
+    /// The server accepted the cancel request
+    Cancelled,
+    /// Sending the cancel request failed
+    Failed(String),
+    /// The cancel request did not complete within the timeout
+    TimedOut,
+}
+
+/// Serializes query executions on one connection
+pub struct ExecutionGuard {
+    connection_name: String,
+    lock: Mutex<()>,
+    busy: Arc<AtomicBool>,
+    queue: bool,
+    /// Queue executions even when `queue` is off, e.g. in a transaction
+    hold_queue: AtomicBool,
+}
+
+/// Held for the duration of one execution; clears the busy flag when dropped
+pub struct ExecutionPermit<'a> {
+    _lock: MutexGuard<'a, ()>,
+    busy: &'a AtomicBool,
+}
+
+impl ExecutionGuard {
+    /// `queue` selects whether a second execution waits (true) or fails (false)
+    pub fn new(connection_name: &str, queue: bool) -> Self {
+        Self {
+            connection_name: connection_name.to_string(),
+            lock: Mutex::new(()),
+            busy: Arc::new(AtomicBool::new(false)),
+            queue,
+            hold_queue: AtomicBool::new(false),
+        }
+    }
+
+    /// Make executions wait their turn regardless of `queue` while `hold` is set
+    pub fn hold_queue(&self, hold: bool) {
+        self.hold_queue.store(hold, Ordering::SeqCst);
+    }
+
+    /// Whether executions are held in the queue, i.e. a transaction is open
+    pub fn holds_queue(&self) -> bool {
+        self.hold_queue.load(Ordering::SeqCst)
+    }
+
+    /// Wait for (or, when not queueing, demand) exclusive use of the connection
+    pub async fn acquire(&self) -> Result<ExecutionPermit<'_>> {
+        let lock = if self.queue || self.hold_queue.load(Ordering::SeqCst) {
+            if self.is_busy() {
+                log::debug!(
+                    "Query running on '{}', queueing execution",
+                    self.connection_name
+                );
+            }
+            self.lock.lock().await
+        } else {
+            match self.lock.try_lock() {
+                Ok(lock) => lock,
+                Err(_) => anyhow::bail!(
+                    "A query is already running on '{}' (queue_queries = false)",
+                    self.connection_name
+                ),
+            }
+        };
+
+        self.busy.store(true, Ordering::SeqCst);
+        Ok(ExecutionPermit {
+            _lock: lock,
+            busy: &self.busy,
+        })
+    }
+
+    /// Whether a query is currently executing
+    pub fn is_busy(&self) -> bool {
+        self.busy.load(Ordering::SeqCst)
+    }
+
+    /// Shared busy flag, e.g. for the auto-execute watcher
+    pub fn busy_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.busy)
+    }
+
+    /// Best-effort cancel of the running query, e.g. before closing the connection
+    ///
+    /// `cancel` (typically `CancelToken::cancel_query`) is only awaited when a
+    /// query is running, and for at most `timeout`. The outcome is logged.
+    pub async fn cancel_running<F, E>(&self, timeout: Duration, cancel: F) -> CancelOutcome
+    where
+        F: Future<Output = Result<(), E>>,
+        E: std::fmt::Display,
+    {
+        if !self.is_busy() {
+            return CancelOutcome::Idle;
+        }
+
+        log::info!("Cancelling running query on '{}'", self.connection_name);
+        let outcome = match tokio::time::timeout(timeout, cancel).await {
+            Ok(Ok(())) => CancelOutcome::Cancelled,
+            Ok(Err(e)) => CancelOutcome::Failed(e.to_string()),
+            Err(_) => CancelOutcome::TimedOut,
+        };
+
+        match &outcome {
+            CancelOutcome::Cancelled => {
+                log::info!("Cancelled running query on '{}'", self.connection_name)
+            }
+            CancelOutcome::Failed(e) => log::warn!(
+                "Failed to cancel running query on '{}': {}",
+                self.connection_name,
+                e
+            ),
+            CancelOutcome::TimedOut => log::warn!(
+                "Cancel request for '{}' timed out after {:?}",
+                self.connection_name,
+                timeout
+            ),
+            CancelOutcome::Idle => {}
+        }
+        outcome
+    }
+}
+
+impl Drop for ExecutionPermit<'_> {
+    fn drop(&mut self) {
+        self.busy.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Notify;
+
+    /// Stand-in for a slow query: holds a permit, then writes its result
+    async fn slow_execution(
+        guard: Arc<ExecutionGuard>,
+        results: Arc<Mutex<Vec<&'static str>>>,
+        id: &'static str,
+        delay: Duration,
+    ) -> Result<()> {
+        let _permit = guard.acquire().await?;
+        tokio::time::sleep(delay).await;
+        results.lock().await.push(id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_queued_executions_run_in_start_order() {
+        let guard = Arc::new(ExecutionGuard::new("test", true));
+        let results = Arc::new(Mutex::new(Vec::new()));
+
+        let first = tokio::spawn(slow_execution(
+            Arc::clone(&guard),
+            Arc::clone(&results),
+            "first",
+            Duration::from_millis(200),
+        ));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(guard.is_busy());
+
+        // Faster, but started later: must not overtake the running execution
+        let second = tokio::spawn(slow_execution(
+            Arc::clone(&guard),
+            Arc::clone(&results),
+            "second",
+            Duration::from_millis(10),
+        ));
+
+        first.await.unwrap().unwrap();
+        second.await.unwrap().unwrap();
+
+        assert_eq!(*results.lock().await, vec!["first", "second"]);
+        assert!(!guard.is_busy());
+    }
+
+    #[tokio::test]
+    async fn test_unqueued_execution_fails_while_busy() {
+        let guard = Arc::new(ExecutionGuard::new("test", false));
+        let results = Arc::new(Mutex::new(Vec::new()));
+
+        let first = tokio::spawn(slow_execution(
+            Arc::clone(&guard),
+            Arc::clone(&results),
+            "first",
+            Duration::from_millis(200),
+        ));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let err = slow_execution(
+            Arc::clone(&guard),
+            Arc::clone(&results),
+            "second",
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("already running on 'test'"));
+
+        first.await.unwrap().unwrap();
+        assert_eq!(*results.lock().await, vec!["first"]);
+
+        // Once idle, executions are accepted again
+        slow_execution(guard, Arc::clone(&results), "third", Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(*results.lock().await, vec!["first", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_held_queue_waits_without_queueing() {
+        let guard = Arc::new(ExecutionGuard::new("test", false));
+        let results = Arc::new(Mutex::new(Vec::new()));
+        guard.hold_queue(true);
+
+        let first = tokio::spawn(slow_execution(
+            Arc::clone(&guard),
+            Arc::clone(&results),
+            "first",
+            Duration::from_millis(100),
+        ));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        slow_execution(
+            Arc::clone(&guard),
+            Arc::clone(&results),
+            "second",
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+
+        first.await.unwrap().unwrap();
+        assert_eq!(*results.lock().await, vec!["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_busy_flag_cleared_on_failure() {
+        let guard = ExecutionGuard::new("test", true);
+        let flag = guard.busy_flag();
+
+        let result: Result<()> = async {
+            let _permit = guard.acquire().await?;
+            assert!(flag.load(Ordering::SeqCst));
+            anyhow::bail!("query failed")
+        }
+        .await;
+
+        assert!(result.is_err());
+        assert!(!flag.load(Ordering::SeqCst));
+    }
+
+    /// Stand-in for a query the server runs until it is cancelled
+    async fn in_flight_query(guard: Arc<ExecutionGuard>, server: Arc<Notify>) -> Result<()> {
+        let _permit = guard.acquire().await?;
+        server.notified().await;
+        anyhow::bail!("canceling statement due to user request")
+    }
+
+    #[tokio::test]
+    async fn test_cancel_skipped_when_idle() {
+        let guard = ExecutionGuard::new("test", true);
+        let sent = AtomicBool::new(false);
+
+        let outcome = guard
+            .cancel_running(CANCEL_TIMEOUT, async {
+                sent.store(true, Ordering::SeqCst);
+                Ok::<_, anyhow::Error>(())
+            })
+            .await;
+
+        assert_eq!(outcome, CancelOutcome::Idle);
+        assert!(!sent.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_in_flight_query() {
+        let guard = Arc::new(ExecutionGuard::new("test", true));
+        let server = Arc::new(Notify::new());
+        let query = tokio::spawn(in_flight_query(Arc::clone(&guard), Arc::clone(&server)));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(guard.is_busy());
+
+        let outcome = guard
+            .cancel_running(CANCEL_TIMEOUT, async {
+                server.notify_one();
+                Ok::<_, anyhow::Error>(())
+            })
+            .await;
+        assert_eq!(outcome, CancelOutcome::Cancelled);
+
+        // The query ends with the server's cancellation error and frees the connection
+        let result = tokio::time::timeout(Duration::from_secs(1), query)
+            .await
+            .expect("query still running after cancel")
+            .unwrap();
+        assert!(result.unwrap_err().to_string().contains("canceling"));
+        assert!(!guard.is_busy());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_failure_is_reported() {
+        let guard = Arc::new(ExecutionGuard::new("test", true));
+        let server = Arc::new(Notify::new());
+        let query = tokio::spawn(in_flight_query(Arc::clone(&guard), Arc::clone(&server)));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let outcome = guard
+            .cancel_running(CANCEL_TIMEOUT, async {
+                Err::<(), _>(anyhow::anyhow!("connection refused"))
+            })
+            .await;
+        assert_eq!(
+            outcome,
+            CancelOutcome::Failed("connection refused".to_string())
+        );
+
+        server.notify_one();
+        query.await.unwrap().unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_gives_up_after_timeout() {
+        let guard = Arc::new(ExecutionGuard::new("test", true));
+        let server = Arc::new(Notify::new());
+        let query = tokio::spawn(in_flight_query(Arc::clone(&guard), Arc::clone(&server)));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let started = std::time::Instant::now();
+        let outcome = guard
+            .cancel_running(
+                Duration::from_millis(50),
+                std::future::pending::<Result<(), anyhow::Error>>(),
+            )
+            .await;
+        assert_eq!(outcome, CancelOutcome::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        server.notify_one();
+        query.await.unwrap().unwrap_err();
+    }
+}
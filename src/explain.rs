@@ -0,0 +1,166 @@
+//! \explain: show a statement's plan instead of running it
+//!
+//! A first line `\explain` (or a `-- dadbod: explain` line anywhere) makes the
+//! execution send the rest of the SQL file through `EXPLAIN` and write the plan
+//! to results.dbout as plain text, one plan line per line. `\explain analyze`
+//! adds ANALYZE and BUFFERS; as that does run the statement, it runs inside a
+//! transaction (or a savepoint of the open one) that is rolled back afterwards.
+
+/// First-line command
+pub const COMMAND: &str = "\\explain";
+
+/// Comment directive, e.g. `-- dadbod: explain analyze`
+pub const COMMENT_DIRECTIVE: &str = "dadbod: explain";
+
+/// Savepoint an analyzed statement runs under when a transaction is open
+const SAVEPOINT: &str = "dadbod_explain";
+
+/// What \explain was asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explain {
+    /// Run the statement and report actual times and buffers
+    pub analyze: bool,
+}
+
+impl Explain {
+    /// The EXPLAIN statement for `statement`
+    pub fn sql(&self, statement: &str) -> String {
+        let statement = statement.trim().trim_end_matches(';').trim_end();
+        let options = if self.analyze {
+            "ANALYZE, BUFFERS, FORMAT TEXT"
+        } else {
+            "FORMAT TEXT"
+        };
+        format!("EXPLAIN ({}) {}", options, statement)
+    }
+
+    /// Header line saying what happened to the statement
+    pub fn note(&self) -> &'static str {
+        if self.analyze {
+            "Explained with ANALYZE: executed, then rolled back"
+        } else {
+            "Explained, not executed"
+        }
+    }
+}
+
+/// Statements run before and after `EXPLAIN ANALYZE` so its changes are
+/// rolled back; inside an open transaction a savepoint keeps the rest of it
+pub fn rollback_guard(in_transaction: bool) -> (String, Vec<String>) {
+    if in_transaction {
+        (
+            format!("SAVEPOINT {}", SAVEPOINT),
+            vec![
+                format!("ROLLBACK TO SAVEPOINT {}", SAVEPOINT),
+                format!("RELEASE SAVEPOINT {}", SAVEPOINT),
+            ],
+        )
+    } else {
+        ("BEGIN".to_string(), vec!["ROLLBACK".to_string()])
+    }
+}
+
+/// The explain requested by `sql` and the statement to explain, without the
+/// line that asked for it
+pub fn strip_directive(sql: &str) -> Option<(Explain, String)> {
+    let lines: Vec<&str> = sql.trim_start().lines().collect();
+    if let Some(explain) = lines.first().and_then(|line| parse_command(line)) {
+        return Some((explain, lines[1..].join("\n")));
+    }
+
+    let index = lines
+        .iter()
+        .position(|line| parse_comment(line).is_some())?;
+    let explain = parse_comment(lines[index])?;
+    let statement = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != index)
+        .map(|(_, line)| *line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some((explain, statement))
+}
+
+/// `\explain` or `\explain analyze`
+fn parse_command(line: &str) -> Option<Explain> {
+    let mut words = line.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(COMMAND) {
+        return None;
+    }
+    parse_analyze(words)
+}
+
+/// `-- dadbod: explain` or `-- dadbod: explain analyze`
+fn parse_comment(line: &str) -> Option<Explain> {
+    let mut words = line.trim().strip_prefix("--")?.split_whitespace();
+    let matches = COMMENT_DIRECTIVE
+        .split_whitespace()
+        .all(|word| words.next() == Some(word));
+    if !matches {
+        return None;
+    }
+    parse_analyze(words)
+}
+
+fn parse_analyze<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<Explain> {
+    let analyze = match words.next() {
+        None => false,
+        Some(word) if word.eq_ignore_ascii_case("analyze") => true,
+        Some(_) => return None,
+    };
+    words.next().is_none().then_some(Explain { analyze })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_on_first_line() {
+        let (explain, statement) = strip_directive("\\explain\nSELECT * FROM users;").unwrap();
+        assert!(!explain.analyze);
+        assert_eq!(statement, "SELECT * FROM users;");
+        assert_eq!(
+            explain.sql(&statement),
+            "EXPLAIN (FORMAT TEXT) SELECT * FROM users"
+        );
+
+        let (explain, statement) =
+            strip_directive("  \\explain ANALYZE\nUPDATE users SET name = 'x'").unwrap();
+        assert!(explain.analyze);
+        assert_eq!(
+            explain.sql(&statement),
+            "EXPLAIN (ANALYZE, BUFFERS, FORMAT TEXT) UPDATE users SET name = 'x'"
+        );
+    }
+
+    #[test]
+    fn test_comment_directive() {
+        let (explain, statement) =
+            strip_directive("SELECT *\n--  dadbod: explain analyze\nFROM users").unwrap();
+        assert!(explain.analyze);
+        assert_eq!(statement, "SELECT *\nFROM users");
+
+        assert_eq!(strip_directive("-- dadbod: explained\nSELECT 1"), None);
+        assert_eq!(strip_directive("\\explain verbose\nSELECT 1"), None);
+        assert_eq!(strip_directive("SELECT 1 -- \\explain"), None);
+    }
+
+    #[test]
+    fn test_rollback_guard() {
+        assert_eq!(
+            rollback_guard(false),
+            ("BEGIN".to_string(), vec!["ROLLBACK".to_string()])
+        );
+        let (begin, end) = rollback_guard(true);
+        assert_eq!(begin, "SAVEPOINT dadbod_explain");
+        assert_eq!(
+            end,
+            vec![
+                "ROLLBACK TO SAVEPOINT dadbod_explain",
+                "RELEASE SAVEPOINT dadbod_explain"
+            ]
+        );
+    }
+}
@@ -1,3 +1,8 @@
+use crate::config::TunnelOverrides;
+use crate::execution::CancelOutcome;
+use crate::jobs::JobState;
+use crate::tunnel::TunnelStatus;
+use crate::version::VersionInfo;
 use crate::{global_dadbod, global_dadbod_error, WorkspacePaths};
 use std::panic;
 use steel::{
@@ -12,6 +17,7 @@ pub struct SteelWorkspaceInfo {
     pub path: String,
     pub sql_file: String,
     pub dbout_file: String,
+    pub buffers: Vec<String>,
 }
 
 impl Custom for SteelWorkspaceInfo {}
@@ -22,6 +28,7 @@ impl From<WorkspacePaths> for SteelWorkspaceInfo {
             path: wp.path,
             sql_file: wp.sql_file,
             dbout_file: wp.dbout_file,
+            buffers: wp.buffers,
         }
     }
 }
@@ -39,6 +46,95 @@ impl SteelWorkspaceInfo {
     pub fn dbout_file(&self) -> String {
         self.dbout_file.clone()
     }
+
+    pub fn buffers(&self) -> Vec<String> {
+        self.buffers.clone()
+    }
+}
+
+/// FFI-friendly snapshot of a connection's SSH tunnel
+#[derive(Clone, Debug)]
+pub struct SteelTunnelStatus {
+    pub ssh_host: String,
+    pub ssh_port: isize,
+    pub ssh_user: String,
+    pub local_port: isize,
+    /// "host:port", or "unix:/path" for a socket
+    pub remote: String,
+    /// "up", "reconnecting (attempt 2 of 5)" or "failed"
+    pub state: String,
+    pub healthy: bool,
+    pub uptime_secs: isize,
+    pub bytes_sent: isize,
+    pub bytes_received: isize,
+    /// State and traffic as one line, as `Dadbod::tunnel_status` returns it
+    pub summary: String,
+}
+
+impl Custom for SteelTunnelStatus {}
+
+impl From<TunnelStatus> for SteelTunnelStatus {
+    fn from(status: TunnelStatus) -> Self {
+        Self {
+            summary: status.to_string(),
+            ssh_host: status.ssh_host,
+            ssh_port: status.ssh_port as isize,
+            ssh_user: status.ssh_user,
+            local_port: status.local_port as isize,
+            remote: status.remote.to_string(),
+            state: status.state.to_string(),
+            healthy: status.healthy,
+            uptime_secs: status.uptime.as_secs() as isize,
+            bytes_sent: status.bytes_sent as isize,
+            bytes_received: status.bytes_received as isize,
+        }
+    }
+}
+
+impl SteelTunnelStatus {
+    pub fn ssh_host(&self) -> String {
+        self.ssh_host.clone()
+    }
+
+    pub fn ssh_port(&self) -> isize {
+        self.ssh_port
+    }
+
+    pub fn ssh_user(&self) -> String {
+        self.ssh_user.clone()
+    }
+
+    pub fn local_port(&self) -> isize {
+        self.local_port
+    }
+
+    pub fn remote(&self) -> String {
+        self.remote.clone()
+    }
+
+    pub fn state(&self) -> String {
+        self.state.clone()
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.healthy
+    }
+
+    pub fn uptime_secs(&self) -> isize {
+        self.uptime_secs
+    }
+
+    pub fn bytes_sent(&self) -> isize {
+        self.bytes_sent
+    }
+
+    pub fn bytes_received(&self) -> isize {
+        self.bytes_received
+    }
+
+    pub fn summary(&self) -> String {
+        self.summary.clone()
+    }
 }
 
 /// List all available database connections from config.toml
@@ -52,6 +148,30 @@ fn list_connections_ffi() -> Vec<String> {
     }
 }
 
+/// List all connections as (name source target) lists, where source is "env"
+/// for the connection synthesized from PG* variables and "config" otherwise,
+/// and target is "user@host:port/database"
+fn list_connections_detailed_ffi() -> Vec<Vec<String>> {
+    match global_dadbod() {
+        Some(dadbod) => dadbod
+            .list_connections_detailed_blocking()
+            .into_iter()
+            .map(|c| {
+                let source = if c.from_env { "env" } else { "config" };
+                vec![
+                    c.name,
+                    source.to_string(),
+                    format!("{}@{}:{}/{}", c.username, c.host, c.port, c.database),
+                ]
+            })
+            .collect(),
+        None => {
+            log::error!("Cannot list connections: helix-dadbod not initialized");
+            Vec::new()
+        }
+    }
+}
+
 /// Connect to a database by name, returns workspace info
 /// Returns None on error (logs error instead of panicking)
 fn connect_ffi(name: &str) -> Option<SteelWorkspaceInfo> {
@@ -62,7 +182,7 @@ fn connect_ffi(name: &str) -> Option<SteelWorkspaceInfo> {
                 Some(workspace_paths.into())
             }
             Err(e) => {
-                log::error!("Failed to connect to '{}': {}", name, e);
+                log::error!("Failed to connect to '{}': {:#}", name, e);
                 None
             }
         },
@@ -81,6 +201,62 @@ fn connect_ffi(name: &str) -> Option<SteelWorkspaceInfo> {
     }
 }
 
+/// Connect with session-only SSH tunnel overrides, returns workspace info
+/// `overrides` is an association list of (key value) pairs, e.g.
+/// '(("port" "2222") ("key_path" "~/.ssh/debug_key") ("proxy_jump" "none"))
+/// Returns None on error (logs error instead of panicking)
+fn connect_with_overrides_ffi(
+    name: &str,
+    overrides: Vec<Vec<String>>,
+) -> Option<SteelWorkspaceInfo> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let pairs = overrides
+            .iter()
+            .map(|pair| match pair.as_slice() {
+                [key, value] => Ok((key.as_str(), value.as_str())),
+                _ => Err(anyhow::anyhow!(
+                    "Each override must be a (key value) pair, got {:?}",
+                    pair
+                )),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .and_then(TunnelOverrides::from_pairs);
+
+        let overrides = match pairs {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                log::error!("Invalid tunnel overrides for '{}': {}", name, e);
+                return None;
+            }
+        };
+
+        match global_dadbod() {
+            Some(dadbod) => match dadbod.connect_with_overrides_blocking(name, overrides) {
+                Ok(workspace) => {
+                    let workspace_paths: WorkspacePaths = workspace.into();
+                    Some(workspace_paths.into())
+                }
+                Err(e) => {
+                    log::error!("Failed to connect to '{}' with overrides: {:#}", name, e);
+                    None
+                }
+            },
+            None => {
+                log::error!("Cannot connect: helix-dadbod not initialized (check config.toml)");
+                None
+            }
+        }
+    }));
+
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            log::error!("Panic occurred while connecting to '{}'", name);
+            None
+        }
+    }
+}
+
 /// Test a database connection, returns database version string
 /// Returns empty string on error (logs error instead of panicking)
 fn test_connection_ffi(name: &str) -> String {
@@ -88,7 +264,7 @@ fn test_connection_ffi(name: &str) -> String {
         Some(dadbod) => match dadbod.test_connection_blocking(name) {
             Ok(version) => version,
             Err(e) => {
-                log::error!("Connection test failed for '{}': {}", name, e);
+                log::error!("Connection test failed for '{}': {:#}", name, e);
                 String::new()
             }
         },
@@ -114,8 +290,8 @@ fn execute_query_ffi(name: &str) -> String {
         Some(dadbod) => match dadbod.execute_query_blocking(name) {
             Ok(_) => "Query executed successfully".to_string(),
             Err(e) => {
-                log::error!("Query execution failed for '{}': {}", name, e);
-                format!("Error: {}", e)
+                log::error!("Query execution failed for '{}': {:#}", name, e);
+                format!("Error: {:#}", e)
             }
         },
         None => {
@@ -133,6 +309,190 @@ fn execute_query_ffi(name: &str) -> String {
     }
 }
 
+/// Execute the SQL file at `path` (absolute, or starting with `~`) on
+/// connection `name`, writing results to its results.dbout
+/// Returns error message on failure (logs error instead of panicking)
+fn execute_file_ffi(name: &str, path: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.execute_file_blocking(name, path) {
+            Ok(_) => "Query executed successfully".to_string(),
+            Err(e) => {
+                log::error!("Executing {} on '{}' failed: {:#}", path, name, e);
+                format!("Error: {:#}", e)
+            }
+        },
+        None => {
+            log::error!("Cannot execute query: helix-dadbod not initialized (check config.toml)");
+            "Error: Database not initialized - check config.toml".to_string()
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while executing {} on '{}'", path, name);
+        "Error: Panic occurred during query execution".to_string()
+    })
+}
+
+/// Execute numbered workspace buffer `index` ({connection}.{index}.sql; 1 is
+/// the query.sql file), creating it empty on first use
+/// Returns error message on failure (logs error instead of panicking)
+fn execute_query_buffer_ffi(name: &str, index: isize) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.execute_query_buffer_blocking(name, index.max(0) as usize) {
+            Ok(_) => "Query executed successfully".to_string(),
+            Err(e) => {
+                log::error!(
+                    "Query execution failed for '{}' buffer {}: {:#}",
+                    name,
+                    index,
+                    e
+                );
+                format!("Error: {:#}", e)
+            }
+        },
+        None => {
+            log::error!("Cannot execute query: helix-dadbod not initialized (check config.toml)");
+            "Error: Database not initialized - check config.toml".to_string()
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!(
+            "Panic occurred while executing buffer {} of '{}'",
+            index,
+            name
+        );
+        "Error: Panic occurred during query execution".to_string()
+    })
+}
+
+/// Workspace buffers of an active connection that exist, buffer 1 (the
+/// query.sql file) first
+/// Returns an empty list if the connection is not active
+fn list_buffers_ffi(name: &str) -> Vec<String> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.get_connection_info_blocking(name) {
+            Some(info) => info
+                .buffers
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            None => {
+                log::error!("Connection '{}' is not active", name);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while listing buffers of '{}'", name);
+        Vec::new()
+    })
+}
+
+/// Start executing the workspace query.sql file in the background
+/// Returns the job id to poll with query_status, or an error message
+fn execute_query_async_ffi(name: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => dadbod.execute_query_async(name).to_string(),
+        None => {
+            log::error!("Cannot execute query: helix-dadbod not initialized (check config.toml)");
+            "Error: Database not initialized - check config.toml".to_string()
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while starting query for '{}'", name);
+        "Error: Panic occurred during query execution".to_string()
+    })
+}
+
+/// State of a job started by execute_query_async
+/// Returns `[state, elapsed_ms]` with state `pending`, `succeeded` or `failed`
+/// (followed by the error message), or `["unknown"]` for an unknown job or a
+/// finished one whose status was already read
+fn query_status_ffi(job_id: &str) -> Vec<String> {
+    let result = panic::catch_unwind(|| {
+        let status = job_id
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|id| global_dadbod()?.query_status(id));
+        match status {
+            Some(status) => {
+                let mut fields = vec![
+                    status.state.name().to_string(),
+                    status.elapsed.as_millis().to_string(),
+                ];
+                if let JobState::Failed(message) = status.state {
+                    fields.push(format!("Error: {}", message));
+                }
+                fields
+            }
+            None => vec!["unknown".to_string()],
+        }
+    });
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while checking job {}", job_id);
+        vec!["unknown".to_string()]
+    })
+}
+
+/// Execute `sql` as if it were the workspace query.sql file, without writing it
+/// there; returns the same messages as execute_query
+fn execute_sql_ffi(name: &str, sql: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.execute_sql_blocking(name, sql) {
+            Ok(_) => "Query executed successfully".to_string(),
+            Err(e) => {
+                log::error!("Query execution failed for '{}': {:#}", name, e);
+                format!("Error: {:#}", e)
+            }
+        },
+        None => {
+            log::error!("Cannot execute query: helix-dadbod not initialized (check config.toml)");
+            "Error: Database not initialized - check config.toml".to_string()
+        }
+    }));
+
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            log::error!("Panic occurred while executing SQL for '{}'", name);
+            "Error: Panic occurred during query execution".to_string()
+        }
+    }
+}
+
+/// Fetch a single row of `table` by primary key value(s) into results.dbout
+/// `pk_values` holds one value per primary key column, in key order
+/// Returns error message on failure (logs error instead of panicking)
+fn peek_ffi(name: &str, table: &str, pk_values: Vec<String>) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.peek_blocking(name, table, &pk_values) {
+            Ok(_) => "Row fetched successfully".to_string(),
+            Err(e) => {
+                log::error!("Peek at '{}' failed for '{}': {}", table, name, e);
+                format!("Error: {}", e)
+            }
+        },
+        None => {
+            log::error!("Cannot peek: helix-dadbod not initialized (check config.toml)");
+            "Error: Database not initialized - check config.toml".to_string()
+        }
+    }));
+
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            log::error!("Panic occurred while peeking at '{}' for '{}'", table, name);
+            "Error: Panic occurred during peek".to_string()
+        }
+    }
+}
+
 /// Close a specific database connection and its SSH tunnel
 /// Returns error message on failure (logs error instead of panicking)
 fn close_connection_ffi(name: &str) -> String {
@@ -161,6 +521,214 @@ fn close_connection_ffi(name: &str) -> String {
     }
 }
 
+/// Objects listed by the last result of a connection as (kind name describe)
+/// lists, one per result row, where describe is the meta-command showing the
+/// object (e.g. "\\sf public.add(a integer)"). Empty if the last result listed none
+fn last_objects_ffi(name: &str) -> Vec<Vec<String>> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.last_objects_blocking(name) {
+            Ok(objects) => objects
+                .into_iter()
+                .map(|o| {
+                    vec![
+                        o.kind.as_str().to_string(),
+                        format!("{}.{}", o.schema, o.name),
+                        o.describe,
+                    ]
+                })
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to get last objects for '{}': {}", name, e);
+                Vec::new()
+            }
+        },
+        None => {
+            log::error!(
+                "Cannot get last objects: helix-dadbod not initialized (check config.toml)"
+            );
+            Vec::new()
+        }
+    }));
+
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            log::error!("Panic occurred while getting last objects for '{}'", name);
+            Vec::new()
+        }
+    }
+}
+
+/// Cancel the query running on a connection; it then fails with the cancel
+/// error in results.dbout
+/// Returns a status message, prefixed with "Error: " on failure
+fn cancel_query_ffi(name: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.cancel_query_blocking(name) {
+            Ok(CancelOutcome::Cancelled) => "Query cancelled".to_string(),
+            Ok(CancelOutcome::Idle) => format!("No query running on '{}'", name),
+            Ok(CancelOutcome::Failed(e)) => format!("Error: Failed to cancel query: {}", e),
+            Ok(CancelOutcome::TimedOut) => "Error: Cancel request timed out".to_string(),
+            Err(e) => format!("Error: {:#}", e),
+        },
+        None => "Error: Database not initialized - check config.toml".to_string(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while cancelling query for '{}'", name);
+        "Error: Panic occurred while cancelling query".to_string()
+    })
+}
+
+/// Whether a query is currently executing on a connection
+/// Returns false if the connection is not active or on error
+fn query_running_ffi(name: &str) -> bool {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => dadbod.query_running_blocking(name),
+        None => false,
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while checking query state for '{}'", name);
+        false
+    })
+}
+
+/// State of a connection's SSH tunnel and its traffic, e.g.
+/// "reconnecting (attempt 2 of 5), 1.2 MB sent, 35.0 MB received"
+/// Returns empty string if the connection has no tunnel or is not active
+fn tunnel_status_ffi(name: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => dadbod
+            .tunnel_status(name)
+            .map(|status| status.to_string())
+            .unwrap_or_default(),
+        None => String::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while getting tunnel status for '{}'", name);
+        String::new()
+    })
+}
+
+/// Report of the last execution as last_run.json content
+/// Returns empty string if nothing ran yet or the connection is not active
+fn last_run_ffi(name: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.last_run_blocking(name) {
+            Ok(Some(report)) => serde_json::to_string(&report).unwrap_or_else(|e| {
+                log::error!("Failed to serialize last run for '{}': {}", name, e);
+                String::new()
+            }),
+            Ok(None) => String::new(),
+            Err(e) => {
+                log::error!("Failed to get last run for '{}': {}", name, e);
+                String::new()
+            }
+        },
+        None => String::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while getting last run for '{}'", name);
+        String::new()
+    })
+}
+
+/// \prompt values the last execution is waiting for, as (variable label) pairs
+/// Returns an empty list when nothing is pending or the connection is not active
+fn pending_prompts_ffi(name: &str) -> Vec<Vec<String>> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.pending_prompts_blocking(name) {
+            Ok(prompts) => prompts
+                .into_iter()
+                .map(|p| vec![p.variable, p.label])
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to get pending prompts for '{}': {}", name, e);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!(
+            "Panic occurred while getting pending prompts for '{}'",
+            name
+        );
+        Vec::new()
+    })
+}
+
+/// Answer a \prompt of the connection's SQL file
+/// Returns empty string on success, error message on failure
+fn provide_prompt_ffi(name: &str, variable: &str, value: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.provide_prompt_blocking(name, variable, value) {
+            Ok(()) => String::new(),
+            Err(e) => {
+                log::error!(
+                    "Failed to answer prompt '{}' for '{}': {}",
+                    variable,
+                    name,
+                    e
+                );
+                format!("Error: {}", e)
+            }
+        },
+        None => "Error: Database not initialized - check config.toml".to_string(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!(
+            "Panic occurred while answering prompt '{}' for '{}'",
+            variable,
+            name
+        );
+        "Error: Panic occurred while answering prompt".to_string()
+    })
+}
+
+/// Store a connection's password in the OS keyring (password_keyring)
+/// Returns empty string on success, error message on failure
+fn store_password_ffi(name: &str, secret: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.store_password_blocking(name, secret) {
+            Ok(()) => String::new(),
+            Err(e) => {
+                log::error!("Failed to store password for '{}': {:#}", name, e);
+                format!("Error: {:#}", e)
+            }
+        },
+        None => "Error: Database not initialized - check config.toml".to_string(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while storing password for '{}'", name);
+        "Error: Panic occurred while storing password".to_string()
+    })
+}
+
+/// Error of the last execution on a connection, e.g. a failed results write
+/// Returns empty string if the last execution succeeded, a query is running, or
+/// the connection is not active
+fn last_error_ffi(name: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => dadbod
+            .get_connection_info_blocking(name)
+            .and_then(|info| info.last_error)
+            .unwrap_or_default(),
+        None => String::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while getting last error for '{}'", name);
+        String::new()
+    })
+}
+
 /// Get workspace directory path for a connection
 /// Returns empty string if connection is not active (logs error instead of panicking)
 fn get_workspace_path_ffi(name: &str) -> String {
@@ -189,6 +757,170 @@ fn get_workspace_path_ffi(name: &str) -> String {
     }
 }
 
+/// Get the per-phase connect timings of an active connection, formatted as
+/// SQL comment lines. Returns empty string if the connection is not active
+fn get_connect_timings_ffi(name: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.get_connection_info_blocking(name) {
+            Some(info) => info.timings.format(),
+            None => {
+                log::error!("Connection '{}' is not active", name);
+                String::new()
+            }
+        },
+        None => {
+            log::error!(
+                "Cannot get connect timings: helix-dadbod not initialized (check config.toml)"
+            );
+            String::new()
+        }
+    }));
+
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            log::error!(
+                "Panic occurred while getting connect timings for '{}'",
+                name
+            );
+            String::new()
+        }
+    }
+}
+
+/// Recent statements executed on a connection, newest first, as
+/// (started_at summary statement results_file) lists; results_file is empty
+/// unless keep_result_history kept a copy of the results
+/// Returns an empty list on error (logs error instead of panicking)
+fn query_history_ffi(name: &str, limit: isize) -> Vec<Vec<String>> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.query_history(name, limit.max(0) as usize) {
+            Ok(entries) => entries
+                .into_iter()
+                .map(|entry| {
+                    vec![
+                        entry.started_at,
+                        entry.summary,
+                        entry.statement,
+                        entry
+                            .results_file
+                            .map(|path| path.display().to_string())
+                            .unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to read query history of '{}': {:#}", name, e);
+                Vec::new()
+            }
+        },
+        None => {
+            log::error!(
+                "Cannot read query history: helix-dadbod not initialized (check config.toml)"
+            );
+            Vec::new()
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while reading query history of '{}'", name);
+        Vec::new()
+    })
+}
+
+/// Save the connection's SQL file as a named snippet, replacing one of the
+/// same name
+/// Returns empty string on success, "Error: ..." on failure
+fn save_snippet_ffi(name: &str, snippet: &str) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.save_snippet_blocking(name, snippet) {
+            Ok(()) => String::new(),
+            Err(e) => {
+                log::error!(
+                    "Failed to save snippet '{}' of '{}': {:#}",
+                    snippet,
+                    name,
+                    e
+                );
+                format!("Error: {:#}", e)
+            }
+        },
+        None => "Error: Database not initialized - check config.toml".to_string(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!(
+            "Panic occurred while saving snippet '{}' of '{}'",
+            snippet,
+            name
+        );
+        "Error: Panic occurred while saving snippet".to_string()
+    })
+}
+
+/// Replace the connection's SQL file with a saved snippet; `force` replaces
+/// SQL that was never saved as a snippet too
+/// Returns empty string on success, "Error: ..." on failure
+fn load_snippet_ffi(name: &str, snippet: &str, force: bool) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.load_snippet_blocking(name, snippet, force) {
+            Ok(()) => String::new(),
+            Err(e) => {
+                log::error!(
+                    "Failed to load snippet '{}' of '{}': {:#}",
+                    snippet,
+                    name,
+                    e
+                );
+                format!("Error: {:#}", e)
+            }
+        },
+        None => "Error: Database not initialized - check config.toml".to_string(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!(
+            "Panic occurred while loading snippet '{}' of '{}'",
+            snippet,
+            name
+        );
+        "Error: Panic occurred while loading snippet".to_string()
+    })
+}
+
+/// Names of the connection's saved snippets, sorted
+/// Returns an empty list on error (logs error instead of panicking)
+fn list_snippets_ffi(name: &str) -> Vec<String> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => match dadbod.list_snippets_blocking(name) {
+            Ok(snippets) => snippets,
+            Err(e) => {
+                log::error!("Failed to list snippets of '{}': {:#}", name, e);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while listing snippets of '{}'", name);
+        Vec::new()
+    })
+}
+
+/// Directory holding the SQL files and results.dbout, for recognizing them
+/// Returns empty string if not initialized or `workspace_dir` is invalid
+fn workspace_dir_ffi() -> String {
+    match global_dadbod().map(|dadbod| dadbod.workspace_dir()) {
+        Some(Ok(dir)) => dir.display().to_string(),
+        Some(Err(e)) => {
+            log::error!("Cannot get workspace directory: {:#}", e);
+            String::new()
+        }
+        None => String::new(),
+    }
+}
+
 /// Check if helix-dadbod initialized successfully
 /// Returns error message if initialization failed, empty string if successful
 fn get_init_error_ffi() -> String {
@@ -197,6 +929,106 @@ fn get_init_error_ffi() -> String {
         .unwrap_or_default()
 }
 
+/// Version, features and loaded config of this build, for bug reports
+/// Available even when initialization failed
+fn version_info() -> VersionInfo {
+    match global_dadbod() {
+        Some(dadbod) => dadbod.version_info(),
+        None => VersionInfo::collect(None),
+    }
+}
+
+/// One-line version report, e.g. "helix-dadbod 0.0.1 (features: ...; config: ...)"
+fn version_ffi() -> String {
+    version_info().banner()
+}
+
+/// Version report as [field, value] pairs
+/// SSH host keys awaiting the user's decision (`host_key_policy = "ask"`),
+/// as (host port key_type fingerprint) lists
+fn pending_host_keys_ffi() -> Vec<Vec<String>> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => dadbod
+            .pending_host_keys()
+            .into_iter()
+            .map(|key| {
+                vec![
+                    key.host,
+                    key.port.to_string(),
+                    key.key_type,
+                    key.fingerprint,
+                ]
+            })
+            .collect(),
+        None => Vec::new(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while getting pending host keys");
+        Vec::new()
+    })
+}
+
+/// Accept (or, with `accept` false, turn down) the pending host key with
+/// `fingerprint`; an accepted key is appended to known_hosts on the next connect
+/// Returns empty string on success, error message on failure
+fn answer_host_key(fingerprint: &str, accept: bool) -> String {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match global_dadbod() {
+        Some(dadbod) => {
+            let answered = if accept {
+                dadbod.accept_host_key(fingerprint)
+            } else {
+                dadbod.reject_host_key(fingerprint)
+            };
+            match answered {
+                Ok(()) => String::new(),
+                Err(e) => {
+                    log::error!("Failed to answer host key {}: {}", fingerprint, e);
+                    format!("Error: {}", e)
+                }
+            }
+        }
+        None => "Error: Database not initialized - check config.toml".to_string(),
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while answering host key {}", fingerprint);
+        "Error: Panic occurred while answering host key".to_string()
+    })
+}
+
+fn accept_host_key_ffi(fingerprint: &str) -> String {
+    answer_host_key(fingerprint, true)
+}
+
+fn reject_host_key_ffi(fingerprint: &str) -> String {
+    answer_host_key(fingerprint, false)
+}
+
+/// Endpoints, health, uptime and traffic of a connection's SSH tunnel
+/// Returns None if the connection is not active or has no tunnel
+fn get_tunnel_status_ffi(name: &str) -> Option<SteelTunnelStatus> {
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        global_dadbod()?
+            .get_connection_info_blocking(name)?
+            .tunnel
+            .map(SteelTunnelStatus::from)
+    }));
+
+    result.unwrap_or_else(|_| {
+        log::error!("Panic occurred while getting tunnel status for '{}'", name);
+        None
+    })
+}
+
+fn version_info_ffi() -> Vec<Vec<String>> {
+    version_info()
+        .fields()
+        .into_iter()
+        .map(|(name, value)| vec![name.to_string(), value])
+        .collect()
+}
+
 declare_module!(create_module);
 
 fn create_module() -> FFIModule {
@@ -204,16 +1036,122 @@ fn create_module() -> FFIModule {
 
     module
         .register_fn("Dadbod::list_connections", list_connections_ffi)
+        .register_fn(
+            "Dadbod::list_connections_detailed",
+            list_connections_detailed_ffi,
+        )
         .register_fn("Dadbod::connect", connect_ffi)
+        .register_fn("Dadbod::connect_with_overrides", connect_with_overrides_ffi)
         .register_fn("Dadbod::test_connection", test_connection_ffi)
         .register_fn("Dadbod::execute_query", execute_query_ffi)
+        .register_fn("Dadbod::execute_sql", execute_sql_ffi)
+        .register_fn("Dadbod::execute_query_buffer", execute_query_buffer_ffi)
+        .register_fn("Dadbod::list_buffers", list_buffers_ffi)
+        .register_fn("Dadbod::execute_file", execute_file_ffi)
+        .register_fn("Dadbod::execute_query_async", execute_query_async_ffi)
+        .register_fn("Dadbod::query_status", query_status_ffi)
+        .register_fn("Dadbod::peek", peek_ffi)
         .register_fn("Dadbod::close_connection", close_connection_ffi)
         .register_fn("Dadbod::get_workspace_path", get_workspace_path_ffi)
+        .register_fn("Dadbod::workspace_dir", workspace_dir_ffi)
+        .register_fn("Dadbod::query_history", query_history_ffi)
+        .register_fn("Dadbod::save_snippet", save_snippet_ffi)
+        .register_fn("Dadbod::load_snippet", load_snippet_ffi)
+        .register_fn("Dadbod::list_snippets", list_snippets_ffi)
+        .register_fn("Dadbod::last_objects", last_objects_ffi)
+        .register_fn("Dadbod::query_running", query_running_ffi)
+        .register_fn("Dadbod::tunnel_status", tunnel_status_ffi)
+        .register_fn("Dadbod::get_tunnel_status", get_tunnel_status_ffi)
+        .register_fn("Dadbod::pending_host_keys", pending_host_keys_ffi)
+        .register_fn("Dadbod::accept_host_key", accept_host_key_ffi)
+        .register_fn("Dadbod::reject_host_key", reject_host_key_ffi)
+        .register_fn("Dadbod::cancel_query", cancel_query_ffi)
+        .register_fn("Dadbod::last_error", last_error_ffi)
+        .register_fn("Dadbod::last_run", last_run_ffi)
+        .register_fn("Dadbod::pending_prompts", pending_prompts_ffi)
+        .register_fn("Dadbod::provide_prompt", provide_prompt_ffi)
+        .register_fn("Dadbod::store_password", store_password_ffi)
+        .register_fn("Dadbod::get_connect_timings", get_connect_timings_ffi)
         .register_fn("Dadbod::get_init_error", get_init_error_ffi)
+        .register_fn("Dadbod::version", version_ffi)
+        .register_fn("Dadbod::version_info", version_info_ffi)
         // Register workspace info getters
         .register_fn("WorkspaceInfo-path", SteelWorkspaceInfo::path)
         .register_fn("WorkspaceInfo-sql_file", SteelWorkspaceInfo::sql_file)
-        .register_fn("WorkspaceInfo-dbout_file", SteelWorkspaceInfo::dbout_file);
+        .register_fn("WorkspaceInfo-dbout_file", SteelWorkspaceInfo::dbout_file)
+        .register_fn("WorkspaceInfo-buffers", SteelWorkspaceInfo::buffers)
+        // Register tunnel status getters
+        .register_fn("TunnelStatus-ssh_host", SteelTunnelStatus::ssh_host)
+        .register_fn("TunnelStatus-ssh_port", SteelTunnelStatus::ssh_port)
+        .register_fn("TunnelStatus-ssh_user", SteelTunnelStatus::ssh_user)
+        .register_fn("TunnelStatus-local_port", SteelTunnelStatus::local_port)
+        .register_fn("TunnelStatus-remote", SteelTunnelStatus::remote)
+        .register_fn("TunnelStatus-state", SteelTunnelStatus::state)
+        .register_fn("TunnelStatus-healthy", SteelTunnelStatus::healthy)
+        .register_fn("TunnelStatus-uptime_secs", SteelTunnelStatus::uptime_secs)
+        .register_fn("TunnelStatus-bytes_sent", SteelTunnelStatus::bytes_sent)
+        .register_fn(
+            "TunnelStatus-bytes_received",
+            SteelTunnelStatus::bytes_received,
+        )
+        .register_fn("TunnelStatus-summary", SteelTunnelStatus::summary);
 
     module
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_ffi() {
+        let banner = version_ffi();
+        assert!(banner.starts_with(&format!("helix-dadbod {} (", env!("CARGO_PKG_VERSION"))));
+        assert!(banner.contains("db_types: postgres"));
+    }
+
+    #[test]
+    fn test_version_info_ffi_pairs() {
+        let pairs = version_info_ffi();
+        let names: Vec<&str> = pairs.iter().map(|pair| pair[0].as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["version", "features", "db_types", "meta_commands", "config"]
+        );
+        assert!(pairs.iter().all(|pair| pair.len() == 2));
+    }
+
+    #[test]
+    fn test_tunnel_status_getters() {
+        let status = SteelTunnelStatus::from(TunnelStatus {
+            ssh_host: "jump.example.com".to_string(),
+            ssh_port: 22,
+            ssh_user: "sshuser".to_string(),
+            local_port: 7001,
+            remote: crate::tunnel::RemoteTarget::Socket(
+                "/run/postgresql/.s.PGSQL.5432".to_string(),
+            ),
+            state: crate::tunnel::SessionState::Reconnecting {
+                attempt: 2,
+                max_attempts: 5,
+            },
+            healthy: false,
+            uptime: std::time::Duration::from_secs(90),
+            bytes_sent: 1536,
+            bytes_received: 0,
+        });
+        assert_eq!(status.remote(), "unix:/run/postgresql/.s.PGSQL.5432");
+        assert_eq!(status.state(), "reconnecting (attempt 2 of 5)");
+        assert_eq!(status.uptime_secs(), 90);
+        assert_eq!(
+            status.summary(),
+            "reconnecting (attempt 2 of 5), 1.5 kB sent, 0 bytes received"
+        );
+    }
+
+    #[test]
+    fn test_module_registration() {
+        // Registering every function, including the version reports, must not panic
+        create_module();
+    }
+}
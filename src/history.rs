@@ -0,0 +1,671 @@
+//! Query history: every execution archived in the workspace
+//!
+//! Each execution appends its statement to `history/history.sql` below a
+//! header line saying when it ran, on which connection and how it ended, so a
+//! query from hours ago can be found again (`Dadbod::query_history`). With
+//! `keep_result_history = true` the results.dbout it produced is copied to
+//! `history/{timestamp}_{connection}.dbout` as well. After each execution,
+//! entries and copies older than `history_max_age_days` are pruned, then the
+//! oldest ones until the directory fits in `history_max_mb`.
+//!
+//! Appends and the rewrite pruning does take `LOCK_FILE`, since every
+//! connection, and every editor sharing the workspace, writes the same file.
+//! Connection names escape `|` in the header, and statement lines that would
+//! read as a header (or as the line naming the results copy) are prefixed
+//! with `ESCAPE_PREFIX`.
+
+use crate::run_report::{RunReport, RunStatus};
+use crate::workspace;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Subdirectory of the workspace holding the history
+pub const HISTORY_DIR: &str = "history";
+
+/// Log of executed statements inside `HISTORY_DIR`
+pub const HISTORY_FILE: &str = "history.sql";
+
+/// Start of the line above each statement in `HISTORY_FILE`
+const HEADER_PREFIX: &str = "-- dadbod-history: ";
+
+/// Line naming the entry's copy of results.dbout, below its header
+const RESULTS_PREFIX: &str = "-- results: ";
+
+/// Prefix of a statement line that starts like one of the prefixes above (or
+/// like this one), removed again when the history is read
+const ESCAPE_PREFIX: &str = "-- dadbod-escaped: ";
+
+/// Separator of the header's fields
+const FIELD_SEPARATOR: &str = " | ";
+
+/// Lock file inside `HISTORY_DIR`, created while `HISTORY_FILE` is written
+const LOCK_FILE: &str = ".history.lock";
+
+/// Longest a writer waits for the lock
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+/// Age after which a lock is taken to be left by a writer that crashed
+const STALE_LOCK: Duration = Duration::from_secs(5);
+
+/// One executed statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// RFC 3339 timestamp
+    pub started_at: String,
+    pub connection: String,
+    /// How it ended, e.g. `3 rows in 0.012s` or `ERROR 42P01: relation "x" does not exist`
+    pub summary: String,
+    pub statement: String,
+    /// Copy of the results, when `keep_result_history` was set and it hasn't
+    /// been pruned
+    pub results_file: Option<PathBuf>,
+}
+
+impl HistoryEntry {
+    /// The entry as written to `HISTORY_FILE`, ending in a blank line
+    fn format(&self) -> String {
+        let mut text = format!(
+            "{}{}{}{}{}{}\n",
+            HEADER_PREFIX,
+            self.started_at,
+            FIELD_SEPARATOR,
+            escape_field(&self.connection),
+            FIELD_SEPARATOR,
+            self.summary
+        );
+        if let Some(name) = self.results_file.as_ref().and_then(|path| path.file_name()) {
+            text.push_str(&format!("{}{}\n", RESULTS_PREFIX, name.to_string_lossy()));
+        }
+        for line in self.statement.trim_end().lines() {
+            if [HEADER_PREFIX, RESULTS_PREFIX, ESCAPE_PREFIX]
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+            {
+                text.push_str(ESCAPE_PREFIX);
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push('\n');
+        text
+    }
+
+    fn started_at(&self) -> Option<DateTime<Local>> {
+        parse_timestamp(&self.started_at)
+    }
+}
+
+/// Where and how much history is kept (`keep_result_history`,
+/// `history_max_age_days`, `history_max_mb`)
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+    keep_results: bool,
+    /// None keeps entries however old they are
+    max_age: Option<chrono::Duration>,
+    /// 0 for no limit
+    max_bytes: u64,
+}
+
+impl History {
+    /// History in the workspace directory `workspace_dir`
+    pub fn new(workspace_dir: &Path, keep_results: bool, max_age_days: u64, max_mb: u64) -> Self {
+        Self {
+            dir: workspace_dir.join(HISTORY_DIR),
+            keep_results,
+            max_age: (max_age_days > 0).then(|| chrono::Duration::days(max_age_days as i64)),
+            max_bytes: max_mb.saturating_mul(1024 * 1024),
+        }
+    }
+
+    /// Append the execution `report` describes, with a copy of `dbout_file`
+    /// when results are kept, then prune
+    pub fn record(&self, report: &RunReport, dbout_file: &Path) -> Result<()> {
+        // Nothing ran while \prompt values are missing
+        if report.status == RunStatus::Pending {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let results_file = if self.keep_results {
+            let path = self.dir.join(snapshot_name(report));
+            fs::copy(dbout_file, &path).with_context(|| {
+                format!(
+                    "Failed to copy {} to {}",
+                    dbout_file.display(),
+                    path.display()
+                )
+            })?;
+            Some(path)
+        } else {
+            None
+        };
+
+        let entry = HistoryEntry {
+            started_at: report.started_at.clone(),
+            connection: report.connection.clone(),
+            summary: summarize(report),
+            statement: report.statement.clone(),
+            results_file,
+        };
+        let path = self.dir.join(HISTORY_FILE);
+        let _lock = HistoryLock::acquire(&self.dir)?;
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(entry.format().as_bytes()))
+            .with_context(|| format!("Failed to append to {}", path.display()))?;
+
+        self.prune_locked(Local::now())
+    }
+
+    /// Remove entries and result copies older than the maximum age, then the
+    /// oldest ones until the history fits in the maximum size; result copies
+    /// go before entries
+    pub fn prune(&self, now: DateTime<Local>) -> Result<()> {
+        let _lock = HistoryLock::acquire(&self.dir)?;
+        self.prune_locked(now)
+    }
+
+    /// `prune`, holding the history's lock
+    fn prune_locked(&self, now: DateTime<Local>) -> Result<()> {
+        let cutoff = self.max_age.map(|age| now - age);
+        let mut snapshots = self.snapshots()?;
+        snapshots.retain(|(path, _, modified)| {
+            let expired = cutoff.is_some_and(|cutoff| *modified < cutoff);
+            if expired {
+                remove(path);
+            }
+            !expired
+        });
+
+        let log = self.dir.join(HISTORY_FILE);
+        let log_len = fs::metadata(&log).map(|m| m.len()).unwrap_or(0);
+        let mut snapshots_len: u64 = snapshots.iter().map(|(_, len, _)| len).sum();
+        let over =
+            |snapshots_len: u64| self.max_bytes > 0 && log_len + snapshots_len > self.max_bytes;
+        let mut snapshots = snapshots.into_iter();
+        while over(snapshots_len) {
+            let Some((path, len, _)) = snapshots.next() else {
+                break;
+            };
+            remove(&path);
+            snapshots_len -= len;
+        }
+
+        // Entries are appended in order, so the first one is the oldest
+        let log_expired = cutoff.is_some_and(|cutoff| {
+            first_started_at(&log).is_some_and(|started_at| started_at < cutoff)
+        });
+        if !log_expired && !over(snapshots_len) {
+            return Ok(());
+        }
+
+        let contents = fs::read_to_string(&log)
+            .with_context(|| format!("Failed to read {}", log.display()))?;
+        let mut entries = parse_entries(&contents, &self.dir);
+        entries.retain(|entry| match (cutoff, entry.started_at()) {
+            (Some(cutoff), Some(started_at)) => started_at >= cutoff,
+            _ => true,
+        });
+        let mut texts: Vec<String> = entries.iter().map(HistoryEntry::format).collect();
+        if self.max_bytes > 0 {
+            let budget = self.max_bytes.saturating_sub(snapshots_len) as usize;
+            let mut len: usize = texts.iter().map(String::len).sum();
+            let keep_from = texts
+                .iter()
+                .position(|text| {
+                    let fits = len <= budget;
+                    len -= text.len();
+                    fits
+                })
+                .unwrap_or(texts.len());
+            texts.drain(..keep_from);
+        }
+        log::info!(
+            "Pruned query history {} to {} entries",
+            log.display(),
+            texts.len()
+        );
+        workspace::write_atomic(&log, texts.concat().as_bytes())
+            .with_context(|| format!("Failed to write {}", log.display()))
+    }
+
+    /// Result copies as (path, size, modified), oldest first
+    fn snapshots(&self) -> Result<Vec<(PathBuf, u64, DateTime<Local>)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.dir.display()))
+            }
+        };
+        let mut snapshots: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "dbout"))
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                let modified = DateTime::<Local>::from(metadata.modified().ok()?);
+                Some((path, metadata.len(), modified))
+            })
+            .collect();
+        // Names start with the timestamp
+        snapshots.sort();
+        Ok(snapshots)
+    }
+}
+
+/// Most recent entries of `connection` in the history of workspace directory
+/// `workspace_dir`, newest first, at most `limit` of them (0 for all)
+pub fn read_entries(
+    workspace_dir: &Path,
+    connection: &str,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>> {
+    let dir = workspace_dir.join(HISTORY_DIR);
+    let path = dir.join(HISTORY_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    Ok(parse_entries(&contents, &dir)
+        .into_iter()
+        .rev()
+        .filter(|entry| entry.connection == connection)
+        .map(|entry| HistoryEntry {
+            results_file: entry.results_file.filter(|path| path.exists()),
+            ..entry
+        })
+        .take(limit)
+        .collect())
+}
+
+/// Entries of a `HISTORY_FILE` in `dir`, oldest first
+fn parse_entries(contents: &str, dir: &Path) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut statement: Vec<&str> = Vec::new();
+    let finish = |entries: &mut Vec<HistoryEntry>, statement: &mut Vec<&str>| {
+        if let Some(entry) = entries.last_mut() {
+            entry.statement = statement.join("\n").trim_end().to_string();
+        }
+        statement.clear();
+    };
+
+    for line in contents.lines() {
+        if let Some(header) = line.strip_prefix(HEADER_PREFIX) {
+            finish(&mut entries, &mut statement);
+            let (started_at, rest) = header.split_once(FIELD_SEPARATOR).unwrap_or((header, ""));
+            let (connection, summary) = split_escaped_field(rest);
+            entries.push(HistoryEntry {
+                started_at: started_at.to_string(),
+                connection,
+                summary: summary.to_string(),
+                statement: String::new(),
+                results_file: None,
+            });
+        } else if let (Some(entry), Some(name), true) = (
+            entries.last_mut(),
+            line.strip_prefix(RESULTS_PREFIX),
+            statement.is_empty(),
+        ) {
+            entry.results_file = Some(dir.join(name));
+        } else if !entries.is_empty() {
+            statement.push(line.strip_prefix(ESCAPE_PREFIX).unwrap_or(line));
+        }
+    }
+    finish(&mut entries, &mut statement);
+    entries
+}
+
+/// Start time of the first entry of the history file at `path`
+fn first_started_at(path: &Path) -> Option<DateTime<Local>> {
+    let file = fs::File::open(path).ok()?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line).ok()?;
+    let header = line.strip_prefix(HEADER_PREFIX)?;
+    parse_timestamp(header.split(FIELD_SEPARATOR).next()?)
+}
+
+/// `field` with `\` and `|` escaped by a backslash, so it can't end early
+fn escape_field(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// The escaped field `text` starts with, unescaped, and what follows its
+/// separator
+fn split_escaped_field(text: &str) -> (String, &str) {
+    let mut field = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next().map(|(_, escaped)| escaped)),
+            ' ' if text[i..].starts_with(FIELD_SEPARATOR) => {
+                return (field, &text[i + FIELD_SEPARATOR.len()..])
+            }
+            c => field.push(c),
+        }
+    }
+    (field, "")
+}
+
+/// `LOCK_FILE` of a history directory, held while it exists; removed when dropped
+struct HistoryLock {
+    path: PathBuf,
+}
+
+impl HistoryLock {
+    /// Create the lock file in `dir`, waiting up to `LOCK_WAIT` for another
+    /// writer to remove it; one older than `STALE_LOCK` is removed first
+    fn acquire(dir: &Path) -> Result<Self> {
+        let path = dir.join(LOCK_FILE);
+        let start = Instant::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+                }
+            }
+            let age = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+            if age.is_some_and(|age| age > STALE_LOCK) {
+                log::warn!("Removing stale history lock {}", path.display());
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if start.elapsed() > LOCK_WAIT {
+                anyhow::bail!(
+                    "{} is still locked after {:?}; remove it if no editor is writing the history",
+                    path.display(),
+                    LOCK_WAIT
+                );
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for HistoryLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|at| at.with_timezone(&Local))
+}
+
+/// File name of the results copy of `report`, e.g. `20240102-030405.123_prod.dbout`
+fn snapshot_name(report: &RunReport) -> String {
+    let stamp = parse_timestamp(&report.started_at)
+        .unwrap_or_else(Local::now)
+        .format("%Y%m%d-%H%M%S%.3f");
+    let connection: String = report
+        .connection
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}_{}.dbout", stamp, connection)
+}
+
+/// How the execution ended, on one line
+fn summarize(report: &RunReport) -> String {
+    let first_line = |text: &str| text.lines().next().unwrap_or_default().to_string();
+    let duration = format!("{:.3}s", report.duration_ms as f64 / 1000.0);
+    match (&report.error, report.row_count, report.affected_count) {
+        (Some(error), _, _) => match &error.code {
+            Some(code) => format!("ERROR {}: {}", code, first_line(&error.message)),
+            None => format!("error: {}", first_line(&error.message)),
+        },
+        (None, Some(1), _) => format!("1 row in {}", duration),
+        (None, Some(rows), _) => format!("{} rows in {}", rows, duration),
+        (None, None, Some(affected)) => format!("{} affected in {}", affected, duration),
+        (None, None, None) => format!("done in {}", duration),
+    }
+}
+
+/// Remove `path`, logging failures
+fn remove(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => log::debug!("Pruned {}", path.display()),
+        Err(e) => log::warn!("Failed to prune {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_report::RunError;
+    use chrono::TimeZone;
+    use std::time::Duration;
+
+    fn report(connection: &str, statement: &str, at: DateTime<Local>) -> RunReport {
+        RunReport::new(connection, statement, at, Duration::from_millis(12))
+    }
+
+    fn history(
+        name: &str,
+        keep_results: bool,
+        max_age_days: u64,
+        max_mb: u64,
+    ) -> (History, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("helix-dadbod-history-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        (History::new(&dir, keep_results, max_age_days, max_mb), dir)
+    }
+
+    #[test]
+    fn test_record_and_read_entries() {
+        let (history, dir) = history("record", true, 0, 0);
+        let dbout = dir.join("results.dbout");
+        let at = Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+
+        fs::write(&dbout, "│ id │\n│ 1  │\n").unwrap();
+        let select = report("prod", "SELECT id\nFROM users;", at).with_rows(1, "table");
+        history.record(&select, &dbout).unwrap();
+
+        let error = RunError {
+            code: Some("42P01".to_string()),
+            ..RunError::client("relation \"nope\" does not exist")
+        };
+        let failed =
+            report("staging", "SELECT * FROM nope", at).with_error(RunStatus::DbError, error);
+        history.record(&failed, &dbout).unwrap();
+
+        let update = report("prod", "UPDATE users SET active = true", at).with_affected(Some(4));
+        history.record(&update, &dbout).unwrap();
+
+        // Nothing ran
+        let pending = report("prod", "SELECT :id", at).with_status(RunStatus::Pending);
+        history.record(&pending, &dbout).unwrap();
+
+        let entries = read_entries(&dir, "prod", 0).unwrap();
+        let summaries: Vec<&str> = entries.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["4 affected in 0.012s", "1 row in 0.012s"]);
+        assert_eq!(entries[1].statement, "SELECT id\nFROM users;");
+        assert_eq!(entries[1].started_at, at.to_rfc3339());
+        let results_file = entries[1].results_file.clone().unwrap();
+        assert!(results_file
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("20240102-030405.000_prod"));
+        assert_eq!(
+            fs::read_to_string(results_file).unwrap(),
+            "│ id │\n│ 1  │\n"
+        );
+
+        let staging = read_entries(&dir, "staging", 1).unwrap();
+        assert_eq!(
+            staging[0].summary,
+            "ERROR 42P01: relation \"nope\" does not exist"
+        );
+        assert_eq!(read_entries(&dir, "prod", 1).unwrap().len(), 1);
+        assert!(read_entries(&dir, "dev", 0).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_parse_entries_round_trips() {
+        let dir = Path::new("/ws/history");
+        let contents = "-- dadbod-history: 2024-01-02T03:04:05+00:00 | prod | 2 rows in 0.100s\n\
+                        -- results: 20240102-030405.000_prod.dbout\n\
+                        -- the active ones\n\
+                        SELECT *\n\n\
+                        FROM users;\n\n\
+                        -- dadbod-history: 2024-01-02T03:05:00+00:00 | prod | done in 0.001s\n\
+                        \\dt\n\n";
+        let entries = parse_entries(contents, dir);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].statement,
+            "-- the active ones\nSELECT *\n\nFROM users;"
+        );
+        assert_eq!(
+            entries[0].results_file,
+            Some(dir.join("20240102-030405.000_prod.dbout"))
+        );
+        assert_eq!(entries[1].results_file, None);
+        let formatted: String = entries.iter().map(HistoryEntry::format).collect();
+        assert_eq!(formatted, contents);
+    }
+
+    #[test]
+    fn test_entries_that_look_like_the_format_round_trip() {
+        let entry = HistoryEntry {
+            started_at: "2024-01-02T03:04:05+00:00".to_string(),
+            connection: "prod | eu\\west".to_string(),
+            summary: "error: a | b".to_string(),
+            statement: "-- results: not a copy\n\
+                        -- dadbod-history: 2024-01-02T00:00:00+00:00 | x | y\n\
+                        -- dadbod-escaped: z\n\
+                        SELECT 1;"
+                .to_string(),
+            results_file: None,
+        };
+        let formatted = entry.format();
+        assert!(formatted.starts_with(
+            "-- dadbod-history: 2024-01-02T03:04:05+00:00 | prod \\| eu\\\\west | error: a | b\n\
+             -- dadbod-escaped: -- results: not a copy\n"
+        ));
+        assert_eq!(
+            parse_entries(&formatted, Path::new("/ws/history")),
+            vec![entry]
+        );
+    }
+
+    #[test]
+    fn test_history_lock() {
+        let (_, dir) = history("lock", false, 0, 0);
+        let lock = HistoryLock::acquire(&dir).unwrap();
+        assert!(dir.join(LOCK_FILE).exists());
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE).exists());
+
+        // A lock left by a writer that crashed doesn't block for good
+        let stale = fs::File::create(dir.join(LOCK_FILE)).unwrap();
+        stale
+            .set_modified(SystemTime::now() - STALE_LOCK * 2)
+            .unwrap();
+        let start = Instant::now();
+        drop(HistoryLock::acquire(&dir).unwrap());
+        assert!(start.elapsed() < LOCK_WAIT);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_prune_by_age_and_size() {
+        let (history, dir) = history("prune", false, 7, 0);
+        let dbout = dir.join("results.dbout");
+        fs::write(&dbout, "").unwrap();
+        let now = Local::now();
+        for (days_ago, statement) in [(30, "SELECT 'old'"), (1, "SELECT 'recent'")] {
+            let at = now - chrono::Duration::days(days_ago);
+            history
+                .record(&report("prod", statement, at), &dbout)
+                .unwrap();
+        }
+        let statements = |dir: &Path| -> Vec<String> {
+            read_entries(dir, "prod", 0)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.statement)
+                .collect()
+        };
+        assert_eq!(statements(&dir), vec!["SELECT 'recent'"]);
+
+        // Over the size limit the oldest entries go first
+        let history = History {
+            max_bytes: 300,
+            ..history
+        };
+        for i in 0..10 {
+            history
+                .record(&report("prod", &format!("SELECT {}", i), now), &dbout)
+                .unwrap();
+        }
+        let kept = statements(&dir);
+        assert_eq!(kept.first().unwrap(), "SELECT 9");
+        assert!(kept.len() < 10);
+        assert!(
+            fs::metadata(dir.join(HISTORY_DIR).join(HISTORY_FILE))
+                .unwrap()
+                .len()
+                <= 300
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_prune_removes_result_copies_first() {
+        let (history, dir) = history("prune-results", true, 0, 0);
+        let dbout = dir.join("results.dbout");
+        fs::write(&dbout, "x".repeat(200)).unwrap();
+        let at = Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        for second in 0..3 {
+            let at = at + chrono::Duration::seconds(second);
+            history
+                .record(&report("prod", "SELECT 1", at), &dbout)
+                .unwrap();
+        }
+        let history = History {
+            max_bytes: 850,
+            ..history
+        };
+        history.prune(Local::now()).unwrap();
+
+        let entries = read_entries(&dir, "prod", 0).unwrap();
+        assert_eq!(entries.len(), 3, "the log itself fits");
+        let kept: Vec<bool> = entries.iter().map(|e| e.results_file.is_some()).collect();
+        assert_eq!(kept, vec![true, true, false], "oldest copy removed");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,185 @@
+//! Executions running in the background, polled by the editor
+//!
+//! `execute_query_async` starts an execution on the global runtime and returns
+//! a job id straight away, so Helix doesn't freeze on a slow query; the plugin
+//! polls `query_status` on a timer and reloads results.dbout once the job is
+//! done. A finished job is forgotten once its status has been read, or after
+//! `JOB_TTL` if nobody asks.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+/// How long a finished job's status is kept when nobody reads it
+pub const JOB_TTL: Duration = Duration::from_secs(600);
+
+/// Where a job is
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Pending,
+    Succeeded,
+    /// The execution failed; the error, as `execute_query` would return it
+    Failed(String),
+}
+
+impl JobState {
+    /// Name reported to the plugin: `pending`, `succeeded` or `failed`
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed(_) => "failed",
+        }
+    }
+}
+
+/// A job's state and how long it has run (or ran, once finished)
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobStatus {
+    pub state: JobState,
+    pub elapsed: Duration,
+}
+
+struct Job {
+    started: Instant,
+    finished: Option<Instant>,
+    state: JobState,
+}
+
+/// Jobs started by `execute_query_async`, by id
+#[derive(Default)]
+pub struct JobRegistry {
+    last_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, Job>>,
+}
+
+impl JobRegistry {
+    /// Run `future` on `runtime` as a new job and return its id
+    pub fn spawn<F>(self: &Arc<Self>, runtime: &Handle, future: F) -> u64
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        let id = self.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        Self::collect_garbage(&mut jobs, Instant::now());
+        jobs.insert(
+            id,
+            Job {
+                started: Instant::now(),
+                finished: None,
+                state: JobState::Pending,
+            },
+        );
+        drop(jobs);
+
+        let registry = Arc::clone(self);
+        runtime.spawn(async move {
+            let result = future.await;
+            registry.finish(id, result);
+        });
+        id
+    }
+
+    /// Status of job `id`; None for unknown ids and finished jobs already read
+    /// or expired
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        let now = Instant::now();
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        Self::collect_garbage(&mut jobs, now);
+        let job = jobs.get(&id)?;
+        let status = JobStatus {
+            state: job.state.clone(),
+            elapsed: job.finished.unwrap_or(now) - job.started,
+        };
+        if job.finished.is_some() {
+            jobs.remove(&id);
+        }
+        Some(status)
+    }
+
+    fn finish(&self, id: u64, result: Result<()>) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.get_mut(&id) {
+            job.finished = Some(Instant::now());
+            job.state = match result {
+                Ok(()) => JobState::Succeeded,
+                Err(e) => {
+                    log::error!("Background execution {} failed: {:#}", id, e);
+                    JobState::Failed(format!("{:#}", e))
+                }
+            };
+        }
+    }
+
+    /// Drop finished jobs nobody read within `JOB_TTL`
+    fn collect_garbage(jobs: &mut HashMap<u64, Job>, now: Instant) {
+        jobs.retain(|_, job| match job.finished {
+            Some(finished) => now.duration_since(finished) < JOB_TTL,
+            None => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_job_reports_pending_then_outcome_once() {
+        let registry = Arc::new(JobRegistry::default());
+        let (release, released) = oneshot::channel::<()>();
+        let id = registry.spawn(&Handle::current(), async move {
+            released.await?;
+            Ok(())
+        });
+
+        assert_eq!(registry.status(id).unwrap().state, JobState::Pending);
+        release.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(registry.status(id).unwrap().state, JobState::Succeeded);
+        assert_eq!(registry.status(id), None, "read once, then forgotten");
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_keeps_the_error() {
+        let registry = Arc::new(JobRegistry::default());
+        let first = registry.spawn(&Handle::current(), async { Ok(()) });
+        let id = registry.spawn(&Handle::current(), async {
+            anyhow::bail!("Connection 'prod' not active")
+        });
+        assert_ne!(first, id);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let status = registry.status(id).unwrap();
+        assert_eq!(
+            status.state,
+            JobState::Failed("Connection 'prod' not active".to_string())
+        );
+        assert_eq!(status.state.name(), "failed");
+    }
+
+    #[test]
+    fn test_expired_jobs_are_collected() {
+        let now = Instant::now();
+        let job = |finished: Option<Instant>| Job {
+            started: now,
+            finished,
+            state: JobState::Succeeded,
+        };
+        let mut jobs = HashMap::new();
+        jobs.insert(1, job(None));
+        jobs.insert(2, job(Some(now)));
+        jobs.insert(3, job(Some(now + JOB_TTL / 2)));
+
+        JobRegistry::collect_garbage(&mut jobs, now + JOB_TTL);
+        let mut left: Vec<_> = jobs.keys().copied().collect();
+        left.sort();
+        assert_eq!(left, vec![1, 3]);
+    }
+}
@@ -0,0 +1,135 @@
+//! Connection passwords in the platform secret store
+//!
+//! Connections with `password_keyring = true` (or a `keyring_service` /
+//! `keyring_user`) read their password from the OS keyring at connect time:
+//! the macOS Keychain, Windows Credential Manager or the Secret Service on
+//! Linux. Entries are seeded with `Dadbod::store_password`. Passwords read
+//! here are never logged.
+
+use crate::config::Connection;
+use anyhow::{Context, Result};
+use std::fmt;
+
+/// Keyring service used when `keyring_service` is unset
+pub const DEFAULT_SERVICE: &str = "helix-dadbod";
+
+/// Service and user a connection's password is stored under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyringKey {
+    pub service: String,
+    pub user: String,
+}
+
+impl KeyringKey {
+    /// Key of `conn`'s password, or None if it doesn't use the keyring
+    ///
+    /// The service defaults to `helix-dadbod` and the user to the connection
+    /// name.
+    pub fn for_connection(conn: &Connection) -> Option<Self> {
+        if !conn.uses_keyring() {
+            return None;
+        }
+        Some(Self {
+            service: conn
+                .keyring_service
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SERVICE.to_string()),
+            user: conn
+                .keyring_user
+                .clone()
+                .unwrap_or_else(|| conn.name.clone()),
+        })
+    }
+
+    /// Read the password; blocks on the platform secret store
+    pub fn get(&self) -> Result<String> {
+        let entry = self.entry()?;
+        match entry.get_password() {
+            Ok(password) => Ok(password),
+            Err(keyring::Error::NoEntry) => anyhow::bail!(
+                "No keyring entry for {} (store one with Dadbod::store_password)",
+                self
+            ),
+            Err(e) => Err(e).with_context(|| format!("Failed to read keyring entry for {}", self)),
+        }
+    }
+
+    /// Store `password`, replacing any previous one
+    pub fn set(&self, password: &str) -> Result<()> {
+        self.entry()?
+            .set_password(password)
+            .with_context(|| format!("Failed to store keyring entry for {}", self))
+    }
+
+    fn entry(&self) -> Result<keyring::Entry> {
+        keyring::Entry::new(&self.service, &self.user)
+            .with_context(|| format!("Invalid keyring entry {}", self))
+    }
+}
+
+impl fmt::Display for KeyringKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "service '{}', user '{}'", self.service, self.user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(extra: &str) -> Connection {
+        let config: crate::config::SqlConfig = toml::from_str(&format!(
+            r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+            {}
+            "#,
+            extra
+        ))
+        .unwrap();
+        config.connections[0].clone()
+    }
+
+    #[test]
+    fn test_key_for_connection() {
+        assert_eq!(KeyringKey::for_connection(&connection("")), None);
+        assert_eq!(
+            KeyringKey::for_connection(&connection("password_keyring = true")),
+            Some(KeyringKey {
+                service: DEFAULT_SERVICE.to_string(),
+                user: "prod".to_string(),
+            })
+        );
+        // Naming the entry implies using the keyring
+        assert_eq!(
+            KeyringKey::for_connection(&connection(
+                "keyring_service = \"corp-vault\"\nkeyring_user = \"app@prod\""
+            )),
+            Some(KeyringKey {
+                service: "corp-vault".to_string(),
+                user: "app@prod".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_entry_names_key() {
+        // The mock store keeps tests off the real OS keyring; its entries
+        // start out empty
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        let key = KeyringKey {
+            service: "helix-dadbod-test".to_string(),
+            user: "missing-entry".to_string(),
+        };
+        let err = key.get().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "No keyring entry for service 'helix-dadbod-test', user 'missing-entry' \
+             (store one with Dadbod::store_password)"
+        );
+    }
+}
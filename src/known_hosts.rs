@@ -1,45 +1,226 @@
 use anyhow::{Context, Result};
 use russh_keys::key::PublicKey;
 use russh_keys::PublicKeyBase64;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How a server's host key missing from known_hosts is treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyPolicy {
+    /// Reject it
+    #[default]
+    Strict,
+    /// Trust it on first use and append it to known_hosts
+    AcceptNew,
+    /// Reject it until the user accepts its fingerprint, then append it
+    Ask,
+}
 
-/// Verify a host key against ~/.ssh/known_hosts
-pub fn verify_host_key(hostname: &str, port: u16, server_key: &PublicKey) -> Result<bool> {
-    let known_hosts_path = get_known_hosts_path()?;
+/// What ~/.ssh/known_hosts says about a server's host key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    /// An entry of the host has this key
+    Verified,
+    /// The host has no entry
+    UnknownHost,
+    /// The host has entries, none with this key: it changed, or someone is
+    /// in the middle
+    KeyMismatch {
+        /// SHA256 fingerprints of the keys known_hosts lists for the host
+        expected_fingerprints: Vec<String>,
+        /// SHA256 fingerprint of the key the server presented
+        presented: String,
+        /// File and line of the host's first entry
+        file: PathBuf,
+        line: usize,
+    },
+    /// The key is marked `@revoked`
+    Revoked,
+    /// The host's only entries are `@cert-authority` keys, which can't vouch
+    /// for it: russh 0.45 only negotiates plain host keys, so there is never a
+    /// certificate to check against the CA
+    CertAuthority {
+        /// File and line of the host's first `@cert-authority` entry
+        file: PathBuf,
+        line: usize,
+    },
+}
 
-    log::debug!("Verifying host key for {}:{}", hostname, port);
-    log::debug!("Known hosts file: {}", known_hosts_path.display());
+/// How a known_hosts entry's key compares with the server's
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeyComparison {
+    Same,
+    /// Another key of the same type, with this fingerprint
+    Different(String),
+    /// A key of another type, or one that can't be read; like OpenSSH, such
+    /// entries neither verify nor contradict the server's key
+    OtherType,
+}
 
-    if !known_hosts_path.exists() {
-        log::warn!(
-            "Known hosts file does not exist: {}",
-            known_hosts_path.display()
-        );
-        return Ok(false);
+/// System-wide known_hosts file, searched last
+const GLOBAL_KNOWN_HOSTS: &str = "/etc/ssh/ssh_known_hosts";
+
+/// Files searched for host keys, in order: `user_files` (a connection's
+/// known_hosts_file and UserKnownHostsFile from ~/.ssh/config), then
+/// ~/.ssh/known_hosts, ~/.ssh/known_hosts2 and /etc/ssh/ssh_known_hosts.
+/// New keys are appended to the first
+pub fn known_hosts_files(user_files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = user_files.to_vec();
+    match get_known_hosts_path() {
+        Ok(path) => {
+            files.push(path.with_file_name("known_hosts2"));
+            files.insert(user_files.len(), path);
+        }
+        Err(e) => log::warn!("Skipping ~/.ssh/known_hosts: {}", e),
     }
+    files.push(PathBuf::from(GLOBAL_KNOWN_HOSTS));
+    files.dedup();
+    files
+}
 
-    let contents = fs::read_to_string(&known_hosts_path).with_context(|| {
-        format!(
-            "Failed to read known_hosts file: {}",
-            known_hosts_path.display()
-        )
-    })?;
+/// Verify a host key against the known_hosts `files`; the first file with an
+/// entry for the host, under its name or the `ip` connected to, decides
+pub fn verify_host_key(
+    hostname: &str,
+    port: u16,
+    ip: Option<IpAddr>,
+    server_key: &PublicKey,
+    files: &[PathBuf],
+) -> Result<HostKeyStatus> {
+    log::debug!("Verifying host key for {}:{}", hostname, port);
 
-    // Normalize hostname with port if non-standard
-    let host_pattern = if port == 22 {
-        hostname.to_string()
-    } else {
-        format!("[{}]:{}", hostname, port)
-    };
+    let names = host_names(hostname, port, ip);
+    let host_pattern = host_pattern(hostname, port);
 
-    log::debug!("Looking for host pattern: {}", host_pattern);
+    log::debug!("Looking for host names: {}", names.join(", "));
     log::debug!("Server key type: {}", server_key.name());
     log::debug!("Server key fingerprint: {}", server_key.fingerprint());
 
-    let mut line_num = 0;
-    for line in contents.lines() {
-        line_num += 1;
+    let presented = fingerprint(server_key);
+    let status = check_files(files, &names, &presented, |line_num, key_type, key_data| {
+        if key_type_of(key_type) != key_type_of(server_key.name()) {
+            log::debug!("Line {}: Key of another type: {}", line_num, key_type);
+            return KeyComparison::OtherType;
+        }
+        // Try to parse the key and compare
+        match parse_public_key(key_type, key_data) {
+            Ok(known_key) => {
+                log::debug!("Line {}: Known key type: {}", line_num, known_key.name());
+                log::debug!(
+                    "Line {}: Known key fingerprint: {}",
+                    line_num,
+                    known_key.fingerprint()
+                );
+                if keys_match(server_key, &known_key) {
+                    KeyComparison::Same
+                } else {
+                    KeyComparison::Different(fingerprint(&known_key))
+                }
+            }
+            Err(e) => {
+                log::debug!("Line {}: Failed to parse known key: {}", line_num, e);
+                KeyComparison::OtherType
+            }
+        }
+    })?;
+
+    match &status {
+        HostKeyStatus::Verified | HostKeyStatus::Revoked | HostKeyStatus::CertAuthority { .. } => {}
+        HostKeyStatus::UnknownHost => log::warn!(
+            "No matching host key found in known_hosts for {}",
+            host_pattern
+        ),
+        HostKeyStatus::KeyMismatch {
+            expected_fingerprints,
+            presented,
+            file,
+            line,
+        } => log::error!(
+            "@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@\n\
+             @    WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!     @\n\
+             @@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@\n\
+             IT IS POSSIBLE THAT SOMEONE IS DOING SOMETHING NASTY!\n\
+             Someone could be eavesdropping on you right now (man-in-the-middle attack)!\n\
+             It is also possible that the host key for {} has just been changed.\n\
+             The server sent {}.\n\
+             Offending entry in {}:{} expects {}.",
+            host_pattern,
+            presented,
+            file.display(),
+            line,
+            expected_fingerprints.join(", ")
+        ),
+    }
+    Ok(status)
+}
+
+/// Look the host up in the known_hosts `files` under any of its `names`: the
+/// first file with an entry for it decides, unless any marks the key `@revoked`; missing files are
+/// skipped
+fn check_files(
+    files: &[PathBuf],
+    names: &[String],
+    presented: &str,
+    mut compare: impl FnMut(usize, &str, &str) -> KeyComparison,
+) -> Result<HostKeyStatus> {
+    let mut decided = HostKeyStatus::UnknownHost;
+    for path in files {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("Known hosts file does not exist: {}", path.display());
+                continue;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read known_hosts file: {}", path.display())
+                })
+            }
+        };
+        log::debug!("Known hosts file: {}", path.display());
+        // A revoked key fails even when an earlier file lists it
+        match check_entries(&contents, path, names, presented, &mut compare) {
+            HostKeyStatus::Revoked => {
+                log::error!(
+                    "Host key of {} is marked @revoked in {}",
+                    names.join(", "),
+                    path.display()
+                );
+                return Ok(HostKeyStatus::Revoked);
+            }
+            HostKeyStatus::UnknownHost => {}
+            status if decided == HostKeyStatus::UnknownHost => decided = status,
+            _ => {}
+        }
+    }
+    Ok(decided)
+}
+
+/// Look the host up in known_hosts `contents`, read from `path`, under any of
+/// its `names`; `compare` says whether an entry's key type and base64 data are
+/// the server's key, whose fingerprint is `presented`
+///
+/// Lines may start with a marker: `@revoked` keys are rejected wherever they
+/// appear, and `@cert-authority` keys only vouch for host certificates, which
+/// russh never negotiates. A host listed only under a certificate authority
+/// is reported as such rather than as unknown, so no policy trusts its key on
+/// first use.
+fn check_entries(
+    contents: &str,
+    path: &Path,
+    names: &[String],
+    presented: &str,
+    mut compare: impl FnMut(usize, &str, &str) -> KeyComparison,
+) -> HostKeyStatus {
+    let mut status = HostKeyStatus::UnknownHost;
+    let mut certified = None;
+    for (index, line) in contents.lines().enumerate() {
+        let line_num = index + 1;
         let line = line.trim();
 
         // Skip empty lines and comments
@@ -47,8 +228,12 @@ pub fn verify_host_key(hostname: &str, port: u16, server_key: &PublicKey) -> Res
             continue;
         }
 
-        // Parse the line
+        // Parse the line, after its marker if it has one
         let parts: Vec<&str> = line.split_whitespace().collect();
+        let (marker, parts) = match parts.split_first() {
+            Some((first, rest)) if first.starts_with('@') => (Some(*first), rest),
+            _ => (None, &parts[..]),
+        };
         if parts.len() < 3 {
             log::debug!("Line {}: Invalid format (< 3 parts)", line_num);
             continue; // Invalid line
@@ -62,58 +247,263 @@ pub fn verify_host_key(hostname: &str, port: u16, server_key: &PublicKey) -> Res
         let matches = if host_part.starts_with("|1|") {
             // Hashed format: |1|salt|hash
             log::debug!("Line {}: Checking hashed host entry", line_num);
-            match check_hashed_host(&host_pattern, host_part) {
-                Ok(m) => {
-                    log::debug!("Line {}: Hashed host match: {}", line_num, m);
-                    m
-                }
-                Err(e) => {
-                    log::debug!("Line {}: Error checking hashed host: {}", line_num, e);
-                    false
-                }
-            }
+            names
+                .iter()
+                .any(|name| match check_hashed_host(name, host_part) {
+                    Ok(m) => {
+                        log::debug!("Line {}: Hashed host match for {}: {}", line_num, name, m);
+                        m
+                    }
+                    Err(e) => {
+                        log::debug!("Line {}: Error checking hashed host: {}", line_num, e);
+                        false
+                    }
+                })
         } else {
             // Plaintext format: hostname or hostname,hostname2 or pattern
             log::debug!("Line {}: Checking plaintext host: {}", line_num, host_part);
-            let m = check_plaintext_host(&host_pattern, host_part);
+            let m = names
+                .iter()
+                .any(|name| check_plaintext_host(name, host_part));
             log::debug!("Line {}: Plaintext host match: {}", line_num, m);
             m
         };
 
-        if matches {
-            log::debug!(
-                "Line {}: Host matched! Checking key type: {}",
-                line_num,
-                key_type
-            );
-            // Try to parse the key and compare
-            match parse_public_key(key_type, key_data) {
-                Ok(known_key) => {
-                    log::debug!("Line {}: Known key type: {}", line_num, known_key.name());
-                    log::debug!(
-                        "Line {}: Known key fingerprint: {}",
-                        line_num,
-                        known_key.fingerprint()
-                    );
-                    if keys_match(server_key, &known_key) {
-                        log::info!("Host key verified successfully on line {}", line_num);
-                        return Ok(true);
-                    } else {
-                        log::debug!("Line {}: Key mismatch (different fingerprints)", line_num);
+        if !matches {
+            continue;
+        }
+        log::debug!(
+            "Line {}: Host matched! Checking key type: {}",
+            line_num,
+            key_type
+        );
+        match marker {
+            None => match (&mut status, compare(line_num, key_type, key_data)) {
+                (HostKeyStatus::Verified, _) | (_, KeyComparison::OtherType) => {}
+                (_, KeyComparison::Same) => {
+                    log::info!("Host key verified successfully on line {}", line_num);
+                    status = HostKeyStatus::Verified;
+                }
+                (
+                    HostKeyStatus::KeyMismatch {
+                        expected_fingerprints,
+                        ..
+                    },
+                    KeyComparison::Different(fingerprint),
+                ) => {
+                    log::debug!("Line {}: Key mismatch (different fingerprints)", line_num);
+                    if !expected_fingerprints.contains(&fingerprint) {
+                        expected_fingerprints.push(fingerprint);
                     }
                 }
-                Err(e) => {
-                    log::debug!("Line {}: Failed to parse known key: {}", line_num, e);
+                (_, KeyComparison::Different(fingerprint)) => {
+                    log::debug!("Line {}: Key mismatch (different fingerprints)", line_num);
+                    status = HostKeyStatus::KeyMismatch {
+                        expected_fingerprints: vec![fingerprint],
+                        presented: presented.to_string(),
+                        file: path.to_path_buf(),
+                        line: line_num,
+                    };
                 }
+            },
+            Some("@revoked") => {
+                if compare(line_num, key_type, key_data) == KeyComparison::Same {
+                    log::error!(
+                        "Line {}: the server's host key is REVOKED; refusing to connect",
+                        line_num
+                    );
+                    return HostKeyStatus::Revoked;
+                }
+            }
+            Some("@cert-authority") => {
+                certified.get_or_insert(line_num);
             }
+            Some(other) => log::debug!("Line {}: Unknown marker {}", line_num, other),
         }
     }
 
-    log::warn!(
-        "No matching host key found in known_hosts for {}",
-        host_pattern
+    match certified {
+        Some(line) if status == HostKeyStatus::UnknownHost => {
+            log::error!(
+                "{}:{} trusts a certificate authority for {}, but host certificates \
+                 aren't supported",
+                path.display(),
+                line,
+                names.join(", ")
+            );
+            HostKeyStatus::CertAuthority {
+                file: path.to_path_buf(),
+                line,
+            }
+        }
+        _ => status,
+    }
+}
+
+/// Append `key` to the known_hosts file at `known_hosts_path` as the host key
+/// of `hostname`:`port`, creating the file when missing
+pub fn add_host_key(
+    known_hosts_path: &Path,
+    hostname: &str,
+    port: u16,
+    key: &PublicKey,
+) -> Result<()> {
+    let entry = known_hosts_entry(hostname, port, key.name(), &key.public_key_base64());
+    append_entry(known_hosts_path, &entry).with_context(|| {
+        format!(
+            "Failed to add host key of {} to {}",
+            host_pattern(hostname, port),
+            known_hosts_path.display()
+        )
+    })?;
+    log::info!(
+        "Added host key {} of {} to {}",
+        fingerprint(key),
+        host_pattern(hostname, port),
+        known_hosts_path.display()
     );
-    Ok(false)
+    Ok(())
+}
+
+/// Fingerprint of a key as ssh shows it, `SHA256:` and base64
+pub fn fingerprint(key: &PublicKey) -> String {
+    format!("SHA256:{}", key.fingerprint())
+}
+
+/// Names the host may be listed under: `hostname` and the `ip` connected to,
+/// each as [`host_pattern`] makes it and, on port 22, also as `[name]:22`
+fn host_names(hostname: &str, port: u16, ip: Option<IpAddr>) -> Vec<String> {
+    let mut hosts = vec![hostname.to_string()];
+    hosts.extend(ip.map(|ip| ip.to_string()).filter(|ip| ip != hostname));
+    hosts
+        .iter()
+        .flat_map(|host| {
+            let bracketed = (port == 22).then(|| format!("[{}]:22", host));
+            std::iter::once(host_pattern(host, port)).chain(bracketed)
+        })
+        .collect()
+}
+
+/// Host as known_hosts names it: bare on port 22, `[host]:port` otherwise
+pub fn host_pattern(hostname: &str, port: u16) -> String {
+    if port == 22 {
+        hostname.to_string()
+    } else {
+        format!("[{}]:{}", hostname, port)
+    }
+}
+
+/// known_hosts line for a key; RSA keys are stored as `ssh-rsa` whichever
+/// signature hash was negotiated
+fn known_hosts_entry(hostname: &str, port: u16, key_name: &str, key_base64: &str) -> String {
+    format!(
+        "{} {} {}",
+        host_pattern(hostname, port),
+        key_type_of(key_name),
+        key_base64
+    )
+}
+
+/// Append `entry` as a line of the file at `path`, creating the file and its
+/// directory (private, like ~/.ssh) when missing
+fn append_entry(path: &Path, entry: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)?;
+    }
+
+    // An entry after a last line without newline would join it
+    let needs_newline = fs::read(path)
+        .map(|contents| contents.last().is_some_and(|&byte| byte != b'\n'))
+        .unwrap_or(false);
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let separator = if needs_newline { "\n" } else { "" };
+    writeln!(file, "{}{}", separator, entry)?;
+    Ok(())
+}
+
+/// A host key missing from known_hosts, awaiting the user's decision under
+/// `host_key_policy = "ask"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingHostKey {
+    pub host: String,
+    pub port: u16,
+    pub key_type: String,
+    pub fingerprint: String,
+}
+
+/// Host keys awaiting the user's decision and the ones they accepted
+///
+/// Connecting can't wait for an answer, so under the ask policy an unknown
+/// key fails the connect and is recorded here; the plugin shows its
+/// fingerprint, and once it is accepted the next connect appends it to
+/// known_hosts.
+#[derive(Debug, Default)]
+pub struct HostKeyPrompts {
+    pending: Mutex<Vec<PendingHostKey>>,
+    accepted: Mutex<Vec<PendingHostKey>>,
+}
+
+impl HostKeyPrompts {
+    /// Record `key` as awaiting a decision, replacing any earlier key of its host
+    pub fn request(&self, key: PendingHostKey) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.retain(|p| p.host != key.host || p.port != key.port);
+        pending.push(key);
+    }
+
+    /// Keys awaiting a decision, oldest first
+    pub fn pending(&self) -> Vec<PendingHostKey> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Accept the pending keys with `fingerprint`
+    pub fn accept(&self, fingerprint: &str) -> Result<()> {
+        let accepted = self.take_pending(fingerprint)?;
+        self.accepted
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(accepted);
+        Ok(())
+    }
+
+    /// Drop the pending keys with `fingerprint`; they are asked about again on
+    /// the next connect
+    pub fn reject(&self, fingerprint: &str) -> Result<()> {
+        self.take_pending(fingerprint).map(drop)
+    }
+
+    /// Whether `key` was accepted; the acceptance is used up
+    pub fn take_accepted(&self, key: &PendingHostKey) -> bool {
+        let mut accepted = self.accepted.lock().unwrap_or_else(|e| e.into_inner());
+        let before = accepted.len();
+        accepted.retain(|a| a != key);
+        accepted.len() != before
+    }
+
+    fn take_pending(&self, fingerprint: &str) -> Result<Vec<PendingHostKey>> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let (taken, kept): (Vec<_>, Vec<_>) = pending
+            .drain(..)
+            .partition(|p| p.fingerprint == fingerprint);
+        *pending = kept;
+        if taken.is_empty() {
+            anyhow::bail!(
+                "No host key with fingerprint {} awaits confirmation",
+                fingerprint
+            );
+        }
+        Ok(taken)
+    }
 }
 
 /// Get the path to the known_hosts file
@@ -138,7 +528,7 @@ fn check_plaintext_host(hostname: &str, pattern: &str) -> bool {
 }
 
 /// Simple wildcard pattern matching
-fn pattern_match(hostname: &str, pattern: &str) -> bool {
+pub(crate) fn pattern_match(hostname: &str, pattern: &str) -> bool {
     if pattern == "*" {
         return true;
     }
@@ -235,6 +625,15 @@ fn parse_public_key(key_type: &str, key_data: &str) -> Result<PublicKey> {
         .with_context(|| format!("Failed to parse public key of type {}", key_type))
 }
 
+/// Key type of the key algorithm `name`: an RSA key is the same key whichever
+/// hash its signatures use
+fn key_type_of(name: &str) -> &str {
+    match name {
+        "rsa-sha2-256" | "rsa-sha2-512" => "ssh-rsa",
+        other => other,
+    }
+}
+
 /// Compare two public keys for equality
 fn keys_match(key1: &PublicKey, key2: &PublicKey) -> bool {
     // Compare the keys by encoding them as base64 and comparing
@@ -246,6 +645,39 @@ fn keys_match(key1: &PublicKey, key2: &PublicKey) -> bool {
 mod tests {
     use super::*;
 
+    fn names(host: &str) -> Vec<String> {
+        vec![host.to_string()]
+    }
+
+    /// Compares entries with a server ssh-ed25519 key whose base64 data is
+    /// `key`; a key's fingerprint is `SHA256:` and its data
+    fn offers(key: &'static str) -> impl FnMut(usize, &str, &str) -> KeyComparison {
+        move |_, key_type, data| {
+            if key_type != "ssh-ed25519" {
+                KeyComparison::OtherType
+            } else if data == key {
+                KeyComparison::Same
+            } else {
+                KeyComparison::Different(format!("SHA256:{}", data))
+            }
+        }
+    }
+
+    fn entries(contents: &str, names: &[String], key: &'static str) -> HostKeyStatus {
+        let presented = format!("SHA256:{}", key);
+        check_entries(
+            contents,
+            Path::new("known_hosts"),
+            names,
+            &presented,
+            offers(key),
+        )
+    }
+
+    fn search(files: &[PathBuf], names: &[String], key: &'static str) -> HostKeyStatus {
+        check_files(files, names, &format!("SHA256:{}", key), offers(key)).unwrap()
+    }
+
     #[test]
     fn test_pattern_match() {
         assert!(pattern_match("example.com", "example.com"));
@@ -268,6 +700,276 @@ mod tests {
         assert!(!check_plaintext_host("example.com", "example.org"));
     }
 
+    #[test]
+    fn test_check_entries() {
+        let contents = "# comment\n\
+                        other.com ssh-ed25519 AAAAother\n\
+                        example.com,10.0.0.1 ssh-rsa AAAAold\n\
+                        example.com ssh-ed25519 AAAAkey\n";
+
+        assert_eq!(
+            entries(contents, &names("example.com"), "AAAAkey"),
+            HostKeyStatus::Verified
+        );
+        // Every key of the server's type listed for the host, first line first
+        let mut rotated = contents.to_string();
+        rotated.push_str("example.com ssh-ed25519 AAAAnext\n");
+        assert_eq!(
+            entries(&rotated, &names("example.com"), "AAAAnew"),
+            HostKeyStatus::KeyMismatch {
+                expected_fingerprints: vec![
+                    "SHA256:AAAAkey".to_string(),
+                    "SHA256:AAAAnext".to_string()
+                ],
+                presented: "SHA256:AAAAnew".to_string(),
+                file: PathBuf::from("known_hosts"),
+                line: 4,
+            }
+        );
+        // Only keys of other types listed: the server's key is unknown, as in OpenSSH
+        assert_eq!(
+            entries(
+                "example.com ssh-rsa AAAAold\nexample.com ecdsa-sha2-nistp256 AAAAec\n",
+                &names("example.com"),
+                "AAAAkey"
+            ),
+            HostKeyStatus::UnknownHost
+        );
+        assert_eq!(
+            entries(contents, &names("[example.com]:2222"), "AAAAkey"),
+            HostKeyStatus::UnknownHost
+        );
+    }
+
+    /// Hashed with `ssh-keygen -H` from entries for db.example.com, port 22 and
+    /// 2222, and its address 10.0.0.5, in that order
+    const HASHED_DB_EXAMPLE_COM: &str = "\
+|1|/afwiXIC9gWmmudhDWWpsFq/9jk=|xBr7F8u8EZDqfaZIDSMU5bbIim0= ssh-ed25519 AAAAhost
+|1|ygbgYukKHrvDtkY32m2tC402uCc=|WpZ7ENsrhVeaHLq2RHUp8WHYR5s= ssh-ed25519 AAAAhost2222
+|1|F7TNxAjSBZwKDZeK0eh+SJWbnfQ=|M6kpHwEbHYOA4Y2e+7WJyN+PqYI= ssh-ed25519 AAAAip
+|1|M/a4vRvd1EvA0BK8gowZ3ojnQMo=|eGMdpwsH+JV+3vd/poe3RgKDRqM= ssh-ed25519 AAAAip2222
+";
+
+    #[test]
+    fn test_host_names() {
+        let ip = Some("10.0.0.5".parse().unwrap());
+        assert_eq!(
+            host_names("db.example.com", 22, ip),
+            vec![
+                "db.example.com",
+                "[db.example.com]:22",
+                "10.0.0.5",
+                "[10.0.0.5]:22"
+            ]
+        );
+        assert_eq!(
+            host_names("db.example.com", 2222, ip),
+            vec!["[db.example.com]:2222", "[10.0.0.5]:2222"]
+        );
+        assert_eq!(host_names("10.0.0.5", 2222, ip), vec!["[10.0.0.5]:2222"]);
+    }
+
+    #[test]
+    fn test_hashed_entries_by_port_and_address() {
+        let ip = Some("10.0.0.5".parse().unwrap());
+        let check = |contents: &str, port, key: &'static str| {
+            entries(contents, &host_names("db.example.com", port, ip), key)
+        };
+        assert_eq!(
+            check(HASHED_DB_EXAMPLE_COM, 22, "AAAAhost"),
+            HostKeyStatus::Verified
+        );
+        assert_eq!(
+            check(HASHED_DB_EXAMPLE_COM, 2222, "AAAAhost2222"),
+            HostKeyStatus::Verified
+        );
+
+        // Listed only under the address
+        let by_address: String = HASHED_DB_EXAMPLE_COM
+            .lines()
+            .skip(2)
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(check(&by_address, 22, "AAAAip"), HostKeyStatus::Verified);
+        assert_eq!(
+            check(&by_address, 2222, "AAAAip2222"),
+            HostKeyStatus::Verified
+        );
+        assert_eq!(
+            check(&by_address, 2200, "AAAAip2222"),
+            HostKeyStatus::UnknownHost
+        );
+
+        // `[db.example.com]:22`, as some tools write it
+        let bracketed =
+            "|1|TLP6Du7TshDEt/g3ISauHNTsl2g=|vOVssTNgy56o35NKufwCwXT1CxM= ssh-ed25519 AAAAhost\n";
+        assert_eq!(check(bracketed, 22, "AAAAhost"), HostKeyStatus::Verified);
+    }
+
+    #[test]
+    fn test_markers() {
+        let contents = "@cert-authority *.internal.example.com ssh-ed25519 AAAAca\n\
+                        db.internal.example.com ssh-ed25519 AAAAkey\n\
+                        @revoked * ssh-ed25519 AAAAstolen\n";
+
+        assert_eq!(
+            entries(contents, &names("db.internal.example.com"), "AAAAkey"),
+            HostKeyStatus::Verified
+        );
+        // Revoked, although listed as the host's key on an earlier line
+        let listed = "db.internal.example.com ssh-ed25519 AAAAstolen\n";
+        assert_eq!(
+            entries(
+                &format!("{}{}", listed, contents),
+                &names("db.internal.example.com"),
+                "AAAAstolen"
+            ),
+            HostKeyStatus::Revoked
+        );
+        // A certificate authority can't vouch for a plain key, nor leave the
+        // host to be trusted on first use
+        let certified = HostKeyStatus::CertAuthority {
+            file: PathBuf::from("known_hosts"),
+            line: 1,
+        };
+        assert_eq!(
+            entries(contents, &names("web.internal.example.com"), "AAAAweb"),
+            certified
+        );
+        assert_eq!(
+            entries(contents, &names("web.internal.example.com"), "AAAAca"),
+            certified
+        );
+    }
+
+    #[test]
+    fn test_first_file_with_the_host_decides() {
+        let dir = std::env::temp_dir().join("helix-dadbod-known-hosts-files-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let custom = dir.join("custom");
+        let user = dir.join("user");
+        let global = dir.join("global");
+        fs::write(&custom, "other.com ssh-ed25519 AAAAother\n").unwrap();
+        fs::write(&user, "example.com ssh-ed25519 AAAAold\n").unwrap();
+        fs::write(&global, "example.com ssh-ed25519 AAAAkey\n").unwrap();
+
+        let files = [
+            dir.join("missing"),
+            custom.clone(),
+            user.clone(),
+            global.clone(),
+        ];
+        assert_eq!(
+            search(&files, &names("example.com"), "AAAAkey"),
+            HostKeyStatus::KeyMismatch {
+                expected_fingerprints: vec!["SHA256:AAAAold".to_string()],
+                presented: "SHA256:AAAAkey".to_string(),
+                file: user,
+                line: 1,
+            }
+        );
+        let files = [custom.clone(), global];
+        assert_eq!(
+            search(&files, &names("example.com"), "AAAAkey"),
+            HostKeyStatus::Verified
+        );
+        assert_eq!(
+            search(
+                std::slice::from_ref(&custom),
+                &names("example.com"),
+                "AAAAkey"
+            ),
+            HostKeyStatus::UnknownHost
+        );
+
+        // A later file's @revoked wins over an earlier match
+        let revoked = dir.join("revoked");
+        fs::write(&revoked, "@revoked example.com ssh-ed25519 AAAAkey\n").unwrap();
+        let files = [dir.join("global"), custom, revoked];
+        assert_eq!(
+            search(&files, &names("example.com"), "AAAAkey"),
+            HostKeyStatus::Revoked
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_known_hosts_files_order() {
+        let files = known_hosts_files(&[PathBuf::from("/work/known_hosts")]);
+        assert_eq!(files.first(), Some(&PathBuf::from("/work/known_hosts")));
+        assert_eq!(files.last(), Some(&PathBuf::from(GLOBAL_KNOWN_HOSTS)));
+        if let Ok(user) = get_known_hosts_path() {
+            assert_eq!(files[1], user);
+            assert_eq!(files[2], user.with_file_name("known_hosts2"));
+        }
+    }
+
+    #[test]
+    fn test_known_hosts_entry() {
+        assert_eq!(
+            known_hosts_entry("example.com", 22, "ssh-ed25519", "AAAAkey"),
+            "example.com ssh-ed25519 AAAAkey"
+        );
+        assert_eq!(
+            known_hosts_entry("example.com", 2222, "rsa-sha2-512", "AAAAkey"),
+            "[example.com]:2222 ssh-rsa AAAAkey"
+        );
+    }
+
+    #[test]
+    fn test_append_entry() {
+        let dir = std::env::temp_dir().join("helix-dadbod-known-hosts-test");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(".ssh").join("known_hosts");
+
+        append_entry(&path, "a.com ssh-ed25519 AAAAa").unwrap();
+        fs::write(&path, "a.com ssh-ed25519 AAAAa").unwrap();
+        append_entry(&path, "b.com ssh-ed25519 AAAAb").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "a.com ssh-ed25519 AAAAa\nb.com ssh-ed25519 AAAAb\n"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path.parent().unwrap())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_host_key_prompts() {
+        let offered = |host: &str, fingerprint: &str| PendingHostKey {
+            host: host.to_string(),
+            port: 22,
+            key_type: "ssh-ed25519".to_string(),
+            fingerprint: fingerprint.to_string(),
+        };
+        let prompts = HostKeyPrompts::default();
+        prompts.request(offered("a.com", "SHA256:old"));
+        prompts.request(offered("a.com", "SHA256:a"));
+        prompts.request(offered("b.com", "SHA256:b"));
+        assert_eq!(
+            prompts.pending(),
+            vec![offered("a.com", "SHA256:a"), offered("b.com", "SHA256:b")]
+        );
+
+        assert!(prompts.accept("SHA256:old").is_err());
+        prompts.accept("SHA256:a").unwrap();
+        prompts.reject("SHA256:b").unwrap();
+        assert!(prompts.pending().is_empty());
+
+        assert!(!prompts.take_accepted(&offered("b.com", "SHA256:b")));
+        assert!(!prompts.take_accepted(&offered("a.com", "SHA256:other")));
+        assert!(prompts.take_accepted(&offered("a.com", "SHA256:a")));
+        assert!(!prompts.take_accepted(&offered("a.com", "SHA256:a")));
+    }
+
     #[test]
     fn test_non_standard_port_format() {
         // Test that non-standard ports use bracket notation
@@ -1,20 +1,57 @@
+pub mod client;
+pub mod command_tag;
 pub mod config;
+pub mod confirmation;
 pub mod connection;
+pub mod copy;
+pub mod deadline;
+pub mod display;
+pub mod execution;
+pub mod explain;
+pub mod history;
+pub mod jobs;
+pub mod keyring_store;
 pub mod known_hosts;
 pub mod meta_commands;
+pub mod mysql;
+pub mod notice;
+pub mod objects;
+pub mod output;
+pub mod output_file;
+pub mod pg_env;
+pub mod pg_url;
+pub mod pgpass;
+pub mod preflight;
+pub mod prompts;
+pub mod result_stream;
+pub mod run_report;
+pub mod schema;
+pub mod secret_cmd;
+pub mod snippets;
+pub mod sql;
+pub mod sqlite;
 pub mod ssh_config;
+pub mod templates;
+pub mod timings;
+pub mod tls;
+pub mod transaction;
 pub mod tunnel;
+pub mod value;
+pub mod version;
+pub mod watch;
+pub mod watcher;
 pub mod workspace;
 
 // FFI module for Steel integration
 pub mod ffi;
 
 use anyhow::Result;
-use config::SqlConfig;
+use config::{ConnectionSummary, SqlConfig, TunnelOverrides};
 use connection::ConnectionManager;
+use deadline::FfiCall;
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 pub use workspace::Workspace;
 
 // FFI-specific imports
@@ -25,7 +62,16 @@ use std::fs;
 
 /// Main entry point for helix-dadbod library
 pub struct Dadbod {
-    manager: Arc<Mutex<ConnectionManager>>,
+    // ConnectionManager locks per connection internally, so a running query
+    // doesn't block calls on other connections or busy checks
+    manager: Arc<ConnectionManager>,
+    /// Config file this instance was created from, for version reports
+    config_path: Option<PathBuf>,
+    /// Deadlines for the blocking wrappers (`ffi_call_timeout_ms` and its
+    /// overrides)
+    deadlines: deadline::Deadlines,
+    /// Executions started by execute_query_async
+    jobs: Arc<jobs::JobRegistry>,
 }
 
 impl Dadbod {
@@ -50,62 +96,215 @@ impl Dadbod {
 
     /// Create a new Dadbod instance from a config
     pub fn from_config(config: SqlConfig) -> Self {
+        let config_path = config.loaded_from.clone();
+        let deadlines = deadline::Deadlines::from_config(&config);
         let manager = ConnectionManager::new(config);
         Self {
-            manager: Arc::new(Mutex::new(manager)),
+            manager: Arc::new(manager),
+            config_path,
+            deadlines,
+            jobs: Arc::default(),
         }
     }
 
+    /// Version, supported features and the loaded config, for bug reports
+    pub fn version_info(&self) -> version::VersionInfo {
+        version::VersionInfo::collect(self.config_path.as_deref())
+    }
+
     /// List all available connection names
     pub async fn list_connections(&self) -> Vec<String> {
-        let manager = self.manager.lock().await;
-        manager
+        self.manager
             .list_connections()
             .iter()
             .map(|s| s.to_string())
             .collect()
     }
 
+    /// List all available connections with their target details
+    /// Connections synthesized from PG* environment variables are flagged `from_env`
+    pub async fn list_connections_detailed(&self) -> Vec<ConnectionSummary> {
+        self.manager.list_connections_detailed()
+    }
+
     /// Connect to a database by name, returns workspace info
     pub async fn connect(&self, name: &str) -> Result<Workspace> {
-        let manager = self.manager.lock().await;
-        manager.get_or_create_connection(name).await
+        self.manager.get_or_create_connection(name).await
+    }
+
+    /// Connect to a database by name with session-only SSH tunnel overrides
+    /// The overrides are never persisted and are dropped when the connection closes
+    pub async fn connect_with_overrides(
+        &self,
+        name: &str,
+        overrides: TunnelOverrides,
+    ) -> Result<Workspace> {
+        self.manager
+            .get_or_create_connection_with_overrides(name, Some(overrides))
+            .await
     }
 
     /// Test a connection by name
     pub async fn test_connection(&self, name: &str) -> Result<String> {
-        let manager = self.manager.lock().await;
-        manager.test_connection(name).await
+        self.manager.test_connection(name).await
+    }
+
+    /// Store the password of a connection using password_keyring in the OS keyring
+    pub async fn store_password(&self, name: &str, secret: &str) -> Result<()> {
+        self.manager.store_password(name, secret).await
     }
 
     /// Close a specific connection
     pub async fn close_connection(&self, name: &str) -> Result<()> {
-        let manager = self.manager.lock().await;
-        manager.close_connection(name).await
+        self.manager.close_connection(name).await
     }
 
     /// Close all connections and tunnels
     pub async fn close_all(&self) -> Result<()> {
-        let manager = self.manager.lock().await;
-        manager.close_all().await
+        self.manager.close_all().await
     }
 
     /// Execute SQL query from workspace query.sql file
     pub async fn execute_query(&self, name: &str) -> Result<()> {
-        let manager = self.manager.lock().await;
-        manager.execute_query(name).await
+        self.manager.execute_query(name).await
+    }
+
+    /// Execute numbered workspace buffer `index`; 1 is the query.sql file
+    pub async fn execute_query_buffer(&self, name: &str, index: usize) -> Result<()> {
+        self.manager.execute_query_buffer(name, index).await
+    }
+
+    /// Execute the SQL file at `path`, anywhere on disk, like the workspace
+    /// query.sql file
+    pub async fn execute_file(&self, name: &str, path: &str) -> Result<()> {
+        self.manager.execute_file(name, path).await
+    }
+
+    /// Execute `sql` like the workspace query.sql file, leaving the file as is
+    pub async fn execute_sql(&self, name: &str, sql: &str) -> Result<()> {
+        self.manager.execute_sql(name, sql).await
+    }
+
+    /// Start executing the workspace query on the global runtime and return at
+    /// once with a job id for query_status; results go to results.dbout as usual
+    pub fn execute_query_async(&self, name: &str) -> u64 {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.jobs.spawn(GLOBAL_DADBOD.0.handle(), async move {
+            manager.execute_query(&name).await
+        })
+    }
+
+    /// State of a job started by execute_query_async; a finished job's status
+    /// can be read once
+    pub fn query_status(&self, job_id: u64) -> Option<jobs::JobStatus> {
+        self.jobs.status(job_id)
+    }
+
+    /// Fetch a single row by primary key into results.dbout (expanded format)
+    pub async fn peek(&self, name: &str, table: &str, pk_values: &[String]) -> Result<()> {
+        self.manager.peek(name, table, pk_values).await
+    }
+
+    /// Objects (tables, views, functions) listed by the last result, in row order
+    pub async fn last_objects(&self, name: &str) -> Result<Vec<objects::ObjectRef>> {
+        self.manager.last_objects(name).await
+    }
+
+    /// Cancel the query running on the connection, if any
+    pub async fn cancel_query(&self, name: &str) -> Result<execution::CancelOutcome> {
+        self.manager.cancel_query(name).await
+    }
+
+    /// Whether a query is currently executing on the connection
+    pub async fn query_running(&self, name: &str) -> bool {
+        self.manager.query_running(name).await
+    }
+
+    /// Directory holding the SQL files and results.dbout (`workspace_dir`)
+    pub fn workspace_dir(&self) -> Result<PathBuf> {
+        self.manager.workspace_dir()
+    }
+
+    /// Most recent `limit` statements executed on connection `name` (0 for
+    /// all), newest first
+    pub fn query_history(&self, name: &str, limit: usize) -> Result<Vec<history::HistoryEntry>> {
+        self.manager.query_history(name, limit)
+    }
+
+    /// State of the connection's SSH tunnel and its traffic, None without one
+    pub fn tunnel_status(&self, name: &str) -> Option<tunnel::TunnelStatus> {
+        self.manager.tunnel_status(name)
+    }
+
+    /// SSH host keys missing from known_hosts that await the user's decision
+    /// (`host_key_policy = "ask"`)
+    pub fn pending_host_keys(&self) -> Vec<known_hosts::PendingHostKey> {
+        self.manager.host_keys().pending()
+    }
+
+    /// Accept the pending host keys with `fingerprint`; the next connect
+    /// appends them to known_hosts
+    pub fn accept_host_key(&self, fingerprint: &str) -> Result<()> {
+        self.manager.host_keys().accept(fingerprint)
+    }
+
+    /// Turn down the pending host keys with `fingerprint`
+    pub fn reject_host_key(&self, fingerprint: &str) -> Result<()> {
+        self.manager.host_keys().reject(fingerprint)
+    }
+
+    /// Outcome of the last execution, as written to last_run.json
+    pub async fn last_run(&self, name: &str) -> Result<Option<run_report::RunReport>> {
+        self.manager.last_run(name).await
+    }
+
+    /// \prompt values the last execution is waiting for
+    pub async fn pending_prompts(&self, name: &str) -> Result<Vec<prompts::Prompt>> {
+        self.manager.pending_prompts(name).await
+    }
+
+    /// Answer a \prompt; execute again to run the query with it
+    pub async fn provide_prompt(&self, name: &str, variable: &str, value: &str) -> Result<()> {
+        self.manager.provide_prompt(name, variable, value).await
+    }
+
+    /// Save the connection's SQL file as snippet `snippet`
+    pub async fn save_snippet(&self, name: &str, snippet: &str) -> Result<()> {
+        self.manager.save_snippet(name, snippet).await
+    }
+
+    /// Replace the connection's SQL file with snippet `snippet`; unsaved SQL
+    /// is only replaced with `force`
+    pub async fn load_snippet(&self, name: &str, snippet: &str, force: bool) -> Result<()> {
+        self.manager.load_snippet(name, snippet, force).await
+    }
+
+    /// Names of the connection's snippets
+    pub async fn list_snippets(&self, name: &str) -> Result<Vec<String>> {
+        self.manager.list_snippets(name).await
     }
 
     /// Get information about an active connection
     pub async fn get_connection_info(&self, name: &str) -> Option<connection::ConnectionInfo> {
-        let manager = self.manager.lock().await;
-        manager.get_connection_info(name).await
+        self.manager.get_connection_info(name).await
     }
 
     // =========================================================================
     // Blocking wrappers for FFI
     // =========================================================================
 
+    /// Run `future` on the global runtime for at most the deadline of `call`
+    /// After that `call` is detached or cancelled (see `deadline::FfiCall`)
+    fn block_on_with_deadline<T, F>(&self, call: FfiCall, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let rt = &GLOBAL_DADBOD.0;
+        rt.block_on(deadline::run(call, self.deadlines.for_call(call), future))
+    }
+
     /// Synchronous wrapper for list_connections (for FFI)
     /// Uses the global runtime to execute async code
     pub fn list_connections_blocking(&self) -> Vec<String> {
@@ -114,33 +313,216 @@ impl Dadbod {
         rt.block_on(self.list_connections())
     }
 
+    /// Synchronous wrapper for list_connections_detailed (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn list_connections_detailed_blocking(&self) -> Vec<ConnectionSummary> {
+        let rt = &GLOBAL_DADBOD.0;
+        rt.block_on(self.list_connections_detailed())
+    }
+
     /// Synchronous wrapper for connect (for FFI)
     /// Uses the global runtime to execute async code
     pub fn connect_blocking(&self, name: &str) -> Result<Workspace> {
-        let rt = &GLOBAL_DADBOD.0;
-        rt.block_on(self.connect(name))
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::Connect, async move {
+            manager.get_or_create_connection(&name).await
+        })
+    }
+
+    /// Synchronous wrapper for connect_with_overrides (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn connect_with_overrides_blocking(
+        &self,
+        name: &str,
+        overrides: TunnelOverrides,
+    ) -> Result<Workspace> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::Connect, async move {
+            manager
+                .get_or_create_connection_with_overrides(&name, Some(overrides))
+                .await
+        })
     }
 
     /// Synchronous wrapper for execute_query (for FFI)
     /// Uses the global runtime to execute async code
     pub fn execute_query_blocking(&self, name: &str) -> Result<()> {
         log::debug!("execute_query_blocking called for '{}'", name);
-        let rt = &GLOBAL_DADBOD.0;
-        rt.block_on(self.execute_query(name))
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::Execute, async move {
+            manager.execute_query(&name).await
+        })
+    }
+
+    /// Synchronous wrapper for execute_query_buffer (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn execute_query_buffer_blocking(&self, name: &str, index: usize) -> Result<()> {
+        log::debug!(
+            "execute_query_buffer_blocking called for '{}' buffer {}",
+            name,
+            index
+        );
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::Execute, async move {
+            manager.execute_query_buffer(&name, index).await
+        })
+    }
+
+    /// Synchronous wrapper for execute_file (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn execute_file_blocking(&self, name: &str, path: &str) -> Result<()> {
+        log::debug!("execute_file_blocking called for '{}': {}", name, path);
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        let path = path.to_string();
+        self.block_on_with_deadline(FfiCall::Execute, async move {
+            manager.execute_file(&name, &path).await
+        })
+    }
+
+    /// Synchronous wrapper for execute_sql (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn execute_sql_blocking(&self, name: &str, sql: &str) -> Result<()> {
+        log::debug!("execute_sql_blocking called for '{}'", name);
+        let manager = Arc::clone(&self.manager);
+        let (name, sql) = (name.to_string(), sql.to_string());
+        self.block_on_with_deadline(FfiCall::Execute, async move {
+            manager.execute_sql(&name, &sql).await
+        })
+    }
+
+    /// Synchronous wrapper for peek (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn peek_blocking(&self, name: &str, table: &str, pk_values: &[String]) -> Result<()> {
+        let manager = Arc::clone(&self.manager);
+        let (name, table, pk_values) = (name.to_string(), table.to_string(), pk_values.to_vec());
+        self.block_on_with_deadline(FfiCall::Peek, async move {
+            manager.peek(&name, &table, &pk_values).await
+        })
+    }
+
+    /// Synchronous wrapper for last_objects (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn last_objects_blocking(&self, name: &str) -> Result<Vec<objects::ObjectRef>> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::LastObjects, async move {
+            manager.last_objects(&name).await
+        })
     }
 
     /// Synchronous wrapper for test_connection (for FFI)
     /// Uses the global runtime to execute async code
     pub fn test_connection_blocking(&self, name: &str) -> Result<String> {
-        let rt = &GLOBAL_DADBOD.0;
-        rt.block_on(self.test_connection(name))
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::TestConnection, async move {
+            manager.test_connection(&name).await
+        })
     }
 
     /// Synchronous wrapper for close_connection (for FFI)
     /// Uses the global runtime to execute async code
     pub fn close_connection_blocking(&self, name: &str) -> Result<()> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::CloseConnection, async move {
+            manager.close_connection(&name).await
+        })
+    }
+
+    /// Synchronous wrapper for cancel_query (for FFI)
+    /// Uses the global runtime to execute async code; the cancel request
+    /// itself gives up after `CANCEL_TIMEOUT`
+    pub fn cancel_query_blocking(&self, name: &str) -> Result<execution::CancelOutcome> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(
+            FfiCall::Cancel,
+            async move { manager.cancel_query(&name).await },
+        )
+    }
+
+    /// Synchronous wrapper for query_running (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn query_running_blocking(&self, name: &str) -> bool {
         let rt = &GLOBAL_DADBOD.0;
-        rt.block_on(self.close_connection(name))
+        rt.block_on(self.query_running(name))
+    }
+
+    /// Synchronous wrapper for last_run (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn last_run_blocking(&self, name: &str) -> Result<Option<run_report::RunReport>> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(
+            FfiCall::LastRun,
+            async move { manager.last_run(&name).await },
+        )
+    }
+
+    /// Synchronous wrapper for pending_prompts (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn pending_prompts_blocking(&self, name: &str) -> Result<Vec<prompts::Prompt>> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::PendingPrompts, async move {
+            manager.pending_prompts(&name).await
+        })
+    }
+
+    /// Synchronous wrapper for provide_prompt (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn provide_prompt_blocking(&self, name: &str, variable: &str, value: &str) -> Result<()> {
+        let manager = Arc::clone(&self.manager);
+        let (name, variable, value) = (name.to_string(), variable.to_string(), value.to_string());
+        self.block_on_with_deadline(FfiCall::ProvidePrompt, async move {
+            manager.provide_prompt(&name, &variable, &value).await
+        })
+    }
+
+    /// Synchronous wrapper for save_snippet (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn save_snippet_blocking(&self, name: &str, snippet: &str) -> Result<()> {
+        let manager = Arc::clone(&self.manager);
+        let (name, snippet) = (name.to_string(), snippet.to_string());
+        self.block_on_with_deadline(FfiCall::Snippet, async move {
+            manager.save_snippet(&name, &snippet).await
+        })
+    }
+
+    /// Synchronous wrapper for load_snippet (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn load_snippet_blocking(&self, name: &str, snippet: &str, force: bool) -> Result<()> {
+        let manager = Arc::clone(&self.manager);
+        let (name, snippet) = (name.to_string(), snippet.to_string());
+        self.block_on_with_deadline(FfiCall::Snippet, async move {
+            manager.load_snippet(&name, &snippet, force).await
+        })
+    }
+
+    /// Synchronous wrapper for list_snippets (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn list_snippets_blocking(&self, name: &str) -> Result<Vec<String>> {
+        let manager = Arc::clone(&self.manager);
+        let name = name.to_string();
+        self.block_on_with_deadline(FfiCall::Snippet, async move {
+            manager.list_snippets(&name).await
+        })
+    }
+
+    /// Synchronous wrapper for store_password (for FFI)
+    /// Uses the global runtime to execute async code
+    pub fn store_password_blocking(&self, name: &str, secret: &str) -> Result<()> {
+        let manager = Arc::clone(&self.manager);
+        let (name, secret) = (name.to_string(), secret.to_string());
+        self.block_on_with_deadline(FfiCall::StorePassword, async move {
+            manager.store_password(&name, &secret).await
+        })
     }
 
     /// Synchronous wrapper for get_connection_info (for FFI)
@@ -210,12 +592,14 @@ static GLOBAL_DADBOD: Lazy<(tokio::runtime::Runtime, Option<Dadbod>, Option<Stri
 
                     // Create Dadbod instance from config
                     let db = Dadbod::from_config(config);
+                    log::info!("{}", db.version_info().banner());
                     log::info!("helix-dadbod initialized successfully");
                     (Some(db), None)
                 }
                 Err(e) => {
                     // Initialize logging with default level (info) on error
                     init_logging("info");
+                    log::info!("{}", version::VersionInfo::collect(None).banner());
                     let error_msg = format!("Failed to load database config: {}", e);
                     log::error!("{}", error_msg);
                     log::error!("Check ~/.config/helix-dadbod/config.toml for syntax errors");
@@ -244,15 +628,13 @@ pub struct WorkspacePaths {
     pub path: String,
     pub sql_file: String,
     pub dbout_file: String,
+    /// SQL files of the numbered buffers that exist, sql_file (buffer 1) first
+    pub buffers: Vec<String>,
 }
 
 impl From<Workspace> for WorkspacePaths {
     fn from(ws: Workspace) -> Self {
-        Self {
-            path: ws.path.display().to_string(),
-            sql_file: ws.sql_file.display().to_string(),
-            dbout_file: ws.dbout_file.display().to_string(),
-        }
+        Self::from(&ws)
     }
 }
 
@@ -262,6 +644,11 @@ impl From<&Workspace> for WorkspacePaths {
             path: ws.path.display().to_string(),
             sql_file: ws.sql_file.display().to_string(),
             dbout_file: ws.dbout_file.display().to_string(),
+            buffers: ws
+                .buffers()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
         }
     }
 }
@@ -278,15 +665,61 @@ mod tests {
         let config = SqlConfig {
             log_level: "error".to_string(),
             skip_host_key_verification: false,
+            host_key_policy: Default::default(),
+            ssh_keepalive_secs: 30,
+            ssh_reconnect_attempts: 5,
+            ssh_connect_timeout_secs: 10,
+            verbose_connect: false,
+            queue_queries: true,
+            results_fallback_file: None,
+            workspace_dir: None,
+            query_template: None,
+            keep_result_history: false,
+            history_max_age_days: 30,
+            history_max_mb: 50,
+            page_size: None,
+            table_cell_budget: 20_000,
+            ffi_call_timeout_ms: 15_000,
+            ffi_connect_timeout_ms: 30_000,
+            ffi_execute_timeout_ms: 0,
+            null_display: None,
+            max_rows: 1000,
+            max_output_bytes: 10 * 1024 * 1024,
+            max_execute_file_bytes: 1024 * 1024,
+            copy_overwrite: false,
+            loaded_from: None,
             connections: vec![config::Connection {
                 name: "test_db".to_string(),
                 db_type: "postgres".to_string(),
                 host: "localhost".to_string(),
-                port: 5432,
+                port: Some(5432),
                 database: "test".to_string(),
                 username: "test".to_string(),
                 password: Some("test".to_string()),
+                password_cmd: None,
+                password_keyring: false,
+                keyring_service: None,
+                keyring_user: None,
+                url: None,
                 ssh_tunnel: None,
+                remote_socket: None,
+                auto_execute_on_save: false,
+                sslmode: None,
+                ssl_cert: None,
+                ssl_key: None,
+                ssl_key_password: None,
+                ssl_key_password_cmd: None,
+                ssl_root_cert: None,
+                output: None,
+                meta_commands: true,
+                dialect: None,
+                search_path: None,
+                session_settings: Default::default(),
+                application_name: "helix-dadbod".to_string(),
+                connect_timeout_secs: 10,
+                tcp_keepalive_secs: None,
+                create_if_missing: false,
+                from_env: false,
             }],
         };
 
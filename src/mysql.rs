@@ -0,0 +1,228 @@
+//! MySQL connections
+//!
+//! MySQL databases are reached like PostgreSQL ones (directly or through the
+//! SSH tunnel manager) and their results are written to results.dbout in the
+//! same format. Meta-commands are not translated for MySQL.
+
+use crate::client::QueryRows;
+use crate::config::Connection;
+use crate::run_report::{RunError, RunStatus};
+use crate::value::CellValue;
+use anyhow::{Context, Result};
+use mysql_async::prelude::Queryable;
+use mysql_async::{Conn, OptsBuilder, Row, Value};
+
+/// `type` values in config.toml that select MySQL
+pub const MYSQL_DB_TYPES: &[&str] = &["mysql", "mariadb"];
+
+/// `DatabaseClient::backend` of MySQL connections
+pub const BACKEND: &str = "MySQL";
+
+/// Connect to `conn`'s database at `host:port` (the local tunnel port when
+/// tunneled)
+pub async fn connect(conn: &Connection, host: &str, port: u16) -> Result<Conn> {
+    let opts = OptsBuilder::default()
+        .ip_or_hostname(host)
+        .tcp_port(port)
+        .user(Some(&conn.username))
+        .pass(conn.password.as_ref())
+        .db_name(Some(&conn.database));
+
+    Conn::new(opts)
+        .await
+        .with_context(|| format!("Failed to connect to database '{}'", conn.name))
+}
+
+/// Server version string, e.g. `8.0.36`
+pub async fn server_version(conn: &mut Conn) -> Result<String> {
+    let version: Option<String> = conn
+        .query_first("SELECT version()")
+        .await
+        .context("Failed to execute test query")?;
+    version.context("SELECT version() returned no rows")
+}
+
+/// Run `sql` and return the column names and rows of its first result set
+///
+/// Statements without a result set (INSERT, UPDATE, ...) return no columns and
+/// the affected row count; queries return no count.
+pub async fn query(
+    conn: &mut Conn,
+    sql: &str,
+    max_rows: Option<usize>,
+) -> mysql_async::Result<QueryRows> {
+    let mut result = conn.query_iter(sql).await?;
+    let header: Vec<String> = result
+        .columns()
+        .map(|columns| columns.iter().map(|c| c.name_str().to_string()).collect())
+        .unwrap_or_default();
+
+    let mut rows = Vec::new();
+    let mut truncated = false;
+    while let Some(row) = result.next().await? {
+        if max_rows.is_some_and(|max_rows| rows.len() >= max_rows) {
+            truncated = true;
+            break;
+        }
+        rows.push(row_to_cells(&row));
+    }
+    let affected = header.is_empty().then(|| result.affected_rows());
+    // Rows past max_rows and remaining result sets of a multi-statement query
+    // are read off the connection and discarded
+    result.drop_result().await?;
+
+    Ok(QueryRows {
+        header,
+        rows,
+        truncated,
+        affected,
+    })
+}
+
+/// What the mysql client prints for a statement without a result set, e.g.
+/// `Query OK, 3 rows affected`
+pub fn affected_tag(affected: u64) -> String {
+    let rows = if affected == 1 { "row" } else { "rows" };
+    format!("Query OK, {} {} affected", affected, rows)
+}
+
+fn row_to_cells(row: &Row) -> Vec<CellValue> {
+    (0..row.len())
+        .map(|i| match row.as_ref(i) {
+            Some(value) => value_to_cell(value),
+            None => CellValue::Null,
+        })
+        .collect()
+}
+
+/// Convert a MySQL value to a `CellValue`
+///
+/// The text protocol returns every non-NULL value as bytes, so query results
+/// are text; typed values come from the binary protocol.
+pub fn value_to_cell(value: &Value) -> CellValue {
+    match value {
+        Value::NULL => CellValue::Null,
+        Value::Bytes(bytes) => CellValue::Text(match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => format!("\\x{}", hex::encode(bytes)),
+        }),
+        Value::Int(v) => CellValue::Int(*v),
+        Value::UInt(v) => match i64::try_from(*v) {
+            Ok(v) => CellValue::Int(v),
+            Err(_) => CellValue::Numeric(v.to_string()),
+        },
+        Value::Float(v) => CellValue::Float(f64::from(*v)),
+        Value::Double(v) => CellValue::Float(*v),
+        Value::Date(year, month, day, 0, 0, 0, 0) => {
+            CellValue::Text(format!("{:04}-{:02}-{:02}", year, month, day))
+        }
+        Value::Date(year, month, day, hour, minute, second, micros) => CellValue::Text(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}{}",
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            fraction(*micros)
+        )),
+        Value::Time(negative, days, hours, minutes, seconds, micros) => CellValue::Text(format!(
+            "{}{:02}:{:02}:{:02}{}",
+            if *negative { "-" } else { "" },
+            days * 24 + u32::from(*hours),
+            minutes,
+            seconds,
+            fraction(*micros)
+        )),
+    }
+}
+
+/// `.123456` for a non-zero microsecond part
+fn fraction(micros: u32) -> String {
+    if micros == 0 {
+        String::new()
+    } else {
+        format!(".{:06}", micros)
+    }
+}
+
+/// Message shown in results.dbout and how the failure is reported in
+/// last_run.json
+pub fn run_error(err: &mysql_async::Error) -> (RunStatus, RunError) {
+    match err {
+        mysql_async::Error::Server(server) => (
+            RunStatus::DbError,
+            RunError {
+                code: Some(server.state.clone()),
+                ..RunError::client(server.message.clone())
+            },
+        ),
+        other => (RunStatus::ClientError, RunError::client(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mysql_async::ServerError;
+
+    #[test]
+    fn test_affected_tag() {
+        assert_eq!(affected_tag(0), "Query OK, 0 rows affected");
+        assert_eq!(affected_tag(1), "Query OK, 1 row affected");
+        assert_eq!(affected_tag(3), "Query OK, 3 rows affected");
+    }
+
+    #[test]
+    fn test_value_to_cell() {
+        assert_eq!(value_to_cell(&Value::Int(-42)), CellValue::Int(-42));
+        assert_eq!(value_to_cell(&Value::NULL).display("∅"), "∅");
+        assert_eq!(
+            value_to_cell(&Value::Bytes(b"alice".to_vec())).display(""),
+            "alice"
+        );
+        assert_eq!(
+            value_to_cell(&Value::Bytes(vec![0xff, 0x00])).display(""),
+            "\\xff00"
+        );
+        assert_eq!(value_to_cell(&Value::Int(-42)).display(""), "-42");
+        assert_eq!(
+            value_to_cell(&Value::UInt(u64::MAX)).display(""),
+            "18446744073709551615"
+        );
+        assert_eq!(value_to_cell(&Value::Double(1.5)).display(""), "1.5");
+    }
+
+    #[test]
+    fn test_temporal_values() {
+        assert_eq!(
+            value_to_cell(&Value::Date(2024, 1, 2, 0, 0, 0, 0)).display(""),
+            "2024-01-02"
+        );
+        assert_eq!(
+            value_to_cell(&Value::Date(2024, 1, 2, 3, 4, 5, 0)).display(""),
+            "2024-01-02 03:04:05"
+        );
+        assert_eq!(
+            value_to_cell(&Value::Date(2024, 1, 2, 3, 4, 5, 120)).display(""),
+            "2024-01-02 03:04:05.000120"
+        );
+        assert_eq!(
+            value_to_cell(&Value::Time(true, 1, 2, 3, 4, 0)).display(""),
+            "-26:03:04"
+        );
+    }
+
+    #[test]
+    fn test_run_error() {
+        let err = mysql_async::Error::Server(ServerError {
+            code: 1146,
+            message: "Table 'app.nope' doesn't exist".to_string(),
+            state: "42S02".to_string(),
+        });
+        let (status, error) = run_error(&err);
+        assert_eq!(status, RunStatus::DbError);
+        assert_eq!(error.code.as_deref(), Some("42S02"));
+        assert_eq!(error.message, "Table 'app.nope' doesn't exist");
+    }
+}
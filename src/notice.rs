@@ -0,0 +1,114 @@
+//! NOTICE, WARNING and INFO messages the server sends while a statement runs
+//!
+//! PostgreSQL sends `RAISE NOTICE` output and warnings as asynchronous
+//! messages beside the result. The connection task forwards them to the
+//! client, and the execution writes them in a `Messages:` block below the
+//! results header and above the result, where psql prints them.
+
+use tokio_postgres::error::DbError;
+
+/// One message from the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerNotice {
+    /// NOTICE, WARNING, INFO, ...
+    pub severity: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl ServerNotice {
+    pub fn from_db_error(notice: &DbError) -> Self {
+        Self {
+            severity: notice.severity().to_string(),
+            message: notice.message().to_string(),
+            detail: notice.detail().map(str::to_string),
+            hint: notice.hint().map(str::to_string),
+        }
+    }
+
+    /// Lines as psql prints them: `NOTICE:  message`, then DETAIL and HINT
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("{}:  {}", self.severity, self.message)];
+        lines.extend(
+            self.detail
+                .iter()
+                .map(|detail| format!("DETAIL:  {}", detail)),
+        );
+        lines.extend(self.hint.iter().map(|hint| format!("HINT:  {}", hint)));
+        lines
+    }
+}
+
+/// The `Messages:` block for `notices`, followed by a blank line
+pub fn messages_block(notices: &[ServerNotice]) -> String {
+    let mut block = "Messages:\n".to_string();
+    for line in notices.iter().flat_map(ServerNotice::lines) {
+        block.push_str(&line);
+        block.push('\n');
+    }
+    block.push('\n');
+    block
+}
+
+/// `output` with `block` inserted below its `-- ` header lines, or at the top
+/// when it has none
+pub fn insert_below_header(output: &str, block: &str) -> String {
+    let mut header_end = 0;
+    for line in output.split_inclusive('\n') {
+        if !line.starts_with("-- ") {
+            break;
+        }
+        header_end += line.len();
+    }
+    let (header, body) = output.split_at(header_end);
+    match body.strip_prefix('\n') {
+        Some(body) if header_end > 0 => format!("{}\n{}{}", header, block, body),
+        _ => format!("{}{}{}", header, block, body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(severity: &str, message: &str) -> ServerNotice {
+        ServerNotice {
+            severity: severity.to_string(),
+            message: message.to_string(),
+            detail: None,
+            hint: None,
+        }
+    }
+
+    #[test]
+    fn test_messages_block() {
+        let notices = [
+            notice("NOTICE", "processed 3 rows"),
+            ServerNotice {
+                hint: Some("Use a smaller batch".to_string()),
+                ..notice("WARNING", "batch is large")
+            },
+        ];
+        assert_eq!(
+            messages_block(&notices),
+            "Messages:\n\
+             NOTICE:  processed 3 rows\n\
+             WARNING:  batch is large\n\
+             HINT:  Use a smaller batch\n\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_below_header() {
+        let block = "Messages:\nNOTICE:  hi\n\n";
+        assert_eq!(
+            insert_below_header("-- Executed at: now\n-- Rows returned: 1\n\n│1│\n", block),
+            "-- Executed at: now\n-- Rows returned: 1\n\nMessages:\nNOTICE:  hi\n\n│1│\n"
+        );
+        assert_eq!(
+            insert_below_header("UPDATE 1\n", block),
+            "Messages:\nNOTICE:  hi\n\nUPDATE 1\n"
+        );
+    }
+}
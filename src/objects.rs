@@ -0,0 +1,254 @@
+//! Database objects referenced by the last result, for "describe object under cursor"
+//!
+//! After \dt, \dv or \df the workspace gets a `last_objects.json` sidecar that
+//! maps each result row to the object it lists and the meta-command showing it.
+
+use crate::meta_commands::MetaCommand;
+use crate::sql;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// File name of the sidecar inside the workspace directory
+pub const SIDECAR_FILE: &str = "last_objects.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectKind {
+    Table,
+    View,
+    Function,
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Table => "table",
+            ObjectKind::View => "view",
+            ObjectKind::Function => "function",
+        }
+    }
+}
+
+/// Object listed on one result row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectRef {
+    pub kind: ObjectKind,
+    pub schema: String,
+    pub name: String,
+    /// Meta-command that describes or shows the object, e.g. `\sv public.active_users`
+    pub describe: String,
+}
+
+/// Contents of the sidecar: the objects of the last result, in row order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectListing {
+    pub connection: String,
+    pub command: String,
+    pub objects: Vec<ObjectRef>,
+}
+
+/// Kind of object each row of `cmd`'s result lists, if any
+fn listed_kind(cmd: &MetaCommand) -> Option<ObjectKind> {
+    match cmd {
+        MetaCommand::Describe(None, _) | MetaCommand::DescribeTables(..) => Some(ObjectKind::Table),
+        MetaCommand::DescribeViews(..) => Some(ObjectKind::View),
+        MetaCommand::DescribeFunctions(_) => Some(ObjectKind::Function),
+        _ => None,
+    }
+}
+
+/// Map the rows of a meta-command result to the objects they list
+///
+/// Returns None when the command doesn't list objects or the result lacks the
+/// expected Schema/Name columns.
+pub fn objects_from_result(
+    cmd: &MetaCommand,
+    header: &[String],
+    rows: &[Vec<String>],
+) -> Option<Vec<ObjectRef>> {
+    let kind = listed_kind(cmd)?;
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let schema_idx = column("Schema")?;
+    let name_idx = column("Name")?;
+    let args_idx = column("Argument data types");
+
+    let objects = rows
+        .iter()
+        .map(|row| {
+            let schema = row[schema_idx].clone();
+            let name = row[name_idx].clone();
+            let qualified = format!(
+                "{}.{}",
+                sql::ident_if_needed(&schema),
+                sql::ident_if_needed(&name)
+            );
+            let describe = match kind {
+                ObjectKind::Table => format!("\\d {}", qualified),
+                ObjectKind::View => format!("\\sv {}", qualified),
+                ObjectKind::Function => {
+                    let args = args_idx.map(|i| row[i].as_str()).unwrap_or("");
+                    format!("\\sf {}({})", qualified, args)
+                }
+            };
+            ObjectRef {
+                kind,
+                schema,
+                name,
+                describe,
+            }
+        })
+        .collect();
+
+    Some(objects)
+}
+
+/// Replace the sidecar in `dir` with `listing`, or remove it when there is none
+/// so a stale listing never applies to a newer result
+pub fn write_sidecar(dir: &Path, listing: Option<&ObjectListing>) -> Result<()> {
+    let path = dir.join(SIDECAR_FILE);
+    match listing {
+        Some(listing) => {
+            let json = serde_json::to_string_pretty(listing)?;
+            fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+        }
+        None => match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Read the sidecar in `dir`, if the last result listed objects
+pub fn read_sidecar(dir: &Path) -> Result<Option<ObjectListing>> {
+    let path = dir.join(SIDECAR_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let listing = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(listing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tables_from_dt() {
+        let header = strings(&["Schema", "Name", "Type", "Owner"]);
+        let rows = vec![
+            strings(&["public", "users", "table", "app"]),
+            strings(&["Sales", "Orders", "table", "app"]),
+        ];
+
+        for cmd in [
+            MetaCommand::DescribeTables(None, false),
+            MetaCommand::Describe(None, false),
+        ] {
+            let objects = objects_from_result(&cmd, &header, &rows).unwrap();
+            assert_eq!(objects.len(), 2);
+            assert_eq!(objects[0].kind, ObjectKind::Table);
+            assert_eq!(objects[0].describe, "\\d public.users");
+            assert_eq!(objects[1].describe, "\\d \"Sales\".\"Orders\"");
+        }
+    }
+
+    #[test]
+    fn test_views_from_dv() {
+        let header = strings(&["Schema", "Name", "Type", "Owner"]);
+        let rows = vec![strings(&["public", "active_users", "view", "app"])];
+
+        let objects =
+            objects_from_result(&MetaCommand::DescribeViews(None, false), &header, &rows).unwrap();
+        assert_eq!(objects[0].kind, ObjectKind::View);
+        assert_eq!(objects[0].describe, "\\sv public.active_users");
+    }
+
+    #[test]
+    fn test_functions_from_df() {
+        let header = strings(&["Schema", "Name", "Result data type", "Argument data types"]);
+        let rows = vec![
+            strings(&["public", "add", "integer", "a integer, b integer"]),
+            strings(&["public", "now_utc", "timestamp", ""]),
+        ];
+
+        let objects =
+            objects_from_result(&MetaCommand::DescribeFunctions(None), &header, &rows).unwrap();
+        assert_eq!(objects[0].kind, ObjectKind::Function);
+        assert_eq!(objects[0].describe, "\\sf public.add(a integer, b integer)");
+        assert_eq!(objects[1].describe, "\\sf public.now_utc()");
+
+        // The generated command must parse back into \sf
+        assert!(matches!(
+            MetaCommand::parse(&objects[0].describe),
+            Some(MetaCommand::ShowFunction(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn test_other_commands_list_no_objects() {
+        let header = strings(&["Schema", "Name"]);
+        let rows = vec![strings(&["public", "x"])];
+
+        assert!(objects_from_result(
+            &MetaCommand::Describe(Some("x".into()), false),
+            &header,
+            &rows
+        )
+        .is_none());
+        assert!(
+            objects_from_result(&MetaCommand::DescribeIndexes(None, false), &header, &rows)
+                .is_none()
+        );
+        assert!(objects_from_result(&MetaCommand::Locks, &header, &rows).is_none());
+    }
+
+    #[test]
+    fn test_missing_columns_list_no_objects() {
+        let header = strings(&["Name"]);
+        let rows = vec![strings(&["x"])];
+        assert!(
+            objects_from_result(&MetaCommand::DescribeTables(None, false), &header, &rows)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_sidecar_round_trip_and_removal() {
+        let dir = std::env::temp_dir().join("helix-dadbod-objects-test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let listing = ObjectListing {
+            connection: "local".to_string(),
+            command: "\\dt".to_string(),
+            objects: vec![ObjectRef {
+                kind: ObjectKind::Table,
+                schema: "public".to_string(),
+                name: "users".to_string(),
+                describe: "\\d public.users".to_string(),
+            }],
+        };
+
+        write_sidecar(&dir, Some(&listing)).unwrap();
+        let json = fs::read_to_string(dir.join(SIDECAR_FILE)).unwrap();
+        assert!(json.contains("\"kind\": \"table\""));
+        assert_eq!(read_sidecar(&dir).unwrap(), Some(listing));
+
+        write_sidecar(&dir, None).unwrap();
+        assert_eq!(read_sidecar(&dir).unwrap(), None);
+        // Removing again is not an error
+        write_sidecar(&dir, None).unwrap();
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
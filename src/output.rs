@@ -0,0 +1,1212 @@
+//! Composable rendering of results.dbout content
+//!
+//! A `ResultDocument` collects sections (metadata header, SQL echo, tables,
+//! raw text, notices, footer) and renders them once, separated by blank lines.
+//! `DocumentWriter` renders the same sections straight to a writer, with
+//! `TableStream` for tables too large to hold in memory.
+//!
+//! With `page_size` set, tables longer than a page are split into pages, each
+//! with its own header, separated by `-- ── page 2 of 7 ──` marker lines that
+//! the editor can jump between or fold.
+//!
+//! Tables with more cells than `cell_budget` skip comfy_table, which measures
+//! every cell, and are written unaligned (tab-separated) with a note saying so.
+
+use crate::copy;
+use crate::value::{self, CellValue};
+use anyhow::{Context, Result};
+use comfy_table::{presets::UTF8_FULL, Table};
+use std::io::Write;
+
+/// Settings applied when rendering a document
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// comfy_table preset used for table borders
+    pub table_preset: &'static str,
+    /// Rows rendered per table chunk when streaming
+    pub stream_chunk_rows: usize,
+    /// Rows per page; tables with more rows are split into pages
+    pub page_size: Option<usize>,
+    /// Tables with more cells (rows × columns) are rendered unaligned; 0 disables
+    pub cell_budget: usize,
+    /// Align tables even when they exceed `cell_budget` (`\pset align force`)
+    pub force_align: bool,
+    /// Rows stop being written once the output reaches this many bytes; 0
+    /// disables the cap
+    pub max_output_bytes: usize,
+}
+
+/// Default `cell_budget`
+pub const DEFAULT_CELL_BUDGET: usize = 20_000;
+
+/// Default `max_output_bytes`: 10 MB
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 10 * 1024 * 1024;
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            table_preset: UTF8_FULL,
+            stream_chunk_rows: 1000,
+            page_size: None,
+            cell_budget: DEFAULT_CELL_BUDGET,
+            force_align: false,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Page size that applies to a table of `rows` rows, if it needs paging
+    fn paging(&self, rows: usize) -> Option<usize> {
+        self.page_size.filter(|&size| size > 0 && rows > size)
+    }
+
+    /// Whether a table of `rows` rows and `columns` columns is rendered aligned
+    pub fn aligns(&self, rows: usize, columns: usize) -> bool {
+        self.force_align || self.cell_budget == 0 || rows * columns <= self.cell_budget
+    }
+}
+
+/// Footer line of a result whose rows past `max_rows` were left unread
+pub fn max_rows_note(rows: usize) -> String {
+    format!(
+        "Output truncated at {} rows (max_rows); total unknown",
+        rows
+    )
+}
+
+/// Footer line of a result whose rows stopped being written at `max_bytes`
+pub fn max_output_note(max_bytes: usize, shown: usize, rows: usize) -> String {
+    format!(
+        "Output truncated at {}; {} of {} rows shown (increase max_output_bytes to see more)",
+        copy::format_size(max_bytes as u64),
+        shown,
+        rows
+    )
+}
+
+/// Note heading a table rendered unaligned because it exceeded the cell budget
+fn unaligned_note(rows: usize, columns: usize, budget: usize) -> String {
+    format!(
+        "-- Unaligned output: {} cells exceed the {} cell budget (\\pset align force to align)\n",
+        rows * columns,
+        budget
+    )
+}
+
+/// Number of pages `rows` rows take at `page_size` rows per page
+pub fn page_count(rows: usize, page_size: usize) -> usize {
+    rows.div_ceil(page_size.max(1)).max(1)
+}
+
+/// Footer stating how many pages a paged table of `rows` rows took
+fn pages_footer(rows: usize, page_size: usize) -> Section {
+    Section::Footer(vec![format!(
+        "Pages: {} ({} rows per page)",
+        page_count(rows, page_size),
+        page_size
+    )])
+}
+
+/// Marker line starting page `page`; `total` is unknown while streaming
+fn page_marker(page: usize, total: Option<usize>) -> String {
+    match total {
+        Some(total) => format!("-- ── page {} of {} ──\n", page, total),
+        None => format!("-- ── page {} ──\n", page),
+    }
+}
+
+/// A single block of results.dbout output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Section {
+    /// `-- ` prefixed header lines such as execution time and row counts
+    Metadata(Vec<String>),
+    /// SQL shown under a labelled comment, e.g. the SQL a meta-command generated
+    SqlEcho { label: String, sql: String },
+    /// Result table; an empty `rows` renders the empty-result placeholder
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    /// Rows shown one field per line, like psql's expanded display
+    Expanded {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    /// Rows as JSON objects keyed by column name: one array, or one object per
+    /// line when `lines` is set (JSON Lines)
+    Json {
+        header: Vec<String>,
+        rows: Vec<Vec<CellValue>>,
+        lines: bool,
+    },
+    /// Rows with fields joined by `fieldsep`, values written as they are;
+    /// `header` is `None` in tuples-only output
+    Separated {
+        header: Option<Vec<String>>,
+        rows: Vec<Vec<String>>,
+        fieldsep: String,
+    },
+    /// Text written verbatim
+    Text(String),
+    /// Server notices, one per line
+    Notices(Vec<String>),
+    /// `-- ` prefixed trailing lines
+    Footer(Vec<String>),
+}
+
+impl Section {
+    pub fn render(&self, options: &RenderOptions) -> String {
+        match self {
+            Section::Metadata(lines) | Section::Footer(lines) => {
+                lines.iter().map(|line| format!("-- {}\n", line)).collect()
+            }
+            Section::SqlEcho { label, sql } => format!("-- {}:\n{}\n", label, sql),
+            Section::Table { header, rows } => {
+                let aligned = options.aligns(rows.len(), header.len());
+                let table = match options.paging(rows.len()) {
+                    Some(page_size) => {
+                        render_paged_table(header, rows, page_size, aligned, options)
+                    }
+                    None => render_rows(header, rows, aligned, options),
+                };
+                if aligned || rows.is_empty() {
+                    table
+                } else {
+                    unaligned_note(rows.len(), header.len(), options.cell_budget) + &table
+                }
+            }
+            Section::Expanded { header, rows } => render_expanded(header, rows),
+            Section::Json {
+                header,
+                rows,
+                lines,
+            } => render_json(header, rows, *lines),
+            Section::Separated {
+                header,
+                rows,
+                fieldsep,
+            } => header
+                .iter()
+                .chain(rows)
+                .map(|fields| format!("{}\n", fields.join(fieldsep)))
+                .collect(),
+            Section::Text(text) => text.clone(),
+            Section::Notices(notices) => notices
+                .iter()
+                .map(|notice| format!("NOTICE: {}\n", notice))
+                .collect(),
+        }
+    }
+}
+
+/// Render a table, or the placeholder when there are no rows
+///
+/// The table is not newline terminated, matching comfy_table's own output.
+fn render_table(header: &[String], rows: &[Vec<String>], options: &RenderOptions) -> String {
+    if rows.is_empty() {
+        return "(No rows returned)\n".to_string();
+    }
+
+    let mut table = Table::new();
+    table.load_preset(options.table_preset);
+    table.set_header(header);
+
+    // Set padding for all columns (left, right)
+    for i in 0..header.len() {
+        if let Some(column) = table.column_mut(i) {
+            column.set_padding((0, 1));
+        }
+    }
+
+    for row in rows {
+        table.add_row(row);
+    }
+
+    table.to_string()
+}
+
+/// Render rows as a comfy_table table, or tab-separated when not `aligned`
+fn render_rows(
+    header: &[String],
+    rows: &[Vec<String>],
+    aligned: bool,
+    options: &RenderOptions,
+) -> String {
+    if aligned {
+        render_table(header, rows, options)
+    } else {
+        render_unaligned(header, rows)
+    }
+}
+
+/// Render a header line and one line per row, fields separated by tabs
+///
+/// Tabs, newlines and backslashes inside values are escaped (`\t`, `\n`, `\\`)
+/// so every row stays on one line.
+fn render_unaligned(header: &[String], rows: &[Vec<String>]) -> String {
+    if rows.is_empty() {
+        return "(No rows returned)\n".to_string();
+    }
+
+    let mut out = String::new();
+    push_unaligned_line(&mut out, header);
+    out.push_str(&render_unaligned_rows(rows));
+    out
+}
+
+/// Unaligned lines for `rows` without the header line
+fn render_unaligned_rows(rows: &[Vec<String>]) -> String {
+    let mut out = String::new();
+    for row in rows {
+        push_unaligned_line(&mut out, row);
+    }
+    out
+}
+
+fn push_unaligned_line(out: &mut String, fields: &[String]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push('\t');
+        }
+        for c in field.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                _ => out.push(c),
+            }
+        }
+    }
+    out.push('\n');
+}
+
+/// Render rows as a JSON array with one object per line, or as JSON Lines
+fn render_json(header: &[String], rows: &[Vec<CellValue>], lines: bool) -> String {
+    let objects = rows.iter().map(|row| value::json_object(header, row));
+    if lines {
+        return objects.map(|object| format!("{}\n", object)).collect();
+    }
+    if rows.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n  {}\n]\n", objects.collect::<Vec<_>>().join(",\n  "))
+}
+
+/// Render a table as pages of `page_size` rows, each with its own header and
+/// every page after the first preceded by a page marker line
+fn render_paged_table(
+    header: &[String],
+    rows: &[Vec<String>],
+    page_size: usize,
+    aligned: bool,
+    options: &RenderOptions,
+) -> String {
+    let total = page_count(rows.len(), page_size);
+    let mut out = String::new();
+    for (i, page) in rows.chunks(page_size).enumerate() {
+        if i > 0 {
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&page_marker(i + 1, Some(total)));
+        }
+        out.push_str(&render_rows(header, page, aligned, options));
+    }
+    out
+}
+
+/// Render rows as `-[ RECORD n ]` blocks with one `column | value` line per field
+fn render_expanded(header: &[String], rows: &[Vec<String>]) -> String {
+    if rows.is_empty() {
+        return "(No rows returned)\n".to_string();
+    }
+
+    let name_width = header.iter().map(|h| h.chars().count()).max().unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .flatten()
+        .flat_map(|value| value.lines())
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for (i, row) in rows.iter().enumerate() {
+        let title = format!("-[ RECORD {} ]", i + 1);
+        let rule_width = (name_width + 3 + value_width).max(title.chars().count());
+        out.push_str(&format!("{:-<width$}\n", title, width = rule_width));
+
+        for (name, value) in header.iter().zip(row) {
+            let mut lines = value.lines();
+            let first = lines.next().unwrap_or("");
+            out.push_str(&format!(
+                "{:<width$} | {}\n",
+                name,
+                first,
+                width = name_width
+            ));
+            for line in lines {
+                out.push_str(&format!("{:<width$} | {}\n", "", line, width = name_width));
+            }
+        }
+    }
+    out
+}
+
+/// Append a rendered section to `out`, separating it from earlier output by a blank line
+fn push_section(out: &mut String, rendered: &str) {
+    if !out.is_empty() {
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out.push_str(rendered);
+}
+
+/// Results document built from sections and rendered once
+#[derive(Debug, Clone, Default)]
+pub struct ResultDocument {
+    sections: Vec<Section>,
+    options: RenderOptions,
+}
+
+impl ResultDocument {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: RenderOptions) -> Self {
+        Self {
+            sections: Vec::new(),
+            options,
+        }
+    }
+
+    pub fn push(&mut self, section: Section) -> &mut Self {
+        self.sections.push(section);
+        self
+    }
+
+    pub fn metadata<I, S>(&mut self, lines: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let lines: Vec<String> = lines.into_iter().map(Into::into).collect();
+        if lines.is_empty() {
+            return self;
+        }
+        self.push(Section::Metadata(lines))
+    }
+
+    pub fn sql_echo(&mut self, label: impl Into<String>, sql: impl Into<String>) -> &mut Self {
+        self.push(Section::SqlEcho {
+            label: label.into(),
+            sql: sql.into(),
+        })
+    }
+
+    /// Add a table; when it spans several pages a footer states the page count
+    pub fn table(&mut self, header: Vec<String>, rows: Vec<Vec<String>>) -> &mut Self {
+        let footer = self
+            .options
+            .paging(rows.len())
+            .map(|page_size| pages_footer(rows.len(), page_size));
+        self.push(Section::Table { header, rows });
+        if let Some(footer) = footer {
+            self.push(footer);
+        }
+        self
+    }
+
+    pub fn expanded(&mut self, header: Vec<String>, rows: Vec<Vec<String>>) -> &mut Self {
+        self.push(Section::Expanded { header, rows })
+    }
+
+    pub fn json(
+        &mut self,
+        header: Vec<String>,
+        rows: Vec<Vec<CellValue>>,
+        lines: bool,
+    ) -> &mut Self {
+        self.push(Section::Json {
+            header,
+            rows,
+            lines,
+        })
+    }
+
+    pub fn separated(
+        &mut self,
+        header: Option<Vec<String>>,
+        rows: Vec<Vec<String>>,
+        fieldsep: impl Into<String>,
+    ) -> &mut Self {
+        self.push(Section::Separated {
+            header,
+            rows,
+            fieldsep: fieldsep.into(),
+        })
+    }
+
+    pub fn text(&mut self, text: impl Into<String>) -> &mut Self {
+        self.push(Section::Text(text.into()))
+    }
+
+    pub fn notices(&mut self, notices: Vec<String>) -> &mut Self {
+        if notices.is_empty() {
+            return self;
+        }
+        self.push(Section::Notices(notices))
+    }
+
+    pub fn footer<I, S>(&mut self, lines: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push(Section::Footer(lines.into_iter().map(Into::into).collect()))
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Render all sections into the final results.dbout content
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for section in &self.sections {
+            push_section(&mut out, &section.render(&self.options));
+        }
+        out
+    }
+
+    /// Render only the results (tables, rows and text) for a file: no `-- `
+    /// metadata, footers, notices or echoed SQL, and tables neither paged nor
+    /// noted when unaligned
+    pub fn render_data(&self) -> String {
+        let mut out = String::new();
+        for section in &self.sections {
+            let rendered = match section {
+                Section::Metadata(_)
+                | Section::Footer(_)
+                | Section::Notices(_)
+                | Section::SqlEcho { .. } => continue,
+                Section::Table { header, rows } => {
+                    let aligned = self.options.aligns(rows.len(), header.len());
+                    render_rows(header, rows, aligned, &self.options)
+                }
+                section => section.render(&self.options),
+            };
+            push_section(&mut out, &rendered);
+        }
+        out
+    }
+
+    /// Render like `render`, with rows left out until the output fits in
+    /// `max_output_bytes` and a footer saying how many of `rows` are shown
+    ///
+    /// JSON output gets no footer, so it stays parseable; the cut is logged.
+    pub fn render_capped(&mut self, rows: usize) -> String {
+        let max_bytes = self.options.max_output_bytes;
+        let mut output = self.render();
+        if max_bytes == 0 || output.len() <= max_bytes {
+            return output;
+        }
+
+        let mut shown = rows;
+        while shown > 0 && output.len() > max_bytes {
+            // The output grows about linearly with the rows shown
+            let estimate = (shown as u128 * max_bytes as u128 / output.len() as u128) as usize;
+            shown = estimate.min(shown - 1);
+            self.truncate_rows(shown);
+            output = self.render();
+        }
+        log::warn!(
+            "Output of {} rows cut to {} rows by max_output_bytes ({})",
+            rows,
+            shown,
+            max_bytes
+        );
+        if self
+            .sections
+            .iter()
+            .any(|section| matches!(section, Section::Json { .. }))
+        {
+            return output;
+        }
+        self.footer([max_output_note(max_bytes, shown, rows)]);
+        self.render()
+    }
+
+    /// Keep the first `rows` rows of every section holding rows, and the page
+    /// count below a paged table in step
+    fn truncate_rows(&mut self, rows: usize) {
+        let mut sections = Vec::with_capacity(self.sections.len());
+        // Page count footer of the table just cut, and its new row count
+        let mut stale_footer = None;
+        for mut section in std::mem::take(&mut self.sections) {
+            if let Some((footer, table_rows)) = stale_footer.take() {
+                if section == footer {
+                    let page_size = self.options.paging(table_rows);
+                    sections.extend(page_size.map(|size| pages_footer(table_rows, size)));
+                    continue;
+                }
+            }
+            match &mut section {
+                Section::Table { rows: table, .. } => {
+                    let footer = self
+                        .options
+                        .paging(table.len())
+                        .map(|size| pages_footer(table.len(), size));
+                    table.truncate(rows);
+                    stale_footer = footer.map(|footer| (footer, table.len()));
+                }
+                Section::Expanded { rows: table, .. } | Section::Separated { rows: table, .. } => {
+                    table.truncate(rows)
+                }
+                Section::Json { rows: values, .. } => values.truncate(rows),
+                _ => {}
+            }
+            sections.push(section);
+        }
+        self.sections = sections;
+    }
+}
+
+/// Writes sections to `W` as they are produced instead of buffering a document
+pub struct DocumentWriter<W: Write> {
+    writer: W,
+    options: RenderOptions,
+    /// Whether anything was written, and whether it ended with a newline
+    last: Option<bool>,
+    /// Bytes written so far
+    written: usize,
+}
+
+impl<W: Write> DocumentWriter<W> {
+    pub fn new(writer: W, options: RenderOptions) -> Self {
+        Self {
+            writer,
+            options,
+            last: None,
+            written: 0,
+        }
+    }
+
+    /// Bytes written so far
+    pub fn bytes_written(&self) -> usize {
+        self.written
+    }
+
+    fn write_rendered(&mut self, rendered: &str) -> Result<()> {
+        if rendered.is_empty() {
+            return Ok(());
+        }
+        let separator = match self.last {
+            None => "",
+            Some(true) => "\n",
+            Some(false) => "\n\n",
+        };
+        self.writer
+            .write_all(separator.as_bytes())
+            .and_then(|_| self.writer.write_all(rendered.as_bytes()))
+            .context("Failed to write results")?;
+        self.written += separator.len() + rendered.len();
+        self.last = Some(rendered.ends_with('\n'));
+        Ok(())
+    }
+
+    /// Write output that continues the previous section without a blank line
+    fn write_continuation(&mut self, rendered: &str) -> Result<()> {
+        let separator = if self.last == Some(false) { "\n" } else { "" };
+        self.writer
+            .write_all(separator.as_bytes())
+            .and_then(|_| self.writer.write_all(rendered.as_bytes()))
+            .context("Failed to write results")?;
+        self.written += separator.len() + rendered.len();
+        self.last = Some(rendered.ends_with('\n'));
+        Ok(())
+    }
+
+    /// Render and write a complete section
+    pub fn section(&mut self, section: &Section) -> Result<()> {
+        let rendered = section.render(&self.options);
+        self.write_rendered(&rendered)
+    }
+
+    /// Start a table whose rows are written in chunks as they arrive
+    pub fn table_stream(&mut self, header: Vec<String>) -> TableStream<'_, W> {
+        TableStream {
+            writer: self,
+            header,
+            pending: Vec::new(),
+            rows_written: 0,
+            total_rows: None,
+        }
+    }
+
+    /// Flush what was written so far to the underlying writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush results")
+    }
+
+    /// Flush and return the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush().context("Failed to flush results")?;
+        Ok(self.writer)
+    }
+}
+
+/// Table being streamed through a `DocumentWriter`
+///
+/// Rows are rendered every `stream_chunk_rows` rows, so column widths are
+/// computed per chunk rather than across the whole result. With `page_size`
+/// set, each page is rendered as one chunk instead. The cell budget only
+/// applies when the row count is given with `with_total_rows`.
+pub struct TableStream<'a, W: Write> {
+    writer: &'a mut DocumentWriter<W>,
+    header: Vec<String>,
+    pending: Vec<Vec<String>>,
+    rows_written: usize,
+    total_rows: Option<usize>,
+}
+
+impl<W: Write> TableStream<'_, W> {
+    /// Row count known up front, so page markers can state the page total and
+    /// tables over the cell budget are written unaligned
+    pub fn with_total_rows(mut self, total_rows: usize) -> Self {
+        self.total_rows = Some(total_rows);
+        self
+    }
+
+    fn chunk_rows(&self) -> usize {
+        self.writer
+            .options
+            .page_size
+            .filter(|&size| size > 0)
+            .unwrap_or(self.writer.options.stream_chunk_rows)
+            .max(1)
+    }
+
+    /// Bytes written to the document so far, rows still pending excluded
+    pub fn bytes_written(&self) -> usize {
+        self.writer.bytes_written()
+    }
+
+    pub fn push_row(&mut self, row: Vec<String>) -> Result<()> {
+        self.pending.push(row);
+        if self.pending.len() >= self.chunk_rows() {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn aligned(&self) -> bool {
+        match self.total_rows {
+            Some(total) => self.writer.options.aligns(total, self.header.len()),
+            None => true,
+        }
+    }
+
+    fn flush_chunk(&mut self) -> Result<()> {
+        let rows = std::mem::take(&mut self.pending);
+        let aligned = self.aligned();
+        let paged = self.writer.options.page_size.is_some_and(|size| size > 0);
+        let mut rendered = render_rows(&self.header, &rows, aligned, &self.writer.options);
+        if !aligned && self.rows_written == 0 && !rows.is_empty() {
+            let note = unaligned_note(
+                self.total_rows.unwrap_or_default(),
+                self.header.len(),
+                self.writer.options.cell_budget,
+            );
+            rendered = note + &rendered;
+        }
+
+        if !aligned && !paged && self.rows_written > 0 {
+            // Later chunks continue the same unaligned table without a header
+            self.writer
+                .write_continuation(&render_unaligned_rows(&rows))?;
+        } else if paged && self.rows_written > 0 {
+            let page = self.rows_written / self.chunk_rows() + 1;
+            let total = self
+                .total_rows
+                .map(|total| page_count(total, self.chunk_rows()));
+            let marker = page_marker(page, total);
+            self.writer
+                .write_continuation(&format!("{}{}", marker, rendered))?;
+        } else {
+            self.writer.write_rendered(&rendered)?;
+        }
+        self.rows_written += rows.len();
+        // A reader of the file sees each chunk as soon as it is rendered
+        self.writer
+            .writer
+            .flush()
+            .context("Failed to flush results")
+    }
+
+    /// Write any remaining rows and return the total row count
+    /// A paged table is followed by a footer stating the page count
+    pub fn finish(mut self) -> Result<usize> {
+        if !self.pending.is_empty() || self.rows_written == 0 {
+            self.flush_chunk()?;
+        }
+        if let Some(page_size) = self.writer.options.paging(self.rows_written) {
+            self.writer
+                .section(&pages_footer(self.rows_written, page_size))?;
+        }
+        Ok(self.rows_written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<String> {
+        vec!["id".to_string(), "name".to_string()]
+    }
+
+    fn rows() -> Vec<Vec<String>> {
+        vec![
+            vec!["1".to_string(), "alice".to_string()],
+            vec!["2".to_string(), "bob".to_string()],
+        ]
+    }
+
+    fn execution_header(rows: Option<usize>) -> Vec<String> {
+        let mut lines = vec![
+            "Executed at: 2024-01-02 03:04:05".to_string(),
+            "Execution time: 0.012s".to_string(),
+        ];
+        if let Some(rows) = rows {
+            lines.push(format!("Rows returned: {}", rows));
+        }
+        lines
+    }
+
+    #[test]
+    fn test_snapshot_query_with_rows() {
+        let mut doc = ResultDocument::new();
+        doc.metadata(execution_header(Some(2)))
+            .table(header(), rows());
+
+        let expected = "\
+-- Executed at: 2024-01-02 03:04:05
+-- Execution time: 0.012s
+-- Rows returned: 2
+
+┌───┬──────┐
+│id ┆name  │
+╞═══╪══════╡
+│1  ┆alice │
+├╌╌╌┼╌╌╌╌╌╌┤
+│2  ┆bob   │
+└───┴──────┘";
+        assert_eq!(doc.render(), expected);
+    }
+
+    #[test]
+    fn test_snapshot_query_without_rows() {
+        let mut doc = ResultDocument::new();
+        doc.metadata(execution_header(Some(0)))
+            .table(header(), Vec::new());
+
+        let expected = "\
+-- Executed at: 2024-01-02 03:04:05
+-- Execution time: 0.012s
+-- Rows returned: 0
+
+(No rows returned)
+";
+        assert_eq!(doc.render(), expected);
+    }
+
+    #[test]
+    fn test_snapshot_query_error() {
+        let mut doc = ResultDocument::new();
+        doc.metadata(execution_header(None))
+            .text("ERROR: relation \"nope\" does not exist\n")
+            .sql_echo("Generated SQL", "SELECT * FROM nope");
+
+        let expected = "\
+-- Executed at: 2024-01-02 03:04:05
+-- Execution time: 0.012s
+
+ERROR: relation \"nope\" does not exist
+
+-- Generated SQL:
+SELECT * FROM nope
+";
+        assert_eq!(doc.render(), expected);
+    }
+
+    #[test]
+    fn test_snapshot_json() {
+        let rows = vec![
+            vec![CellValue::Int(1), CellValue::Text("alice".to_string())],
+            vec![CellValue::Int(2), CellValue::Null],
+        ];
+
+        let mut doc = ResultDocument::new();
+        doc.metadata(execution_header(Some(2)))
+            .json(header(), rows.clone(), false);
+        let expected = "\
+-- Executed at: 2024-01-02 03:04:05
+-- Execution time: 0.012s
+-- Rows returned: 2
+
+[
+  {\"id\":1,\"name\":\"alice\"},
+  {\"id\":2,\"name\":null}
+]
+";
+        assert_eq!(doc.render(), expected);
+
+        let mut doc = ResultDocument::new();
+        doc.json(header(), rows, true);
+        assert_eq!(
+            doc.render(),
+            "{\"id\":1,\"name\":\"alice\"}\n{\"id\":2,\"name\":null}\n"
+        );
+
+        let mut doc = ResultDocument::new();
+        doc.json(header(), Vec::new(), false);
+        assert_eq!(doc.render(), "[]\n");
+    }
+
+    #[test]
+    fn test_snapshot_separated() {
+        let rows = vec![
+            vec!["1".to_string(), "alice".to_string()],
+            vec!["2".to_string(), "NULL".to_string()],
+        ];
+
+        let mut doc = ResultDocument::new();
+        doc.separated(Some(header()), rows.clone(), "|");
+        assert_eq!(doc.render(), "id|name\n1|alice\n2|NULL\n");
+
+        let mut doc = ResultDocument::new();
+        doc.separated(None, rows, "\t");
+        assert_eq!(doc.render(), "1\talice\n2\tNULL\n");
+    }
+
+    #[test]
+    fn test_section_after_table_starts_on_new_line() {
+        let mut doc = ResultDocument::new();
+        doc.table(header(), rows())
+            .notices(vec!["table \"t\" does not exist, skipping".to_string()])
+            .footer(["Done"]);
+
+        let rendered = doc.render();
+        assert!(
+            rendered.ends_with("┘\n\nNOTICE: table \"t\" does not exist, skipping\n\n-- Done\n")
+        );
+    }
+
+    #[test]
+    fn test_empty_notices_are_omitted() {
+        let mut doc = ResultDocument::new();
+        doc.text("x\n").notices(Vec::new());
+        assert_eq!(doc.sections().len(), 1);
+        assert_eq!(doc.render(), "x\n");
+    }
+
+    #[test]
+    fn test_snapshot_expanded_record() {
+        let mut doc = ResultDocument::new();
+        doc.expanded(
+            header(),
+            vec![vec!["42".to_string(), "line one\nline two".to_string()]],
+        );
+
+        let expected = "\
+-[ RECORD 1 ]--
+id   | 42
+name | line one
+     | line two
+";
+        assert_eq!(doc.render(), expected);
+    }
+
+    #[test]
+    fn test_writer_matches_document() {
+        let mut doc = ResultDocument::new();
+        doc.metadata(execution_header(Some(2)))
+            .table(header(), rows())
+            .footer(["end"]);
+
+        let mut writer = DocumentWriter::new(Vec::new(), RenderOptions::default());
+        for section in doc.sections() {
+            writer.section(section).unwrap();
+        }
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(written, doc.render());
+    }
+
+    #[test]
+    fn test_table_stream_renders_in_chunks() {
+        let options = RenderOptions {
+            stream_chunk_rows: 1,
+            ..RenderOptions::default()
+        };
+        let mut writer = DocumentWriter::new(Vec::new(), options);
+        writer
+            .section(&Section::Metadata(vec!["streamed".to_string()]))
+            .unwrap();
+
+        let mut stream = writer.table_stream(header());
+        for row in rows() {
+            stream.push_row(row).unwrap();
+        }
+        assert_eq!(stream.finish().unwrap(), 2);
+
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert!(written.starts_with("-- streamed\n\n┌"));
+        assert_eq!(written.matches("│id ┆").count(), 2);
+        assert!(written.contains("│1  ┆alice │"));
+        assert!(written.contains("│2  ┆bob  │"));
+    }
+
+    fn numbered_rows(count: usize) -> Vec<Vec<String>> {
+        (1..=count)
+            .map(|i| vec![i.to_string(), format!("user{}", i)])
+            .collect()
+    }
+
+    fn paged(page_size: usize) -> RenderOptions {
+        RenderOptions {
+            page_size: Some(page_size),
+            ..RenderOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_render_capped() {
+        let mut doc = ResultDocument::new();
+        doc.table(header(), numbered_rows(100));
+        let full = doc.render();
+        assert_eq!(doc.render_capped(100), full);
+
+        let max_bytes = full.len() / 3;
+        let mut doc = ResultDocument::with_options(RenderOptions {
+            page_size: Some(10),
+            max_output_bytes: max_bytes,
+            ..RenderOptions::default()
+        });
+        doc.metadata(["Rows returned: 100"])
+            .table(header(), numbered_rows(100));
+        let capped = doc.render_capped(100);
+
+        let Some(Section::Table { rows, .. }) = doc.sections().get(1) else {
+            panic!("the table stays second");
+        };
+        let shown = rows.len();
+        assert!(shown > 0 && shown < 100, "{} rows shown", shown);
+        assert!(capped.starts_with("-- Rows returned: 100\n"));
+        let (rendered, footer) = capped.split_at(capped.find("\n\n-- Pages:").unwrap());
+        assert!(rendered.len() <= max_bytes);
+        assert_eq!(
+            footer,
+            format!(
+                "\n\n-- Pages: {} (10 rows per page)\n\n\
+                 -- Output truncated at {}; {} of 100 rows shown (increase max_output_bytes to see more)\n",
+                page_count(shown, 10),
+                copy::format_size(max_bytes as u64),
+                shown
+            )
+        );
+    }
+
+    #[test]
+    fn test_page_count() {
+        assert_eq!(page_count(0, 10), 1);
+        assert_eq!(page_count(10, 10), 1);
+        assert_eq!(page_count(11, 10), 2);
+        assert_eq!(page_count(7000, 1000), 7);
+        assert_eq!(page_count(7001, 1000), 8);
+    }
+
+    #[test]
+    fn test_paged_table_repeats_header() {
+        let mut doc = ResultDocument::with_options(paged(2));
+        doc.table(header(), numbered_rows(5));
+        let rendered = doc.render();
+
+        assert_eq!(rendered.matches("│id ┆").count(), 3);
+        assert!(!rendered.contains("page 1 of"));
+        assert!(rendered.contains("┘\n-- ── page 2 of 3 ──\n┌"));
+        assert!(rendered.contains("┘\n-- ── page 3 of 3 ──\n┌"));
+        assert!(rendered.ends_with("\n\n-- Pages: 3 (2 rows per page)\n"));
+
+        // Rows stay in order across pages
+        let page_3 = rendered.split("page 3 of 3").nth(1).unwrap();
+        assert!(page_3.contains("user5") && !page_3.contains("user4"));
+    }
+
+    #[test]
+    fn test_table_within_page_size_is_not_paged() {
+        let mut doc = ResultDocument::with_options(paged(5));
+        doc.table(header(), numbered_rows(5));
+        let rendered = doc.render();
+
+        assert_eq!(rendered.matches("│id ┆").count(), 1);
+        assert!(!rendered.contains("page"));
+        assert!(!rendered.contains("Pages:"));
+    }
+
+    #[test]
+    fn test_paged_stream_matches_document() {
+        let mut doc = ResultDocument::with_options(paged(2));
+        doc.table(header(), numbered_rows(5));
+
+        let mut writer = DocumentWriter::new(Vec::new(), paged(2));
+        let mut stream = writer.table_stream(header()).with_total_rows(5);
+        for row in numbered_rows(5) {
+            stream.push_row(row).unwrap();
+        }
+        assert_eq!(stream.finish().unwrap(), 5);
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(written, doc.render());
+    }
+
+    #[test]
+    fn test_paged_stream_without_total() {
+        let mut writer = DocumentWriter::new(Vec::new(), paged(2));
+        let mut stream = writer.table_stream(header());
+        for row in numbered_rows(3) {
+            stream.push_row(row).unwrap();
+        }
+        stream.finish().unwrap();
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert!(written.contains("┘\n-- ── page 2 ──\n┌"));
+        assert!(written.ends_with("-- Pages: 2 (2 rows per page)\n"));
+    }
+
+    #[test]
+    fn test_empty_table_stream_writes_placeholder() {
+        let mut writer = DocumentWriter::new(Vec::new(), RenderOptions::default());
+        let stream = writer.table_stream(header());
+        assert_eq!(stream.finish().unwrap(), 0);
+
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(written, "(No rows returned)\n");
+    }
+
+    fn budget(cell_budget: usize) -> RenderOptions {
+        RenderOptions {
+            cell_budget,
+            ..RenderOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_cell_budget_cutoff() {
+        // 3 rows × 2 columns = 6 cells
+        let mut within = ResultDocument::with_options(budget(6));
+        within.table(header(), numbered_rows(3));
+        assert!(within.render().starts_with('┌'));
+
+        let mut over = ResultDocument::with_options(budget(5));
+        over.table(header(), numbered_rows(3));
+        assert_eq!(
+            over.render(),
+            "-- Unaligned output: 6 cells exceed the 5 cell budget (\\pset align force to align)\n\
+             id\tname\n\
+             1\tuser1\n\
+             2\tuser2\n\
+             3\tuser3\n"
+        );
+
+        let mut unlimited = ResultDocument::with_options(budget(0));
+        unlimited.table(header(), numbered_rows(3));
+        assert_eq!(unlimited.render(), within.render());
+    }
+
+    #[test]
+    fn test_force_align_overrides_budget() {
+        let forced = RenderOptions {
+            force_align: true,
+            ..budget(1)
+        };
+        let mut doc = ResultDocument::with_options(forced);
+        doc.table(header(), numbered_rows(3));
+
+        let mut aligned = ResultDocument::new();
+        aligned.table(header(), numbered_rows(3));
+        assert_eq!(doc.render(), aligned.render());
+    }
+
+    #[test]
+    fn test_unaligned_escapes_separators() {
+        let rows = vec![vec!["a\tb".to_string(), "line 1\nline 2 \\".to_string()]];
+        assert_eq!(
+            render_unaligned(&header(), &rows),
+            "id\tname\na\\tb\tline 1\\nline 2 \\\\\n"
+        );
+    }
+
+    #[test]
+    fn test_unaligned_stream_matches_document() {
+        let options = RenderOptions {
+            stream_chunk_rows: 2,
+            ..budget(5)
+        };
+        let mut doc = ResultDocument::with_options(options.clone());
+        doc.table(header(), numbered_rows(5));
+
+        let mut writer = DocumentWriter::new(Vec::new(), options);
+        let mut stream = writer.table_stream(header()).with_total_rows(5);
+        for row in numbered_rows(5) {
+            stream.push_row(row).unwrap();
+        }
+        stream.finish().unwrap();
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(written, doc.render());
+        assert_eq!(written.matches("id\tname").count(), 1);
+    }
+
+    #[test]
+    fn test_unaligned_paged_stream_matches_document() {
+        let options = RenderOptions {
+            page_size: Some(2),
+            ..budget(5)
+        };
+        let mut doc = ResultDocument::with_options(options.clone());
+        doc.table(header(), numbered_rows(5));
+
+        let mut writer = DocumentWriter::new(Vec::new(), options);
+        let mut stream = writer.table_stream(header()).with_total_rows(5);
+        for row in numbered_rows(5) {
+            stream.push_row(row).unwrap();
+        }
+        stream.finish().unwrap();
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(written, doc.render());
+        assert!(written.contains("1\tuser1\n2\tuser2\n-- ── page 2 of 3 ──\nid\tname\n"));
+    }
+
+    #[test]
+    fn test_render_data_leaves_out_comment_lines() {
+        let mut doc = ResultDocument::with_options(paged(2));
+        doc.metadata(["Executed at: 2024-01-02 03:04:05", "Rows returned: 3"])
+            .notices(vec!["table t does not exist, skipping".to_string()])
+            .table(header(), numbered_rows(3))
+            .text("INSERT 0 3\n")
+            .footer(["Output written to /tmp/out.txt"]);
+
+        let mut plain = ResultDocument::new();
+        plain.table(header(), numbered_rows(3)).text("INSERT 0 3\n");
+        assert_eq!(doc.render_data(), plain.render());
+        assert!(doc.render().contains("-- Executed at"));
+    }
+}
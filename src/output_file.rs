@@ -0,0 +1,52 @@
+//! \o: copy query results to a file
+//!
+//! After `\o path`, the results of every query are appended to that file as
+//! well as shown in results.dbout, until a bare `\o` stops it. Only the
+//! results go to the file, not the `-- ` lines around them. The file is
+//! truncated when `\o` names it, like psql does.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Create `path` (and its parent directories) empty, ready for results
+pub fn start(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    fs::write(path, "").with_context(|| format!("Failed to create {}", path.display()))
+}
+
+/// Append one query's results to `path`
+pub fn append(path: &Path, content: &str) -> Result<()> {
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .with_context(|| format!("Failed to write results to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_truncates_and_append_accumulates() {
+        let dir = std::env::temp_dir().join("helix-dadbod-output-file-test");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("nested/out.txt");
+
+        start(&path).unwrap();
+        append(&path, "first\n").unwrap();
+        append(&path, "second\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+
+        start(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
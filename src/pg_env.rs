@@ -0,0 +1,189 @@
+//! Zero-config connection from the standard libpq environment variables
+//!
+//! When no config.toml exists but PGHOST or PGDATABASE is set, a single
+//! connection named "env" is synthesized the way psql would resolve it.
+
+use crate::config::{Connection, DEFAULT_APPLICATION_NAME, DEFAULT_CONNECT_TIMEOUT_SECS};
+use crate::pgpass;
+
+/// Name of the connection synthesized from the environment
+pub const ENV_CONNECTION_NAME: &str = "env";
+
+/// Build the "env" connection from libpq variables read through `env`
+///
+/// Returns None unless PGHOST or PGDATABASE is set. PGPASSWORD takes precedence
+/// over a matching entry in PGPASSFILE (default ~/.pgpass).
+pub fn connection_from_env<F>(env: F) -> Option<Connection>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let var = |key: &str| env(key).filter(|v| !v.is_empty());
+
+    let pghost = var("PGHOST");
+    let pgdatabase = var("PGDATABASE");
+    if pghost.is_none() && pgdatabase.is_none() {
+        return None;
+    }
+
+    let host = pghost.unwrap_or_else(|| "localhost".to_string());
+    let port = match var("PGPORT") {
+        Some(port) => match port.parse() {
+            Ok(port) => port,
+            Err(_) => {
+                log::warn!("Ignoring invalid PGPORT '{}', using 5432", port);
+                5432
+            }
+        },
+        None => 5432,
+    };
+    let username = var("PGUSER")
+        .or_else(|| var("USER"))
+        .or_else(|| var("USERNAME"))
+        .unwrap_or_else(|| "postgres".to_string());
+    // libpq defaults the database name to the user name
+    let database = pgdatabase.unwrap_or_else(|| username.clone());
+
+    let connect_timeout_secs = match var("PGCONNECT_TIMEOUT") {
+        Some(timeout) => timeout.parse().unwrap_or_else(|_| {
+            log::warn!(
+                "Ignoring invalid PGCONNECT_TIMEOUT '{}', using {}",
+                timeout,
+                DEFAULT_CONNECT_TIMEOUT_SECS
+            );
+            DEFAULT_CONNECT_TIMEOUT_SECS
+        }),
+        None => DEFAULT_CONNECT_TIMEOUT_SECS,
+    };
+
+    let password = var("PGPASSWORD").or_else(|| {
+        let passfile = pgpass::passfile(&env)?;
+        pgpass::password_in(&passfile, &host, port, &database, &username)
+    });
+
+    log::info!(
+        "Using connection '{}' from environment: {}@{}:{}/{}",
+        ENV_CONNECTION_NAME,
+        username,
+        host,
+        port,
+        database
+    );
+
+    Some(Connection {
+        name: ENV_CONNECTION_NAME.to_string(),
+        db_type: "postgres".to_string(),
+        host,
+        port: Some(port),
+        database,
+        username,
+        password,
+        password_cmd: None,
+        password_keyring: false,
+        keyring_service: None,
+        keyring_user: None,
+        url: None,
+        ssh_tunnel: None,
+        remote_socket: None,
+        auto_execute_on_save: false,
+        sslmode: var("PGSSLMODE"),
+        ssl_cert: None,
+        ssl_key: None,
+        ssl_key_password: None,
+        ssl_key_password_cmd: None,
+        ssl_root_cert: var("PGSSLROOTCERT"),
+        output: None,
+        meta_commands: true,
+        dialect: None,
+        search_path: None,
+        session_settings: Default::default(),
+        application_name: var("PGAPPNAME").unwrap_or_else(|| DEFAULT_APPLICATION_NAME.to_string()),
+        connect_timeout_secs,
+        tcp_keepalive_secs: None,
+        create_if_missing: false,
+        from_env: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_requires_host_or_database() {
+        assert!(connection_from_env(env_of(&[("PGUSER", "alice")])).is_none());
+        assert!(connection_from_env(env_of(&[("PGHOST", "")])).is_none());
+        assert!(connection_from_env(env_of(&[("PGDATABASE", "app")])).is_some());
+    }
+
+    #[test]
+    fn test_reads_all_variables() {
+        let conn = connection_from_env(env_of(&[
+            ("PGHOST", "db.internal"),
+            ("PGPORT", "6432"),
+            ("PGUSER", "alice"),
+            ("PGDATABASE", "app"),
+            ("PGPASSWORD", "secret"),
+            ("PGSSLMODE", "require"),
+            ("PGSSLROOTCERT", "/etc/ssl/db-ca.crt"),
+            ("PGAPPNAME", "nightly-report"),
+            ("PGCONNECT_TIMEOUT", "3"),
+        ]))
+        .unwrap();
+
+        assert_eq!(conn.name, ENV_CONNECTION_NAME);
+        assert_eq!(conn.host, "db.internal");
+        assert_eq!(conn.port, Some(6432));
+        assert_eq!(conn.username, "alice");
+        assert_eq!(conn.database, "app");
+        assert_eq!(conn.password.as_deref(), Some("secret"));
+        assert_eq!(conn.sslmode.as_deref(), Some("require"));
+        assert_eq!(conn.ssl_root_cert.as_deref(), Some("/etc/ssl/db-ca.crt"));
+        assert_eq!(conn.application_name, "nightly-report");
+        assert_eq!(conn.connect_timeout_secs, 3);
+        assert!(conn.from_env);
+    }
+
+    #[test]
+    fn test_defaults_follow_libpq() {
+        let conn = connection_from_env(env_of(&[("PGHOST", "db"), ("USER", "bob")])).unwrap();
+
+        assert_eq!(conn.port(), 5432);
+        assert_eq!(conn.username, "bob");
+        assert_eq!(conn.database, "bob");
+        assert_eq!(conn.sslmode, None);
+        assert_eq!(conn.application_name, DEFAULT_APPLICATION_NAME);
+        assert_eq!(conn.connect_timeout_secs, DEFAULT_CONNECT_TIMEOUT_SECS);
+    }
+
+    #[test]
+    fn test_pgpassword_wins_over_passfile() {
+        let dir = std::env::temp_dir().join("helix-dadbod-pgpass-precedence");
+        std::fs::create_dir_all(&dir).unwrap();
+        let passfile = dir.join("pgpass");
+        std::fs::write(&passfile, "*:*:*:*:from-file\n").unwrap();
+        let passfile = passfile.to_string_lossy().to_string();
+
+        let conn = connection_from_env(env_of(&[
+            ("PGHOST", "db"),
+            ("PGPASSFILE", &passfile),
+            ("PGPASSWORD", "from-env"),
+        ]))
+        .unwrap();
+        assert_eq!(conn.password.as_deref(), Some("from-env"));
+
+        let conn =
+            connection_from_env(env_of(&[("PGHOST", "db"), ("PGPASSFILE", &passfile)])).unwrap();
+        assert_eq!(conn.password.as_deref(), Some("from-file"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
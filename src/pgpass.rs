@@ -0,0 +1,213 @@
+//! Password lookup in ~/.pgpass (or $PGPASSFILE)
+//!
+//! Lines are `host:port:database:username:password`, where `*` matches
+//! anything and `\:` / `\\` escape literal characters. The first matching line
+//! wins, as in libpq.
+
+use std::path::{Path, PathBuf};
+
+/// The password file: $PGPASSFILE, or ~/.pgpass, with variables read through
+/// `env`
+pub fn passfile<F>(env: F) -> Option<PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    env("PGPASSFILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".pgpass")))
+}
+
+/// Password for a connection from the password file at `path`, if the file
+/// exists and a line matches
+///
+/// The matching line is logged by number, never with its password.
+pub fn password_in(
+    path: &Path,
+    host: &str,
+    port: u16,
+    database: &str,
+    username: &str,
+) -> Option<String> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let (line_number, password) = find(&contents, host, port, database, username)?;
+    log::debug!(
+        "Using password from {} line {} for {}@{}:{}/{}",
+        path.display(),
+        line_number,
+        username,
+        host,
+        port,
+        database
+    );
+    Some(password)
+}
+
+/// Find the password for a connection in .pgpass content
+pub fn lookup(
+    contents: &str,
+    host: &str,
+    port: u16,
+    database: &str,
+    username: &str,
+) -> Option<String> {
+    find(contents, host, port, database, username).map(|(_, password)| password)
+}
+
+/// First matching line's number (1-based) and password
+fn find(
+    contents: &str,
+    host: &str,
+    port: u16,
+    database: &str,
+    username: &str,
+) -> Option<(usize, String)> {
+    let port = port.to_string();
+    let wanted = [host, port.as_str(), database, username];
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .find_map(|(i, line)| {
+            let fields = split_line(line);
+            if fields.len() != 5 {
+                return None;
+            }
+            let matches = fields[..4]
+                .iter()
+                .zip(wanted)
+                .all(|(field, value)| field.wildcard || field.value == value);
+            matches.then(|| (i + 1, fields[4].value.clone()))
+        })
+}
+
+/// A field of a .pgpass line
+#[derive(Default)]
+struct Field {
+    /// Text with backslash escapes resolved
+    value: String,
+    /// Whether the field is an unescaped `*`
+    wildcard: bool,
+}
+
+/// Split a .pgpass line on unescaped colons, resolving backslash escapes
+fn split_line(line: &str) -> Vec<Field> {
+    let mut fields = vec![Field::default()];
+    let mut raw = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        let in_password = fields.len() == 5;
+        let field = fields.last_mut().unwrap();
+        match c {
+            '\\' => {
+                raw.push(c);
+                if let Some(escaped) = chars.next() {
+                    raw.push(escaped);
+                    field.value.push(escaped);
+                }
+            }
+            // The password is the last field and may contain unescaped colons
+            ':' if !in_password => {
+                field.wildcard = raw == "*";
+                raw.clear();
+                fields.push(Field::default());
+            }
+            _ => {
+                raw.push(c);
+                field.value.push(c);
+            }
+        }
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passfile() {
+        let custom = passfile(|key| (key == "PGPASSFILE").then(|| "/etc/pgpass".to_string()));
+        assert_eq!(custom, Some(PathBuf::from("/etc/pgpass")));
+
+        let default = passfile(|key| (key == "PGPASSFILE").then(String::new));
+        assert_eq!(default, dirs::home_dir().map(|home| home.join(".pgpass")));
+    }
+
+    #[test]
+    fn test_lookup_matching() {
+        let contents = "\
+# comment
+other:5432:*:*:wrong
+db:5432:app:alice:first
+*:*:*:alice:fallback
+";
+        assert_eq!(
+            lookup(contents, "db", 5432, "app", "alice").as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            lookup(contents, "db", 6432, "app", "alice").as_deref(),
+            Some("fallback")
+        );
+        assert_eq!(lookup(contents, "db", 5432, "app", "bob"), None);
+    }
+
+    #[test]
+    fn test_wildcards_per_field() {
+        let contents = "db:*:app:alice:any-port\n*:6432:*:*:pgbouncer\n";
+        assert_eq!(
+            find(contents, "db", 5433, "app", "alice"),
+            Some((1, "any-port".to_string()))
+        );
+        assert_eq!(
+            find(contents, "replica", 6432, "other", "bob"),
+            Some((2, "pgbouncer".to_string()))
+        );
+        // `*` is only a wildcard as a whole field
+        assert_eq!(
+            lookup("d*:5432:app:alice:x\n", "db", 5432, "app", "alice"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        let contents = "db\\:1:*:*:*:pa\\\\ss:word\n";
+        assert_eq!(
+            lookup(contents, "db:1", 5432, "app", "alice").as_deref(),
+            Some("pa\\ss:word")
+        );
+        // An escaped `*` is a literal asterisk, not a wildcard
+        assert_eq!(
+            lookup("\\*:5432:app:alice:x\n", "db", 5432, "app", "alice"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_malformed_lines_are_skipped() {
+        let contents = "db:5432:app\ndb:5432:app:alice:ok\n";
+        assert_eq!(
+            find(contents, "db", 5432, "app", "alice"),
+            Some((2, "ok".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_password_in_missing_file() {
+        assert_eq!(
+            password_in(
+                Path::new("/nonexistent/.pgpass"),
+                "db",
+                5432,
+                "app",
+                "alice"
+            ),
+            None
+        );
+    }
+}
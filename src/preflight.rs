@@ -0,0 +1,333 @@
+//! \preflight: report what a statement would touch without running it
+//!
+//! A trailing `\preflight` line makes the execution parse the SQL file with
+//! sqlparser, list the relations each statement reads or writes and check the
+//! current role's privilege on each with has_table_privilege. Nothing in the
+//! SQL file is executed.
+
+use crate::sql;
+use sqlparser::ast::{
+    FromTable, ObjectName, Query, Statement, TableFactor, TableWithJoins, Visit, Visitor,
+};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+
+/// Directive that turns an execution into a preflight check
+pub const DIRECTIVE: &str = "\\preflight";
+
+/// What a statement does with a relation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+}
+
+impl Operation {
+    /// Privilege name as understood by has_table_privilege
+    pub fn privilege(&self) -> &'static str {
+        match self {
+            Operation::Select => "SELECT",
+            Operation::Insert => "INSERT",
+            Operation::Update => "UPDATE",
+            Operation::Delete => "DELETE",
+            Operation::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// A relation and one operation a statement performs on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    /// Name as written in the statement, e.g. `public.users` or `"Users"`
+    pub relation: String,
+    pub operation: Operation,
+}
+
+/// Relations referenced by the SQL file, in order of first reference
+#[derive(Debug, Default, PartialEq)]
+pub struct Analysis {
+    pub accesses: Vec<Access>,
+    /// Why statements (or the whole file) could not be analyzed
+    pub unanalyzed: Vec<String>,
+}
+
+/// SQL preceding a trailing `\preflight`, either on its own line or after the
+/// statement's semicolon
+pub fn strip_directive(sql: &str) -> Option<&str> {
+    let rest = sql.trim_end().strip_suffix(DIRECTIVE)?;
+    (rest.is_empty() || rest.ends_with(char::is_whitespace) || rest.ends_with(';'))
+        .then(|| rest.trim_end())
+}
+
+/// Collects the relations a statement reads and writes
+#[derive(Default)]
+struct RelationCollector {
+    accesses: Vec<Access>,
+    /// Write targets, which are not also reported as reads
+    targets: HashSet<String>,
+    /// Names defined by WITH clauses, which are not relations
+    ctes: HashSet<String>,
+}
+
+impl RelationCollector {
+    fn add(&mut self, name: &ObjectName, operation: Operation) {
+        let relation = name.to_string();
+        if name.0.len() == 1 && self.ctes.contains(&name.0[0].value.to_lowercase()) {
+            return;
+        }
+        let access = Access {
+            relation,
+            operation,
+        };
+        if !self.accesses.contains(&access) {
+            self.accesses.push(access);
+        }
+    }
+
+    fn add_target(&mut self, name: &ObjectName, operation: Operation) {
+        self.targets.insert(name.to_string());
+        self.add(name, operation);
+    }
+
+    fn add_target_tables(&mut self, tables: &[TableWithJoins], operation: Operation) {
+        for table in tables {
+            if let TableFactor::Table { name, .. } = &table.relation {
+                self.add_target(name, operation);
+            }
+        }
+    }
+}
+
+impl Visitor for RelationCollector {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                self.ctes.insert(cte.alias.name.value.to_lowercase());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<()> {
+        match statement {
+            Statement::Insert(insert) => self.add_target(&insert.table_name, Operation::Insert),
+            Statement::Update { table, .. } => {
+                self.add_target_tables(std::slice::from_ref(table), Operation::Update)
+            }
+            Statement::Delete(delete) => match &delete.from {
+                FromTable::WithFromKeyword(tables) | FromTable::WithoutKeyword(tables) => {
+                    self.add_target_tables(tables, Operation::Delete)
+                }
+            },
+            Statement::Truncate { table_names, .. } => {
+                for target in table_names {
+                    self.add_target(&target.name, Operation::Truncate);
+                }
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<()> {
+        // Table functions like generate_series() carry arguments
+        if let TableFactor::Table {
+            name, args: None, ..
+        } = table_factor
+        {
+            if !self.targets.contains(&name.to_string()) {
+                self.add(name, Operation::Select);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Whether the relations of `statement` can be listed
+fn is_analyzable(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::Query(_)
+            | Statement::Insert(_)
+            | Statement::Update { .. }
+            | Statement::Delete(_)
+            | Statement::Truncate { .. }
+    )
+}
+
+/// List the relations read and written by the statements in `sql`
+///
+/// SQL that sqlparser can't parse, and statements other than queries and DML,
+/// end up in `unanalyzed` instead of failing.
+pub fn analyze(sql: &str) -> Analysis {
+    let statements = match Parser::parse_sql(&PostgreSqlDialect {}, sql) {
+        Ok(statements) => statements,
+        Err(e) => {
+            return Analysis {
+                accesses: Vec::new(),
+                unanalyzed: vec![format!("could not analyze: {}", e)],
+            }
+        }
+    };
+
+    let mut collector = RelationCollector::default();
+    let mut unanalyzed = Vec::new();
+    for statement in &statements {
+        if is_analyzable(statement) {
+            collector.targets.clear();
+            let _ = statement.visit(&mut collector);
+        } else {
+            let keyword = statement.to_string();
+            let keyword = keyword.split_whitespace().next().unwrap_or_default();
+            unanalyzed.push(format!(
+                "could not analyze: {} statements are not checked",
+                keyword
+            ));
+        }
+    }
+
+    Analysis {
+        accesses: collector.accesses,
+        unanalyzed,
+    }
+}
+
+/// Query returning each access with whether the current role holds the
+/// privilege: `yes`, `no`, or `missing` when the relation doesn't exist
+pub fn privilege_sql(accesses: &[Access]) -> String {
+    let values = accesses
+        .iter()
+        .enumerate()
+        .map(|(i, access)| {
+            format!(
+                "({}, {}, {})",
+                i + 1,
+                sql::literal(&access.relation),
+                sql::literal(access.operation.privilege())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n  ");
+
+    format!(
+        "SELECT r.relation AS \"Relation\",
+  r.privilege AS \"Operation\",
+  CASE
+    WHEN pg_catalog.to_regclass(r.relation) IS NULL THEN 'missing'
+    WHEN pg_catalog.has_table_privilege(r.relation, r.privilege) THEN 'yes'
+    ELSE 'no'
+  END AS \"Allowed\"
+FROM (VALUES
+  {values}
+) AS r(n, relation, privilege)
+ORDER BY r.n;",
+        values = values
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(relation: &str, operation: Operation) -> Access {
+        Access {
+            relation: relation.to_string(),
+            operation,
+        }
+    }
+
+    #[test]
+    fn test_strip_directive() {
+        assert_eq!(
+            strip_directive("DELETE FROM t;\n\\preflight\n"),
+            Some("DELETE FROM t;")
+        );
+        assert_eq!(
+            strip_directive("DELETE FROM t; \\preflight"),
+            Some("DELETE FROM t;")
+        );
+        assert_eq!(strip_directive("SELECT 1"), None);
+        assert_eq!(strip_directive("SELECT '\\preflight'"), None);
+    }
+
+    #[test]
+    fn test_select_reads_joined_tables() {
+        let analysis = analyze(
+            "SELECT * FROM public.users u JOIN orders o ON o.user_id = u.id \
+             WHERE u.id IN (SELECT user_id FROM bans)",
+        );
+        assert_eq!(
+            analysis.accesses,
+            vec![
+                access("public.users", Operation::Select),
+                access("orders", Operation::Select),
+                access("bans", Operation::Select),
+            ]
+        );
+        assert!(analysis.unanalyzed.is_empty());
+    }
+
+    #[test]
+    fn test_dml_targets() {
+        let analysis = analyze(
+            "INSERT INTO archive SELECT * FROM events;
+             UPDATE users SET active = false FROM bans WHERE bans.user_id = users.id;
+             DELETE FROM sessions;
+             TRUNCATE audit_log;",
+        );
+        assert_eq!(
+            analysis.accesses,
+            vec![
+                access("archive", Operation::Insert),
+                access("events", Operation::Select),
+                access("users", Operation::Update),
+                access("bans", Operation::Select),
+                access("sessions", Operation::Delete),
+                access("audit_log", Operation::Truncate),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ctes_and_table_functions_are_not_relations() {
+        let analysis = analyze(
+            "WITH recent AS (SELECT * FROM orders) \
+             SELECT * FROM recent, generate_series(1, 3)",
+        );
+        assert_eq!(analysis.accesses, vec![access("orders", Operation::Select)]);
+    }
+
+    #[test]
+    fn test_unanalyzable_sql_degrades() {
+        let analysis = analyze("SELEC nonsense FROM");
+        assert!(analysis.accesses.is_empty());
+        assert_eq!(analysis.unanalyzed.len(), 1);
+        assert!(analysis.unanalyzed[0].starts_with("could not analyze"));
+
+        let analysis = analyze("CREATE INDEX idx ON users (email); SELECT * FROM users");
+        assert_eq!(analysis.accesses, vec![access("users", Operation::Select)]);
+        assert_eq!(
+            analysis.unanalyzed,
+            vec!["could not analyze: CREATE statements are not checked"]
+        );
+    }
+
+    #[test]
+    fn test_privilege_sql() {
+        let sql = privilege_sql(&[
+            access("public.users", Operation::Select),
+            access("o'brien", Operation::Delete),
+        ]);
+        assert!(sql.contains("(1, 'public.users', 'SELECT'),\n  (2, 'o''brien', 'DELETE')\n"));
+        assert!(sql.contains("pg_catalog.has_table_privilege(r.relation, r.privilege)"));
+        assert!(sql.contains("pg_catalog.to_regclass(r.relation) IS NULL THEN 'missing'"));
+        assert!(sql.ends_with("ORDER BY r.n;"));
+    }
+}
@@ -0,0 +1,470 @@
+//! `\prompt` input placeholders and `:var` interpolation
+//!
+//! A line like `\prompt 'Customer id' cust_id` in the SQL file declares a
+//! value the editor asks the user for. Executing the file with unanswered
+//! prompts runs nothing: the prompts are reported as pending, the plugin asks
+//! for each value and executes again. Answered values are substituted for
+//! `:cust_id` (or `:'cust_id'`) as quoted literals and for `:"cust_id"` as a
+//! quoted identifier. Answers last until the SQL file changes.
+//!
+//! Files run through `\i` are resolved the same way; their prompts join the
+//! SQL file's and share its answers.
+
+use crate::sql;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// A value declared by a `\prompt` line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    pub variable: String,
+    /// Text shown when asking; the variable name when not given
+    pub label: String,
+}
+
+/// What an execution of the SQL file should do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Run this SQL: `\prompt` lines removed and answers interpolated
+    Ready(String),
+    /// Ask for these values first
+    Pending(Vec<Prompt>),
+}
+
+/// Prompt answers for one connection's session
+#[derive(Debug, Default)]
+pub struct PromptState {
+    /// SQL file contents the answers were given for
+    source: Option<String>,
+    prompts: Vec<Prompt>,
+    values: HashMap<String, String>,
+}
+
+impl PromptState {
+    /// Resolve the SQL file `contents` for execution
+    ///
+    /// Answers given for different contents are discarded first.
+    pub fn resolve(&mut self, contents: &str) -> Result<Resolution> {
+        if self.source.as_deref() != Some(contents) {
+            self.source = Some(contents.to_string());
+            self.values.clear();
+        }
+
+        let (prompts, sql) = extract_prompts(contents)?;
+        self.prompts = prompts;
+
+        let pending = self.pending();
+        if !pending.is_empty() {
+            return Ok(Resolution::Pending(pending));
+        }
+        if self.prompts.is_empty() {
+            return Ok(Resolution::Ready(sql));
+        }
+        Ok(Resolution::Ready(interpolate(&sql, &self.values)))
+    }
+
+    /// Resolve the contents of a file the SQL file runs through \\i
+    ///
+    /// Its prompts are added to the SQL file's, so answering them and
+    /// executing the SQL file again gets past them; the answers are kept until
+    /// the SQL file itself changes.
+    pub fn resolve_included(&mut self, contents: &str) -> Result<Resolution> {
+        let (prompts, sql) = extract_prompts(contents)?;
+        let pending: Vec<Prompt> = prompts
+            .iter()
+            .filter(|prompt| !self.values.contains_key(&prompt.variable))
+            .cloned()
+            .collect();
+        for prompt in prompts {
+            if !self.prompts.iter().any(|p| p.variable == prompt.variable) {
+                self.prompts.push(prompt);
+            }
+        }
+
+        if !pending.is_empty() {
+            return Ok(Resolution::Pending(pending));
+        }
+        if self.prompts.is_empty() {
+            return Ok(Resolution::Ready(sql));
+        }
+        Ok(Resolution::Ready(interpolate(&sql, &self.values)))
+    }
+
+    /// Prompts of the last resolved SQL that have no answer yet
+    pub fn pending(&self) -> Vec<Prompt> {
+        self.prompts
+            .iter()
+            .filter(|prompt| !self.values.contains_key(&prompt.variable))
+            .cloned()
+            .collect()
+    }
+
+    /// Answer the prompt for `variable`
+    pub fn provide(&mut self, variable: &str, value: &str) -> Result<()> {
+        if !self.prompts.iter().any(|p| p.variable == variable) {
+            anyhow::bail!("No \\prompt for '{}' in the SQL file", variable);
+        }
+        self.values.insert(variable.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Split `\prompt [label] variable` lines off `sql`
+///
+/// The label may be single-quoted to include spaces. Returns the prompts in
+/// order and the SQL without those lines.
+pub fn extract_prompts(sql: &str) -> Result<(Vec<Prompt>, String)> {
+    let mut prompts: Vec<Prompt> = Vec::new();
+    let mut rest = String::new();
+
+    for line in sql.lines() {
+        let Some(args) = line.trim().strip_prefix("\\prompt") else {
+            rest.push_str(line);
+            rest.push('\n');
+            continue;
+        };
+        if !args.is_empty() && !args.starts_with(char::is_whitespace) {
+            // e.g. \promptly: not ours
+            rest.push_str(line);
+            rest.push('\n');
+            continue;
+        }
+
+        let prompt = parse_prompt(args.trim())?;
+        if !prompts.iter().any(|p| p.variable == prompt.variable) {
+            prompts.push(prompt);
+        }
+    }
+
+    Ok((prompts, rest))
+}
+
+fn parse_prompt(args: &str) -> Result<Prompt> {
+    let (label, variable) = match args.strip_prefix('\'') {
+        Some(quoted) => {
+            let end = quoted
+                .find('\'')
+                .ok_or_else(|| anyhow::anyhow!("Unterminated label in \\prompt {}", args))?;
+            (Some(&quoted[..end]), quoted[end + 1..].trim())
+        }
+        None => match args.rsplit_once(char::is_whitespace) {
+            Some((label, variable)) => (Some(label.trim()), variable),
+            None => (None, args),
+        },
+    };
+
+    if !is_variable_name(variable) {
+        anyhow::bail!(
+            "\\prompt needs a variable name, e.g. \\prompt 'Customer id' cust_id (got '{}')",
+            args
+        );
+    }
+    Ok(Prompt {
+        variable: variable.to_string(),
+        label: label
+            .filter(|label| !label.is_empty())
+            .unwrap_or(variable)
+            .to_string(),
+    })
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Substitute `values` for `:name`, `:'name'` (quoted literals) and `:"name"`
+/// (quoted identifiers)
+///
+/// References inside string literals, quoted identifiers, dollar-quoted
+/// bodies and comments are left alone, as are `::` casts and names without a
+/// value.
+pub fn interpolate(sql: &str, values: &HashMap<String, String>) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut out = String::with_capacity(sql.len());
+    let mut i = 0;
+
+    // Copy chars[start..end] and continue after them
+    let copy = |out: &mut String, start: usize, end: usize| {
+        out.extend(&chars[start..end.min(chars.len())]);
+        end
+    };
+
+    while i < chars.len() {
+        match chars[i] {
+            '\'' => {
+                let escapes = i > 0 && matches!(chars[i - 1], 'E' | 'e');
+                i = copy(&mut out, i, skip_quoted(&chars, i, '\'', escapes));
+            }
+            '"' => i = copy(&mut out, i, skip_quoted(&chars, i, '"', false)),
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(chars.len(), |n| i + n);
+                i = copy(&mut out, i, end);
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i = copy(&mut out, i, skip_block_comment(&chars, i))
+            }
+            '$' => match dollar_tag(&chars, i) {
+                Some(tag) => i = copy(&mut out, i, skip_dollar_quoted(&chars, i, &tag)),
+                None => i = copy(&mut out, i, i + 1),
+            },
+            ':' if chars.get(i + 1) == Some(&':') => i = copy(&mut out, i, i + 2),
+            ':' => match variable_reference(&chars, i) {
+                Some((name, quote, end)) if values.contains_key(&name) => {
+                    let value = &values[&name];
+                    out.push_str(&match quote {
+                        Some('"') => sql::ident(value),
+                        _ => sql::literal(value),
+                    });
+                    i = end;
+                }
+                _ => i = copy(&mut out, i, i + 1),
+            },
+            _ => i = copy(&mut out, i, i + 1),
+        }
+    }
+    out
+}
+
+/// `:name`, `:'name'` or `:"name"` at `start`: the name, quote and end index
+fn variable_reference(chars: &[char], start: usize) -> Option<(String, Option<char>, usize)> {
+    let quote = chars
+        .get(start + 1)
+        .copied()
+        .filter(|c| *c == '\'' || *c == '"');
+    let name_start = start + 1 + usize::from(quote.is_some());
+    let name_end = chars[name_start..]
+        .iter()
+        .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+        .map_or(chars.len(), |n| name_start + n);
+
+    let name: String = chars[name_start..name_end].iter().collect();
+    if !is_variable_name(&name) {
+        return None;
+    }
+    match quote {
+        Some(q) if chars.get(name_end) == Some(&q) => Some((name, quote, name_end + 1)),
+        Some(_) => None,
+        None => Some((name, None, name_end)),
+    }
+}
+
+/// End index (exclusive) of the quoted text starting at `start`
+fn skip_quoted(chars: &[char], start: usize, quote: char, escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if escapes && chars[i] == '\\' {
+            i += 2;
+        } else if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+/// End index (exclusive) of the possibly nested block comment at `start`
+fn skip_block_comment(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+            depth += 1;
+            i += 2;
+        } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+/// `$tag$` opening a dollar-quoted string at `start`, if any
+fn dollar_tag(chars: &[char], start: usize) -> Option<String> {
+    // $1 is a parameter, not a tag
+    if chars.get(start + 1).is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let len = chars[start + 1..]
+        .iter()
+        .position(|c| !(c.is_alphanumeric() || *c == '_'))?;
+    (chars.get(start + 1 + len) == Some(&'$'))
+        .then(|| chars[start..start + len + 2].iter().collect())
+}
+
+/// End index (exclusive) of the dollar-quoted string opened by `tag` at `start`
+fn skip_dollar_quoted(chars: &[char], start: usize, tag: &str) -> usize {
+    let tag: Vec<char> = tag.chars().collect();
+    let body = start + tag.len();
+    (body..chars.len())
+        .find(|&i| chars[i..].starts_with(&tag))
+        .map_or(chars.len(), |i| i + tag.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    const SQL: &str = "\\prompt 'Customer id' cust_id\n\
+                       \\prompt since\n\
+                       SELECT * FROM orders\n\
+                       WHERE customer_id = :cust_id AND created_at > :'since';\n";
+
+    #[test]
+    fn test_extract_prompts() {
+        let (prompts, rest) = extract_prompts(SQL).unwrap();
+        assert_eq!(
+            prompts,
+            vec![
+                Prompt {
+                    variable: "cust_id".to_string(),
+                    label: "Customer id".to_string()
+                },
+                Prompt {
+                    variable: "since".to_string(),
+                    label: "since".to_string()
+                },
+            ]
+        );
+        assert!(rest.starts_with("SELECT * FROM orders\n"));
+
+        assert!(extract_prompts("\\prompt 'Customer id'\n").is_err());
+        assert!(extract_prompts("\\prompt 'unterminated cust_id\n").is_err());
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut state = PromptState::default();
+
+        let Resolution::Pending(pending) = state.resolve(SQL).unwrap() else {
+            panic!("expected pending prompts");
+        };
+        assert_eq!(pending.len(), 2);
+
+        state.provide("cust_id", "42").unwrap();
+        assert_eq!(state.pending().len(), 1);
+        assert!(matches!(
+            state.resolve(SQL).unwrap(),
+            Resolution::Pending(p) if p[0].variable == "since"
+        ));
+
+        state.provide("since", "2024-01-01").unwrap();
+        assert_eq!(
+            state.resolve(SQL).unwrap(),
+            Resolution::Ready(
+                "SELECT * FROM orders\n\
+                 WHERE customer_id = '42' AND created_at > '2024-01-01';\n"
+                    .to_string()
+            )
+        );
+
+        // Answers are kept for re-executions of the same file
+        assert!(matches!(state.resolve(SQL).unwrap(), Resolution::Ready(_)));
+    }
+
+    #[test]
+    fn test_included_prompts_share_the_sql_files_answers() {
+        const MAIN: &str = "\\i report.sql\n";
+        const INCLUDED: &str = "\\prompt 'Customer id' cust_id\nSELECT :cust_id;\n";
+        let mut state = PromptState::default();
+
+        assert_eq!(
+            state.resolve(MAIN).unwrap(),
+            Resolution::Ready(MAIN.to_string())
+        );
+        assert!(matches!(
+            state.resolve_included(INCLUDED).unwrap(),
+            Resolution::Pending(p) if p[0].variable == "cust_id"
+        ));
+        assert_eq!(state.pending().len(), 1);
+
+        // Answered, then the SQL file runs again and reaches the include
+        state.provide("cust_id", "42").unwrap();
+        assert!(matches!(state.resolve(MAIN).unwrap(), Resolution::Ready(_)));
+        assert_eq!(
+            state.resolve_included(INCLUDED).unwrap(),
+            Resolution::Ready("SELECT '42';\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_answers_cleared_when_file_changes() {
+        let mut state = PromptState::default();
+        state.resolve(SQL).unwrap();
+        state.provide("cust_id", "42").unwrap();
+        state.provide("since", "2024-01-01").unwrap();
+
+        let edited = SQL.replace("orders", "invoices");
+        assert!(matches!(
+            state.resolve(&edited).unwrap(),
+            Resolution::Pending(p) if p.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_provide_unknown_variable_fails() {
+        let mut state = PromptState::default();
+        state.resolve(SQL).unwrap();
+        assert!(state.provide("customer", "1").is_err());
+    }
+
+    #[test]
+    fn test_sql_without_prompts_is_unchanged() {
+        let mut state = PromptState::default();
+        let sql = "SELECT ':not_a_var', now()::date;\n";
+        assert_eq!(
+            state.resolve(sql).unwrap(),
+            Resolution::Ready(sql.to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpolated_values_are_quoted() {
+        let vars = values(&[
+            ("name", "O'Brien'; DROP TABLE users; --"),
+            ("col", "we\"ird"),
+        ]);
+        assert_eq!(
+            interpolate(
+                "SELECT :\"col\" FROM t WHERE a = :name OR b = :'name'",
+                &vars
+            ),
+            "SELECT \"we\"\"ird\" FROM t WHERE a = 'O''Brien''; DROP TABLE users; --' \
+             OR b = 'O''Brien''; DROP TABLE users; --'"
+        );
+    }
+
+    #[test]
+    fn test_interpolate_skips_quoted_text_and_casts() {
+        let vars = values(&[("id", "7")]);
+        let sql = "SELECT ':id', \":id\", $$ :id $$, $f$ :id $f$, E'\\' :id', \
+                   x::int, $1 -- :id\n/* :id /* :id */ */ FROM t WHERE id = :id AND y = :other";
+        assert_eq!(
+            interpolate(sql, &vars),
+            sql.replacen("id = :id", "id = '7'", 1)
+        );
+    }
+}
@@ -0,0 +1,417 @@
+//! Writing large results to results.dbout while they arrive
+//!
+//! Plain queries shown as tables receive their rows through a channel as the
+//! server sends them. A result that fits in one `stream_chunk_rows` chunk is
+//! handed back and rendered as usual. A longer one is written to
+//! results.dbout chunk by chunk, so rows show up while the query runs and never
+//! all sit in memory.
+//!
+//! The header of a streamed result is written first, with padded placeholders
+//! for the execution time and row count that are overwritten in place once the
+//! query ends. A query that fails midway leaves the rows so far in the file,
+//! with the error below them. Once the file reaches `max_output_bytes`, the
+//! remaining rows are still read and counted but no longer written.
+//!
+//! Like `Workspace::write_results`, the file is written as a temporary one
+//! beside results.dbout and renamed over it, once the placeholder header is
+//! in it, so the previous results are never seen cut short. From then on it
+//! only grows, and the final header replaces placeholders of its own length.
+
+use crate::client::StreamedRow;
+use crate::display::{self, DisplaySettings};
+use crate::output::{self, DocumentWriter, RenderOptions, Section, TableStream};
+use crate::run_report::RunError;
+use crate::value::CellValue;
+use crate::workspace;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Rows in flight between the database and results.dbout
+pub const CHANNEL_ROWS: usize = 1024;
+
+/// Width the execution time and row count lines are padded to, so the final
+/// values fit over the placeholders
+const HEADER_LINE_WIDTH: usize = 32;
+
+/// What `receive` did with a result
+pub enum Received {
+    /// The whole result, which fit in one chunk, for the usual rendering
+    Buffered {
+        header: Vec<String>,
+        rows: Vec<Vec<CellValue>>,
+    },
+    /// Rows written to results.dbout; `finish` completes the file
+    Streamed(Box<StreamedResult>),
+}
+
+/// A result written to results.dbout whose header still holds placeholders
+pub struct StreamedResult {
+    writer: DocumentWriter<BufWriter<File>>,
+    options: RenderOptions,
+    /// The placeholder header as written at the start of the file
+    placeholder: String,
+    display: DisplaySettings,
+    started_at: DateTime<Local>,
+    pub row_count: usize,
+    /// Rows written before `max_output_bytes` stopped the output, if it did
+    shown_rows: Option<usize>,
+    /// Values cut short by `max_column_width`
+    truncated_values: usize,
+}
+
+/// Read `rows` until the result ends, writing it to `dbout` once it has more
+/// rows than fit in one chunk
+pub async fn receive(
+    mut rows: mpsc::Receiver<StreamedRow>,
+    dbout: &Path,
+    display: &DisplaySettings,
+    options: &RenderOptions,
+    started_at: DateTime<Local>,
+) -> Result<Received> {
+    let chunk_rows = options.stream_chunk_rows.max(1);
+    let mut header = Vec::new();
+    let mut pending = Vec::new();
+    while let Some(message) = rows.recv().await {
+        match message {
+            StreamedRow::Header(names) => header = names,
+            StreamedRow::Row(row) => pending.push(row),
+        }
+        if pending.len() > chunk_rows {
+            break;
+        }
+    }
+    if pending.len() <= chunk_rows {
+        return Ok(Received::Buffered {
+            header,
+            rows: pending,
+        });
+    }
+
+    let temp = workspace::temp_path(dbout);
+    let file = File::create(&temp)
+        .with_context(|| format!("Failed to write results to {}", temp.display()))?;
+    let mut writer = DocumentWriter::new(BufWriter::new(file), options.clone());
+    let placeholder = Section::Metadata(header_lines(display, started_at, None));
+    let started = writer
+        .section(&placeholder)
+        .and_then(|_| writer.flush())
+        .and_then(|_| {
+            fs::rename(&temp, dbout)
+                .with_context(|| format!("Failed to write results to {}", dbout.display()))
+        });
+    if let Err(e) = started {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    let mut truncated_values = 0;
+    let mut to_strings = |row: Vec<CellValue>| {
+        let mut cells: Vec<String> = row
+            .iter()
+            .map(|value| value.display(&display.null_display))
+            .collect();
+        truncated_values +=
+            display::truncate_cells(std::slice::from_mut(&mut cells), display.max_column_width);
+        cells
+    };
+
+    let max_bytes = options.max_output_bytes;
+    let mut row_count = 0;
+    let mut shown_rows = None;
+    let mut take = |table: &mut TableStream<'_, BufWriter<File>>, row: Vec<CellValue>| {
+        row_count += 1;
+        if shown_rows.is_none() {
+            table.push_row(to_strings(row))?;
+            if max_bytes > 0 && table.bytes_written() >= max_bytes {
+                shown_rows = Some(row_count);
+            }
+        }
+        anyhow::Ok(())
+    };
+
+    let mut table = writer.table_stream(header);
+    for row in pending {
+        take(&mut table, row)?;
+    }
+    while let Some(message) = rows.recv().await {
+        if let StreamedRow::Row(row) = message {
+            take(&mut table, row)?;
+        }
+    }
+    table.finish()?;
+    if let Some(shown) = shown_rows {
+        log::warn!(
+            "Streamed output of {} rows cut to {} rows by max_output_bytes ({})",
+            row_count,
+            shown,
+            max_bytes
+        );
+    }
+
+    Ok(Received::Streamed(Box::new(StreamedResult {
+        writer,
+        options: options.clone(),
+        placeholder: placeholder.render(options),
+        display: display.clone(),
+        started_at,
+        row_count,
+        shown_rows,
+        truncated_values,
+    })))
+}
+
+impl StreamedResult {
+    /// Write what follows the rows (notes, or the error that ended the query)
+    /// and put the final execution time and row count in the header
+    ///
+    /// `outcome` is whether rows past `max_rows` were left unread, or the error.
+    pub fn finish(mut self, duration: Duration, outcome: Result<bool, &RunError>) -> Result<()> {
+        let mut footer = Vec::new();
+        if self.truncated_values > 0 {
+            footer.push(display::truncation_note(
+                self.truncated_values,
+                self.display.max_column_width,
+            ));
+        }
+        if let Some(shown) = self.shown_rows {
+            footer.push(output::max_output_note(
+                self.options.max_output_bytes,
+                shown,
+                self.row_count,
+            ));
+        }
+        match outcome {
+            Ok(true) => footer.push(output::max_rows_note(self.row_count)),
+            Ok(false) => {}
+            Err(error) => {
+                self.writer
+                    .section(&Section::Text(format!("ERROR: {}\n", error.message)))?;
+            }
+        }
+        if !footer.is_empty() {
+            self.writer.section(&Section::Footer(footer))?;
+        }
+
+        let mut file = self
+            .writer
+            .finish()?
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush results")?;
+        let header = Section::Metadata(header_lines(
+            &self.display,
+            self.started_at,
+            Some((duration, self.row_count)),
+        ))
+        .render(&self.options);
+        if header.len() != self.placeholder.len() {
+            log::warn!("Streamed result header no longer fits its placeholder");
+            return Ok(());
+        }
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.write_all(header.as_bytes()))
+            .context("Failed to write results header")
+    }
+}
+
+/// Header lines of a streamed result: placeholders while it runs, then its
+/// execution time and row count
+fn header_lines(
+    display: &DisplaySettings,
+    started_at: DateTime<Local>,
+    finished: Option<(Duration, usize)>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    if display.timing {
+        lines.push(format!(
+            "Executed at: {}",
+            started_at.format("%Y-%m-%d %H:%M:%S")
+        ));
+        let time = match finished {
+            Some((duration, _)) => format!("{:.3}s", duration.as_secs_f64()),
+            None => "running...".to_string(),
+        };
+        lines.push(pad(format!("Execution time: {}", time)));
+    }
+    if !display.tuples_only {
+        let rows = match finished {
+            Some((_, rows)) => rows.to_string(),
+            None => "streaming...".to_string(),
+        };
+        lines.push(pad(format!("Rows returned: {}", rows)));
+    }
+    lines
+}
+
+fn pad(line: String) -> String {
+    format!("{:<width$}", line, width = HEADER_LINE_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn dbout(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("helix-dadbod-result-stream-test");
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn options() -> RenderOptions {
+        RenderOptions {
+            stream_chunk_rows: 2,
+            ..RenderOptions::default()
+        }
+    }
+
+    fn started_at() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    /// A channel already holding a header and `count` rows, and closed
+    fn result(count: i64) -> mpsc::Receiver<StreamedRow> {
+        let (tx, rx) = mpsc::channel(100);
+        tx.try_send(StreamedRow::Header(vec!["id".to_string()]))
+            .unwrap();
+        for id in 1..=count {
+            tx.try_send(StreamedRow::Row(vec![CellValue::Int(id)]))
+                .unwrap();
+        }
+        rx
+    }
+
+    #[tokio::test]
+    async fn test_short_result_is_buffered() {
+        let path = dbout("buffered.dbout");
+        let _ = fs::remove_file(&path);
+
+        let received = receive(
+            result(2),
+            &path,
+            &DisplaySettings::default(),
+            &options(),
+            started_at(),
+        )
+        .await
+        .unwrap();
+        let Received::Buffered { header, rows } = received else {
+            panic!("a result of one chunk is not streamed");
+        };
+        assert_eq!(header, vec!["id"]);
+        assert_eq!(rows.len(), 2);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_long_result_is_written_while_it_arrives() {
+        let path = dbout("streamed.dbout");
+        let received = receive(
+            result(5),
+            &path,
+            &DisplaySettings::default(),
+            &options(),
+            started_at(),
+        )
+        .await
+        .unwrap();
+        let Received::Streamed(streamed) = received else {
+            panic!("a result longer than a chunk is streamed");
+        };
+        assert_eq!(streamed.row_count, 5);
+
+        let partial = fs::read_to_string(&path).unwrap();
+        assert!(partial.starts_with(
+            "-- Executed at: 2024-01-02 03:04:05\n\
+             -- Execution time: running...      \n\
+             -- Rows returned: streaming...     \n\n"
+        ));
+        assert!(partial.contains("│5  │"), "{}", partial);
+
+        streamed
+            .finish(Duration::from_millis(1234), Ok(true))
+            .unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with(
+            "-- Executed at: 2024-01-02 03:04:05\n\
+             -- Execution time: 1.234s          \n\
+             -- Rows returned: 5                \n\n"
+        ));
+        assert!(content.ends_with("\n-- Output truncated at 5 rows (max_rows); total unknown\n"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rows_past_max_output_bytes_are_counted_not_written() {
+        let path = dbout("capped.dbout");
+        let options = RenderOptions {
+            max_output_bytes: 1,
+            ..options()
+        };
+        let Received::Streamed(streamed) = receive(
+            result(6),
+            &path,
+            &DisplaySettings::default(),
+            &options,
+            started_at(),
+        )
+        .await
+        .unwrap() else {
+            panic!("a result longer than a chunk is streamed");
+        };
+        assert_eq!(streamed.row_count, 6);
+        streamed
+            .finish(Duration::from_millis(5), Ok(false))
+            .unwrap();
+
+        // The header alone reaches the cap, so only the first row is written
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("-- Rows returned: 6 "));
+        assert!(content.contains("│1  │"));
+        assert!(!content.contains("│2  │"), "{}", content);
+        assert!(content.ends_with(
+            "\n-- Output truncated at 1 bytes; 1 of 6 rows shown (increase max_output_bytes to see more)\n"
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_error_midway_keeps_the_rows_so_far() {
+        let path = dbout("failed.dbout");
+        let display = DisplaySettings {
+            timing: false,
+            ..DisplaySettings::default()
+        };
+        let Received::Streamed(streamed) =
+            receive(result(3), &path, &display, &options(), started_at())
+                .await
+                .unwrap()
+        else {
+            panic!("a result longer than a chunk is streamed");
+        };
+        let error = RunError::client("connection reset");
+        streamed
+            .finish(Duration::from_millis(5), Err(&error))
+            .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("-- Rows returned: 3                \n\n"));
+        assert!(content.contains("│3  │"));
+        assert!(
+            content.ends_with("\n\nERROR: connection reset\n"),
+            "{}",
+            content
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
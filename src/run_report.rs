@@ -0,0 +1,354 @@
+//! Machine-readable report of the last execution
+//!
+//! Every execution also writes `last_run.json` to the workspace so tools can
+//! check the outcome without parsing results.dbout. The format is versioned by
+//! its `schema` field; fields are only ever added within a schema version.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// File name of the report inside the workspace directory
+pub const REPORT_FILE: &str = "last_run.json";
+
+/// Version of the report format
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Statements longer than this many characters are truncated in the report
+pub const MAX_STATEMENT_CHARS: usize = 1000;
+
+/// How an execution ended
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    /// The server rejected the statement
+    DbError,
+    /// The statement failed before or after reaching the server, e.g. the
+    /// connection dropped or results.dbout could not be written
+    ClientError,
+    /// Nothing ran: the SQL file's \prompt values are still missing
+    Pending,
+}
+
+/// Error details; everything but `message` is only known for server errors
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunError {
+    /// SQLSTATE, e.g. `42P01`
+    pub code: Option<String>,
+    pub message: String,
+    /// 1-based character position in the statement
+    pub position: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    /// Where the error occurred, e.g. the PL/pgSQL function and line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+impl RunError {
+    pub fn from_db_error(err: &tokio_postgres::error::DbError) -> Self {
+        let position = match err.position() {
+            Some(tokio_postgres::error::ErrorPosition::Original(position)) => Some(*position),
+            _ => None,
+        };
+        Self {
+            code: Some(err.code().code().to_string()),
+            message: err.message().to_string(),
+            position,
+            detail: err.detail().map(str::to_string),
+            hint: err.hint().map(str::to_string),
+            context: err.where_().map(str::to_string),
+        }
+    }
+
+    pub fn client(message: impl Into<String>) -> Self {
+        Self {
+            code: None,
+            message: message.into(),
+            position: None,
+            detail: None,
+            hint: None,
+            context: None,
+        }
+    }
+
+    /// The error as written to results.dbout, like psql shows it: the message,
+    /// the line of `sql` (the statement sent) at `position` with a caret under
+    /// the spot, then DETAIL, HINT and CONTEXT
+    pub fn describe(&self, sql: &str) -> String {
+        let mut text = format!("ERROR: {}\n", self.message);
+        if let Some((number, line, column)) = self.position.and_then(|p| locate(sql, p)) {
+            let prefix = format!("LINE {}: ", number);
+            // Tabs stay tabs so the caret lines up however they are shown
+            let indent: String = line
+                .chars()
+                .take(column)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            text.push_str(&format!(
+                "{}{}\n{}{}^\n",
+                prefix,
+                line,
+                " ".repeat(prefix.len()),
+                indent
+            ));
+        }
+        let labelled = [
+            ("DETAIL", &self.detail),
+            ("HINT", &self.hint),
+            ("CONTEXT", &self.context),
+        ];
+        for (label, value) in labelled {
+            if let Some(value) = value {
+                text.push_str(&format!("{}:  {}\n", label, value));
+            }
+        }
+        text
+    }
+}
+
+/// Line number (1-based), text and column (in characters) of the 1-based
+/// character `position` in `sql`; one past the end is the end of input
+fn locate(sql: &str, position: u32) -> Option<(usize, &str, usize)> {
+    let offset = (position as usize).checked_sub(1)?;
+    let end = match sql.char_indices().nth(offset) {
+        Some((end, _)) => end,
+        None if sql.chars().count() == offset => sql.len(),
+        None => return None,
+    };
+    let before = &sql[..end];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = sql[line_start..].lines().next().unwrap_or("");
+    Some((
+        before.matches('\n').count() + 1,
+        line,
+        before[line_start..].chars().count(),
+    ))
+}
+
+/// Outcome of one execution, as written to `last_run.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub schema: u32,
+    pub connection: String,
+    /// Statement sent to the server (the generated SQL for meta-commands),
+    /// cut to `MAX_STATEMENT_CHARS`
+    pub statement: String,
+    pub statement_truncated: bool,
+    /// RFC 3339 timestamp
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub status: RunStatus,
+    /// Rows returned by a query
+    pub row_count: Option<u64>,
+    /// Rows changed by INSERT/UPDATE/DELETE, when known
+    pub affected_count: Option<u64>,
+    pub error: Option<RunError>,
+    /// How the result was written to results.dbout: `table`, `expanded`,
+    /// `unaligned` or `text`
+    pub output_format: String,
+}
+
+impl RunReport {
+    /// Report for `statement` on `connection` that started at `started_at`
+    /// and took `duration`; defaults to a successful run without counts
+    pub fn new(
+        connection: &str,
+        statement: &str,
+        started_at: DateTime<Local>,
+        duration: Duration,
+    ) -> Self {
+        let statement_truncated = statement.chars().count() > MAX_STATEMENT_CHARS;
+        Self {
+            schema: SCHEMA_VERSION,
+            connection: connection.to_string(),
+            statement: statement.chars().take(MAX_STATEMENT_CHARS).collect(),
+            statement_truncated,
+            started_at: started_at.to_rfc3339(),
+            duration_ms: duration.as_millis() as u64,
+            status: RunStatus::Success,
+            row_count: None,
+            affected_count: None,
+            error: None,
+            output_format: "text".to_string(),
+        }
+    }
+
+    pub fn with_rows(mut self, row_count: usize, output_format: &str) -> Self {
+        self.row_count = Some(row_count as u64);
+        self.output_format = output_format.to_string();
+        self
+    }
+
+    pub fn with_affected(mut self, affected_count: Option<u64>) -> Self {
+        self.affected_count = affected_count;
+        self
+    }
+
+    pub fn with_status(mut self, status: RunStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_error(mut self, status: RunStatus, error: RunError) -> Self {
+        self.status = status;
+        self.error = Some(error);
+        self
+    }
+}
+
+/// Write `report` as the workspace's `last_run.json`
+pub fn write_report(dir: &Path, report: &RunReport) -> Result<()> {
+    let path = dir.join(REPORT_FILE);
+    let json = serde_json::to_string_pretty(report)?;
+    fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Read the workspace's `last_run.json`, if anything has run yet
+pub fn read_report(dir: &Path) -> Result<Option<RunReport>> {
+    let path = dir.join(REPORT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let report = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn report(statement: &str) -> RunReport {
+        let started_at = Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        RunReport::new("local", statement, started_at, Duration::from_millis(1234))
+    }
+
+    fn to_json(report: &RunReport) -> serde_json::Value {
+        serde_json::to_value(report).unwrap()
+    }
+
+    #[test]
+    fn test_success_report() {
+        let report = report("SELECT * FROM users").with_rows(2, "table");
+        let value = to_json(&report);
+
+        assert_eq!(value["schema"], json!(1));
+        assert_eq!(value["connection"], json!("local"));
+        assert_eq!(value["statement"], json!("SELECT * FROM users"));
+        assert_eq!(value["statement_truncated"], json!(false));
+        assert_eq!(value["duration_ms"], json!(1234));
+        assert_eq!(value["status"], json!("success"));
+        assert_eq!(value["row_count"], json!(2));
+        assert_eq!(value["affected_count"], json!(null));
+        assert_eq!(value["error"], json!(null));
+        assert_eq!(value["output_format"], json!("table"));
+        assert!(value["started_at"]
+            .as_str()
+            .unwrap()
+            .starts_with("2024-01-02T03:04:05"));
+    }
+
+    #[test]
+    fn test_db_error_report() {
+        let error = RunError {
+            code: Some("42P01".to_string()),
+            position: Some(15),
+            ..RunError::client("relation \"nope\" does not exist")
+        };
+        let value = to_json(&report("SELECT * FROM nope").with_error(RunStatus::DbError, error));
+
+        assert_eq!(value["status"], json!("db_error"));
+        assert_eq!(
+            value["error"],
+            json!({
+                "code": "42P01",
+                "message": "relation \"nope\" does not exist",
+                "position": 15
+            })
+        );
+        assert_eq!(value["row_count"], json!(null));
+        assert_eq!(value["output_format"], json!("text"));
+    }
+
+    #[test]
+    fn test_describe_points_at_the_error() {
+        let sql = "SELECT id,\n       name\nFROM users WHERE id IN (1, 2,)\nORDER BY id";
+        let error = RunError {
+            code: Some("42601".to_string()),
+            position: Some(sql.find(')').unwrap() as u32 + 1),
+            hint: Some("Remove the trailing comma".to_string()),
+            ..RunError::client("syntax error at or near \")\"")
+        };
+        assert_eq!(
+            error.describe(sql),
+            format!(
+                "ERROR: syntax error at or near \")\"\n\
+                 LINE 3: FROM users WHERE id IN (1, 2,)\n\
+                 {}^\n\
+                 HINT:  Remove the trailing comma\n",
+                " ".repeat("LINE 3: FROM users WHERE id IN (1, 2,".len())
+            )
+        );
+
+        // A position past the statement (or without one) shows no excerpt
+        let error = RunError {
+            position: Some(100),
+            ..RunError::client("boom")
+        };
+        assert_eq!(error.describe("SELECT 1"), "ERROR: boom\n");
+        assert_eq!(
+            locate("SELECT (", 9),
+            Some((1, "SELECT (", 8)),
+            "end of input"
+        );
+        assert_eq!(locate("\tSELECT é x", 11), Some((1, "\tSELECT é x", 10)));
+    }
+
+    #[test]
+    fn test_client_error_report() {
+        let value = to_json(&report("SELECT 1").with_error(
+            RunStatus::ClientError,
+            RunError::client("connection closed"),
+        ));
+
+        assert_eq!(value["status"], json!("client_error"));
+        assert_eq!(
+            value["error"],
+            json!({ "code": null, "message": "connection closed", "position": null })
+        );
+    }
+
+    #[test]
+    fn test_long_statement_truncated() {
+        let statement = format!("SELECT '{}'", "é".repeat(2 * MAX_STATEMENT_CHARS));
+        let report = report(&statement);
+        assert!(report.statement_truncated);
+        assert_eq!(report.statement.chars().count(), MAX_STATEMENT_CHARS);
+    }
+
+    #[test]
+    fn test_report_round_trips_through_file() {
+        let dir = std::env::temp_dir().join("helix-dadbod-run-report-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(read_report(&dir).unwrap(), None);
+
+        let report = report("SELECT 1").with_rows(1, "expanded");
+        write_report(&dir, &report).unwrap();
+        assert_eq!(read_report(&dir).unwrap(), Some(report));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
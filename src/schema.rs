@@ -0,0 +1,345 @@
+//! Schema introspection and identifier handling
+//!
+//! Parses user-supplied table names the way PostgreSQL does (unquoted parts
+//! fold to lower case, quoted parts are kept verbatim) and looks up table
+//! structure such as primary key columns.
+
+use crate::sql;
+use anyhow::{Context, Result};
+use tokio_postgres::Client;
+
+/// Optionally schema-qualified table name
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifiedName {
+    pub schema: Option<String>,
+    pub name: String,
+}
+
+impl QualifiedName {
+    /// Parse `table`, `schema.table` or their quoted forms, e.g. `"Sales"."Orders"`
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut current = String::new();
+        let mut quoted = false;
+        let mut was_quoted = false;
+        let mut chars = input.trim().chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        current.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                }
+                '"' => {
+                    quoted = true;
+                    was_quoted = true;
+                }
+                '.' if !quoted => {
+                    parts.push(Self::finish_part(&current, was_quoted, input)?);
+                    current.clear();
+                    was_quoted = false;
+                }
+                _ if quoted => current.push(c),
+                _ => current.extend(c.to_lowercase()),
+            }
+        }
+
+        if quoted {
+            anyhow::bail!("Unterminated quoted identifier in '{}'", input);
+        }
+        parts.push(Self::finish_part(&current, was_quoted, input)?);
+
+        match parts.len() {
+            1 => Ok(Self {
+                schema: None,
+                name: parts.remove(0),
+            }),
+            2 => Ok(Self {
+                name: parts.remove(1),
+                schema: Some(parts.remove(0)),
+            }),
+            _ => anyhow::bail!(
+                "Invalid table name '{}': expected table or schema.table",
+                input
+            ),
+        }
+    }
+
+    fn finish_part(part: &str, was_quoted: bool, input: &str) -> Result<String> {
+        let part = if was_quoted { part } else { part.trim() };
+        if part.is_empty() {
+            anyhow::bail!("Invalid table name '{}': empty identifier", input);
+        }
+        Ok(part.to_string())
+    }
+
+    /// The name with only the parts that need it quoted, e.g. `public."Orders"`
+    pub fn display(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!(
+                "{}.{}",
+                sql::ident_if_needed(schema),
+                sql::ident_if_needed(&self.name)
+            ),
+            None => sql::ident_if_needed(&self.name),
+        }
+    }
+
+    /// The name quoted for use in SQL, e.g. `"public"."Orders"`
+    pub fn quoted(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!("{}.{}", sql::ident(schema), sql::ident(&self.name)),
+            None => sql::ident(&self.name),
+        }
+    }
+}
+
+/// Primary key column with its SQL type as rendered by format_type()
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyColumn {
+    pub name: String,
+    pub type_name: String,
+}
+
+/// Look up the primary key columns of `table` in key order
+pub async fn primary_key_columns(client: &Client, table: &QualifiedName) -> Result<Vec<KeyColumn>> {
+    let sql = "SELECT a.attname::text, pg_catalog.format_type(a.atttypid, a.atttypmod)
+FROM pg_catalog.pg_index i
+JOIN pg_catalog.pg_attribute a
+  ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+WHERE i.indrelid = $1::text::regclass
+  AND i.indisprimary
+ORDER BY array_position(i.indkey::int2[], a.attnum)";
+
+    let rows = client
+        .query(sql, &[&table.quoted()])
+        .await
+        .with_context(|| format!("Failed to look up primary key of {}", table.quoted()))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| KeyColumn {
+            name: row.get(0),
+            type_name: row.get(1),
+        })
+        .collect())
+}
+
+/// Column of a table as needed for statement templates
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableColumn {
+    pub name: String,
+    pub type_name: String,
+    pub not_null: bool,
+    /// Default expression, e.g. `now()` or `nextval('users_id_seq'::regclass)`
+    pub default: Option<String>,
+    /// GENERATED { ALWAYS | BY DEFAULT } AS IDENTITY (PostgreSQL 10+)
+    pub is_identity: bool,
+    /// GENERATED ALWAYS AS (expr) STORED (PostgreSQL 12+)
+    pub is_generated: bool,
+}
+
+/// SQL listing the columns of the table bound to $1, for a server reporting
+/// `server_version_num`
+fn table_columns_sql(server_version_num: i32) -> String {
+    let identity = if server_version_num >= 100000 {
+        "a.attidentity <> ''"
+    } else {
+        "false"
+    };
+    let generated = if server_version_num >= 120000 {
+        "a.attgenerated <> ''"
+    } else {
+        "false"
+    };
+
+    format!(
+        "SELECT a.attname::text,
+  pg_catalog.format_type(a.atttypid, a.atttypmod),
+  a.attnotnull,
+  pg_catalog.pg_get_expr(d.adbin, d.adrelid),
+  {identity},
+  {generated}
+FROM pg_catalog.pg_attribute a
+LEFT JOIN pg_catalog.pg_attrdef d
+  ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+WHERE a.attrelid = $1::text::regclass
+  AND a.attnum > 0
+  AND NOT a.attisdropped
+ORDER BY a.attnum",
+    )
+}
+
+/// Look up the columns of `table` in table order
+pub async fn table_columns(
+    client: &Client,
+    table: &QualifiedName,
+    server_version_num: i32,
+) -> Result<Vec<TableColumn>> {
+    let rows = client
+        .query(&table_columns_sql(server_version_num), &[&table.quoted()])
+        .await
+        .with_context(|| format!("Failed to look up columns of {}", table.quoted()))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| TableColumn {
+            name: row.get(0),
+            type_name: row.get(1),
+            not_null: row.get(2),
+            default: row.get(3),
+            is_identity: row.get(4),
+            is_generated: row.get(5),
+        })
+        .collect())
+}
+
+/// Build `SELECT * FROM table WHERE key = $1 [AND ...]` for the given key columns
+///
+/// Values are bound as text and cast to each key column's type, so callers can
+/// pass whatever the user typed. Every key column needs a value.
+pub fn select_by_key_sql(
+    table: &QualifiedName,
+    keys: &[KeyColumn],
+    values: &[String],
+) -> Result<String> {
+    if keys.is_empty() {
+        anyhow::bail!("Table {} has no primary key", table.quoted());
+    }
+    if keys.len() != values.len() {
+        let names: Vec<&str> = keys.iter().map(|k| k.name.as_str()).collect();
+        anyhow::bail!(
+            "Table {} has a {}-column primary key ({}), but {} value(s) were given; \
+             supply one value per key column in that order",
+            table.quoted(),
+            keys.len(),
+            names.join(", "),
+            values.len()
+        );
+    }
+
+    let conditions: Vec<String> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+            format!(
+                "{} = ${}::text::{}",
+                sql::ident(&key.name),
+                i + 1,
+                key.type_name
+            )
+        })
+        .collect();
+
+    Ok(format!(
+        "SELECT * FROM {} WHERE {}",
+        table.quoted(),
+        conditions.join(" AND ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, type_name: &str) -> KeyColumn {
+        KeyColumn {
+            name: name.to_string(),
+            type_name: type_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_unquoted_folds_to_lower_case() {
+        let name = QualifiedName::parse("Public.Users").unwrap();
+        assert_eq!(name.schema.as_deref(), Some("public"));
+        assert_eq!(name.name, "users");
+        assert_eq!(name.quoted(), "\"public\".\"users\"");
+    }
+
+    #[test]
+    fn test_parse_quoted_mixed_case() {
+        let name = QualifiedName::parse("\"Sales\".\"Order Items\"").unwrap();
+        assert_eq!(name.schema.as_deref(), Some("Sales"));
+        assert_eq!(name.name, "Order Items");
+        assert_eq!(name.quoted(), "\"Sales\".\"Order Items\"");
+
+        let name = QualifiedName::parse("\"My.Table\"").unwrap();
+        assert_eq!(name.schema, None);
+        assert_eq!(name.name, "My.Table");
+
+        let name = QualifiedName::parse("\"a\"\"b\"").unwrap();
+        assert_eq!(name.quoted(), "\"a\"\"b\"");
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_names() {
+        assert!(QualifiedName::parse("").is_err());
+        assert!(QualifiedName::parse("a.b.c").is_err());
+        assert!(QualifiedName::parse("schema.").is_err());
+        assert!(QualifiedName::parse("\"open").is_err());
+    }
+
+    #[test]
+    fn test_display_quotes_only_where_needed() {
+        let name = QualifiedName::parse("public.\"Order Items\"").unwrap();
+        assert_eq!(name.display(), "public.\"Order Items\"");
+    }
+
+    #[test]
+    fn test_table_columns_sql_version_buckets() {
+        let sql = table_columns_sql(90600);
+        assert!(!sql.contains("attidentity"));
+        assert!(!sql.contains("attgenerated"));
+
+        let sql = table_columns_sql(110000);
+        assert!(sql.contains("a.attidentity <> ''"));
+        assert!(!sql.contains("attgenerated"));
+
+        let sql = table_columns_sql(160002);
+        assert!(sql.contains("a.attgenerated <> ''"));
+    }
+
+    #[test]
+    fn test_select_by_single_key() {
+        let table = QualifiedName::parse("public.users").unwrap();
+        let sql = select_by_key_sql(&table, &[key("id", "bigint")], &["42".to_string()]).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM \"public\".\"users\" WHERE \"id\" = $1::text::bigint"
+        );
+    }
+
+    #[test]
+    fn test_select_by_composite_key() {
+        let table = QualifiedName::parse("order_items").unwrap();
+        let keys = [key("order_id", "integer"), key("lineNo", "smallint")];
+        let sql = select_by_key_sql(&table, &keys, &["7".to_string(), "2".to_string()]).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM \"order_items\" WHERE \"order_id\" = $1::text::integer \
+             AND \"lineNo\" = $2::text::smallint"
+        );
+    }
+
+    #[test]
+    fn test_composite_key_requires_all_values() {
+        let table = QualifiedName::parse("order_items").unwrap();
+        let keys = [key("order_id", "integer"), key("line_no", "smallint")];
+        let err = select_by_key_sql(&table, &keys, &["7".to_string()]).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("2-column primary key (order_id, line_no)"));
+        assert!(msg.contains("1 value(s)"));
+    }
+
+    #[test]
+    fn test_table_without_primary_key() {
+        let table = QualifiedName::parse("logs").unwrap();
+        let err = select_by_key_sql(&table, &[], &["1".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("has no primary key"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Secrets read from external commands
+//!
+//! `password_cmd` and `ssl_key_password_cmd` name a shell command whose trimmed
+//! stdout is the secret, so it never has to be written to config.toml. The
+//! output is never logged; failures report the exit status and stderr.
+
+use anyhow::{Context, Result};
+use tokio::process::Command;
+
+/// Run `cmd` through `sh -c` and return its trimmed stdout
+pub async fn run(cmd: &str) -> Result<String> {
+    log::debug!("Running secret command: {}", cmd);
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run `{}`", cmd))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "`{}` failed ({}){}",
+            cmd,
+            output.status,
+            match stderr.trim() {
+                "" => String::new(),
+                stderr => format!(": {}", stderr),
+            }
+        );
+    }
+
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("`{}` printed invalid UTF-8", cmd))?;
+    let secret = stdout.trim();
+    if secret.is_empty() {
+        anyhow::bail!("`{}` printed nothing", cmd);
+    }
+    Ok(secret.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trimmed_stdout() {
+        assert_eq!(run("printf '  s3cret\\n\\n'").await.unwrap(), "s3cret");
+    }
+
+    #[tokio::test]
+    async fn test_failure_reports_status_and_stderr() {
+        let err = run("echo 'vault is sealed' >&2; exit 3")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("exit status: 3"), "{}", err);
+        assert!(err.ends_with(": vault is sealed"), "{}", err);
+
+        let err = run("true").await.unwrap_err().to_string();
+        assert!(err.ends_with("printed nothing"), "{}", err);
+    }
+}
@@ -0,0 +1,158 @@
+//! Named query snippets, saved per connection
+//!
+//! `Dadbod::save_snippet` copies a connection's SQL file to
+//! `snippets/{connection}/{name}.sql` in the workspace, `Dadbod::load_snippet`
+//! writes one back and `Dadbod::list_snippets` names them. Loading refuses to
+//! replace SQL that hasn't been saved as a snippet, unless forced: the SQL
+//! file must be empty or unchanged since the last snippet was saved or loaded.
+
+use anyhow::{Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Subdirectory of the workspace holding the snippets
+pub const SNIPPETS_DIR: &str = "snippets";
+
+/// Hash of SQL file contents, to tell whether they changed since a snippet
+/// was saved or loaded
+pub fn content_hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Snippets of one connection
+#[derive(Debug, Clone)]
+pub struct Snippets {
+    dir: PathBuf,
+}
+
+impl Snippets {
+    /// Snippets of `connection` in the workspace directory `workspace_dir`
+    pub fn new(workspace_dir: &Path, connection: &str) -> Self {
+        Self {
+            dir: workspace_dir.join(SNIPPETS_DIR).join(connection),
+        }
+    }
+
+    /// Save `contents` as snippet `name`, replacing an existing one
+    pub fn save(&self, name: &str, contents: &str) -> Result<PathBuf> {
+        let path = self.path(name)?;
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        if path.exists() {
+            log::info!("Overwriting snippet '{}' ({})", name, path.display());
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Contents of snippet `name`
+    pub fn load(&self, name: &str) -> Result<String> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            anyhow::bail!(
+                "No snippet '{}' (saved snippets: {})",
+                name,
+                self.list()?.join(", ")
+            );
+        }
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    /// Names of the saved snippets, sorted
+    pub fn list(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.dir.display()))
+            }
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let is_sql = path.extension().is_some_and(|ext| ext == "sql");
+                let name = path.file_stem()?.to_str()?.to_string();
+                is_sql.then_some(name)
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// File of snippet `name`; names that would leave the directory are refused
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\'])
+            && !name.chars().any(char::is_control);
+        if !valid {
+            anyhow::bail!(
+                "Invalid snippet name '{}': use a plain file name without slashes or a leading dot",
+                name
+            );
+        }
+        Ok(self.dir.join(format!("{}.sql", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippets(name: &str) -> (Snippets, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("helix-dadbod-snippets-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        (Snippets::new(&dir, "prod"), dir)
+    }
+
+    #[test]
+    fn test_save_load_and_list() {
+        let (snippets, dir) = snippets("save");
+        assert!(snippets.list().unwrap().is_empty());
+
+        let path = snippets
+            .save("slow orders", "SELECT * FROM orders;")
+            .unwrap();
+        assert_eq!(path, dir.join("snippets/prod/slow orders.sql"));
+        snippets.save("active-users", "SELECT 1;").unwrap();
+        snippets.save("active-users", "SELECT 2;").unwrap();
+
+        assert_eq!(
+            snippets.list().unwrap(),
+            vec!["active-users".to_string(), "slow orders".to_string()]
+        );
+        assert_eq!(snippets.load("active-users").unwrap(), "SELECT 2;");
+        assert_eq!(
+            snippets.load("nope").unwrap_err().to_string(),
+            "No snippet 'nope' (saved snippets: active-users, slow orders)"
+        );
+        // Other connections have their own
+        assert!(Snippets::new(&dir, "staging").list().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let (snippets, _) = snippets("invalid");
+        for name in ["", "../prod", "a/b", "a\\b", ".hidden", "tab\there"] {
+            assert!(
+                snippets.save(name, "SELECT 1").is_err(),
+                "{:?} should be refused",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash("SELECT 1"), content_hash("SELECT 1"));
+        assert_ne!(content_hash("SELECT 1"), content_hash("SELECT 2"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Quoting of identifiers and literals embedded in generated SQL
+//!
+//! All SQL built from user input (table names, patterns, intervals) goes
+//! through these helpers so quoting follows PostgreSQL's rules everywhere.
+
+/// Quote an identifier, doubling embedded double quotes: `we"ird` -> `"we""ird"`
+pub fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Words that must be quoted even when written in lower case
+const RESERVED_WORDS: &[&str] = &[
+    "all",
+    "analyse",
+    "analyze",
+    "and",
+    "any",
+    "array",
+    "as",
+    "asc",
+    "asymmetric",
+    "both",
+    "case",
+    "cast",
+    "check",
+    "collate",
+    "column",
+    "constraint",
+    "create",
+    "current_catalog",
+    "current_date",
+    "current_role",
+    "current_time",
+    "current_timestamp",
+    "current_user",
+    "default",
+    "deferrable",
+    "desc",
+    "distinct",
+    "do",
+    "else",
+    "end",
+    "except",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "from",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "initially",
+    "intersect",
+    "into",
+    "lateral",
+    "leading",
+    "limit",
+    "localtime",
+    "localtimestamp",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "only",
+    "or",
+    "order",
+    "placing",
+    "primary",
+    "references",
+    "returning",
+    "select",
+    "session_user",
+    "some",
+    "symmetric",
+    "table",
+    "then",
+    "to",
+    "trailing",
+    "true",
+    "union",
+    "unique",
+    "user",
+    "using",
+    "variadic",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+/// Quote an identifier only if it would not survive unquoted, for readable SQL
+pub fn ident_if_needed(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$')
+        && !RESERVED_WORDS.contains(&name);
+
+    if plain {
+        name.to_string()
+    } else {
+        ident(name)
+    }
+}
+
+/// Quote a string literal, doubling embedded single quotes: `it's` -> `'it''s'`
+///
+/// Values containing backslashes are written as `E'...'` with the backslashes
+/// doubled, so they mean the same whether or not standard_conforming_strings is on.
+pub fn literal(value: &str) -> String {
+    let quoted = value.replace('\'', "''");
+    if value.contains('\\') {
+        format!("E'{}'", quoted.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::QualifiedName;
+
+    /// Names that break naive interpolation
+    const NASTY: &[&str] = &[
+        "users",
+        "Users",
+        "we\"ird",
+        "\"",
+        "\"\"",
+        "it's",
+        "'; DROP TABLE users; --",
+        "back\\slash",
+        "tab\there",
+        "new\nline",
+        "with space",
+        "dot.ted",
+        "order",
+        "select",
+        "1st",
+        "42",
+        "$dollar",
+        "col$1",
+        "café",
+        "表",
+        "emoji 🐘",
+        "ǅ",
+    ];
+
+    /// Undo `literal`, following PostgreSQL's lexer rules for the two forms it emits
+    fn unquote_literal(quoted: &str) -> String {
+        let (escaped, body) = match quoted.strip_prefix('E') {
+            Some(rest) => (true, rest),
+            None => (false, quoted),
+        };
+        let body = body
+            .strip_prefix('\'')
+            .and_then(|b| b.strip_suffix('\''))
+            .expect("literal must be quoted");
+
+        let mut out = String::new();
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => assert_eq!(chars.next(), Some('\''), "lone quote in {}", quoted),
+                '\\' if escaped => assert_eq!(chars.next(), Some('\\'), "lone backslash"),
+                _ => {}
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    #[test]
+    fn test_ident_escapes_quotes() {
+        assert_eq!(ident("users"), "\"users\"");
+        assert_eq!(ident("we\"ird"), "\"we\"\"ird\"");
+    }
+
+    #[test]
+    fn test_ident_round_trips() {
+        for name in NASTY {
+            for quoted in [ident(name), ident_if_needed(name)] {
+                let parsed = QualifiedName::parse(&quoted).unwrap();
+                assert_eq!(parsed.schema, None, "{} parsed as qualified", quoted);
+                assert_eq!(parsed.name, *name, "{} did not round-trip", quoted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ident_if_needed() {
+        assert_eq!(ident_if_needed("created_at"), "created_at");
+        assert_eq!(ident_if_needed("col$1"), "col$1");
+        assert_eq!(ident_if_needed("userId"), "\"userId\"");
+        assert_eq!(ident_if_needed("1st"), "\"1st\"");
+        assert_eq!(ident_if_needed("order"), "\"order\"");
+        assert_eq!(ident_if_needed("user"), "\"user\"");
+        assert_eq!(ident_if_needed("café"), "\"café\"");
+    }
+
+    #[test]
+    fn test_literal_round_trips() {
+        for value in NASTY {
+            let quoted = literal(value);
+            assert_eq!(
+                unquote_literal(&quoted),
+                *value,
+                "{} did not round-trip",
+                quoted
+            );
+            assert_eq!(quoted.starts_with('E'), value.contains('\\'));
+        }
+    }
+
+    #[test]
+    fn test_literal_forms() {
+        assert_eq!(literal("users"), "'users'");
+        assert_eq!(literal("it's"), "'it''s'");
+        assert_eq!(literal("a\\b'c"), "E'a\\\\b''c'");
+        assert_eq!(literal(""), "''");
+    }
+}
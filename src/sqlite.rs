@@ -0,0 +1,253 @@
+//! SQLite connections to local database files
+//!
+//! A connection's `database` is the file path; there is no host, port or
+//! tunnel. The file is opened when first used, so a missing file is reported in
+//! results.dbout on execute instead of being created empty (unless
+//! `create_if_missing` is set). Meta-commands are not translated for SQLite.
+
+use crate::client::QueryRows;
+use crate::config::Connection;
+use crate::run_report::{RunError, RunStatus};
+use crate::ssh_config;
+use crate::value::CellValue;
+use anyhow::{Context, Result};
+use rusqlite::types::ValueRef;
+use rusqlite::{Batch, OpenFlags};
+use std::path::{Path, PathBuf};
+
+/// `type` values in config.toml that select SQLite
+pub const SQLITE_DB_TYPES: &[&str] = &["sqlite", "sqlite3"];
+
+/// A SQLite database file, opened on first use
+pub struct SqliteDb {
+    path: PathBuf,
+    create_if_missing: bool,
+    conn: Option<rusqlite::Connection>,
+}
+
+impl SqliteDb {
+    pub fn new(path: PathBuf, create_if_missing: bool) -> Self {
+        Self {
+            path,
+            create_if_missing,
+            conn: None,
+        }
+    }
+
+    /// Database of `conn`, with `~` in its path expanded
+    pub fn from_connection(conn: &Connection) -> Self {
+        Self::new(
+            ssh_config::expand_tilde(&conn.database),
+            conn.create_if_missing,
+        )
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The open database, opening the file first if needed
+    pub fn open(&mut self) -> Result<&rusqlite::Connection> {
+        if self.conn.is_none() {
+            if !self.create_if_missing && !self.path.exists() {
+                anyhow::bail!(
+                    "SQLite database file {} does not exist \
+                     (set create_if_missing = true to create it)",
+                    self.path.display()
+                );
+            }
+            let mut flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI;
+            if self.create_if_missing {
+                flags |= OpenFlags::SQLITE_OPEN_CREATE;
+            }
+            let conn = rusqlite::Connection::open_with_flags(&self.path, flags)
+                .with_context(|| format!("Failed to open {}", self.path.display()))?;
+            self.conn = Some(conn);
+        }
+        Ok(self.conn.as_ref().expect("opened above"))
+    }
+
+    /// Version of the SQLite library, from `sqlite_version()`
+    pub fn version(&mut self) -> Result<String> {
+        let conn = self.open()?;
+        conn.query_row("SELECT sqlite_version()", [], |row| row.get(0))
+            .context("Failed to execute test query")
+    }
+
+    /// Run every statement in `sql` and return the column names and rows of the
+    /// last one that returns rows, at most `max_rows` of them if set
+    pub fn query(&mut self, sql: &str, max_rows: Option<usize>) -> Result<QueryRows> {
+        let conn = self.open()?;
+        let mut header = Vec::new();
+        let mut data = Vec::new();
+        let mut truncated = false;
+
+        let mut batch = Batch::new(conn, sql);
+        while let Some(mut stmt) = batch.next()? {
+            if stmt.column_count() == 0 {
+                stmt.raw_execute()?;
+                continue;
+            }
+            header = stmt.column_names().iter().map(|c| c.to_string()).collect();
+            let column_count = header.len();
+            data.clear();
+            truncated = false;
+            let mut rows = stmt.raw_query();
+            while let Some(row) = rows.next()? {
+                if max_rows.is_some_and(|max_rows| data.len() >= max_rows) {
+                    truncated = true;
+                    break;
+                }
+                data.push(
+                    (0..column_count)
+                        .map(|i| row.get_ref(i).map(value_to_cell))
+                        .collect::<rusqlite::Result<Vec<_>>>()?,
+                );
+            }
+        }
+
+        Ok(QueryRows {
+            header,
+            rows: data,
+            truncated,
+            // Rows changed by the last INSERT, UPDATE or DELETE
+            affected: Some(conn.changes()),
+        })
+    }
+
+    /// Run every statement in `sql` and return the number of rows they changed
+    pub fn execute(&mut self, sql: &str) -> Result<u64> {
+        let conn = self.open()?;
+        let before = conn.total_changes();
+        conn.execute_batch(sql)?;
+        Ok(conn.total_changes() - before)
+    }
+
+    /// Close the file; it is opened again if the database is used afterwards
+    pub fn close(&mut self) -> Result<()> {
+        if let Some(conn) = self.conn.take() {
+            conn.close()
+                .map_err(|(_, e)| e)
+                .with_context(|| format!("Failed to close {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Convert a SQLite value to a `CellValue`
+pub fn value_to_cell(value: ValueRef<'_>) -> CellValue {
+    match value {
+        ValueRef::Null => CellValue::Null,
+        ValueRef::Integer(v) => CellValue::Int(v),
+        ValueRef::Real(v) => CellValue::Float(v),
+        ValueRef::Text(text) => CellValue::Text(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => CellValue::Text(format!("\\x{}", hex::encode(bytes))),
+    }
+}
+
+/// How a failed execution is reported in last_run.json: errors raised by
+/// SQLite for the statement are database errors, anything else (e.g. the
+/// file could not be opened) is a client error
+pub fn run_error(err: &anyhow::Error) -> (RunStatus, RunError) {
+    let message = format!("{:#}", err);
+    match err.downcast_ref::<rusqlite::Error>() {
+        Some(rusqlite::Error::SqliteFailure(failure, _)) => (
+            RunStatus::DbError,
+            RunError {
+                code: Some(failure.extended_code.to_string()),
+                ..RunError::client(message)
+            },
+        ),
+        _ => (RunStatus::ClientError, RunError::client(message)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value;
+    use std::fs;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("helix-dadbod-sqlite-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_missing_file_is_not_created() {
+        let path = temp_db("missing.db");
+        let mut db = SqliteDb::new(path.clone(), false);
+
+        let err = db.query("SELECT 1", None).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+        assert!(err.to_string().contains("create_if_missing"));
+        assert!(!path.exists());
+        assert_eq!(run_error(&err).0, RunStatus::ClientError);
+    }
+
+    #[test]
+    fn test_query_renders_last_result() {
+        let path = temp_db("query.db");
+        let mut db = SqliteDb::new(path.clone(), true);
+
+        let QueryRows {
+            header,
+            rows: data,
+            truncated,
+            ..
+        } = db
+            .query(
+                "CREATE TABLE users (id INTEGER, name TEXT, score REAL, avatar BLOB);
+                 INSERT INTO users VALUES (1, 'alice', 1.5, x'ff00'), (2, NULL, NULL, NULL);
+                 SELECT * FROM users ORDER BY id;",
+                None,
+            )
+            .unwrap();
+        assert!(!truncated);
+
+        assert_eq!(header, vec!["id", "name", "score", "avatar"]);
+        assert_eq!(data[0][2], CellValue::Float(1.5));
+        assert_eq!(
+            value::display_rows(&data, "∅"),
+            vec![
+                vec!["1", "alice", "1.5", "\\xff00"],
+                vec!["2", "∅", "∅", "∅"],
+            ]
+        );
+        assert!(db.version().unwrap().starts_with('3'));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_statement_error_is_db_error() {
+        let path = temp_db("error.db");
+        let mut db = SqliteDb::new(path.clone(), true);
+
+        let err = db.query("SELECT * FROM nope", None).unwrap_err();
+        let (status, error) = run_error(&err);
+        assert_eq!(status, RunStatus::DbError);
+        assert!(
+            error.message.contains("no such table: nope"),
+            "{}",
+            error.message
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_statement_without_rows() {
+        let path = temp_db("empty.db");
+        let mut db = SqliteDb::new(path.clone(), true);
+
+        let result = db.query("CREATE TABLE t (id INTEGER)", None).unwrap();
+        assert!(result.header.is_empty());
+        assert!(result.rows.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
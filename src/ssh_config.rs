@@ -1,11 +1,20 @@
 //! SSH config file parser
 //!
 //! Parses ~/.ssh/config files to extract connection details for SSH tunnels
+//!
+//! `Include` lines are replaced by the files they name, recursively, so lookups
+//! see one config as if the files were concatenated. As in OpenSSH, a host
+//! takes options from every `Host` block matching it, and for each keyword the
+//! first value found in file order wins.
 
+use crate::known_hosts::pattern_match;
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// How deep Includes may nest, as in OpenSSH
+const MAX_INCLUDE_DEPTH: usize = 16;
 
 /// Parsed SSH configuration for a host
 #[derive(Debug, Clone)]
@@ -13,15 +22,67 @@ pub struct SshHostConfig {
     pub hostname: String,
     pub port: u16,
     pub user: Option<String>,
-    pub identity_file: Option<PathBuf>,
+    /// IdentityFile entries of every matching block, in the order they're tried
+    pub identity_files: Vec<PathBuf>,
+    /// `IdentitiesOnly yes`: try only `identity_files`, not the default keys
+    pub identities_only: bool,
+    /// UserKnownHostsFile entries of the host's block
+    pub known_hosts_files: Vec<PathBuf>,
+    /// ProxyJump hops, `[user@]host[:port]` each, in the order they're connected
+    pub proxy_jump: Vec<String>,
+    /// ProxyCommand with its tokens expanded; tunnels don't run it
+    pub proxy_command: Option<String>,
+}
+
+/// One ProxyJump hop: a host alias or name, and the user and port it may set
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpHost {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl JumpHost {
+    /// Parse `[user@]host[:port]`, where host may be a bracketed IPv6
+    /// address, or the `ssh://[user@]host[:port]` form
+    pub fn parse(spec: &str) -> Result<Self> {
+        let rest = spec.strip_prefix("ssh://").unwrap_or(spec);
+        let (user, rest) = match rest.rsplit_once('@') {
+            Some((user, rest)) => (Some(user.to_string()), rest),
+            None => (None, rest),
+        };
+        let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+            let (host, after) = bracketed
+                .split_once(']')
+                .with_context(|| format!("Unclosed '[' in ProxyJump host '{}'", spec))?;
+            (host, after.strip_prefix(':'))
+        } else {
+            match rest.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            }
+        };
+        if host.is_empty() {
+            anyhow::bail!("ProxyJump host '{}' has no host name", spec);
+        }
+        let port = port
+            .map(|port| {
+                port.parse()
+                    .with_context(|| format!("Invalid port in ProxyJump host '{}'", spec))
+            })
+            .transpose()?;
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+        })
+    }
 }
 
 /// Parse SSH config file and extract configuration for a specific host
 pub fn parse_ssh_config(host_name: &str) -> Result<SshHostConfig> {
     let config_path = get_ssh_config_path()?;
-
-    let contents = fs::read_to_string(&config_path)
-        .with_context(|| format!("Failed to read SSH config from {}", config_path.display()))?;
+    let contents = read_ssh_config(&config_path)?;
 
     parse_host_from_config(&contents, host_name).with_context(|| {
         format!(
@@ -32,87 +93,342 @@ pub fn parse_ssh_config(host_name: &str) -> Result<SshHostConfig> {
     })
 }
 
+/// The hosts to connect through, first hop first, to reach `host_name`, whose
+/// ProxyJump is `proxy_jump`; see `jump_hosts_from_config`. Without an SSH
+/// config the hops are used as written
+pub fn resolve_jump_hosts(host_name: &str, proxy_jump: &[String]) -> Result<Vec<SshHostConfig>> {
+    if proxy_jump.is_empty() {
+        return Ok(Vec::new());
+    }
+    let config_path = get_ssh_config_path()?;
+    let contents = if config_path.exists() {
+        read_ssh_config(&config_path)?
+    } else {
+        String::new()
+    };
+    jump_hosts_from_config(&contents, proxy_jump, &mut vec![host_name.to_string()])
+}
+
+/// IdentityFile entries of the `Host *` block(s) in ~/.ssh/config, in order
+/// Returns an empty list when the config file doesn't exist or sets none
+pub fn default_identity_files() -> Vec<PathBuf> {
+    let Ok(config_path) = get_ssh_config_path() else {
+        return Vec::new();
+    };
+    match read_ssh_config(&config_path) {
+        Ok(contents) => wildcard_identity_files(&contents),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// UserKnownHostsFile entries of the `Host *` block(s) in ~/.ssh/config, in order
+/// Returns an empty list when the config file doesn't exist or sets none
+pub fn default_known_hosts_files() -> Vec<PathBuf> {
+    let Ok(config_path) = get_ssh_config_path() else {
+        return Vec::new();
+    };
+    match read_ssh_config(&config_path) {
+        Ok(contents) => {
+            known_hosts_files(wildcard_entries(&contents, "UserKnownHostsFile").into_iter())
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
 /// Get the path to the SSH config file
 fn get_ssh_config_path() -> Result<PathBuf> {
     let home = std::env::var("HOME").context("HOME environment variable not set")?;
     Ok(PathBuf::from(home).join(".ssh").join("config"))
 }
 
-/// Parse SSH config content and extract configuration for a specific host
-fn parse_host_from_config(content: &str, target_host: &str) -> Result<SshHostConfig> {
-    let mut current_host: Option<String> = None;
-    let mut host_config: HashMap<String, String> = HashMap::new();
+/// Read the SSH config at `config_path` with its Includes in place; relative
+/// Include paths are resolved against the config's directory, ~/.ssh
+fn read_ssh_config(config_path: &Path) -> Result<String> {
+    let contents = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read SSH config from {}", config_path.display()))?;
+    let ssh_dir = config_path.parent().unwrap_or(Path::new("."));
+    expand_includes(&contents, ssh_dir, 0)
+}
 
+/// `content` with every `Include` line replaced by the contents of the files
+/// it names, in glob order; patterns matching nothing include nothing
+fn expand_includes(content: &str, ssh_dir: &Path, depth: usize) -> Result<String> {
+    let mut expanded = String::with_capacity(content.len());
     for line in content.lines() {
-        let trimmed = line.trim();
-
-        // Skip comments and empty lines
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
+        let patterns = match parse_line(line) {
+            Some((keyword, patterns)) if keyword == "include" => patterns,
+            _ => {
+                expanded.push_str(line);
+                expanded.push('\n');
+                continue;
+            }
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            anyhow::bail!(
+                "SSH config Includes nest deeper than {} levels; do they include each other?",
+                MAX_INCLUDE_DEPTH
+            );
         }
 
-        // Split into key and value
-        let parts: Vec<&str> = trimmed.splitn(2, char::is_whitespace).collect();
-        if parts.len() < 2 {
-            continue;
+        for pattern in patterns.split_whitespace() {
+            let pattern = ssh_dir.join(expand_tilde(pattern));
+            let matches = glob::glob(&pattern.to_string_lossy())
+                .with_context(|| format!("Invalid Include pattern {}", pattern.display()))?;
+            for path in matches.filter_map(Result::ok).filter(|path| path.is_file()) {
+                log::debug!("Including SSH config {}", path.display());
+                let included = fs::read_to_string(&path).with_context(|| {
+                    format!("Failed to read included SSH config {}", path.display())
+                })?;
+                expanded.push_str(&expand_includes(&included, ssh_dir, depth + 1)?);
+            }
         }
+    }
+    Ok(expanded)
+}
+
+/// Parse SSH config content and extract configuration for a specific host
+///
+/// Options before the first `Host` line apply to every host; `Match` blocks
+/// are skipped. The host must match a `Host` pattern other than `*`, so a
+/// misspelled name isn't taken for a host of that name. Without a HostName,
+/// the host name itself is connected to.
+fn parse_host_from_config(content: &str, target_host: &str) -> Result<SshHostConfig> {
+    let (found, host_config) = host_options(content, target_host, None);
+    if !found {
+        anyhow::bail!("Host '{}' not found in SSH config", target_host);
+    }
+    Ok(host_config)
+}
 
-        let key = parts[0];
-        let value = parts[1].trim();
+/// Resolve ProxyJump hops through the config in `content`, each hop's user
+/// and port winning over its own block's
+///
+/// As `ssh -J` does, the first hop is reached through its own ProxyJump,
+/// recursively, and each later hop through the one before it. `visiting` holds
+/// the hosts whose ProxyJump is being resolved, to catch loops.
+fn jump_hosts_from_config(
+    content: &str,
+    proxy_jump: &[String],
+    visiting: &mut Vec<String>,
+) -> Result<Vec<SshHostConfig>> {
+    let mut chain = Vec::new();
+    for (index, spec) in proxy_jump.iter().enumerate() {
+        let jump = JumpHost::parse(spec)?;
+        if visiting.contains(&jump.host) {
+            anyhow::bail!(
+                "ProxyJump loop in SSH config: {} -> {}",
+                visiting.join(" -> "),
+                jump.host
+            );
+        }
 
-        match key {
-            "Host" => {
-                // If we were parsing the target host and now found a new Host entry, we're done
-                if current_host.as_deref() == Some(target_host) {
-                    break;
-                }
+        // A hop needn't have a block of its own; `Host *` still applies
+        let (_, mut hop) = host_options(content, &jump.host, jump.user.as_deref());
+        if index == 0 && !hop.proxy_jump.is_empty() {
+            visiting.push(jump.host.clone());
+            chain.extend(jump_hosts_from_config(content, &hop.proxy_jump, visiting)?);
+            visiting.pop();
+        }
+        hop.proxy_jump.clear();
+        hop.port = jump.port.unwrap_or(hop.port);
+        chain.push(hop);
+    }
+    Ok(chain)
+}
 
-                // Start parsing a new host
-                current_host = Some(value.to_string());
-                host_config.clear();
+/// Options of `target_host` in `content`, and whether a `Host` pattern other
+/// than `*` matched it; `user`, when given, wins over the config's User
+fn host_options(content: &str, target_host: &str, user: Option<&str>) -> (bool, SshHostConfig) {
+    let mut active = true;
+    let mut found = false;
+    // Keywords are case-insensitive; they're kept lowercase
+    let mut host_config: HashMap<String, String> = HashMap::new();
+    // Unlike other keywords, IdentityFile accumulates
+    let mut identity_files: Vec<&str> = Vec::new();
+
+    for (key, value) in content.lines().filter_map(parse_line) {
+        match key.as_str() {
+            "host" => {
+                active = host_matches(target_host, value);
+                found |= active
+                    && value
+                        .split_whitespace()
+                        .any(|pattern| pattern != "*" && !pattern.starts_with('!'));
             }
-            _ => {
-                // Only collect config for the target host
-                if current_host.as_deref() == Some(target_host) {
-                    host_config.insert(key.to_string(), value.to_string());
-                }
+            "match" => active = false,
+            "identityfile" if active && !identity_files.contains(&value) => {
+                identity_files.push(value);
+            }
+            "identityfile" => {}
+            _ if active => {
+                // The first value obtained wins
+                host_config.entry(key).or_insert_with(|| value.to_string());
             }
+            _ => {}
         }
     }
 
-    // Check if we found the target host
-    if current_host.as_deref() != Some(target_host) {
-        anyhow::bail!("Host '{}' not found in SSH config", target_host);
-    }
-
     // Extract required and optional fields
-    let hostname = host_config
-        .get("HostName")
-        .or_else(|| host_config.get("Hostname"))
-        .context("HostName not specified in SSH config")?
-        .to_string();
-
     let port = host_config
-        .get("Port")
+        .get("port")
         .and_then(|p| p.parse().ok())
         .unwrap_or(22);
 
-    let user = host_config.get("User").map(|u| u.to_string());
+    let user = user
+        .map(str::to_string)
+        .or_else(|| host_config.get("user").cloned());
+
+    // %h is the name looked up in HostName, the resolved host name elsewhere
+    let hostname = match host_config.get("hostname") {
+        Some(hostname) => expand_tokens(hostname, target_host, port, user.as_deref()),
+        None => target_host.to_string(),
+    };
+
+    let identity_files = identity_files
+        .into_iter()
+        .map(|path| expand_tilde(&expand_tokens(path, &hostname, port, user.as_deref())))
+        .collect();
+    let identities_only = host_config
+        .get("identitiesonly")
+        .is_some_and(|value| value.eq_ignore_ascii_case("yes"));
+
+    let known_hosts_files = known_hosts_files(
+        host_config
+            .get("userknownhostsfile")
+            .map(String::as_str)
+            .into_iter(),
+    );
+
+    let proxy_jump = match host_config.get("proxyjump").map(String::as_str) {
+        None | Some("none") => Vec::new(),
+        Some(hops) => hops.split(',').map(|hop| hop.trim().to_string()).collect(),
+    };
+
+    let proxy_command = match host_config.get("proxycommand").map(String::as_str) {
+        None | Some("none") => None,
+        Some(command) => Some(expand_tokens(command, &hostname, port, user.as_deref())),
+    };
+
+    (
+        found,
+        SshHostConfig {
+            hostname,
+            port,
+            user,
+            identity_files,
+            identities_only,
+            known_hosts_files,
+            proxy_jump,
+            proxy_command,
+        },
+    )
+}
 
-    let identity_file = host_config
-        .get("IdentityFile")
-        .map(|path| expand_tilde(path));
+/// Keyword, lowercase, and value of a config line, which may be written
+/// `Keyword value`, `Keyword=value` or `Keyword = "value"`; None for blank
+/// lines, comments and keywords without a value
+fn parse_line(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+    let end = trimmed.find(|c: char| c.is_whitespace() || c == '=')?;
+    let (keyword, rest) = trimmed.split_at(end);
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let value = rest
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .unwrap_or(rest);
+    Some((keyword.to_ascii_lowercase(), value))
+}
 
-    Ok(SshHostConfig {
-        hostname,
-        port,
-        user,
-        identity_file,
-    })
+/// Whether `host` matches the patterns of a `Host` line: one of them matches
+/// and none of its `!` negated ones does; case is ignored
+fn host_matches(host: &str, patterns: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let mut matched = false;
+    for pattern in patterns.split_whitespace() {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix('!') {
+            Some(negated) if pattern_match(&host, negated) => return false,
+            Some(_) => {}
+            None => matched |= pattern_match(&host, &pattern),
+        }
+    }
+    matched
+}
+
+/// Paths of UserKnownHostsFile values, each of which may list several files;
+/// `none` lists none
+fn known_hosts_files<'a>(values: impl Iterator<Item = &'a str>) -> Vec<PathBuf> {
+    values
+        .flat_map(str::split_whitespace)
+        .filter(|path| *path != "none")
+        .map(expand_tilde)
+        .collect()
+}
+
+/// Collect IdentityFile entries from `Host` blocks whose patterns include `*`
+/// Unlike other keywords, IdentityFile may repeat; every entry is kept
+fn wildcard_identity_files(content: &str) -> Vec<PathBuf> {
+    wildcard_entries(content, "IdentityFile")
+        .into_iter()
+        .map(expand_tilde)
+        .collect()
+}
+
+/// Values of `keyword` in `Host` blocks whose patterns include `*`, in order
+fn wildcard_entries<'a>(content: &'a str, keyword: &str) -> Vec<&'a str> {
+    let mut in_wildcard = false;
+    let mut values = Vec::new();
+
+    for (key, value) in content.lines().filter_map(parse_line) {
+        match key.as_str() {
+            "host" => in_wildcard = value.split_whitespace().any(|pattern| pattern == "*"),
+            key if key.eq_ignore_ascii_case(keyword) && in_wildcard => values.push(value),
+            _ => {}
+        }
+    }
+
+    values
+}
+
+/// Expand the `%d` (home directory), `%h` (remote host name), `%p` (port),
+/// `%r` (remote user, the local one when unset), `%u` (local user) and `%%`
+/// tokens of a HostName, IdentityFile or ProxyCommand value
+fn expand_tokens(value: &str, hostname: &str, port: u16, remote_user: Option<&str>) -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let local_user = std::env::var("USER").unwrap_or_default();
+    let mut expanded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('d') => expanded.push_str(&home),
+            Some('h') => expanded.push_str(hostname),
+            Some('p') => expanded.push_str(&port.to_string()),
+            Some('r') => expanded.push_str(remote_user.unwrap_or(&local_user)),
+            Some('u') => expanded.push_str(&local_user),
+            Some('%') => expanded.push('%'),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
 }
 
 /// Expand ~ to the home directory
-fn expand_tilde(path: &str) -> PathBuf {
+pub(crate) fn expand_tilde(path: &str) -> PathBuf {
     if let Some(stripped) = path.strip_prefix("~/") {
         if let Ok(home) = std::env::var("HOME") {
             return PathBuf::from(home).join(stripped);
@@ -144,7 +460,11 @@ Host another
         assert_eq!(result.hostname, "example.com");
         assert_eq!(result.port, 2222);
         assert_eq!(result.user.unwrap(), "testuser");
-        assert!(result.identity_file.is_some());
+        assert_eq!(
+            result.identity_files,
+            vec![expand_tilde("~/.ssh/example_key")]
+        );
+        assert!(!result.identities_only);
     }
 
     #[test]
@@ -158,7 +478,7 @@ Host minimal
         assert_eq!(result.hostname, "minimal.com");
         assert_eq!(result.port, 22); // Default port
         assert!(result.user.is_none());
-        assert!(result.identity_file.is_none());
+        assert!(result.identity_files.is_empty());
     }
 
     #[test]
@@ -172,6 +492,377 @@ Host example
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("db", "db"));
+        assert!(host_matches("API.prod.example.com", "*.prod.example.com"));
+        assert!(host_matches("db2", "web db?"));
+        assert!(!host_matches("db10", "db?"));
+        assert!(!host_matches(
+            "bastion.prod.example.com",
+            "*.prod.example.com !bastion.*"
+        ));
+        assert!(
+            !host_matches("db", "!db"),
+            "a negation alone matches nothing"
+        );
+    }
+
+    const LAYERED_CONFIG: &str = r#"
+User everyone
+
+Host db *.prod.example.com !bastion.prod.example.com
+    Port 2222
+    IdentityFile ~/.ssh/prod_key
+
+Host db
+    HostName db.internal
+    Port 3333
+    User dba
+
+Match host db
+    User matched
+
+Host *
+    User fallback
+    Port 22
+    IdentityFile /keys/default
+    HostName wrong.example.com
+"#;
+
+    #[test]
+    fn test_first_value_wins_across_blocks() {
+        let db = parse_host_from_config(LAYERED_CONFIG, "db").unwrap();
+        // Port from the first matching block, HostName from the second
+        assert_eq!(db.port, 2222);
+        assert_eq!(db.hostname, "db.internal");
+        // User from before the first Host line
+        assert_eq!(db.user.as_deref(), Some("everyone"));
+        // IdentityFile accumulates over the matching blocks
+        assert_eq!(
+            db.identity_files,
+            vec![
+                expand_tilde("~/.ssh/prod_key"),
+                PathBuf::from("/keys/default")
+            ]
+        );
+
+        // HostName and IdentityFile from `Host *` when nothing earlier sets them
+        let api = parse_host_from_config(LAYERED_CONFIG, "api.prod.example.com").unwrap();
+        assert_eq!(api.port, 2222);
+        assert_eq!(api.hostname, "wrong.example.com");
+        assert_eq!(api.identity_files[0], expand_tilde("~/.ssh/prod_key"));
+
+        // Negated, so only `Host *` applies, which doesn't name it
+        assert!(parse_host_from_config(LAYERED_CONFIG, "bastion.prod.example.com").is_err());
+    }
+
+    #[test]
+    fn test_host_defaults_from_wildcard_block() {
+        let config = r#"
+Host web
+    HostName web.internal
+
+Host *.internal
+    User deploy
+
+Host *
+    Port 2200
+    IdentityFile /keys/default
+"#;
+
+        let web = parse_host_from_config(config, "web").unwrap();
+        assert_eq!(web.hostname, "web.internal");
+        assert_eq!(web.port, 2200);
+        assert_eq!(web.user, None, "matched against the alias, not HostName");
+        assert_eq!(web.identity_files, vec![PathBuf::from("/keys/default")]);
+
+        // Without HostName, the name itself is connected to
+        let cache = parse_host_from_config(config, "cache.internal").unwrap();
+        assert_eq!(cache.hostname, "cache.internal");
+        assert_eq!(cache.user.as_deref(), Some("deploy"));
+    }
+
+    #[test]
+    fn test_jump_host_parse() {
+        let jump = |user: Option<&str>, host: &str, port| JumpHost {
+            user: user.map(str::to_string),
+            host: host.to_string(),
+            port,
+        };
+        assert_eq!(JumpHost::parse("gw").unwrap(), jump(None, "gw", None));
+        assert_eq!(
+            JumpHost::parse("ops@gw.example.com:2222").unwrap(),
+            jump(Some("ops"), "gw.example.com", Some(2222))
+        );
+        assert_eq!(
+            JumpHost::parse("ssh://ops@[2001:db8::1]:2200").unwrap(),
+            jump(Some("ops"), "2001:db8::1", Some(2200))
+        );
+        assert_eq!(
+            JumpHost::parse("[2001:db8::1]").unwrap(),
+            jump(None, "2001:db8::1", None)
+        );
+        assert!(JumpHost::parse("gw:ssh").is_err());
+        assert!(JumpHost::parse("ops@").is_err());
+    }
+
+    const JUMP_CONFIG: &str = r#"
+Host db-bastion
+    HostName db.internal
+    User dba
+    ProxyJump corp-gateway, ops@10.0.0.7:2200
+
+Host corp-gateway
+    HostName gw.example.com
+    User gate
+    ProxyJump edge
+
+Host edge
+    HostName edge.example.com
+    Port 2022
+
+Host loop-a
+    HostName a.internal
+    ProxyJump loop-b
+
+Host loop-b
+    HostName b.internal
+    ProxyJump loop-a
+
+Host *
+    IdentityFile /keys/default
+"#;
+
+    #[test]
+    fn test_jump_hosts_from_config() {
+        let db = parse_host_from_config(JUMP_CONFIG, "db-bastion").unwrap();
+        assert_eq!(db.proxy_jump, vec!["corp-gateway", "ops@10.0.0.7:2200"]);
+
+        let chain =
+            jump_hosts_from_config(JUMP_CONFIG, &db.proxy_jump, &mut vec!["db-bastion".into()])
+                .unwrap();
+        let hops: Vec<(String, u16, Option<String>)> = chain
+            .iter()
+            .map(|hop| (hop.hostname.clone(), hop.port, hop.user.clone()))
+            .collect();
+        assert_eq!(
+            hops,
+            vec![
+                ("edge.example.com".to_string(), 2022, None),
+                ("gw.example.com".to_string(), 22, Some("gate".to_string())),
+                ("10.0.0.7".to_string(), 2200, Some("ops".to_string())),
+            ]
+        );
+        // A hop without a block of its own still gets `Host *`
+        assert_eq!(
+            chain[2].identity_files,
+            vec![PathBuf::from("/keys/default")]
+        );
+        assert!(chain.iter().all(|hop| hop.proxy_jump.is_empty()));
+
+        let a = parse_host_from_config(JUMP_CONFIG, "loop-a").unwrap();
+        let error = jump_hosts_from_config(JUMP_CONFIG, &a.proxy_jump, &mut vec!["loop-a".into()])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "ProxyJump loop in SSH config: loop-a -> loop-b -> loop-a"
+        );
+    }
+
+    #[test]
+    fn test_identity_files_and_tokens() {
+        let config = r#"
+Host db
+    HostName db.internal
+    User dba
+    IdentityFile ~/.ssh/%h_%r
+    IdentityFile %d/.ssh/100%%
+    IdentitiesOnly yes
+
+Host *
+    IdentityFile ~/.ssh/%h_%r
+    IdentityFile /keys/%u
+"#;
+
+        let home = std::env::var("HOME").unwrap_or_default();
+        let local_user = std::env::var("USER").unwrap_or_default();
+        let db = parse_host_from_config(config, "db").unwrap();
+        assert_eq!(
+            db.identity_files,
+            vec![
+                expand_tilde("~/.ssh/db.internal_dba"),
+                PathBuf::from(format!("{}/.ssh/100%", home)),
+                PathBuf::from(format!("/keys/{}", local_user)),
+            ]
+        );
+        assert!(db.identities_only);
+    }
+
+    #[test]
+    fn test_expand_tokens() {
+        let home = std::env::var("HOME").unwrap_or_default();
+        let local_user = std::env::var("USER").unwrap_or_default();
+        let cases = [
+            (
+                "~/.ssh/keys/%h",
+                Some("dba"),
+                "~/.ssh/keys/db.internal".to_string(),
+            ),
+            ("%h:%p", None, "db.internal:2222".to_string()),
+            ("%r@%h", Some("dba"), "dba@db.internal".to_string()),
+            ("%r", None, local_user.clone()),
+            ("%u", Some("dba"), local_user),
+            ("%d/.ssh/id", None, format!("{}/.ssh/id", home)),
+            ("100%%", None, "100%".to_string()),
+            ("%x and %", None, "%x and %".to_string()),
+        ];
+        for (value, remote_user, expected) in cases {
+            assert_eq!(
+                expand_tokens(value, "db.internal", 2222, remote_user),
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_keyword_case_and_value_forms() {
+        let config = r#"
+host db
+    hostname %h.internal
+    PORT=2222
+    user = "dba"
+    identityfile "~/.ssh/my keys/%h"
+    ProxyCommand ssh -W %h:%p gateway
+
+Host other
+    HostName other.internal
+"#;
+
+        let db = parse_host_from_config(config, "db").unwrap();
+        assert_eq!(db.hostname, "db.internal");
+        assert_eq!(db.port, 2222);
+        assert_eq!(db.user.as_deref(), Some("dba"));
+        assert_eq!(
+            db.identity_files,
+            vec![expand_tilde("~/.ssh/my keys/db.internal")]
+        );
+        assert_eq!(
+            db.proxy_command.as_deref(),
+            Some("ssh -W db.internal:2222 gateway")
+        );
+        assert_eq!(
+            parse_line("Include=a b"),
+            Some(("include".to_string(), "a b"))
+        );
+        assert_eq!(parse_line("  # Port 22"), None);
+        assert_eq!(parse_line("Port"), None);
+    }
+
+    #[test]
+    fn test_wildcard_identity_files() {
+        let config = r#"
+Host example
+    IdentityFile ~/.ssh/example_key
+
+Host *
+    AddKeysToAgent yes
+    IdentityFile /keys/work_ed25519
+    IdentityFile /keys/legacy_rsa
+"#;
+
+        assert_eq!(
+            wildcard_identity_files(config),
+            vec![
+                PathBuf::from("/keys/work_ed25519"),
+                PathBuf::from("/keys/legacy_rsa")
+            ]
+        );
+        assert!(wildcard_identity_files("Host example\n    IdentityFile /k\n").is_empty());
+    }
+
+    #[test]
+    fn test_user_known_hosts_files() {
+        let config = r#"
+Host example
+    HostName example.com
+    UserKnownHostsFile /etc/work/known_hosts /etc/work/known_hosts.old
+
+Host *
+    UserKnownHostsFile /etc/fallback_known_hosts
+"#;
+
+        let result = parse_host_from_config(config, "example").unwrap();
+        assert_eq!(
+            result.known_hosts_files,
+            vec![
+                PathBuf::from("/etc/work/known_hosts"),
+                PathBuf::from("/etc/work/known_hosts.old")
+            ]
+        );
+        assert_eq!(
+            known_hosts_files(wildcard_entries(config, "UserKnownHostsFile").into_iter()),
+            vec![PathBuf::from("/etc/fallback_known_hosts")]
+        );
+        assert!(known_hosts_files(["none"].into_iter()).is_empty());
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = std::env::temp_dir().join("helix-dadbod-ssh-config-include-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("config.d/archive")).unwrap();
+        fs::create_dir_all(dir.join("work")).unwrap();
+        fs::write(
+            dir.join("config"),
+            "Include config.d/* missing/*\n\
+             Host *\n    IdentityFile /keys/default\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.d/10-db"),
+            "Host db\n    HostName db.internal\n    Include work/db-extra\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.d/20-web"),
+            "Host web\n    HostName web.internal",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("work/db-extra"),
+            "    Port 2222\n    User deploy\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.d/archive/old"),
+            "Host db\n    HostName old\n",
+        )
+        .unwrap();
+
+        let contents = read_ssh_config(&dir.join("config")).unwrap();
+        let db = parse_host_from_config(&contents, "db").unwrap();
+        assert_eq!(db.hostname, "db.internal");
+        assert_eq!(db.port, 2222);
+        assert_eq!(db.user.as_deref(), Some("deploy"));
+        assert_eq!(
+            parse_host_from_config(&contents, "web").unwrap().hostname,
+            "web.internal"
+        );
+        assert_eq!(
+            wildcard_identity_files(&contents),
+            vec![PathBuf::from("/keys/default")]
+        );
+
+        // Files that include each other
+        fs::write(dir.join("work/db-extra"), "Include config.d/10-db\n").unwrap();
+        let error = read_ssh_config(&dir.join("config")).unwrap_err();
+        assert!(error.to_string().contains("nest deeper than 16 levels"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn test_expand_tilde() {
         let expanded = expand_tilde("~/test/path");
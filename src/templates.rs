@@ -0,0 +1,282 @@
+//! INSERT/UPDATE statement skeletons for \insert-template and \update-template
+//!
+//! Templates are appended to the connection's SQL file between delimiter
+//! comments. Each value placeholder carries the column's type, nullability and
+//! default as an inline comment.
+
+use crate::schema::{KeyColumn, QualifiedName, TableColumn};
+use crate::sql;
+use anyhow::Result;
+
+/// Columns a template should assign, skipping generated and identity columns
+pub fn writable_columns(columns: &[TableColumn]) -> Vec<&TableColumn> {
+    columns
+        .iter()
+        .filter(|c| !c.is_generated && !c.is_identity)
+        .collect()
+}
+
+/// Columns left out of templates, for reporting
+pub fn skipped_columns(columns: &[TableColumn]) -> Vec<&TableColumn> {
+    columns
+        .iter()
+        .filter(|c| c.is_generated || c.is_identity)
+        .collect()
+}
+
+/// Inline comment describing a column, e.g. `-- email: text NOT NULL`
+fn type_hint(column: &TableColumn) -> String {
+    let mut hint = format!("-- {}: {}", column.name, column.type_name);
+    if column.not_null {
+        hint.push_str(" NOT NULL");
+    }
+    if let Some(default) = &column.default {
+        hint.push_str(&format!(" DEFAULT {}", default));
+    }
+    hint
+}
+
+/// Placeholder value: DEFAULT where the column has one, NULL otherwise
+fn placeholder(column: &TableColumn) -> &'static str {
+    if column.default.is_some() {
+        "DEFAULT"
+    } else {
+        "NULL"
+    }
+}
+
+/// Join `items` as indented lines, with a comma after all but the last item
+/// and each line's comment after the comma
+fn comma_lines(items: &[(String, String)]) -> String {
+    let last = items.len().saturating_sub(1);
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, (code, comment))| {
+            let comma = if i < last { "," } else { "" };
+            if comment.is_empty() {
+                format!("    {}{}\n", code, comma)
+            } else {
+                format!("    {}{} {}\n", code, comma, comment)
+            }
+        })
+        .collect()
+}
+
+/// Build an `INSERT INTO table (...) VALUES (...);` skeleton
+pub fn insert_template(table: &QualifiedName, columns: &[TableColumn]) -> Result<String> {
+    let writable = writable_columns(columns);
+    if writable.is_empty() {
+        anyhow::bail!("Table {} has no writable columns", table.display());
+    }
+
+    let names: Vec<(String, String)> = writable
+        .iter()
+        .map(|c| (sql::ident_if_needed(&c.name), String::new()))
+        .collect();
+    let values: Vec<(String, String)> = writable
+        .iter()
+        .map(|c| (placeholder(c).to_string(), type_hint(c)))
+        .collect();
+
+    Ok(format!(
+        "INSERT INTO {} (\n{}) VALUES (\n{});\n",
+        table.display(),
+        comma_lines(&names),
+        comma_lines(&values)
+    ))
+}
+
+/// Build an `UPDATE table SET ... WHERE pk = ...;` skeleton
+pub fn update_template(
+    table: &QualifiedName,
+    columns: &[TableColumn],
+    keys: &[KeyColumn],
+) -> Result<String> {
+    if keys.is_empty() {
+        anyhow::bail!(
+            "Table {} has no primary key to build an UPDATE on",
+            table.display()
+        );
+    }
+
+    let assignments: Vec<(String, String)> = writable_columns(columns)
+        .into_iter()
+        .filter(|c| !keys.iter().any(|k| k.name == c.name))
+        .map(|c| {
+            (
+                format!("{} = {}", sql::ident_if_needed(&c.name), placeholder(c)),
+                type_hint(c),
+            )
+        })
+        .collect();
+    if assignments.is_empty() {
+        anyhow::bail!("Table {} has no writable non-key columns", table.display());
+    }
+
+    let conditions: Vec<String> = keys
+        .iter()
+        .map(|k| format!("{} = NULL", sql::ident_if_needed(&k.name)))
+        .collect();
+    let key_hints: Vec<String> = keys
+        .iter()
+        .map(|k| format!("{}: {}", k.name, k.type_name))
+        .collect();
+
+    Ok(format!(
+        "UPDATE {} SET\n{}WHERE {}; -- {} (primary key)\n",
+        table.display(),
+        comma_lines(&assignments),
+        conditions.join(" AND "),
+        key_hints.join(", ")
+    ))
+}
+
+/// Wrap a template in delimiter comments naming the command that produced it
+pub fn delimited(command: &str, table: &QualifiedName, template: &str) -> String {
+    format!(
+        "-- ---- {} {} ----\n{}-- ---- end {} ----\n",
+        command,
+        table.display(),
+        template,
+        command
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, type_name: &str) -> TableColumn {
+        TableColumn {
+            name: name.to_string(),
+            type_name: type_name.to_string(),
+            not_null: false,
+            default: None,
+            is_identity: false,
+            is_generated: false,
+        }
+    }
+
+    fn users() -> Vec<TableColumn> {
+        vec![
+            TableColumn {
+                is_identity: true,
+                not_null: true,
+                ..column("id", "bigint")
+            },
+            TableColumn {
+                not_null: true,
+                ..column("email", "text")
+            },
+            column("displayName", "character varying(80)"),
+            TableColumn {
+                not_null: true,
+                default: Some("now()".to_string()),
+                ..column("created_at", "timestamp with time zone")
+            },
+            TableColumn {
+                is_generated: true,
+                default: Some("lower(email)".to_string()),
+                ..column("email_lower", "text")
+            },
+        ]
+    }
+
+    fn id_key() -> Vec<KeyColumn> {
+        vec![KeyColumn {
+            name: "id".to_string(),
+            type_name: "bigint".to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_generated_and_identity_columns_are_skipped() {
+        let columns = users();
+        let writable: Vec<&str> = writable_columns(&columns)
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(writable, vec!["email", "displayName", "created_at"]);
+
+        let skipped: Vec<&str> = skipped_columns(&columns)
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(skipped, vec!["id", "email_lower"]);
+    }
+
+    #[test]
+    fn test_serial_columns_are_kept_with_default() {
+        let columns = vec![TableColumn {
+            not_null: true,
+            default: Some("nextval('t_id_seq'::regclass)".to_string()),
+            ..column("id", "integer")
+        }];
+        let sql = insert_template(&QualifiedName::parse("t").unwrap(), &columns).unwrap();
+        assert!(
+            sql.contains("DEFAULT -- id: integer NOT NULL DEFAULT nextval('t_id_seq'::regclass)")
+        );
+    }
+
+    #[test]
+    fn test_insert_template() {
+        let table = QualifiedName::parse("public.users").unwrap();
+        let sql = insert_template(&table, &users()).unwrap();
+
+        let expected = "\
+INSERT INTO public.users (
+    email,
+    \"displayName\",
+    created_at
+) VALUES (
+    NULL, -- email: text NOT NULL
+    NULL, -- displayName: character varying(80)
+    DEFAULT -- created_at: timestamp with time zone NOT NULL DEFAULT now()
+);
+";
+        assert_eq!(sql, expected);
+    }
+
+    #[test]
+    fn test_update_template_keys_on_primary_key() {
+        let table = QualifiedName::parse("users").unwrap();
+        let sql = update_template(&table, &users(), &id_key()).unwrap();
+
+        let expected = "\
+UPDATE users SET
+    email = NULL, -- email: text NOT NULL
+    \"displayName\" = NULL, -- displayName: character varying(80)
+    created_at = DEFAULT -- created_at: timestamp with time zone NOT NULL DEFAULT now()
+WHERE id = NULL; -- id: bigint (primary key)
+";
+        assert_eq!(sql, expected);
+    }
+
+    #[test]
+    fn test_update_template_requires_primary_key() {
+        let table = QualifiedName::parse("logs").unwrap();
+        let err = update_template(&table, &users(), &[]).unwrap_err();
+        assert!(err.to_string().contains("no primary key"));
+    }
+
+    #[test]
+    fn test_insert_template_requires_writable_columns() {
+        let table = QualifiedName::parse("t").unwrap();
+        let columns = vec![TableColumn {
+            is_identity: true,
+            ..column("id", "bigint")
+        }];
+        assert!(insert_template(&table, &columns).is_err());
+    }
+
+    #[test]
+    fn test_delimited() {
+        let table = QualifiedName::parse("users").unwrap();
+        let block = delimited("\\insert-template", &table, "INSERT ...;\n");
+        assert_eq!(
+            block,
+            "-- ---- \\insert-template users ----\nINSERT ...;\n-- ---- end \\insert-template ----\n"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Per-phase timings of connection establishment
+//!
+//! Used to diagnose slow connects: whether the time goes into DNS, the SSH
+//! handshake, authentication or the database itself.
+
+use std::time::Duration;
+
+/// Durations of each phase of establishing a connection
+/// Phases that did not happen (e.g. SSH phases for a direct connection) are None
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectTimings {
+    /// Sessions with the ProxyJump hosts the SSH host is reached through
+    pub proxy_jump: Option<Duration>,
+    /// DNS resolution of the SSH host
+    pub resolve: Option<Duration>,
+    /// TCP connect to the SSH host
+    pub tcp_connect: Option<Duration>,
+    /// SSH protocol handshake including host key verification
+    pub ssh_handshake: Option<Duration>,
+    /// SSH public key authentication
+    pub ssh_auth: Option<Duration>,
+    /// Binding the local tunnel port
+    pub local_bind: Option<Duration>,
+    /// Database connection and authentication
+    pub db_connect: Option<Duration>,
+    /// Initial server version query
+    pub banner_query: Option<Duration>,
+}
+
+impl ConnectTimings {
+    /// Recorded phases in the order they happen
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        [
+            ("proxy jump", self.proxy_jump),
+            ("resolve", self.resolve),
+            ("tcp connect", self.tcp_connect),
+            ("ssh handshake", self.ssh_handshake),
+            ("ssh auth", self.ssh_auth),
+            ("local bind", self.local_bind),
+            ("db connect", self.db_connect),
+            ("banner query", self.banner_query),
+        ]
+        .into_iter()
+        .filter_map(|(name, duration)| duration.map(|d| (name, d)))
+        .collect()
+    }
+
+    /// Sum of all recorded phases
+    pub fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, d)| *d).sum()
+    }
+
+    /// Format the breakdown as SQL comment lines for results.dbout
+    pub fn format(&self) -> String {
+        let mut output = format!(
+            "-- Connect timings (total {:.3}s):\n",
+            self.total().as_secs_f64()
+        );
+        for (name, duration) in self.phases() {
+            output.push_str(&format!(
+                "--   {:<14} {:.3}s\n",
+                name,
+                duration.as_secs_f64()
+            ));
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direct_connection_timings() {
+        let timings = ConnectTimings {
+            db_connect: Some(Duration::from_millis(250)),
+            banner_query: Some(Duration::from_millis(5)),
+            ..Default::default()
+        };
+
+        assert_eq!(timings.total(), Duration::from_millis(255));
+        assert_eq!(
+            timings.format(),
+            "-- Connect timings (total 0.255s):\n\
+             --   db connect     0.250s\n\
+             --   banner query   0.005s\n"
+        );
+    }
+
+    #[test]
+    fn test_tunneled_connection_phase_order() {
+        let timings = ConnectTimings {
+            proxy_jump: None,
+            resolve: Some(Duration::from_millis(12)),
+            tcp_connect: Some(Duration::from_millis(30)),
+            ssh_handshake: Some(Duration::from_millis(8000)),
+            ssh_auth: Some(Duration::from_millis(120)),
+            local_bind: Some(Duration::from_millis(1)),
+            db_connect: Some(Duration::from_millis(90)),
+            banner_query: Some(Duration::from_millis(2)),
+        };
+
+        let names: Vec<&str> = timings.phases().iter().map(|(n, _)| *n).collect();
+        assert_eq!(
+            names,
+            vec![
+                "resolve",
+                "tcp connect",
+                "ssh handshake",
+                "ssh auth",
+                "local bind",
+                "db connect",
+                "banner query"
+            ]
+        );
+        assert_eq!(timings.total(), Duration::from_millis(8255));
+        assert!(timings.format().contains("--   ssh handshake  8.000s\n"));
+    }
+
+    #[test]
+    fn test_empty_timings() {
+        let timings = ConnectTimings::default();
+        assert!(timings.phases().is_empty());
+        assert_eq!(timings.format(), "-- Connect timings (total 0.000s):\n");
+    }
+}
@@ -0,0 +1,282 @@
+//! TLS for PostgreSQL connections
+//!
+//! Connections without an sslmode (or with `sslmode = "disable"`) and without a
+//! client certificate keep connecting in plaintext. Otherwise an OpenSSL
+//! connector is built at connect time, loading `ssl_cert`/`ssl_key` for client
+//! certificate authentication. As in libpq, `prefer` and `require` encrypt
+//! without verifying the server; `verify-ca` checks its certificate chain and
+//! `verify-full` also its host name, which is the configured host even when the
+//! connection goes through an SSH tunnel. The chain is checked against
+//! `ssl_root_cert` when set, otherwise against the system's trusted CAs.
+
+use crate::config::Connection;
+use crate::secret_cmd;
+use crate::ssh_config;
+use anyhow::{Context, Result};
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::X509;
+use postgres_openssl::MakeTlsConnector;
+use std::path::Path;
+
+/// Whether `conn` connects over TLS
+pub fn wants_tls(conn: &Connection) -> bool {
+    conn.ssl_cert.is_some()
+        || conn
+            .sslmode
+            .as_deref()
+            .is_some_and(|mode| mode != "disable")
+}
+
+/// sslmode for the connection string; tokio_postgres only knows disable,
+/// prefer and require, so certificate checks are done by the connector
+pub fn conn_str_sslmode(sslmode: &str) -> &str {
+    match sslmode {
+        "allow" => "prefer",
+        "verify-ca" | "verify-full" => "require",
+        other => other,
+    }
+}
+
+/// TLS connector for `conn`, or None when it connects in plaintext
+///
+/// Fails with the file's path when the root, client certificate or key is
+/// missing or unreadable.
+pub async fn connector(conn: &Connection) -> Result<Option<MakeTlsConnector>> {
+    if !wants_tls(conn) {
+        return Ok(None);
+    }
+
+    let sslmode = conn.sslmode.as_deref().unwrap_or("prefer");
+    let mut builder =
+        SslConnector::builder(SslMethod::tls_client()).context("Failed to initialize TLS")?;
+    match sslmode {
+        "verify-ca" | "verify-full" => {
+            builder.set_verify(SslVerifyMode::PEER);
+            if let Some(root_cert) = &conn.ssl_root_cert {
+                let path = ssh_config::expand_tilde(root_cert);
+                let mut store = X509StoreBuilder::new()?;
+                for cert in load_pem_certs(&path, "ssl_root_cert")? {
+                    store.add_cert(cert)?;
+                }
+                builder.set_verify_cert_store(store.build())?;
+            }
+        }
+        _ => builder.set_verify(SslVerifyMode::NONE),
+    }
+
+    if let (Some(cert), Some(key)) = (&conn.ssl_cert, &conn.ssl_key) {
+        let chain = load_pem_certs(&ssh_config::expand_tilde(cert), "ssl_cert")?;
+        let password = match (&conn.ssl_key_password, &conn.ssl_key_password_cmd) {
+            (Some(password), _) => Some(password.clone()),
+            (None, Some(cmd)) => Some(
+                secret_cmd::run(cmd)
+                    .await
+                    .context("Failed to get ssl_key password")?,
+            ),
+            (None, None) => None,
+        };
+        let key = load_key(&ssh_config::expand_tilde(key), password.as_deref())?;
+
+        let mut chain = chain.into_iter();
+        let leaf = chain.next().context("ssl_cert contains no certificate")?;
+        builder.set_certificate(&leaf)?;
+        for intermediate in chain {
+            builder.add_extra_chain_cert(intermediate)?;
+        }
+        builder.set_private_key(&key)?;
+        builder
+            .check_private_key()
+            .context("ssl_key does not match ssl_cert")?;
+    }
+
+    let mut connector = MakeTlsConnector::new(builder.build());
+    let verify_host = (sslmode == "verify-full").then(|| conn.host.clone());
+    connector.set_callback(move |config, _domain| {
+        // The domain is "localhost" for tunneled connections
+        config.set_verify_hostname(false);
+        if let Some(host) = &verify_host {
+            config.param_mut().set_host(host)?;
+        }
+        Ok(())
+    });
+
+    Ok(Some(connector))
+}
+
+/// Certificates of the PEM file `field` names, in file order (leaf first for
+/// a chain)
+fn load_pem_certs(path: &Path, field: &str) -> Result<Vec<X509>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("Failed to read {} {}", field, path.display()))?;
+    let certs = X509::stack_from_pem(&pem)
+        .with_context(|| format!("Invalid certificate in {} {}", field, path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("{} {} contains no certificate", field, path.display());
+    }
+    Ok(certs)
+}
+
+/// Private key of a PEM file, decrypted with `password` if given
+fn load_key(path: &Path, password: Option<&str>) -> Result<PKey<Private>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("Failed to read ssl_key {}", path.display()))?;
+    let key = match password {
+        Some(password) => PKey::private_key_from_pem_passphrase(&pem, password.as_bytes()),
+        // An empty passphrase instead of OpenSSL's terminal prompt
+        None => PKey::private_key_from_pem_callback(&pem, |_| Ok(0)),
+    };
+    key.with_context(|| {
+        format!(
+            "Failed to load ssl_key {} (encrypted keys need ssl_key_password or ssl_key_password_cmd)",
+            path.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::rsa::Rsa;
+    use openssl::symm::Cipher;
+    use openssl::x509::X509NameBuilder;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Self-signed client certificate and its key, the key encrypted with
+    /// `password` if given
+    fn write_client_cert(name: &str, password: Option<&str>) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join("helix-dadbod-tls-test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", "dadbod").unwrap();
+        let subject = subject.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&subject).unwrap();
+        cert.set_issuer_name(&subject).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let cert_path = dir.join(format!("{}.crt", name));
+        let key_path = dir.join(format!("{}.key", name));
+        fs::write(&cert_path, cert.build().to_pem().unwrap()).unwrap();
+        let key_pem = match password {
+            Some(password) => key
+                .private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), password.as_bytes())
+                .unwrap(),
+            None => key.private_key_to_pem_pkcs8().unwrap(),
+        };
+        fs::write(&key_path, key_pem).unwrap();
+        (cert_path, key_path)
+    }
+
+    fn connection(sslmode: Option<&str>) -> Connection {
+        let config: crate::config::SqlConfig = toml::from_str(
+            r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+            "#,
+        )
+        .unwrap();
+        let mut conn = config.connections[0].clone();
+        conn.sslmode = sslmode.map(str::to_string);
+        conn
+    }
+
+    #[test]
+    fn test_wants_tls() {
+        assert!(!wants_tls(&connection(None)));
+        assert!(!wants_tls(&connection(Some("disable"))));
+        assert!(wants_tls(&connection(Some("require"))));
+
+        let mut conn = connection(None);
+        conn.ssl_cert = Some("client.crt".to_string());
+        assert!(wants_tls(&conn));
+    }
+
+    #[test]
+    fn test_conn_str_sslmode() {
+        assert_eq!(conn_str_sslmode("verify-full"), "require");
+        assert_eq!(conn_str_sslmode("verify-ca"), "require");
+        assert_eq!(conn_str_sslmode("allow"), "prefer");
+        assert_eq!(conn_str_sslmode("require"), "require");
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_without_sslmode() {
+        assert!(connector(&connection(None)).await.unwrap().is_none());
+        assert!(connector(&connection(Some("require")))
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_client_cert_with_encrypted_key() {
+        let (cert, key) = write_client_cert("encrypted", Some("hunter2"));
+        let mut conn = connection(Some("require"));
+        conn.ssl_cert = Some(cert.display().to_string());
+        conn.ssl_key = Some(key.display().to_string());
+
+        let err = connector(&conn).await.err().unwrap();
+        assert!(
+            format!("{:#}", err).contains(&key.display().to_string()),
+            "{:#}",
+            err
+        );
+
+        conn.ssl_key_password = Some("hunter2".to_string());
+        assert!(connector(&conn).await.unwrap().is_some());
+
+        conn.ssl_key_password = None;
+        conn.ssl_key_password_cmd = Some("echo hunter2".to_string());
+        assert!(connector(&conn).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_root_cert_for_verified_modes() {
+        let (root, _) = write_client_cert("root", None);
+        let mut conn = connection(Some("verify-ca"));
+        conn.ssl_root_cert = Some(root.display().to_string());
+        assert!(connector(&conn).await.unwrap().is_some());
+
+        conn.ssl_root_cert = Some("/nonexistent/root.crt".to_string());
+        let err = connector(&conn).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Failed to read ssl_root_cert /nonexistent/root.crt"
+        );
+
+        // Modes that don't verify the server don't read it
+        conn.sslmode = Some("require".to_string());
+        assert!(connector(&conn).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_missing_cert_names_path() {
+        let (_, key) = write_client_cert("plain", None);
+        let mut conn = connection(Some("verify-full"));
+        conn.ssl_cert = Some("/nonexistent/client.crt".to_string());
+        conn.ssl_key = Some(key.display().to_string());
+
+        let err = connector(&conn).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Failed to read ssl_cert /nonexistent/client.crt"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Transactions that span executions
+//!
+//! A connection keeps one session for its lifetime, so a `BEGIN` executed on
+//! its own stays open for the executions that follow until `COMMIT` or
+//! `ROLLBACK`. The state is followed from the statements that ran: drivers
+//! don't report it, and the results header and `close_connection` need it.
+
+use crate::command_tag::leading_words;
+
+/// Transaction state of a connection's session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionState {
+    #[default]
+    Idle,
+    Open,
+    /// A statement failed inside the transaction; PostgreSQL ignores
+    /// everything but ROLLBACK (to a savepoint) until it ends
+    Failed,
+}
+
+impl TransactionState {
+    /// State after every statement of `sql` ran successfully
+    pub fn after(self, sql: &str) -> Self {
+        statements(sql)
+            .into_iter()
+            .fold(self, |state, statement| state.after_statement(statement))
+    }
+
+    /// State after `sql` failed; `aborts_on_error` is whether the server
+    /// aborts an open transaction on errors (PostgreSQL does, MySQL and SQLite
+    /// don't)
+    pub fn after_error(self, aborts_on_error: bool) -> Self {
+        match self {
+            TransactionState::Open if aborts_on_error => TransactionState::Failed,
+            state => state,
+        }
+    }
+
+    pub fn is_open(self) -> bool {
+        self != TransactionState::Idle
+    }
+
+    /// Line noting an open transaction above the results
+    pub fn header(self) -> Option<&'static str> {
+        match self {
+            TransactionState::Idle => None,
+            TransactionState::Open => Some("-- In transaction\n"),
+            TransactionState::Failed => {
+                Some("-- In transaction (aborted by an error: ROLLBACK to continue)\n")
+            }
+        }
+    }
+
+    fn after_statement(self, statement: &str) -> Self {
+        let words = leading_words(statement);
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        match words.as_slice() {
+            ["BEGIN", ..] | ["START", "TRANSACTION", ..] => TransactionState::Open,
+            ["COMMIT" | "ROLLBACK", "PREPARED", ..] => self,
+            ["ROLLBACK", "TO", ..] | ["ROLLBACK", "WORK" | "TRANSACTION", "TO", ..] => match self {
+                TransactionState::Failed => TransactionState::Open,
+                state => state,
+            },
+            ["COMMIT" | "END" | "ROLLBACK" | "ABORT", rest @ ..] => {
+                if rest.ends_with(&["AND", "CHAIN"]) {
+                    TransactionState::Open
+                } else {
+                    TransactionState::Idle
+                }
+            }
+            ["PREPARE", "TRANSACTION", ..] => TransactionState::Idle,
+            _ => self,
+        }
+    }
+}
+
+/// `sql` split at semicolons outside quotes, comments and dollar-quoted bodies
+fn statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = sql[i..].find('\n').map_or(bytes.len(), |end| i + end);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = sql[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 1);
+            }
+            b'$' => {
+                if let Some(tag) = dollar_tag(&sql[i..]) {
+                    let body = i + tag.len();
+                    i = sql[body..]
+                        .find(tag)
+                        .map_or(bytes.len(), |end| body + end + tag.len() - 1);
+                }
+            }
+            b';' => {
+                statements.push(&sql[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    statements.push(&sql[start..]);
+    statements
+}
+
+/// The `$tag$` opening a dollar-quoted string at the start of `sql`
+fn dollar_tag(sql: &str) -> Option<&str> {
+    let rest = &sql[1..];
+    let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))?;
+    let name = &rest[..end];
+    let valid = !name.starts_with(|c: char| c.is_ascii_digit());
+    (valid && rest[end..].starts_with('$')).then(|| &sql[..end + 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TransactionState::*;
+
+    #[test]
+    fn test_begin_and_end() {
+        assert_eq!(Idle.after("BEGIN"), Open);
+        assert_eq!(Idle.after("start transaction read only"), Open);
+        assert_eq!(Open.after("UPDATE users SET name = 'x'"), Open);
+        assert_eq!(Open.after("-- done\nCOMMIT;"), Idle);
+        assert_eq!(Open.after("rollback"), Idle);
+        assert_eq!(Open.after("END"), Idle);
+        assert_eq!(Open.after("COMMIT AND CHAIN"), Open);
+        assert_eq!(Open.after("COMMIT AND NO CHAIN"), Idle);
+        assert_eq!(Idle.after("SELECT 1"), Idle);
+    }
+
+    #[test]
+    fn test_every_statement_is_followed() {
+        assert_eq!(Idle.after("BEGIN; UPDATE t SET x = 1;"), Open);
+        assert_eq!(Idle.after("BEGIN; UPDATE t SET x = 1; COMMIT;"), Idle);
+        assert_eq!(Open.after("SELECT 'a;commit'; SELECT 1"), Open);
+        assert_eq!(
+            Open.after(
+                "CREATE FUNCTION f() RETURNS int AS $body$ BEGIN RETURN 1; END; $body$ \
+                 LANGUAGE plpgsql"
+            ),
+            Open
+        );
+    }
+
+    #[test]
+    fn test_errors_abort_postgres_transactions() {
+        assert_eq!(Open.after_error(true), Failed);
+        assert_eq!(Open.after_error(false), Open);
+        assert_eq!(Idle.after_error(true), Idle);
+        assert_eq!(Failed.after("ROLLBACK TO SAVEPOINT before_update"), Open);
+        assert_eq!(Failed.after("ROLLBACK"), Idle);
+        // COMMIT of an aborted transaction rolls it back
+        assert_eq!(Failed.after("COMMIT"), Idle);
+    }
+
+    #[test]
+    fn test_header() {
+        assert_eq!(Idle.header(), None);
+        assert_eq!(Open.header(), Some("-- In transaction\n"));
+        assert!(Failed.header().unwrap().contains("ROLLBACK"));
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(
+            statements("SELECT ';'; /* ; */ SELECT $1; SELECT $$;$$"),
+            vec!["SELECT ';'", " /* ; */ SELECT $1", " SELECT $$;$$"]
+        );
+        assert_eq!(statements("SELECT 1 -- a; b"), vec!["SELECT 1 -- a; b"]);
+    }
+}
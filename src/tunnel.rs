@@ -1,13 +1,23 @@
-use crate::config::SshTunnel;
-use crate::ssh_config;
+use crate::config::{SqlConfig, SshTunnel, TunnelOverrides};
+use crate::copy::format_size;
+use crate::known_hosts::{self, HostKeyPolicy, HostKeyPrompts, HostKeyStatus, PendingHostKey};
+use crate::secret_cmd;
+use crate::ssh_config::{self, SshHostConfig};
+use crate::timings::ConnectTimings;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use russh::client;
+use russh::Channel;
 use russh_keys::*;
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
@@ -15,21 +25,134 @@ use tokio::task::JoinHandle;
 const TUNNEL_PORT_START: u16 = 7001;
 const TUNNEL_PORT_END: u16 = 7020;
 
+/// Default seconds between SSH keepalives
+pub const DEFAULT_SSH_KEEPALIVE_SECS: u64 = 30;
+
+/// Default attempts to re-establish a dropped SSH session
+pub const DEFAULT_SSH_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Default seconds connecting to an SSH server may take
+pub const DEFAULT_SSH_CONNECT_TIMEOUT_SECS: u64 = 10;
+
 /// SSH client handler for russh
 struct SshClientHandler {
     hostname: String,
     port: u16,
     skip_verification: bool,
+    host_key_policy: HostKeyPolicy,
+    /// known_hosts files searched for the host key; new keys go to the first
+    known_hosts_files: Vec<PathBuf>,
+    /// Address the connection went to, which known_hosts may list the host under
+    remote_ip: Option<std::net::IpAddr>,
+    /// Keys awaiting and given the user's decision under the ask policy
+    host_keys: Arc<HostKeyPrompts>,
+    /// Why the host key was rejected, for the connect error
+    rejection: Arc<std::sync::Mutex<Option<HostKeyRejection>>>,
+    /// Session with the ProxyJump host this one is tunneled through, which
+    /// lives as long as this one
+    _via: Option<client::Handle<SshClientHandler>>,
+}
+
+/// Why a server's host key was rejected
+#[derive(Debug, Clone, PartialEq)]
+enum HostKeyRejection {
+    /// Missing from known_hosts, under the strict policy
+    Unknown,
+    /// known_hosts has a different key for the host
+    KeyMismatch {
+        expected_fingerprints: Vec<String>,
+        presented: String,
+        file: PathBuf,
+        line: usize,
+    },
+    /// known_hosts marks the key `@revoked`
+    Revoked { fingerprint: String },
+    /// known_hosts only trusts a certificate authority for the host
+    CertAuthority { file: PathBuf, line: usize },
+    /// Missing from known_hosts and not accepted yet, under the ask policy
+    Unconfirmed {
+        key_type: String,
+        fingerprint: String,
+    },
 }
 
 impl SshClientHandler {
-    fn new(hostname: String, port: u16, skip_verification: bool) -> Self {
-        Self {
-            hostname,
-            port,
-            skip_verification,
+    /// Treat a key missing from known_hosts as `host_key_policy` says
+    fn check_unknown_key(&self, server_public_key: &key::PublicKey) -> Result<bool, russh::Error> {
+        let offered = PendingHostKey {
+            host: self.hostname.clone(),
+            port: self.port,
+            key_type: server_public_key.name().to_string(),
+            fingerprint: known_hosts::fingerprint(server_public_key),
+        };
+        match self.host_key_policy {
+            HostKeyPolicy::Strict => {
+                log::error!(
+                    "Host key verification failed for {}:{} - host not found in known_hosts",
+                    self.hostname,
+                    self.port
+                );
+                self.reject(HostKeyRejection::Unknown)
+            }
+            HostKeyPolicy::AcceptNew => {
+                log::warn!(
+                    "Accepting new host key {} {} of {}:{} (host_key_policy = \"accept-new\")",
+                    offered.key_type,
+                    offered.fingerprint,
+                    self.hostname,
+                    self.port
+                );
+                self.remember(server_public_key);
+                Ok(true)
+            }
+            HostKeyPolicy::Ask if self.host_keys.take_accepted(&offered) => {
+                log::info!(
+                    "Host key {} of {}:{} accepted by the user",
+                    offered.fingerprint,
+                    self.hostname,
+                    self.port
+                );
+                self.remember(server_public_key);
+                Ok(true)
+            }
+            HostKeyPolicy::Ask => {
+                log::warn!(
+                    "Host key {} {} of {}:{} is not in known_hosts; awaiting confirmation",
+                    offered.key_type,
+                    offered.fingerprint,
+                    self.hostname,
+                    self.port
+                );
+                let rejection = HostKeyRejection::Unconfirmed {
+                    key_type: offered.key_type.clone(),
+                    fingerprint: offered.fingerprint.clone(),
+                };
+                self.host_keys.request(offered);
+                self.reject(rejection)
+            }
+        }
+    }
+
+    /// Append an accepted key to known_hosts; when that fails it is trusted
+    /// for this session only
+    fn remember(&self, server_public_key: &key::PublicKey) {
+        let Some(path) = self.known_hosts_files.first() else {
+            log::warn!(
+                "No known_hosts file to add the host key to; trusting it for this session only"
+            );
+            return;
+        };
+        if let Err(e) =
+            known_hosts::add_host_key(path, &self.hostname, self.port, server_public_key)
+        {
+            log::warn!("{:#}; trusting the key for this session only", e);
         }
     }
+
+    fn reject(&self, rejection: HostKeyRejection) -> Result<bool, russh::Error> {
+        *self.rejection.lock().unwrap_or_else(|e| e.into_inner()) = Some(rejection);
+        Err(russh::Error::UnknownKey)
+    }
 }
 
 #[async_trait]
@@ -43,15 +166,22 @@ impl client::Handler for SshClientHandler {
         // Skip verification if configured to do so (INSECURE)
         if self.skip_verification {
             log::warn!(
-                "SECURITY WARNING: Skipping host key verification for {}:{} (skip_host_key_verification is enabled)",
+                "SECURITY WARNING: Skipping host key verification for {}:{} (skip_host_key_verification or strict_host_key_checking = false)",
                 self.hostname, self.port
             );
             return Ok(true);
         }
 
-        // Verify the server's host key against known_hosts
-        match crate::known_hosts::verify_host_key(&self.hostname, self.port, server_public_key) {
-            Ok(true) => {
+        // Verify the server's host key against known_hosts; a changed key is
+        // rejected whatever the policy
+        match known_hosts::verify_host_key(
+            &self.hostname,
+            self.port,
+            self.remote_ip,
+            server_public_key,
+            &self.known_hosts_files,
+        ) {
+            Ok(HostKeyStatus::Verified) => {
                 log::info!(
                     "Host key verified successfully for {}:{}",
                     self.hostname,
@@ -59,14 +189,43 @@ impl client::Handler for SshClientHandler {
                 );
                 Ok(true)
             }
-            Ok(false) => {
+            Ok(HostKeyStatus::KeyMismatch {
+                expected_fingerprints,
+                presented,
+                file,
+                line,
+            }) => {
                 log::error!(
-                    "Host key verification failed for {}:{} - host not found in known_hosts",
+                    "Host key verification failed for {}:{} - it does not match known_hosts",
                     self.hostname,
                     self.port
                 );
-                Err(russh::Error::UnknownKey)
+                self.reject(HostKeyRejection::KeyMismatch {
+                    expected_fingerprints,
+                    presented,
+                    file,
+                    line,
+                })
+            }
+            Ok(HostKeyStatus::Revoked) => {
+                log::error!(
+                    "SECURITY WARNING: {}:{} presented a host key marked @revoked in known_hosts",
+                    self.hostname,
+                    self.port
+                );
+                self.reject(HostKeyRejection::Revoked {
+                    fingerprint: known_hosts::fingerprint(server_public_key),
+                })
+            }
+            Ok(HostKeyStatus::CertAuthority { file, line }) => {
+                log::error!(
+                    "Host key verification failed for {}:{} - known_hosts only lists a certificate authority for it",
+                    self.hostname,
+                    self.port
+                );
+                self.reject(HostKeyRejection::CertAuthority { file, line })
             }
+            Ok(HostKeyStatus::UnknownHost) => self.check_unknown_key(server_public_key),
             Err(e) => {
                 log::error!(
                     "Error verifying host key for {}:{}: {}",
@@ -84,18 +243,377 @@ impl client::Handler for SshClientHandler {
 pub struct TunnelManager {
     tunnels: Arc<Mutex<HashMap<String, ActiveTunnel>>>,
     port_allocator: Arc<Mutex<PortAllocator>>,
-    skip_host_key_verification: bool,
+    /// SSH sessions shared by tunnels through the same server as the same user
+    sessions: Arc<Mutex<Sessions>>,
+    /// Held while connection `name`'s tunnel is being created, so concurrent
+    /// requests for it make a single tunnel without holding `tunnels`
+    creating_tunnels: ConnectLocks<String>,
+    /// Held while the session for a key is being established, so tunnels
+    /// through the same server share it without holding `sessions`
+    connecting_sessions: ConnectLocks<SessionKey>,
+    settings: TunnelSettings,
+    /// Host keys awaiting the user's decision under `host_key_policy = "ask"`
+    host_keys: Arc<HostKeyPrompts>,
+}
+
+/// SSH settings from config.toml shared by every tunnel
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunnelSettings {
+    /// How host keys missing from known_hosts are treated
+    pub host_key_policy: HostKeyPolicy,
+    /// Interval of SSH keepalives; None disables them
+    pub keepalive_interval: Option<Duration>,
+    /// Attempts to re-establish a dropped SSH session before giving up
+    pub reconnect_attempts: u32,
+    /// Longest connecting to the SSH server may take, handshake and
+    /// authentication included
+    pub connect_timeout: Duration,
+}
+
+impl TunnelSettings {
+    pub fn from_config(config: &SqlConfig) -> Self {
+        Self {
+            host_key_policy: config.host_key_policy,
+            keepalive_interval: (config.ssh_keepalive_secs > 0)
+                .then(|| Duration::from_secs(config.ssh_keepalive_secs)),
+            reconnect_attempts: config.ssh_reconnect_attempts,
+            connect_timeout: Duration::from_secs(config.ssh_connect_timeout_secs),
+        }
+    }
+}
+
+impl Default for TunnelSettings {
+    fn default() -> Self {
+        Self {
+            host_key_policy: HostKeyPolicy::default(),
+            keepalive_interval: Some(Duration::from_secs(DEFAULT_SSH_KEEPALIVE_SECS)),
+            reconnect_attempts: DEFAULT_SSH_RECONNECT_ATTEMPTS,
+            connect_timeout: Duration::from_secs(DEFAULT_SSH_CONNECT_TIMEOUT_SECS),
+        }
+    }
 }
 
 /// An active SSH tunnel
 pub struct ActiveTunnel {
     pub local_port: u16,
-    pub remote_host: String,
-    pub remote_port: u16,
+    /// Where the database listens, as seen from the SSH server
+    pub remote: RemoteTarget,
+    /// SSH endpoint the tunnel was created with, including any session overrides
+    pub ssh_target: SshTarget,
+    /// Time spent in each phase of establishing the tunnel
+    pub timings: ConnectTimings,
+    /// When the tunnel was created
+    pub created: Instant,
+    /// SSH session and ports shared with the forwarding task
+    forwarder: Arc<Forwarder>,
     /// Handle to the background task that forwards connections
     _forwarding_task: JoinHandle<()>,
 }
 
+impl ActiveTunnel {
+    /// Whether the tunnel can still forward: its forwarding task runs and its
+    /// SSH session is alive. A reconnecting tunnel is alive
+    fn is_alive(&self) -> bool {
+        !self._forwarding_task.is_finished() && self.forwarder.session.is_alive()
+    }
+}
+
+/// State of a tunnel's SSH session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionState {
+    Up,
+    /// The session dropped and is being re-established
+    Reconnecting {
+        attempt: u32,
+        max_attempts: u32,
+    },
+    /// Reconnecting failed for good; the tunnel is torn down
+    Failed,
+}
+
+impl std::fmt::Display for SessionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionState::Up => write!(f, "up"),
+            SessionState::Reconnecting {
+                attempt,
+                max_attempts,
+            } => write!(f, "reconnecting (attempt {} of {})", attempt, max_attempts),
+            SessionState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Where a tunnel's connections go, as seen from the SSH server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteTarget {
+    /// A host and port, reached through direct-tcpip channels
+    Tcp { host: String, port: u16 },
+    /// A UNIX socket path, reached through streamlocal@openssh.com channels
+    Socket(String),
+}
+
+impl std::fmt::Display for RemoteTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteTarget::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            RemoteTarget::Socket(path) => write!(f, "unix:{}", path),
+        }
+    }
+}
+
+/// Where a tunnel goes, the state of its SSH session and the bytes forwarded
+/// through it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TunnelStatus {
+    /// SSH server the session is connected to
+    pub ssh_host: String,
+    pub ssh_port: u16,
+    pub ssh_user: String,
+    pub local_port: u16,
+    /// Where the database listens, as seen from the SSH server
+    pub remote: RemoteTarget,
+    pub state: SessionState,
+    /// Whether the session is up and the tunnel forwarding
+    pub healthy: bool,
+    /// Time since the tunnel was created (or last rebuilt)
+    pub uptime: Duration,
+    /// Bytes from local clients to the database
+    pub bytes_sent: u64,
+    /// Bytes from the database to local clients
+    pub bytes_received: u64,
+}
+
+impl std::fmt::Display for TunnelStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {} sent, {} received",
+            self.state,
+            format_size(self.bytes_sent),
+            format_size(self.bytes_received)
+        )
+    }
+}
+
+/// SSH endpoint of a tunnel, resolved from config.toml, ~/.ssh/config and any
+/// session-only overrides (in increasing order of precedence)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshTarget {
+    pub host: String,
+    pub port: u16,
+    /// SSH user, defaults to the current user when unset
+    pub user: Option<String>,
+    /// Private keys offered in order, followed by the default key (Host *
+    /// IdentityFile, id_ed25519, id_ecdsa or id_rsa) unless `identities_only`
+    pub key_paths: Vec<PathBuf>,
+    /// Offer only `key_paths`, as `IdentitiesOnly yes` in ~/.ssh/config asks
+    pub identities_only: bool,
+    /// Where the private key's passphrase comes from, if it is encrypted
+    pub key_passphrase: KeyPassphrase,
+    /// known_hosts files searched before the default ones: the connection's
+    /// known_hosts_file, then UserKnownHostsFile from ~/.ssh/config
+    pub known_hosts_files: Vec<PathBuf>,
+    /// ProxyJump hosts the server is reached through, first hop first
+    pub jump_hosts: Vec<SshTarget>,
+    /// Skip host key verification of the server and its jump hosts, as the
+    /// tunnel's strict_host_key_checking or the global flag says
+    pub skip_host_key_verification: bool,
+    /// Whether session overrides were applied
+    pub ephemeral: bool,
+}
+
+/// Source of an encrypted private key's passphrase: the output of
+/// `key_passphrase_cmd`, else the `key_passphrase_env` variable
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyPassphrase {
+    pub cmd: Option<String>,
+    pub env: Option<String>,
+}
+
+impl KeyPassphrase {
+    /// The passphrase, or None when no source is configured; never logged
+    pub async fn resolve(&self) -> Result<Option<String>> {
+        if let Some(cmd) = &self.cmd {
+            let passphrase = secret_cmd::run(cmd)
+                .await
+                .context("Failed to get SSH key passphrase from key_passphrase_cmd")?;
+            return Ok(Some(passphrase));
+        }
+        match &self.env {
+            Some(var) => match std::env::var(var) {
+                Ok(passphrase) => Ok(Some(passphrase)),
+                Err(_) => anyhow::bail!("key_passphrase_env: ${} is not set", var),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl SshTarget {
+    /// Resolve the SSH endpoint for a tunnel config, applying overrides last
+    pub fn resolve(ssh_config: &SshTunnel, overrides: Option<&TunnelOverrides>) -> Result<Self> {
+        let target = match ssh_config {
+            SshTunnel::Explicit {
+                host,
+                port,
+                user,
+                key_path,
+                key_passphrase_cmd,
+                key_passphrase_env,
+                known_hosts_file,
+                ..
+            } => Self {
+                host: host.clone(),
+                port: *port,
+                user: Some(user.clone()),
+                key_paths: key_path.iter().cloned().collect(),
+                identities_only: false,
+                key_passphrase: KeyPassphrase {
+                    cmd: key_passphrase_cmd.clone(),
+                    env: key_passphrase_env.clone(),
+                },
+                known_hosts_files: known_hosts_file
+                    .iter()
+                    .map(|path| ssh_config::expand_tilde(path))
+                    .chain(ssh_config::default_known_hosts_files())
+                    .collect(),
+                jump_hosts: Vec::new(),
+                skip_host_key_verification: false,
+                ephemeral: false,
+            },
+            SshTunnel::ConfigRef {
+                ssh_config: config_name,
+                key_passphrase_cmd,
+                key_passphrase_env,
+                known_hosts_file,
+                ..
+            } => {
+                let host_config = ssh_config::parse_ssh_config(config_name).with_context(|| {
+                    format!("Failed to parse SSH config for host '{}'", config_name)
+                })?;
+
+                log::info!(
+                    "  Parsed config '{}': {}@{}:{}",
+                    config_name,
+                    host_config.user.as_deref().unwrap_or("<current user>"),
+                    host_config.hostname,
+                    host_config.port
+                );
+                if let Some(command) = &host_config.proxy_command {
+                    log::warn!(
+                        "SSH host '{}' has ProxyCommand {}, which tunnels don't run; \
+                         connecting directly. Use ProxyJump to go through a jump host",
+                        config_name,
+                        command
+                    );
+                }
+
+                let key_passphrase = KeyPassphrase {
+                    cmd: key_passphrase_cmd.clone(),
+                    env: key_passphrase_env.clone(),
+                };
+                let jump_hosts: Vec<SshTarget> =
+                    ssh_config::resolve_jump_hosts(config_name, &host_config.proxy_jump)
+                        .with_context(|| {
+                            format!("Failed to resolve ProxyJump of SSH host '{}'", config_name)
+                        })?
+                        .into_iter()
+                        .map(|hop| {
+                            // A hop using the same key needs the same passphrase
+                            let same_key = hop.identity_files == host_config.identity_files
+                                || hop
+                                    .identity_files
+                                    .iter()
+                                    .any(|key| host_config.identity_files.contains(key));
+                            Self {
+                                key_passphrase: if same_key {
+                                    key_passphrase.clone()
+                                } else {
+                                    KeyPassphrase::default()
+                                },
+                                ..Self::from(hop)
+                            }
+                        })
+                        .collect();
+                for hop in &jump_hosts {
+                    log::info!("  Through ProxyJump host {}:{}", hop.host, hop.port);
+                }
+
+                // UserKnownHostsFile of the first block that sets it, `Host *` included
+                let config_files = host_config.known_hosts_files.clone();
+                Self {
+                    key_passphrase,
+                    jump_hosts,
+                    known_hosts_files: known_hosts_file
+                        .iter()
+                        .map(|path| ssh_config::expand_tilde(path))
+                        .chain(config_files)
+                        .collect(),
+                    ..Self::from(host_config)
+                }
+            }
+        };
+
+        let Some(overrides) = overrides else {
+            return Ok(target);
+        };
+        let mut target = target.with_overrides(overrides);
+        if let Some(proxy_jump) = &overrides.proxy_jump {
+            target.jump_hosts = ssh_config::resolve_jump_hosts(&target.host, proxy_jump)
+                .context("Failed to resolve the proxy_jump override")?
+                .into_iter()
+                .map(Self::from)
+                .collect();
+            for hop in &target.jump_hosts {
+                log::info!(
+                    "  Through overridden ProxyJump host {}:{}",
+                    hop.host,
+                    hop.port
+                );
+            }
+        }
+        Ok(target)
+    }
+
+    /// Replace any fields set in `overrides`, except the ProxyJump hops, which
+    /// `resolve` looks up in ~/.ssh/config
+    fn with_overrides(self, overrides: &TunnelOverrides) -> Self {
+        Self {
+            host: overrides.host.clone().unwrap_or(self.host),
+            port: overrides.port.unwrap_or(self.port),
+            user: overrides.user.clone().or(self.user),
+            key_paths: match &overrides.key_path {
+                Some(key_path) => vec![key_path.clone()],
+                None => self.key_paths,
+            },
+            identities_only: self.identities_only,
+            key_passphrase: self.key_passphrase,
+            known_hosts_files: self.known_hosts_files,
+            jump_hosts: self.jump_hosts,
+            skip_host_key_verification: self.skip_host_key_verification,
+            ephemeral: true,
+        }
+    }
+}
+
+impl From<SshHostConfig> for SshTarget {
+    fn from(host_config: SshHostConfig) -> Self {
+        Self {
+            host: host_config.hostname,
+            port: host_config.port,
+            user: host_config.user,
+            key_paths: host_config.identity_files,
+            identities_only: host_config.identities_only,
+            key_passphrase: KeyPassphrase::default(),
+            known_hosts_files: host_config.known_hosts_files,
+            jump_hosts: Vec::new(),
+            skip_host_key_verification: false,
+            ephemeral: false,
+        }
+    }
+}
+
 /// Allocates local ports for tunnels
 struct PortAllocator {
     allocated: HashMap<u16, String>, // port -> connection_name
@@ -153,27 +671,80 @@ impl PortAllocator {
 }
 
 impl TunnelManager {
-    pub fn new(skip_host_key_verification: bool) -> Self {
+    pub fn new(settings: TunnelSettings) -> Self {
         Self {
             tunnels: Arc::new(Mutex::new(HashMap::new())),
             port_allocator: Arc::new(Mutex::new(PortAllocator::new())),
-            skip_host_key_verification,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            creating_tunnels: ConnectLocks::default(),
+            connecting_sessions: ConnectLocks::default(),
+            settings,
+            host_keys: Arc::new(HostKeyPrompts::default()),
         }
     }
 
+    /// Host keys awaiting the user's decision, and where to give it
+    pub fn host_keys(&self) -> &HostKeyPrompts {
+        &self.host_keys
+    }
+
     /// Get or create a tunnel for the given connection
+    ///
+    /// `overrides` replace individual SSH settings for this tunnel only; they are
+    /// never persisted and disappear when the tunnel is closed. An existing
+    /// tunnel whose SSH session died is rebuilt on the same local port.
+    /// `skip_host_key_verification` is the connection's, see
+    /// `SshTunnel::skip_host_key_verification`.
     pub async fn get_or_create_tunnel(
         &self,
         connection_name: &str,
         ssh_config: &SshTunnel,
-        remote_host: &str,
-        remote_port: u16,
+        overrides: Option<&TunnelOverrides>,
+        remote: &RemoteTarget,
+        skip_host_key_verification: bool,
     ) -> Result<u16> {
-        let mut tunnels = self.tunnels.lock().await;
+        let creating = self.creating_tunnels.get(&connection_name.to_string());
+        let _creating = creating.lock().await;
+
+        // Check if tunnel already exists; the SSH handshake below runs without
+        // the map, so other connections' tunnels stay usable meanwhile
+        let stale = {
+            let mut tunnels = self.tunnels.lock().await;
+            if let Some(tunnel) = tunnels.get(connection_name) {
+                if tunnel.is_alive() {
+                    return Ok(tunnel.local_port);
+                }
+            }
+            tunnels.remove(connection_name)
+        };
+        if let Some(stale) = stale {
+            let local_port = stale.local_port;
+            let tunnel = self.rebuild_tunnel(connection_name, stale).await;
+            return match tunnel {
+                Ok(tunnel) => {
+                    self.tunnels
+                        .lock()
+                        .await
+                        .insert(connection_name.to_string(), tunnel);
+                    Ok(local_port)
+                }
+                Err(e) => {
+                    self.port_allocator.lock().await.deallocate(local_port);
+                    Err(e)
+                }
+            };
+        }
 
-        // Check if tunnel already exists
-        if let Some(tunnel) = tunnels.get(connection_name) {
-            return Ok(tunnel.local_port);
+        let target = SshTarget {
+            skip_host_key_verification,
+            ..SshTarget::resolve(ssh_config, overrides)?
+        };
+        if target.ephemeral {
+            log::warn!(
+                "Using ephemeral tunnel overrides for '{}' (not persisted): {:?}",
+                connection_name,
+                overrides
+            );
         }
 
         // Allocate a local port
@@ -183,333 +754,209 @@ impl TunnelManager {
             .context("Failed to allocate local port for tunnel")?;
         drop(allocator);
 
-        // Create the tunnel
+        // Create the tunnel; the caller adds the context, so a rejected host
+        // key reads as the handshake error says
         let tunnel = self
-            .create_tunnel(ssh_config, local_port, remote_host, remote_port)
+            .create_tunnel(connection_name, &target, local_port, remote)
             .await
-            .with_context(|| {
-                format!(
-                    "Failed to create SSH tunnel for connection '{}' on local port {}",
-                    connection_name, local_port
+            .inspect_err(|e| {
+                log::error!(
+                    "SSH tunnel for '{}' on local port {} failed: {:#}",
+                    connection_name,
+                    local_port,
+                    e
                 )
             })?;
 
-        tunnels.insert(connection_name.to_string(), tunnel);
+        self.tunnels
+            .lock()
+            .await
+            .insert(connection_name.to_string(), tunnel);
 
         Ok(local_port)
     }
 
+    /// Replace `stale`, whose SSH session died, by a tunnel to the same target
+    /// on the same local port, so the port connections were made with stays valid
+    async fn rebuild_tunnel(
+        &self,
+        connection_name: &str,
+        stale: ActiveTunnel,
+    ) -> Result<ActiveTunnel> {
+        log::warn!(
+            "SSH session of tunnel '{}' on port {} is dead, rebuilding it",
+            connection_name,
+            stale.local_port
+        );
+        // The local listener is dropped with the task; wait for that so the
+        // port can be bound again
+        stale._forwarding_task.abort();
+        let _ = stale._forwarding_task.await;
+        release_session(&mut *self.sessions.lock().await, &stale.forwarder.session);
+
+        self.create_tunnel(
+            connection_name,
+            &stale.ssh_target,
+            stale.local_port,
+            &stale.remote,
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to rebuild SSH tunnel for connection '{}' on local port {}",
+                connection_name, stale.local_port
+            )
+        })
+    }
+
     /// Actually create and start the SSH tunnel
     async fn create_tunnel(
         &self,
-        ssh_config: &SshTunnel,
+        connection_name: &str,
+        target: &SshTarget,
         local_port: u16,
-        remote_host: &str,
-        remote_port: u16,
+        remote: &RemoteTarget,
     ) -> Result<ActiveTunnel> {
-        match ssh_config {
-            SshTunnel::Explicit {
-                host,
-                port,
-                user,
-                key_path,
-            } => {
-                log::info!(
-                    "Creating SSH tunnel: {}@{}:{} -> localhost:{} -> {}:{}",
-                    user,
-                    host,
-                    port,
-                    local_port,
-                    remote_host,
-                    remote_port
-                );
-
-                let key_file = if let Some(path) = key_path {
-                    path.clone()
-                } else {
-                    // Find the default SSH key (tries id_rsa, id_ed25519)
-                    find_default_ssh_key()
-                        .context("No SSH key specified and no default key found")?
-                };
-
-                log::info!("  Using key: {}", key_file.display());
-
-                // Load the private key
-                let private_key = load_secret_key(&key_file, None).with_context(|| {
-                    format!("Failed to load SSH key from {}", key_file.display())
-                })?;
-
-                // Create SSH configuration
-                let ssh_client_config = client::Config::default();
-                let ssh_client_config = Arc::new(ssh_client_config);
-
-                // Connect to SSH server
-                log::debug!("Connecting to SSH server {}:{}...", host, port);
-                let ssh_handler =
-                    SshClientHandler::new(host.clone(), *port, self.skip_host_key_verification);
-                let mut ssh_session =
-                    client::connect(ssh_client_config, (host.as_str(), *port), ssh_handler)
-                        .await
-                        .with_context(|| {
-                            format!(
-                                "Failed to connect to SSH server {}:{}. \
-                         Possible reasons:\n  \
-                         - Network connectivity issues\n  \
-                         - Host key verification failed (if skip_host_key_verification=false)\n  \
-                         - SSH server unreachable",
-                                host, port
-                            )
-                        })?;
-                log::debug!("SSH connection established to {}:{}", host, port);
-
-                // Authenticate
-                log::debug!("Authenticating as user '{}'...", user);
-                ssh_session
-                    .authenticate_publickey(user, Arc::new(private_key))
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "SSH authentication failed for user '{}'. \
-                             Check that:\n  \
-                             - The SSH key is correct\n  \
-                             - The user '{}' has access to the SSH server\n  \
-                             - The public key is in ~/.ssh/authorized_keys on the server",
-                            user, user
-                        )
-                    })?;
-                log::debug!("SSH authentication successful");
-
-                // Bind local listener
-                log::debug!("Binding to local port {}...", local_port);
-                let local_listener = TcpListener::bind(("127.0.0.1", local_port))
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to bind to local port {}. \
-                             Port may already be in use.",
-                            local_port
-                        )
-                    })?;
-                log::debug!("Local listener bound to 127.0.0.1:{}", local_port);
-
-                log::info!("  Tunnel established on localhost:{}", local_port);
-
-                // Wrap SSH session in Arc for sharing across tasks
-                log::debug!("Starting tunnel forwarding task");
-                let ssh_session = Arc::new(Mutex::new(ssh_session));
-
-                // Spawn forwarding task
-                let remote_host_string = remote_host.to_string();
-                let remote_host_for_task = remote_host_string.clone();
-                let forwarding_task = tokio::spawn(async move {
-                    loop {
-                        match local_listener.accept().await {
-                            Ok((mut local_socket, _)) => {
-                                let remote_host_clone = remote_host_for_task.clone();
-                                let ssh_session_clone = Arc::clone(&ssh_session);
-
-                                tokio::spawn(async move {
-                                    let session = ssh_session_clone.lock().await;
-                                    match session
-                                        .channel_open_direct_tcpip(
-                                            &remote_host_clone,
-                                            remote_port as u32,
-                                            "127.0.0.1",
-                                            local_port as u32,
-                                        )
-                                        .await
-                                    {
-                                        Ok(ssh_channel) => {
-                                            drop(session); // Release the lock
-                                            let mut ssh_stream = ssh_channel.into_stream();
-
-                                            if let Err(e) = tokio::io::copy_bidirectional(
-                                                &mut local_socket,
-                                                &mut ssh_stream,
-                                            )
-                                            .await
-                                            {
-                                                log::error!("Forwarding error: {}", e);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            log::error!("Failed to open SSH channel: {}", e);
-                                        }
-                                    }
-                                });
-                            }
-                            Err(e) => {
-                                log::error!("Failed to accept local connection: {}", e);
-                                break;
-                            }
-                        }
+        let connector = SessionConnector {
+            target: target.clone(),
+            settings: self.settings.clone(),
+            host_keys: Arc::clone(&self.host_keys),
+        };
+
+        let key = connector.session_key()?;
+
+        log::info!(
+            "Creating SSH tunnel: {}@{}:{} -> localhost:{} -> {}",
+            key.user,
+            target.host,
+            target.port,
+            local_port,
+            remote
+        );
+
+        let mut timings = ConnectTimings::default();
+        let session = self.acquire_session(key, connector, &mut timings).await?;
+
+        // A missing socket or a server without streamlocal forwarding would
+        // otherwise only show as the database closing the connection. The
+        // probe channel is closed right away rather than left to the server
+        if let RemoteTarget::Socket(_) = remote {
+            match session.open_channel(remote, local_port).await {
+                Ok(probe) => {
+                    if let Err(e) = probe.close().await {
+                        log::debug!("Failed to close probe channel to {}: {}", remote, e);
                     }
-                });
-
-                Ok(ActiveTunnel {
-                    local_port,
-                    remote_host: remote_host_string,
-                    remote_port,
-                    _forwarding_task: forwarding_task,
-                })
+                }
+                Err(e) => {
+                    release_session(&mut *self.sessions.lock().await, &session);
+                    return Err(e);
+                }
             }
-            SshTunnel::ConfigRef {
-                ssh_config: config_name,
-            } => {
-                log::info!(
-                    "Creating SSH tunnel using config: {} -> localhost:{} -> {}:{}",
-                    config_name,
-                    local_port,
-                    remote_host,
-                    remote_port
-                );
-
-                // Parse the SSH config file
-                let host_config = ssh_config::parse_ssh_config(config_name).with_context(|| {
-                    format!("Failed to parse SSH config for host '{}'", config_name)
-                })?;
-
-                log::info!(
-                    "  Parsed config: {}@{}:{}",
-                    host_config.user.as_deref().unwrap_or("<current user>"),
-                    host_config.hostname,
-                    host_config.port
-                );
-
-                // Determine the user (use current user if not specified in config)
-                let user = if let Some(u) = host_config.user {
-                    u
-                } else {
-                    std::env::var("USER")
-                        .or_else(|_| std::env::var("USERNAME"))
-                        .context("Could not determine username. Please specify User in SSH config or set USER environment variable")?
-                };
-
-                // Determine the key file (use specified, or fall back to auto-discovery)
-                let key_file = if let Some(path) = host_config.identity_file {
-                    path
-                } else {
-                    find_default_ssh_key().context(
-                        "No IdentityFile specified in SSH config and no default key found",
-                    )?
-                };
-
-                log::info!("  Using key: {}", key_file.display());
-
-                // Load the private key
-                let private_key = load_secret_key(&key_file, None).with_context(|| {
-                    format!("Failed to load SSH key from {}", key_file.display())
-                })?;
-
-                // Create SSH configuration
-                let ssh_client_config = client::Config::default();
-                let ssh_client_config = Arc::new(ssh_client_config);
+        }
 
-                // Connect to SSH server
-                let ssh_handler = SshClientHandler::new(
-                    host_config.hostname.clone(),
-                    host_config.port,
-                    self.skip_host_key_verification,
-                );
-                let mut ssh_session = client::connect(
-                    ssh_client_config,
-                    (host_config.hostname.as_str(), host_config.port),
-                    ssh_handler,
-                )
-                .await
-                .with_context(|| {
+        // Bind local listener
+        log::debug!("Binding to local port {}...", local_port);
+        let phase = Instant::now();
+        let local_listener = match TcpListener::bind(("127.0.0.1", local_port)).await {
+            Ok(local_listener) => local_listener,
+            Err(e) => {
+                release_session(&mut *self.sessions.lock().await, &session);
+                return Err(e).with_context(|| {
                     format!(
-                        "Failed to connect to SSH server {}:{}\n\
-                         Host key verification failed - connect to the SSH host once from outside helix",
-                        host_config.hostname, host_config.port
+                        "Failed to bind to local port {}. \
+                         Port may already be in use.",
+                        local_port
                     )
-                })?;
-
-                // Authenticate
-                ssh_session
-                    .authenticate_publickey(&user, Arc::new(private_key))
-                    .await
-                    .context("SSH authentication failed")?;
-
-                // Bind local listener
-                let local_listener = TcpListener::bind(("127.0.0.1", local_port))
-                    .await
-                    .with_context(|| format!("Failed to bind to local port {}", local_port))?;
-
-                log::info!("  Tunnel established on localhost:{}", local_port);
-
-                // Wrap SSH session in Arc for sharing across tasks
-                let ssh_session = Arc::new(Mutex::new(ssh_session));
-
-                // Spawn forwarding task
-                let remote_host_string = remote_host.to_string();
-                let remote_host_for_task = remote_host_string.clone();
-                let forwarding_task = tokio::spawn(async move {
-                    loop {
-                        match local_listener.accept().await {
-                            Ok((mut local_socket, _)) => {
-                                let remote_host_clone = remote_host_for_task.clone();
-                                let ssh_session_clone = Arc::clone(&ssh_session);
-
-                                tokio::spawn(async move {
-                                    let session = ssh_session_clone.lock().await;
-                                    match session
-                                        .channel_open_direct_tcpip(
-                                            &remote_host_clone,
-                                            remote_port as u32,
-                                            "127.0.0.1",
-                                            local_port as u32,
-                                        )
-                                        .await
-                                    {
-                                        Ok(ssh_channel) => {
-                                            drop(session); // Release the lock
-                                            let mut ssh_stream = ssh_channel.into_stream();
-
-                                            if let Err(e) = tokio::io::copy_bidirectional(
-                                                &mut local_socket,
-                                                &mut ssh_stream,
-                                            )
-                                            .await
-                                            {
-                                                log::error!("Forwarding error: {}", e);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            log::error!("Failed to open SSH channel: {}", e);
-                                        }
-                                    }
-                                });
-                            }
-                            Err(e) => {
-                                log::error!("Failed to accept local connection: {}", e);
-                                break;
-                            }
-                        }
-                    }
                 });
+            }
+        };
+        timings.local_bind = Some(phase.elapsed());
+        log::debug!("Local listener bound to 127.0.0.1:{}", local_port);
+
+        log::info!(
+            "  Tunnel established on localhost:{} in {:.3}s",
+            local_port,
+            timings.total().as_secs_f64()
+        );
+
+        log::debug!("Starting tunnel forwarding task");
+        let forwarder = Arc::new(Forwarder {
+            connection_name: connection_name.to_string(),
+            session,
+            remote: remote.clone(),
+            local_port,
+            tunnels: Arc::downgrade(&self.tunnels),
+            port_allocator: Arc::downgrade(&self.port_allocator),
+            sessions: Arc::downgrade(&self.sessions),
+            traffic: Traffic::default(),
+        });
+        let forwarding_task = tokio::spawn(Arc::clone(&forwarder).run(local_listener));
+
+        Ok(ActiveTunnel {
+            local_port,
+            remote: remote.clone(),
+            ssh_target: target.clone(),
+            timings,
+            created: Instant::now(),
+            forwarder,
+            _forwarding_task: forwarding_task,
+        })
+    }
 
-                Ok(ActiveTunnel {
-                    local_port,
-                    remote_host: remote_host_string,
-                    remote_port,
-                    _forwarding_task: forwarding_task,
-                })
+    /// The live SSH session to `key`'s server as `key`'s user, shared with the
+    /// tunnels already using it, or a new one
+    async fn acquire_session(
+        &self,
+        key: SessionKey,
+        connector: SessionConnector,
+        timings: &mut ConnectTimings,
+    ) -> Result<Arc<SharedSession>> {
+        let connecting = self.connecting_sessions.get(&key);
+        let _connecting = connecting.lock().await;
+
+        if let Some(entry) = self.sessions.lock().await.get_mut(&key) {
+            if entry.session.is_alive() {
+                log::info!(
+                    "  Reusing SSH session to {}@{}:{}",
+                    key.user,
+                    key.host,
+                    key.port
+                );
+                entry.tunnels += 1;
+                return Ok(Arc::clone(&entry.session));
             }
         }
+
+        // The handshake may wait on the network or on the user's host key
+        // decision; tunnels through other servers don't wait for it
+        let handle = connector.connect(timings).await?;
+        let session = Arc::new(SharedSession::new(
+            key.clone(),
+            connector,
+            handle,
+            self.settings.reconnect_attempts,
+        ));
+        // Tunnels still on a dead session this replaces release it when they
+        // are rebuilt or closed
+        self.sessions.lock().await.insert(
+            key,
+            SessionEntry {
+                session: Arc::clone(&session),
+                tunnels: 1,
+            },
+        );
+        Ok(session)
     }
 
-    /// Close a specific tunnel
+    /// Close a specific tunnel; its SSH session is closed with the last tunnel
+    /// using it
     pub async fn close_tunnel(&self, connection_name: &str) -> Result<()> {
         let mut tunnels = self.tunnels.lock().await;
 
         if let Some(tunnel) = tunnels.remove(connection_name) {
             let mut allocator = self.port_allocator.lock().await;
-            allocator.deallocate(tunnel.local_port);
-
-            // The forwarding task will be dropped and cancelled automatically
-            tunnel._forwarding_task.abort();
-            log::info!("Closed tunnel on port {}", tunnel.local_port);
+            let mut sessions = self.sessions.lock().await;
+            shut_down(tunnel, &mut allocator, &mut sessions);
         }
 
         Ok(())
@@ -519,52 +966,1051 @@ impl TunnelManager {
     pub async fn close_all(&self) -> Result<()> {
         let mut tunnels = self.tunnels.lock().await;
         let mut allocator = self.port_allocator.lock().await;
+        let mut sessions = self.sessions.lock().await;
 
         for (_, tunnel) in tunnels.drain() {
-            allocator.deallocate(tunnel.local_port);
-            tunnel._forwarding_task.abort();
-            log::info!("Closed tunnel on port {}", tunnel.local_port);
+            shut_down(tunnel, &mut allocator, &mut sessions);
         }
 
         Ok(())
     }
 
+    /// Get the establishment timings of an existing tunnel
+    pub async fn get_tunnel_timings(&self, connection_name: &str) -> Option<ConnectTimings> {
+        let tunnels = self.tunnels.lock().await;
+        tunnels.get(connection_name).map(|t| t.timings.clone())
+    }
+
     /// Get the local port for an existing tunnel
     pub async fn get_tunnel_port(&self, connection_name: &str) -> Option<u16> {
         let tunnels = self.tunnels.lock().await;
         tunnels.get(connection_name).map(|t| t.local_port)
     }
-}
 
-impl Default for TunnelManager {
-    fn default() -> Self {
-        Self::new(false)
+    /// Whether `connection_name` has a tunnel and its SSH session is alive
+    pub async fn is_tunnel_alive(&self, connection_name: &str) -> bool {
+        let tunnels = self.tunnels.lock().await;
+        tunnels
+            .get(connection_name)
+            .is_some_and(ActiveTunnel::is_alive)
+    }
+
+    /// Endpoints, session state and traffic of `connection_name`'s tunnel;
+    /// None when it has none, or while the map of tunnels is being changed
+    pub fn tunnel_status(&self, connection_name: &str) -> Option<TunnelStatus> {
+        let tunnels = self.tunnels.try_lock().ok()?;
+        let tunnel = tunnels.get(connection_name)?;
+        let forwarder = &tunnel.forwarder;
+        let key = &forwarder.session.key;
+        let state = forwarder.session.state();
+        Some(TunnelStatus {
+            ssh_host: key.host.clone(),
+            ssh_port: key.port,
+            ssh_user: key.user.clone(),
+            local_port: tunnel.local_port,
+            remote: tunnel.remote.clone(),
+            healthy: state == SessionState::Up && tunnel.is_alive(),
+            state,
+            uptime: tunnel.created.elapsed(),
+            bytes_sent: forwarder.traffic.sent.load(Ordering::Relaxed),
+            bytes_received: forwarder.traffic.received.load(Ordering::Relaxed),
+        })
     }
 }
 
-/// Find the default SSH private key
-/// Tries the following keys in order:
-/// 1. ~/.ssh/id_rsa
-/// 2. ~/.ssh/id_ed25519
-fn find_default_ssh_key() -> Result<PathBuf> {
-    let home = std::env::var("HOME").context("HOME environment variable not set")?;
-    let ssh_dir = PathBuf::from(home).join(".ssh");
+/// Whether failing to open a channel means the SSH session itself is gone,
+/// rather than the server refusing this one forward
+fn is_session_error(error: &russh::Error) -> bool {
+    !matches!(error, russh::Error::ChannelOpenFailure(_))
+}
 
-    // Try common SSH key types in order
-    let key_candidates = vec![ssh_dir.join("id_rsa"), ssh_dir.join("id_ed25519")];
+/// Wait after failed reconnect attempt `attempt`: 1s, doubling up to 30s
+fn reconnect_delay(attempt: u32) -> Duration {
+    let secs = 1u64 << attempt.saturating_sub(1).min(5);
+    Duration::from_secs(secs.min(30))
+}
 
-    for key_path in key_candidates {
-        if key_path.exists() {
-            return Ok(key_path);
-        }
+/// What it takes to (re-)establish a tunnel's SSH session
+struct SessionConnector {
+    target: SshTarget,
+    settings: TunnelSettings,
+    host_keys: Arc<HostKeyPrompts>,
+}
+
+impl SessionConnector {
+    /// Server and user of the session, which tunnels with the same ones share
+    fn session_key(&self) -> Result<SessionKey> {
+        Ok(SessionKey {
+            host: self.target.host.clone(),
+            port: self.target.port,
+            user: self.user()?,
+            skip_host_key_verification: self.target.skip_host_key_verification,
+        })
     }
 
-    anyhow::bail!(
-        "No SSH private key found. Tried:\n  \
-         - ~/.ssh/id_rsa\n  \
-         - ~/.ssh/id_ed25519"
-    )
-}
+    /// SSH user, the current user if the target doesn't set one
+    fn user(&self) -> Result<String> {
+        match &self.target.user {
+            Some(user) => Ok(user.clone()),
+            None => std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .context("Could not determine username. Please specify User in SSH config or set USER environment variable"),
+        }
+    }
+
+    /// Connect to the SSH server and authenticate, recording each phase in `timings`
+    async fn connect(
+        &self,
+        timings: &mut ConnectTimings,
+    ) -> Result<client::Handle<SshClientHandler>> {
+        let connecting = self.connect_through_jumps(timings);
+        match tokio::time::timeout(self.settings.connect_timeout, connecting).await {
+            Ok(result) => result,
+            Err(_) => Err(connect_timeout_error(
+                &self.target,
+                self.settings.connect_timeout,
+                timings,
+            )),
+        }
+    }
+
+    /// Open a session with each ProxyJump host through the one before it,
+    /// then with the server through the last one
+    async fn connect_through_jumps(
+        &self,
+        timings: &mut ConnectTimings,
+    ) -> Result<client::Handle<SshClientHandler>> {
+        let phase = Instant::now();
+        let mut via = None;
+        for hop in &self.target.jump_hosts {
+            log::info!("  Connecting to ProxyJump host {}:{}", hop.host, hop.port);
+            let connector = SessionConnector {
+                target: SshTarget {
+                    skip_host_key_verification: self.target.skip_host_key_verification,
+                    ..hop.clone()
+                },
+                settings: self.settings.clone(),
+                host_keys: Arc::clone(&self.host_keys),
+            };
+            let handle = connector
+                .open_session(via, &mut ConnectTimings::default())
+                .await
+                .with_context(|| format!("Failed to connect to ProxyJump host {}", hop.host))?;
+            via = Some(handle);
+        }
+        if via.is_some() {
+            timings.proxy_jump = Some(phase.elapsed());
+        }
+        self.open_session(via, timings).await
+    }
+
+    /// Connect to and authenticate with the SSH server, directly or through
+    /// the session `via` a ProxyJump host, recording each phase in `timings`
+    async fn open_session(
+        &self,
+        via: Option<client::Handle<SshClientHandler>>,
+        timings: &mut ConnectTimings,
+    ) -> Result<client::Handle<SshClientHandler>> {
+        let target = &self.target;
+        let user = self.user()?;
+        let keys = self.load_keys().await?;
+
+        let mut ssh_session = match via {
+            Some(via) => {
+                // The jump host opens the TCP connection to the server
+                let phase = Instant::now();
+                let channel = via
+                    .channel_open_direct_tcpip(
+                        target.host.as_str(),
+                        target.port.into(),
+                        "127.0.0.1",
+                        0,
+                    )
+                    .await
+                    .map_err(|e| jump_error(target, e))?;
+                timings.tcp_connect = Some(phase.elapsed());
+                self.handshake(channel.into_stream(), None, Some(via), timings)
+                    .await?
+            }
+            None => {
+                let stream = self.connect_tcp(timings).await?;
+                let remote_ip = stream.peer_addr().ok().map(|addr| addr.ip());
+                self.handshake(stream, remote_ip, None, timings).await?
+            }
+        };
+
+        // Authenticate
+        // Authenticate, offering each key in turn
+        let key_files: Vec<PathBuf> = keys.iter().map(|(path, _)| path.clone()).collect();
+        let phase = Instant::now();
+        let mut reason = None;
+        for (key_file, private_key) in keys {
+            log::debug!(
+                "Authenticating as user '{}' with key {}...",
+                user,
+                key_file.display()
+            );
+            match ssh_session
+                .authenticate_publickey(&user, Arc::new(private_key))
+                .await
+            {
+                Ok(true) => {
+                    timings.ssh_auth = Some(phase.elapsed());
+                    log::info!(
+                        "  Authenticated to {}:{} as '{}' with key {}",
+                        target.host,
+                        target.port,
+                        user,
+                        key_file.display()
+                    );
+                    return Ok(ssh_session);
+                }
+                Ok(false) => log::debug!("Key {} was rejected", key_file.display()),
+                Err(e) => {
+                    reason = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        Err(auth_error(target, &user, &key_files, reason.as_deref()))
+    }
+
+    /// The private keys to offer, in order: the target's, then the default key
+    /// unless IdentitiesOnly is set; keys that fail to load are skipped
+    async fn load_keys(&self) -> Result<Vec<(PathBuf, key::KeyPair)>> {
+        let target = &self.target;
+        let mut key_files = target.key_paths.clone();
+        if target.identities_only {
+            if key_files.is_empty() {
+                anyhow::bail!(
+                    "IdentitiesOnly is set for SSH server {} but no IdentityFile is",
+                    target.host
+                );
+            }
+        } else {
+            // Find the default SSH key (Host * IdentityFile, then id_ed25519, id_ecdsa, id_rsa)
+            match find_default_ssh_key() {
+                Ok(path) if !key_files.contains(&path) => key_files.push(path),
+                Ok(_) => {}
+                Err(e) if key_files.is_empty() => {
+                    return Err(e.context("No SSH key specified and no default key found"))
+                }
+                Err(e) => log::debug!("No default SSH key to fall back to: {:#}", e),
+            }
+        }
+
+        let passphrase = target.key_passphrase.resolve().await?;
+        let mut keys = Vec::new();
+        let mut first_error = None;
+        for key_file in key_files {
+            match load_private_key(&key_file, passphrase.as_deref()) {
+                Ok(private_key) => {
+                    log::info!("  Using key: {}", key_file.display());
+                    keys.push((key_file, private_key));
+                }
+                Err(e) => {
+                    log::warn!("Skipping SSH key: {:#}", e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) if keys.is_empty() => Err(e),
+            _ => Ok(keys),
+        }
+    }
+
+    /// Resolve the SSH server and open a TCP connection to it
+    async fn connect_tcp(&self, timings: &mut ConnectTimings) -> Result<TcpStream> {
+        let target = &self.target;
+
+        // Resolve the SSH host
+        let phase = Instant::now();
+        let addrs: Vec<std::net::SocketAddr> =
+            tokio::net::lookup_host((target.host.as_str(), target.port))
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Could not resolve SSH host '{}' ({}); check the host name in \
+                         config.toml or ~/.ssh/config, and your DNS or VPN",
+                        target.host,
+                        e
+                    )
+                })?
+                .collect();
+        timings.resolve = Some(phase.elapsed());
+
+        // Open the TCP connection to the SSH server
+        log::debug!(
+            "Connecting to SSH server {}:{}...",
+            target.host,
+            target.port
+        );
+        let phase = Instant::now();
+        let stream = TcpStream::connect(&addrs[..])
+            .await
+            .map_err(|e| tcp_connect_error(target, &e))?;
+        timings.tcp_connect = Some(phase.elapsed());
+        Ok(stream)
+    }
+
+    /// SSH handshake, including host key verification, over `stream`, which
+    /// goes to `remote_ip` when it's a TCP connection, or through the session
+    /// `via` a ProxyJump host
+    async fn handshake<S>(
+        &self,
+        stream: S,
+        remote_ip: Option<std::net::IpAddr>,
+        via: Option<client::Handle<SshClientHandler>>,
+        timings: &mut ConnectTimings,
+    ) -> Result<client::Handle<SshClientHandler>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let target = &self.target;
+
+        // Create SSH configuration; keepalives stop firewalls from dropping an
+        // idle session, and russh closes the session when they go unanswered
+        let ssh_client_config = Arc::new(client::Config {
+            keepalive_interval: self.settings.keepalive_interval,
+            ..Default::default()
+        });
+
+        // SSH handshake (includes host key verification)
+        let rejection = Arc::new(std::sync::Mutex::new(None));
+        let ssh_handler = SshClientHandler {
+            hostname: target.host.clone(),
+            port: target.port,
+            skip_verification: target.skip_host_key_verification,
+            host_key_policy: self.settings.host_key_policy,
+            known_hosts_files: known_hosts::known_hosts_files(&target.known_hosts_files),
+            remote_ip,
+            host_keys: Arc::clone(&self.host_keys),
+            rejection: Arc::clone(&rejection),
+            _via: via,
+        };
+        let phase = Instant::now();
+        let ssh_session = client::connect_stream(ssh_client_config, stream, ssh_handler)
+            .await
+            .map_err(|e| {
+                let rejection = rejection.lock().unwrap_or_else(|e| e.into_inner()).take();
+                handshake_error(target, &e, rejection)
+            })?;
+        timings.ssh_handshake = Some(phase.elapsed());
+        log::debug!(
+            "SSH connection established to {}:{}",
+            target.host,
+            target.port
+        );
+        Ok(ssh_session)
+    }
+}
+
+/// Error for a TCP connection to the SSH server that failed, by cause
+fn tcp_connect_error(target: &SshTarget, error: &std::io::Error) -> anyhow::Error {
+    match error.kind() {
+        std::io::ErrorKind::ConnectionRefused => anyhow::anyhow!(
+            "SSH server {}:{} refused the connection; check the port and that sshd runs there",
+            target.host,
+            target.port
+        ),
+        std::io::ErrorKind::TimedOut => anyhow::anyhow!(
+            "Connecting to SSH server {}:{} timed out; the host may be down or a firewall \
+             or VPN may block it",
+            target.host,
+            target.port
+        ),
+        _ => anyhow::anyhow!(
+            "Could not reach SSH server {}:{} ({}); check your network or VPN",
+            target.host,
+            target.port,
+            error
+        ),
+    }
+}
+
+/// Error for a ProxyJump host that couldn't open the connection to the server
+fn jump_error(target: &SshTarget, error: russh::Error) -> anyhow::Error {
+    match error {
+        russh::Error::ChannelOpenFailure(reason) => anyhow::anyhow!(
+            "ProxyJump host refused to connect to SSH server {}:{} ({:?}); check that the \
+             jump host can reach it and allows TCP forwarding (AllowTcpForwarding)",
+            target.host,
+            target.port,
+            reason
+        ),
+        e => anyhow::anyhow!(
+            "Lost the ProxyJump session while connecting to SSH server {}:{}: {}",
+            target.host,
+            target.port,
+            e
+        ),
+    }
+}
+
+/// Error for an SSH handshake that failed; a rejected host key says how to
+/// accept it
+fn handshake_error(
+    target: &SshTarget,
+    error: &russh::Error,
+    rejection: Option<HostKeyRejection>,
+) -> anyhow::Error {
+    match (error, rejection) {
+        (
+            _,
+            Some(HostKeyRejection::KeyMismatch {
+                expected_fingerprints,
+                presented,
+                file,
+                line,
+            }),
+        ) => anyhow::anyhow!(
+            "WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED for SSH server {}:{}! Someone \
+             could be eavesdropping on you (man-in-the-middle attack), or the host key has just \
+             been changed. The server sent {}, but {}:{} expects {}. Once the server's admin \
+             confirms the new key, remove the old one with ssh-keygen -R '{}'",
+            target.host,
+            target.port,
+            presented,
+            file.display(),
+            line,
+            expected_fingerprints.join(" or "),
+            known_hosts::host_pattern(&target.host, target.port)
+        ),
+        (_, Some(HostKeyRejection::Revoked { fingerprint })) => anyhow::anyhow!(
+            "Host key {} of SSH server {}:{} is REVOKED in known_hosts; refusing to connect. \
+             Ask the server's admin for its current key",
+            fingerprint,
+            target.host,
+            target.port
+        ),
+        (_, Some(HostKeyRejection::CertAuthority { file, line })) => anyhow::anyhow!(
+            "{}:{} trusts a certificate authority (@cert-authority) for SSH server {}:{}, but \
+             host certificates aren't supported; add the server's own host key to known_hosts",
+            file.display(),
+            line,
+            target.host,
+            target.port
+        ),
+        (
+            _,
+            Some(HostKeyRejection::Unconfirmed {
+                key_type,
+                fingerprint,
+            }),
+        ) => anyhow::anyhow!(
+            "Host key {} {} of SSH server {}:{} is not in ~/.ssh/known_hosts; accept it to \
+             connect (host_key_policy = \"ask\")",
+            key_type,
+            fingerprint,
+            target.host,
+            target.port
+        ),
+        (_, Some(HostKeyRejection::Unknown)) => anyhow::anyhow!(
+            "Host key of SSH server {}:{} is not in ~/.ssh/known_hosts; connect once with ssh \
+             from a terminal to check and accept it, or set host_key_policy = \"accept-new\" \
+             or \"ask\" in config.toml",
+            target.host,
+            target.port
+        ),
+        (russh::Error::UnknownKey, None) => anyhow::anyhow!(
+            "Host key of SSH server {}:{} was rejected: it is missing from or doesn't match \
+             ~/.ssh/known_hosts; connect once with ssh from a terminal to check and accept it",
+            target.host,
+            target.port
+        ),
+        _ => anyhow::anyhow!(
+            "SSH handshake with {}:{} failed ({}); check that the port is an SSH server",
+            target.host,
+            target.port,
+            error
+        ),
+    }
+}
+
+/// Error for a key the SSH server didn't accept
+fn auth_error(
+    target: &SshTarget,
+    user: &str,
+    key_files: &[PathBuf],
+    reason: Option<&str>,
+) -> anyhow::Error {
+    let reason = reason.map(|r| format!(" ({})", r)).unwrap_or_default();
+    let keys = key_files
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    anyhow::anyhow!(
+        "SSH server {}:{} rejected key{} {} for user '{}'{}; check the user and that the \
+         public key is in ~/.ssh/authorized_keys on the server",
+        target.host,
+        target.port,
+        if key_files.len() == 1 { "" } else { "s" },
+        keys,
+        user,
+        reason
+    )
+}
+
+/// Error for a connect that ran past `timeout`, naming the phase it was in
+fn connect_timeout_error(
+    target: &SshTarget,
+    timeout: Duration,
+    timings: &ConnectTimings,
+) -> anyhow::Error {
+    let jumps = !target.jump_hosts.is_empty();
+    let phase = if jumps && timings.proxy_jump.is_none() {
+        "connecting to its ProxyJump hosts"
+    } else if !jumps && timings.resolve.is_none() {
+        "resolving the host name"
+    } else if timings.tcp_connect.is_none() {
+        "opening the TCP connection"
+    } else if timings.ssh_handshake.is_none() {
+        "in the SSH handshake"
+    } else {
+        "authenticating"
+    };
+    anyhow::anyhow!(
+        "Timed out after {}s connecting to SSH server {}:{} ({}); the host may be down \
+         or a firewall or VPN may block it. Raise ssh_connect_timeout_secs if it is just slow",
+        timeout.as_secs(),
+        target.host,
+        target.port,
+        phase
+    )
+}
+
+/// Server and user an SSH session is shared by; a session whose host key
+/// wasn't verified is never shared with a tunnel that verifies it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SessionKey {
+    host: String,
+    port: u16,
+    user: String,
+    skip_host_key_verification: bool,
+}
+
+/// SSH sessions by server and user, each shared by the tunnels through it
+type Sessions = HashMap<SessionKey, SessionEntry>;
+
+/// One lock per key, held while that key's tunnel or session is being made
+struct ConnectLocks<K>(std::sync::Mutex<HashMap<K, Arc<Mutex<()>>>>);
+
+impl<K> Default for ConnectLocks<K> {
+    fn default() -> Self {
+        Self(std::sync::Mutex::new(HashMap::new()))
+    }
+}
+
+impl<K: Clone + Eq + std::hash::Hash> ConnectLocks<K> {
+    /// The lock of `key`, to be held while making its tunnel or session
+    fn get(&self, key: &K) -> Arc<Mutex<()>> {
+        Arc::clone(
+            self.0
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(key.clone())
+                .or_default(),
+        )
+    }
+}
+
+struct SessionEntry {
+    session: Arc<SharedSession>,
+    /// Tunnels using the session; it is disconnected when the last one closes
+    tunnels: usize,
+}
+
+/// Drop one tunnel's share of `session`, disconnecting it once no tunnel uses it
+fn release_session(sessions: &mut Sessions, session: &Arc<SharedSession>) {
+    // A session that died may have been replaced by a new one under its key
+    let Some(entry) = sessions
+        .get_mut(&session.key)
+        .filter(|entry| Arc::ptr_eq(&entry.session, session))
+    else {
+        return;
+    };
+    entry.tunnels -= 1;
+    if entry.tunnels == 0 {
+        sessions.remove(&session.key);
+        let session = Arc::clone(session);
+        tokio::spawn(async move { session.disconnect().await });
+    }
+}
+
+/// Stop forwarding through `tunnel`, free its port and release its share of
+/// the SSH session
+fn shut_down(tunnel: ActiveTunnel, allocator: &mut PortAllocator, sessions: &mut Sessions) {
+    allocator.deallocate(tunnel.local_port);
+    // The forwarding task will be dropped and cancelled automatically
+    tunnel._forwarding_task.abort();
+    release_session(sessions, &tunnel.forwarder.session);
+    log::info!("Closed tunnel on port {}", tunnel.local_port);
+}
+
+/// An SSH session the direct-tcpip channels of one or more tunnels are opened
+/// on, re-established when it drops
+///
+/// Channels are opened concurrently on a shared handle; the handle is only
+/// swapped, under a short lock, once a reconnect succeeded.
+struct SharedSession {
+    key: SessionKey,
+    connector: SessionConnector,
+    handle: std::sync::Mutex<Arc<client::Handle<SshClientHandler>>>,
+    /// Held by whoever re-establishes the session; the others wait for it
+    reconnecting: Mutex<()>,
+    state: std::sync::Mutex<SessionState>,
+    /// Reconnect attempts before the session is given up
+    max_attempts: u32,
+}
+
+impl SharedSession {
+    fn new(
+        key: SessionKey,
+        connector: SessionConnector,
+        handle: client::Handle<SshClientHandler>,
+        max_attempts: u32,
+    ) -> Self {
+        Self {
+            key,
+            connector,
+            handle: std::sync::Mutex::new(Arc::new(handle)),
+            reconnecting: Mutex::new(()),
+            state: std::sync::Mutex::new(SessionState::Up),
+            max_attempts,
+        }
+    }
+
+    fn state(&self) -> SessionState {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set_state(&self, state: SessionState) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = state;
+    }
+
+    fn handle(&self) -> Arc<client::Handle<SshClientHandler>> {
+        Arc::clone(&self.handle.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Whether the session wasn't given up and russh hasn't closed it (it does
+    /// after `keepalive_max` unanswered keepalives); a reconnecting session is
+    /// alive
+    fn is_alive(&self) -> bool {
+        match self.state() {
+            SessionState::Up => !self.handle().is_closed(),
+            SessionState::Reconnecting { .. } => true,
+            SessionState::Failed => false,
+        }
+    }
+
+    /// Open a channel to `remote` for a connection accepted on `local_port`,
+    /// re-establishing the session first when it is gone
+    async fn open_channel(
+        &self,
+        remote: &RemoteTarget,
+        local_port: u16,
+    ) -> Result<Channel<client::Msg>> {
+        let handle = self.handle();
+        if !handle.is_closed() {
+            match open_remote(&handle, remote, local_port).await {
+                Err(e) if is_session_error(&e) => {
+                    log::warn!(
+                        "SSH session to {}:{} dropped: {}",
+                        self.key.host,
+                        self.key.port,
+                        e
+                    );
+                }
+                result => return result.map_err(|e| channel_error(remote, e)),
+            }
+        }
+        let handle = self.replace(&handle).await?;
+        open_remote(&handle, remote, local_port)
+            .await
+            .map_err(|e| channel_error(remote, e))
+    }
+
+    /// A live handle in place of `dead`: the one another channel already
+    /// reconnected, or a new one
+    async fn replace(
+        &self,
+        dead: &Arc<client::Handle<SshClientHandler>>,
+    ) -> Result<Arc<client::Handle<SshClientHandler>>> {
+        let _reconnecting = self.reconnecting.lock().await;
+        let current = self.handle();
+        if !Arc::ptr_eq(&current, dead) {
+            return Ok(current);
+        }
+        if self.state() == SessionState::Failed {
+            anyhow::bail!(
+                "SSH session to {}:{} was given up",
+                self.key.host,
+                self.key.port
+            );
+        }
+        let handle = Arc::new(self.reconnect().await?);
+        *self.handle.lock().unwrap_or_else(|e| e.into_inner()) = Arc::clone(&handle);
+        Ok(handle)
+    }
+
+    /// Re-establish the session, waiting longer after each failed attempt;
+    /// after `max_attempts` failures it is marked failed
+    async fn reconnect(&self) -> Result<client::Handle<SshClientHandler>> {
+        for attempt in 1..=self.max_attempts {
+            self.set_state(SessionState::Reconnecting {
+                attempt,
+                max_attempts: self.max_attempts,
+            });
+            if attempt > 1 {
+                tokio::time::sleep(reconnect_delay(attempt - 1)).await;
+            }
+            match self.connector.connect(&mut ConnectTimings::default()).await {
+                Ok(handle) => {
+                    log::info!(
+                        "Reconnected SSH session to {}:{}",
+                        self.key.host,
+                        self.key.port
+                    );
+                    self.set_state(SessionState::Up);
+                    return Ok(handle);
+                }
+                Err(e) => log::warn!(
+                    "Reconnect {} of {} to SSH server {}:{} failed: {:#}",
+                    attempt,
+                    self.max_attempts,
+                    self.key.host,
+                    self.key.port,
+                    e
+                ),
+            }
+        }
+        self.set_state(SessionState::Failed);
+        anyhow::bail!(
+            "Gave up reconnecting to SSH server {}:{} after {} attempts",
+            self.key.host,
+            self.key.port,
+            self.max_attempts
+        )
+    }
+
+    async fn disconnect(&self) {
+        if let Err(e) = self
+            .handle()
+            .disconnect(russh::Disconnect::ByApplication, "", "en")
+            .await
+        {
+            log::debug!(
+                "Failed to disconnect from SSH server {}:{}: {}",
+                self.key.host,
+                self.key.port,
+                e
+            );
+        }
+        log::info!("Closed SSH session to {}:{}", self.key.host, self.key.port);
+    }
+}
+
+async fn open_remote(
+    handle: &client::Handle<SshClientHandler>,
+    remote: &RemoteTarget,
+    local_port: u16,
+) -> Result<Channel<client::Msg>, russh::Error> {
+    match remote {
+        RemoteTarget::Tcp { host, port } => {
+            handle
+                .channel_open_direct_tcpip(host, *port as u32, "127.0.0.1", local_port as u32)
+                .await
+        }
+        RemoteTarget::Socket(path) => handle.channel_open_direct_streamlocal(path).await,
+    }
+}
+
+/// Error for a channel to `remote` the SSH server refused, saying why for the
+/// refusals of streamlocal channels OpenSSH distinguishes
+fn channel_error(remote: &RemoteTarget, error: russh::Error) -> anyhow::Error {
+    use russh::ChannelOpenFailure::*;
+    match (remote, &error) {
+        (
+            RemoteTarget::Socket(_),
+            russh::Error::ChannelOpenFailure(AdministrativelyProhibited | UnknownChannelType),
+        ) => anyhow::anyhow!(
+            "The SSH server does not support streamlocal forwarding to UNIX sockets; \
+             enable AllowStreamLocalForwarding in its sshd_config ({:?})",
+            error
+        ),
+        (RemoteTarget::Socket(path), russh::Error::ChannelOpenFailure(ConnectFailed)) => {
+            anyhow::anyhow!(
+                "Socket path {} not found on the SSH server; check remote_socket and that \
+                 the database listens there",
+                path
+            )
+        }
+        _ => anyhow::Error::new(error).context(format!("Failed to open SSH channel to {}", remote)),
+    }
+}
+
+/// Bytes forwarded through a tunnel, over all its connections
+#[derive(Debug, Default)]
+struct Traffic {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+/// A local socket that counts the bytes read from it as sent and the bytes
+/// written to it as received
+struct Counted<'a, S> {
+    inner: S,
+    traffic: &'a Traffic,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Counted<'_, S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = polled {
+            let read = (buf.filled().len() - before) as u64;
+            self.traffic.sent.fetch_add(read, Ordering::Relaxed);
+        }
+        polled
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Counted<'_, S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let polled = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = polled {
+            self.traffic
+                .received
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        polled
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Copy between a local socket and its SSH channel until either side closes,
+/// counting the bytes in `traffic`
+async fn pump<L, R>(local_socket: L, mut remote: R, traffic: &Traffic) -> std::io::Result<()>
+where
+    L: AsyncRead + AsyncWrite + Unpin,
+    R: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut local = Counted {
+        inner: local_socket,
+        traffic,
+    };
+    tokio::io::copy_bidirectional(&mut local, &mut remote).await?;
+    Ok(())
+}
+
+/// Forwards connections accepted on a tunnel's local port through its SSH
+/// session
+///
+/// The local listener stays bound while the session reconnects: connections
+/// accepted meanwhile wait for the new session instead of being refused.
+struct Forwarder {
+    connection_name: String,
+    session: Arc<SharedSession>,
+    remote: RemoteTarget,
+    local_port: u16,
+    traffic: Traffic,
+    /// The manager's maps, to remove tunnels from when giving up
+    tunnels: Weak<Mutex<HashMap<String, ActiveTunnel>>>,
+    port_allocator: Weak<Mutex<PortAllocator>>,
+    sessions: Weak<Mutex<Sessions>>,
+}
+
+impl Forwarder {
+    /// Accept local connections until the tunnel is closed or given up
+    async fn run(self: Arc<Self>, local_listener: TcpListener) {
+        let mut accept_failures = 0;
+        loop {
+            match local_listener.accept().await {
+                Ok((local_socket, _)) => {
+                    accept_failures = 0;
+                    tokio::spawn(Arc::clone(&self).forward(local_socket));
+                }
+                Err(e) => {
+                    log::error!("Failed to accept local connection: {}", e);
+                    accept_failures += 1;
+                    if accept_failures > self.session.max_attempts {
+                        self.tear_down(false).await;
+                        return;
+                    }
+                    tokio::time::sleep(reconnect_delay(accept_failures)).await;
+                }
+            }
+        }
+    }
+
+    /// Forward one local connection to the remote target
+    async fn forward(self: Arc<Self>, local_socket: TcpStream) {
+        let opened = self
+            .session
+            .open_channel(&self.remote, self.local_port)
+            .await;
+        let ssh_channel = match opened {
+            Ok(ssh_channel) => ssh_channel,
+            Err(e) => {
+                log::error!(
+                    "Failed to open SSH channel for '{}': {:#}",
+                    self.connection_name,
+                    e
+                );
+                if self.session.state() == SessionState::Failed {
+                    self.tear_down(true).await;
+                }
+                return;
+            }
+        };
+        if let Err(e) = pump(local_socket, ssh_channel.into_stream(), &self.traffic).await {
+            log::error!("Forwarding error: {}", e);
+        }
+    }
+
+    /// Close this tunnel, or with `whole_session` every tunnel on its SSH
+    /// session, freeing their ports
+    async fn tear_down(&self, whole_session: bool) {
+        let (Some(tunnels), Some(port_allocator), Some(sessions)) = (
+            self.tunnels.upgrade(),
+            self.port_allocator.upgrade(),
+            self.sessions.upgrade(),
+        ) else {
+            return;
+        };
+        let mut tunnels = tunnels.lock().await;
+        let mut allocator = port_allocator.lock().await;
+        let mut sessions = sessions.lock().await;
+
+        // A rebuilt tunnel may have replaced this one already
+        let doomed: Vec<String> = tunnels
+            .iter()
+            .filter(|(_, tunnel)| {
+                if whole_session {
+                    Arc::ptr_eq(&tunnel.forwarder.session, &self.session)
+                } else {
+                    std::ptr::eq(Arc::as_ptr(&tunnel.forwarder), self)
+                }
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in doomed {
+            if let Some(tunnel) = tunnels.remove(&name) {
+                log::error!(
+                    "Closing SSH tunnel '{}' on port {}",
+                    name,
+                    tunnel.local_port
+                );
+                shut_down(tunnel, &mut allocator, &mut sessions);
+            }
+        }
+    }
+}
+
+impl Default for TunnelManager {
+    fn default() -> Self {
+        Self::new(TunnelSettings::default())
+    }
+}
+
+/// Load the private key at `path`, decrypting it with `passphrase` if given
+///
+/// An encrypted key without a passphrase, or with one that doesn't decrypt it,
+/// gets an error saying so instead of the generic load failure.
+fn load_private_key(path: &Path, passphrase: Option<&str>) -> Result<key::KeyPair> {
+    load_secret_key(path, passphrase).map_err(|e| match (e, passphrase) {
+        (russh_keys::Error::KeyIsEncrypted, None) => anyhow::anyhow!(
+            "SSH key {} is encrypted; set key_passphrase_cmd or key_passphrase_env",
+            path.display()
+        ),
+        (e @ russh_keys::Error::IO(_), _) => {
+            anyhow::Error::new(e).context(format!("Failed to read SSH key {}", path.display()))
+        }
+        (e, Some(_)) => anyhow::Error::new(e).context(format!(
+            "Failed to load SSH key {}: key appears to be encrypted and the provided passphrase did not work",
+            path.display()
+        )),
+        (e, None) => anyhow::Error::new(e)
+            .context(format!("Failed to load SSH key from {}", path.display())),
+    })
+}
+
+/// Built-in default key names, most preferred first
+const DEFAULT_KEY_NAMES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Find the default SSH private key
+/// Tries the following keys in order:
+/// 1. IdentityFile entries of the `Host *` block in ~/.ssh/config
+/// 2. ~/.ssh/id_ed25519
+/// 3. ~/.ssh/id_ecdsa
+/// 4. ~/.ssh/id_rsa
+fn find_default_ssh_key() -> Result<PathBuf> {
+    let home = std::env::var("HOME").context("HOME environment variable not set")?;
+    let ssh_dir = PathBuf::from(home).join(".ssh");
+
+    find_ssh_key_in(&ssh_dir, &ssh_config::default_identity_files())
+}
+
+/// Pick the first readable key among `configured` files, then the built-in
+/// names in `ssh_dir`
+fn find_ssh_key_in(ssh_dir: &Path, configured: &[PathBuf]) -> Result<PathBuf> {
+    let key_candidates: Vec<PathBuf> = configured
+        .iter()
+        .cloned()
+        .chain(DEFAULT_KEY_NAMES.iter().map(|name| ssh_dir.join(name)))
+        .collect();
+
+    for key_path in &key_candidates {
+        if !key_path.exists() {
+            continue;
+        }
+        match std::fs::read(key_path) {
+            Ok(_) => return Ok(key_path.clone()),
+            Err(e) => {
+                let mut public = key_path.clone().into_os_string();
+                public.push(".pub");
+                if Path::new(&public).exists() {
+                    log::warn!(
+                        "Skipping SSH key {}: public key exists but private key is unreadable ({})",
+                        key_path.display(),
+                        e
+                    );
+                } else {
+                    log::debug!("Skipping SSH key {}: {}", key_path.display(), e);
+                }
+            }
+        }
+    }
+
+    let tried: Vec<String> = key_candidates
+        .iter()
+        .map(|path| format!("  - {}", path.display()))
+        .collect();
+    anyhow::bail!("No SSH private key found. Tried:\n{}", tried.join("\n"))
+}
 
 #[cfg(test)]
 mod tests {
@@ -573,23 +2019,549 @@ mod tests {
     #[test]
     fn test_find_default_ssh_key() {
         // This test will pass if at least one of the default keys exists
-        // If neither exists, the function should return an error
+        // If none exists, the function should return an error
         match find_default_ssh_key() {
             Ok(key_path) => {
-                // Verify it's one of the expected keys
-                let path_str = key_path.to_string_lossy();
-                assert!(
-                    path_str.ends_with("id_rsa") || path_str.ends_with("id_ed25519"),
-                    "Key path should end with id_rsa or id_ed25519, got: {}",
-                    path_str
-                );
+                // Verify it's one of the expected keys (or a configured one)
+                assert!(key_path.exists(), "Key path should exist: {:?}", key_path);
             }
             Err(e) => {
-                // If no keys exist, verify the error message mentions both keys
+                // If no keys exist, verify the error message lists the defaults
                 let error_msg = format!("{}", e);
-                assert!(error_msg.contains("id_rsa"));
                 assert!(error_msg.contains("id_ed25519"));
+                assert!(error_msg.contains("id_ecdsa"));
+                assert!(error_msg.contains("id_rsa"));
             }
         }
     }
+
+    /// Fresh ~/.ssh stand-in containing `keys` (written as files)
+    fn ssh_dir_with(name: &str, keys: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("helix-dadbod-ssh-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for key in keys {
+            std::fs::write(dir.join(key), "key").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_default_key_order() {
+        let dir = ssh_dir_with("order", &["id_rsa", "id_ecdsa", "id_ed25519"]);
+        assert_eq!(find_ssh_key_in(&dir, &[]).unwrap(), dir.join("id_ed25519"));
+
+        std::fs::remove_file(dir.join("id_ed25519")).unwrap();
+        assert_eq!(find_ssh_key_in(&dir, &[]).unwrap(), dir.join("id_ecdsa"));
+
+        std::fs::remove_file(dir.join("id_ecdsa")).unwrap();
+        assert_eq!(find_ssh_key_in(&dir, &[]).unwrap(), dir.join("id_rsa"));
+
+        std::fs::remove_file(dir.join("id_rsa")).unwrap();
+        let err = find_ssh_key_in(&dir, &[]).unwrap_err().to_string();
+        assert!(err.contains("id_ed25519") && err.contains("id_ecdsa") && err.contains("id_rsa"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_configured_identity_file_preferred() {
+        let dir = ssh_dir_with("configured", &["id_ed25519", "work_key"]);
+        let configured = vec![dir.join("missing_key"), dir.join("work_key")];
+
+        assert_eq!(
+            find_ssh_key_in(&dir, &configured).unwrap(),
+            dir.join("work_key")
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_unreadable_key_is_skipped() {
+        let dir = ssh_dir_with("unreadable", &["id_ed25519.pub", "id_ecdsa"]);
+        // A directory exists but can't be read as a key file, even as root
+        std::fs::create_dir(dir.join("id_ed25519")).unwrap();
+
+        assert_eq!(find_ssh_key_in(&dir, &[]).unwrap(), dir.join("id_ecdsa"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    fn explicit_tunnel() -> SshTunnel {
+        SshTunnel::Explicit {
+            host: "jump.example.com".to_string(),
+            port: 22,
+            user: "sshuser".to_string(),
+            key_path: Some(PathBuf::from("/keys/config_key")),
+            key_passphrase_cmd: None,
+            key_passphrase_env: None,
+            known_hosts_file: None,
+            strict_host_key_checking: None,
+        }
+    }
+
+    #[test]
+    fn test_passphrase_source_from_config() {
+        let config: crate::config::SqlConfig = toml::from_str(
+            r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            username = "app"
+            database = "app"
+            ssh_tunnel = { ssh_config = "bastion", key_passphrase_env = "BASTION_KEY_PASS" }
+            "#,
+        )
+        .unwrap();
+        let tunnel = config.connections[0].ssh_tunnel.as_ref().unwrap();
+        let SshTunnel::ConfigRef {
+            key_passphrase_env, ..
+        } = tunnel
+        else {
+            panic!("expected an ssh_config tunnel: {:?}", tunnel);
+        };
+        assert_eq!(key_passphrase_env.as_deref(), Some("BASTION_KEY_PASS"));
+
+        let mut tunnel = explicit_tunnel();
+        if let SshTunnel::Explicit {
+            key_passphrase_cmd, ..
+        } = &mut tunnel
+        {
+            *key_passphrase_cmd = Some("pass show ssh/bastion".to_string());
+        }
+        let target = SshTarget::resolve(&tunnel, Some(&TunnelOverrides::default())).unwrap();
+        assert_eq!(
+            target.key_passphrase.cmd.as_deref(),
+            Some("pass show ssh/bastion")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_key_passphrase_sources() {
+        assert_eq!(KeyPassphrase::default().resolve().await.unwrap(), None);
+
+        let from_cmd = KeyPassphrase {
+            cmd: Some("echo ' correct horse '".to_string()),
+            env: Some("HELIX_DADBOD_TEST_UNUSED".to_string()),
+        };
+        assert_eq!(
+            from_cmd.resolve().await.unwrap().as_deref(),
+            Some("correct horse")
+        );
+
+        let missing = KeyPassphrase {
+            cmd: None,
+            env: Some("HELIX_DADBOD_TEST_NO_SUCH_VAR".to_string()),
+        };
+        assert_eq!(
+            missing.resolve().await.unwrap_err().to_string(),
+            "key_passphrase_env: $HELIX_DADBOD_TEST_NO_SUCH_VAR is not set"
+        );
+    }
+
+    #[test]
+    fn test_resolve_explicit_without_overrides() {
+        let target = SshTarget::resolve(&explicit_tunnel(), None).unwrap();
+        assert_eq!(target.host, "jump.example.com");
+        assert_eq!(target.port, 22);
+        assert_eq!(target.user.as_deref(), Some("sshuser"));
+        assert_eq!(target.key_paths, vec![PathBuf::from("/keys/config_key")]);
+        assert!(!target.ephemeral);
+    }
+
+    #[test]
+    fn test_overrides_win_over_explicit_config() {
+        let overrides = TunnelOverrides {
+            port: Some(2222),
+            key_path: Some(PathBuf::from("/keys/debug_key")),
+            ..Default::default()
+        };
+        let target = SshTarget::resolve(&explicit_tunnel(), Some(&overrides)).unwrap();
+        assert_eq!(target.host, "jump.example.com");
+        assert_eq!(target.port, 2222);
+        assert_eq!(target.user.as_deref(), Some("sshuser"));
+        assert_eq!(target.key_paths, vec![PathBuf::from("/keys/debug_key")]);
+        assert!(target.ephemeral);
+    }
+
+    #[test]
+    fn test_known_hosts_file_is_searched_first() {
+        let mut tunnel = explicit_tunnel();
+        if let SshTunnel::Explicit {
+            known_hosts_file, ..
+        } = &mut tunnel
+        {
+            *known_hosts_file = Some("/work/known_hosts".to_string());
+        }
+        let overrides = TunnelOverrides {
+            port: Some(2222),
+            ..Default::default()
+        };
+        let target = SshTarget::resolve(&tunnel, Some(&overrides)).unwrap();
+        assert_eq!(
+            target.known_hosts_files.first(),
+            Some(&PathBuf::from("/work/known_hosts"))
+        );
+    }
+
+    #[test]
+    fn test_overrides_win_over_ssh_config() {
+        let host_config = SshHostConfig {
+            hostname: "bastion.example.com".to_string(),
+            port: 22,
+            user: None,
+            identity_files: vec![
+                PathBuf::from("/keys/ssh_config_key"),
+                PathBuf::from("/keys/fallback_key"),
+            ],
+            identities_only: true,
+            known_hosts_files: Vec::new(),
+            proxy_jump: Vec::new(),
+            proxy_command: None,
+        };
+        let overrides = TunnelOverrides {
+            host: Some("bastion-2.example.com".to_string()),
+            user: Some("debug".to_string()),
+            ..Default::default()
+        };
+        let target = SshTarget::from(host_config).with_overrides(&overrides);
+        assert_eq!(target.host, "bastion-2.example.com");
+        assert_eq!(target.port, 22);
+        assert_eq!(target.user.as_deref(), Some("debug"));
+        assert_eq!(
+            target.key_paths,
+            vec![
+                PathBuf::from("/keys/ssh_config_key"),
+                PathBuf::from("/keys/fallback_key")
+            ]
+        );
+        assert!(target.identities_only);
+        assert!(target.ephemeral);
+
+        let debug_key = TunnelOverrides {
+            key_path: Some(PathBuf::from("/keys/debug_key")),
+            ..Default::default()
+        };
+        let target = target.with_overrides(&debug_key);
+        assert_eq!(target.key_paths, vec![PathBuf::from("/keys/debug_key")]);
+    }
+
+    #[test]
+    fn test_proxy_jump_override_replaces_the_hops() {
+        let overrides = TunnelOverrides {
+            proxy_jump: Some(Vec::new()),
+            ..Default::default()
+        };
+        let mut target = SshTarget::resolve(&explicit_tunnel(), None).unwrap();
+        target.jump_hosts = vec![target.clone()];
+        let direct = target.with_overrides(&overrides);
+        // with_overrides leaves the hops to resolve
+        assert_eq!(direct.jump_hosts.len(), 1);
+
+        let target = SshTarget::resolve(&explicit_tunnel(), Some(&overrides)).unwrap();
+        assert!(target.jump_hosts.is_empty());
+        assert!(target.ephemeral);
+    }
+
+    #[test]
+    fn test_empty_overrides_keep_config_but_mark_ephemeral() {
+        let target =
+            SshTarget::resolve(&explicit_tunnel(), Some(&TunnelOverrides::default())).unwrap();
+        assert_eq!(
+            target,
+            SshTarget {
+                ephemeral: true,
+                ..SshTarget::resolve(&explicit_tunnel(), None).unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn test_only_refused_channels_keep_the_session_alive() {
+        assert!(!is_session_error(&russh::Error::ChannelOpenFailure(
+            russh::ChannelOpenFailure::ConnectFailed
+        )));
+        assert!(is_session_error(&russh::Error::Disconnect));
+        assert!(is_session_error(&russh::Error::SendError));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tunnel_is_not_alive() {
+        let manager = TunnelManager::default();
+        assert!(!manager.is_tunnel_alive("prod").await);
+        assert_eq!(manager.tunnel_status("prod"), None);
+    }
+
+    #[test]
+    fn test_connect_locks_are_per_key() {
+        let locks = ConnectLocks::default();
+        let prod = locks.get(&"prod".to_string());
+        let _creating = prod.try_lock().unwrap();
+        assert!(locks.get(&"prod".to_string()).try_lock().is_err());
+        assert!(locks.get(&"staging".to_string()).try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_tunnels_share_sessions_by_server_and_user() {
+        let connector = |overrides: &TunnelOverrides| SessionConnector {
+            target: SshTarget::resolve(&explicit_tunnel(), Some(overrides)).unwrap(),
+            settings: TunnelSettings::default(),
+            host_keys: Arc::default(),
+        };
+        let key = connector(&TunnelOverrides::default())
+            .session_key()
+            .unwrap();
+        assert_eq!(
+            key,
+            SessionKey {
+                host: "jump.example.com".to_string(),
+                port: 22,
+                user: "sshuser".to_string(),
+                skip_host_key_verification: false,
+            }
+        );
+
+        let other_key = TunnelOverrides {
+            key_path: Some(PathBuf::from("/keys/other_key")),
+            ..Default::default()
+        };
+        assert_eq!(connector(&other_key).session_key().unwrap(), key);
+
+        let other_user = TunnelOverrides {
+            user: Some("debug".to_string()),
+            ..Default::default()
+        };
+        assert_ne!(connector(&other_user).session_key().unwrap(), key);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_connections_are_forwarded_and_counted() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        const SIZE: usize = 4 * 1024 * 1024;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let traffic = Arc::new(Traffic::default());
+        let mut clients = Vec::new();
+        for seed in 1..=2u8 {
+            let client = TcpStream::connect(addr).await.unwrap();
+            let (local_socket, _) = listener.accept().await.unwrap();
+
+            // The remote end of the channel echoes everything back
+            let (channel, remote) = tokio::io::duplex(64 * 1024);
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = tokio::io::split(remote);
+                tokio::io::copy(&mut reader, &mut writer).await
+            });
+            let pump_traffic = Arc::clone(&traffic);
+            tokio::spawn(async move { pump(local_socket, channel, &pump_traffic).await });
+
+            clients.push(tokio::spawn(async move {
+                let data: Vec<u8> = (0..SIZE).map(|i| (i as u8).wrapping_mul(seed)).collect();
+                let (mut reader, mut writer) = client.into_split();
+                let sent = data.clone();
+                let write = async move {
+                    writer.write_all(&sent).await.unwrap();
+                    writer.shutdown().await.unwrap();
+                };
+                let read = async move {
+                    let mut echoed = Vec::with_capacity(SIZE);
+                    reader.read_to_end(&mut echoed).await.unwrap();
+                    echoed
+                };
+                let ((), echoed) = tokio::join!(write, read);
+                assert!(echoed == data, "connection {} got its bytes back", seed);
+            }));
+        }
+        for client in clients {
+            client.await.unwrap();
+        }
+
+        assert_eq!(traffic.sent.load(Ordering::Relaxed), 2 * SIZE as u64);
+        assert_eq!(traffic.received.load(Ordering::Relaxed), 2 * SIZE as u64);
+    }
+
+    #[test]
+    fn test_tunnel_status_display() {
+        let report = TunnelStatus {
+            ssh_host: "jump.example.com".to_string(),
+            ssh_port: 22,
+            ssh_user: "sshuser".to_string(),
+            local_port: 7001,
+            remote: RemoteTarget::Tcp {
+                host: "db.internal".to_string(),
+                port: 5432,
+            },
+            state: SessionState::Up,
+            healthy: true,
+            uptime: Duration::from_secs(90),
+            bytes_sent: 1536,
+            bytes_received: 3 * 1024 * 1024,
+        };
+        assert_eq!(report.to_string(), "up, 1.5 kB sent, 3.0 MB received");
+    }
+
+    #[test]
+    fn test_streamlocal_refusals_say_why() {
+        let socket = RemoteTarget::Socket("/var/run/postgresql/.s.PGSQL.5432".to_string());
+        let refused = |reason| russh::Error::ChannelOpenFailure(reason);
+
+        let unsupported = channel_error(
+            &socket,
+            refused(russh::ChannelOpenFailure::AdministrativelyProhibited),
+        );
+        assert!(unsupported
+            .to_string()
+            .starts_with("The SSH server does not support streamlocal forwarding"));
+
+        let missing = channel_error(&socket, refused(russh::ChannelOpenFailure::ConnectFailed));
+        assert!(missing
+            .to_string()
+            .starts_with("Socket path /var/run/postgresql/.s.PGSQL.5432 not found"));
+
+        let tcp = RemoteTarget::Tcp {
+            host: "db.internal".to_string(),
+            port: 5432,
+        };
+        let refused_tcp = channel_error(&tcp, refused(russh::ChannelOpenFailure::ConnectFailed));
+        assert_eq!(
+            refused_tcp.to_string(),
+            "Failed to open SSH channel to db.internal:5432"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_failures_suggest_a_fix() {
+        let target = SshTarget::resolve(&explicit_tunnel(), None).unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let refused = TcpStream::connect(addr).await.unwrap_err();
+        assert_eq!(
+            tcp_connect_error(&target, &refused).to_string(),
+            "SSH server jump.example.com:22 refused the connection; check the port and that sshd runs there"
+        );
+
+        assert!(handshake_error(&target, &russh::Error::UnknownKey, None)
+            .to_string()
+            .starts_with("Host key of SSH server jump.example.com:22 was rejected"));
+
+        let auth = auth_error(
+            &target,
+            "sshuser",
+            &[PathBuf::from("/keys/config_key")],
+            None,
+        );
+        assert!(auth.to_string().starts_with(
+            "SSH server jump.example.com:22 rejected key /keys/config_key for user 'sshuser';"
+        ));
+        let keys = [
+            PathBuf::from("/keys/work"),
+            PathBuf::from("/keys/id_ed25519"),
+        ];
+        assert!(auth_error(&target, "sshuser", &keys, None)
+            .to_string()
+            .starts_with(
+                "SSH server jump.example.com:22 rejected keys /keys/work, /keys/id_ed25519 for"
+            ));
+    }
+
+    #[test]
+    fn test_host_key_rejections_say_what_to_do() {
+        let target = SshTarget::resolve(&explicit_tunnel(), None).unwrap();
+        let rejected = |rejection| {
+            handshake_error(&target, &russh::Error::UnknownKey, Some(rejection)).to_string()
+        };
+
+        let mismatch = rejected(HostKeyRejection::KeyMismatch {
+            expected_fingerprints: vec!["SHA256:old".to_string(), "SHA256:older".to_string()],
+            presented: "SHA256:new".to_string(),
+            file: PathBuf::from("/home/me/.ssh/known_hosts"),
+            line: 12,
+        });
+        assert!(mismatch.starts_with(
+            "WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED for SSH server jump.example.com:22!"
+        ));
+        assert!(mismatch.contains(
+            "The server sent SHA256:new, but /home/me/.ssh/known_hosts:12 expects SHA256:old \
+             or SHA256:older."
+        ));
+        assert!(mismatch.ends_with("remove the old one with ssh-keygen -R 'jump.example.com'"));
+
+        assert!(rejected(HostKeyRejection::Revoked {
+            fingerprint: "SHA256:abc".to_string(),
+        })
+        .starts_with("Host key SHA256:abc of SSH server jump.example.com:22 is REVOKED"));
+
+        assert_eq!(
+            rejected(HostKeyRejection::Unconfirmed {
+                key_type: "ssh-ed25519".to_string(),
+                fingerprint: "SHA256:abc".to_string(),
+            }),
+            "Host key ssh-ed25519 SHA256:abc of SSH server jump.example.com:22 is not in \
+             ~/.ssh/known_hosts; accept it to connect (host_key_policy = \"ask\")"
+        );
+        assert!(rejected(HostKeyRejection::Unknown).contains("host_key_policy = \"accept-new\""));
+        assert!(rejected(HostKeyRejection::CertAuthority {
+            file: PathBuf::from("/etc/ssh/ssh_known_hosts"),
+            line: 3,
+        })
+        .starts_with(
+            "/etc/ssh/ssh_known_hosts:3 trusts a certificate authority (@cert-authority) for SSH \
+             server jump.example.com:22, but host certificates aren't supported"
+        ));
+    }
+
+    #[test]
+    fn test_connect_timeout_names_the_phase() {
+        let target = SshTarget::resolve(&explicit_tunnel(), None).unwrap();
+        let timings = ConnectTimings {
+            resolve: Some(Duration::from_millis(3)),
+            ..Default::default()
+        };
+        let message = connect_timeout_error(&target, Duration::from_secs(10), &timings).to_string();
+        assert!(
+            message.starts_with(
+                "Timed out after 10s connecting to SSH server jump.example.com:22 (opening the TCP connection)"
+            ),
+            "{}",
+            message
+        );
+        assert!(message.ends_with("Raise ssh_connect_timeout_secs if it is just slow"));
+
+        // Behind a jump host nothing is resolved locally
+        let behind_jump = SshTarget {
+            jump_hosts: vec![target.clone()],
+            ..target.clone()
+        };
+        let phase = |timings: &ConnectTimings| {
+            let message = connect_timeout_error(&behind_jump, Duration::from_secs(10), timings);
+            message.to_string()
+        };
+        assert!(phase(&ConnectTimings::default()).contains("(connecting to its ProxyJump hosts)"));
+        let through_jump = ConnectTimings {
+            proxy_jump: Some(Duration::from_millis(400)),
+            ..Default::default()
+        };
+        assert!(phase(&through_jump).contains("(opening the TCP connection)"));
+    }
+
+    #[test]
+    fn test_reconnect_delay_doubles_up_to_30s() {
+        let delays: Vec<u64> = (1..=7).map(|n| reconnect_delay(n).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+    }
+
+    #[test]
+    fn test_session_state_display() {
+        let status = SessionState::Reconnecting {
+            attempt: 2,
+            max_attempts: 5,
+        };
+        assert_eq!(status.to_string(), "reconnecting (attempt 2 of 5)");
+        assert_eq!(SessionState::Up.to_string(), "up");
+    }
 }
@@ -0,0 +1,178 @@
+//! Values of query results, before they are rendered
+//!
+//! Backends convert what the server returns to `CellValue`, which keeps what
+//! JSON output needs (numbers, booleans, json documents and NULL) while the
+//! table renderers show each value through `display`. json documents are kept
+//! as the server's text, so their key order and numbers come out unchanged.
+
+/// One value of a result row
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// NUMERIC and other exact decimals, kept as text so no precision is lost
+    Numeric(String),
+    /// json and jsonb documents, as the server's text
+    Json(String),
+    /// Everything else, already rendered as text (timestamps, uuids, bytea, ...)
+    Text(String),
+}
+
+impl CellValue {
+    /// Text shown in tables, with NULL shown as `null`
+    pub fn display(&self, null: &str) -> String {
+        match self {
+            CellValue::Null => null.to_string(),
+            CellValue::Bool(v) => v.to_string(),
+            CellValue::Int(v) => v.to_string(),
+            CellValue::Float(v) => v.to_string(),
+            CellValue::Numeric(v) | CellValue::Text(v) | CellValue::Json(v) => v.clone(),
+        }
+    }
+
+    /// JSON text for JSON output
+    ///
+    /// NUMERIC stays a string to keep its precision, as do floats JSON can't
+    /// represent (NaN, Infinity). json documents are written as the server
+    /// sent them, less the whitespace between tokens so each stays on one line.
+    pub fn to_json(&self) -> String {
+        let value = match self {
+            CellValue::Null => serde_json::Value::Null,
+            CellValue::Bool(v) => serde_json::Value::Bool(*v),
+            CellValue::Int(v) => serde_json::Value::from(*v),
+            CellValue::Float(v) => serde_json::Number::from_f64(*v)
+                .map(serde_json::Value::Number)
+                .unwrap_or_else(|| serde_json::Value::String(v.to_string())),
+            CellValue::Numeric(v) | CellValue::Text(v) => serde_json::Value::String(v.clone()),
+            CellValue::Json(v) => return compact_json(v),
+        };
+        value.to_string()
+    }
+}
+
+/// `json` without whitespace outside its strings
+fn compact_json(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+            out.push(c);
+        } else if !c.is_whitespace() {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Every value of `rows` as shown in tables
+pub fn display_rows(rows: &[Vec<CellValue>], null: &str) -> Vec<Vec<String>> {
+    rows.iter()
+        .map(|row| row.iter().map(|value| value.display(null)).collect())
+        .collect()
+}
+
+/// One row as a single-line JSON object, keys in column order
+pub fn json_object(header: &[String], row: &[CellValue]) -> String {
+    let fields: Vec<String> = header
+        .iter()
+        .zip(row)
+        .map(|(name, value)| {
+            format!(
+                "{}:{}",
+                serde_json::Value::String(name.clone()),
+                value.to_json()
+            )
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row() -> (Vec<String>, Vec<CellValue>) {
+        let header = ["id", "name", "active", "score", "price", "doc", "note"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let row = vec![
+            CellValue::Int(1),
+            CellValue::Text("alice".to_string()),
+            CellValue::Bool(true),
+            CellValue::Float(1.5),
+            CellValue::Numeric("12.50".to_string()),
+            CellValue::Json(r#"{"tags": ["a", "b"], "n": 2}"#.to_string()),
+            CellValue::Null,
+        ];
+        (header, row)
+    }
+
+    #[test]
+    fn test_display() {
+        let (_, row) = row();
+        assert_eq!(
+            display_rows(&[row], "∅"),
+            vec![vec![
+                "1",
+                "alice",
+                "true",
+                "1.5",
+                "12.50",
+                r#"{"tags": ["a", "b"], "n": 2}"#,
+                "∅"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_json_object_round_trips() {
+        let (header, row) = row();
+        let object = json_object(&header, &row);
+        assert!(object.starts_with(r#"{"id":1,"name":"alice","active":true,"#));
+
+        let parsed: serde_json::Value = serde_json::from_str(&object).unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "id": 1,
+                "name": "alice",
+                "active": true,
+                "score": 1.5,
+                "price": "12.50",
+                "doc": {"tags": ["a", "b"], "n": 2},
+                "note": null
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_documents_keep_key_order_and_precision() {
+        let doc = CellValue::Json(
+            "{\"z\": 1,\n \"a\": 12345678901234567890.123456789,\n \"s\": \"a \\\" b\"}"
+                .to_string(),
+        );
+        assert_eq!(
+            json_object(&["doc".to_string()], &[doc]),
+            r#"{"doc":{"z":1,"a":12345678901234567890.123456789,"s":"a \" b"}}"#
+        );
+    }
+
+    #[test]
+    fn test_unrepresentable_float_is_a_string() {
+        assert_eq!(CellValue::Float(f64::NAN).to_json(), r#""NaN""#);
+    }
+}
@@ -0,0 +1,116 @@
+//! Version and feature report for bug reports and the startup banner
+
+use crate::connection;
+use crate::meta_commands::MetaCommand;
+use std::path::{Path, PathBuf};
+
+/// Version of this build
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Cargo features and whether this build enabled them
+const FEATURES: &[(&str, bool)] = &[("ffi", cfg!(feature = "ffi"))];
+
+/// What this build of helix-dadbod supports and which config it loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// Enabled cargo features
+    pub features: Vec<&'static str>,
+    /// Accepted connection `type` values
+    pub db_types: Vec<&'static str>,
+    /// Number of supported meta-commands
+    pub meta_commands: usize,
+    /// Config file that was loaded; None when running from PG* variables or
+    /// when initialization failed
+    pub config_path: Option<PathBuf>,
+}
+
+impl VersionInfo {
+    pub fn collect(config_path: Option<&Path>) -> Self {
+        Self {
+            version: VERSION,
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name)
+                .collect(),
+            db_types: connection::supported_db_types(),
+            meta_commands: MetaCommand::command_names().count(),
+            config_path: config_path.map(Path::to_path_buf),
+        }
+    }
+
+    /// Field name/value pairs, in display order
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let list = |items: &[&str]| {
+            if items.is_empty() {
+                "none".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+
+        vec![
+            ("version", self.version.to_string()),
+            ("features", list(&self.features)),
+            ("db_types", list(&self.db_types)),
+            ("meta_commands", self.meta_commands.to_string()),
+            (
+                "config",
+                self.config_path
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|| "(none)".to_string()),
+            ),
+        ]
+    }
+
+    /// One-line banner, e.g. `helix-dadbod 0.0.1 (features: none; db_types: ...)`
+    pub fn banner(&self) -> String {
+        let details: Vec<String> = self
+            .fields()
+            .into_iter()
+            .skip(1)
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        format!("helix-dadbod {} ({})", self.version, details.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_uses_dispatch_tables() {
+        let info = VersionInfo::collect(Some(Path::new("/home/u/config.toml")));
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.db_types, connection::supported_db_types());
+        assert!(info.db_types.contains(&"postgres"));
+        assert_eq!(info.meta_commands, MetaCommand::command_names().count());
+        assert_eq!(info.features.contains(&"ffi"), cfg!(feature = "ffi"));
+        assert_eq!(
+            info.config_path.as_deref(),
+            Some(Path::new("/home/u/config.toml"))
+        );
+    }
+
+    #[test]
+    fn test_banner() {
+        let info = VersionInfo {
+            version: "1.2.3",
+            features: vec![],
+            db_types: vec!["postgres", "postgresql"],
+            meta_commands: 17,
+            config_path: None,
+        };
+
+        assert_eq!(
+            info.banner(),
+            "helix-dadbod 1.2.3 (features: none; db_types: postgres, postgresql; \
+             meta_commands: 17; config: (none))"
+        );
+        assert_eq!(info.fields()[0], ("version", "1.2.3".to_string()));
+    }
+}
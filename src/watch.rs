@@ -0,0 +1,128 @@
+//! \watch: re-run the last query on an interval
+//!
+//! `\watch 5` re-runs the connection's most recently executed query every five
+//! seconds, rewriting results.dbout each time, until `\watch stop`, another
+//! execution or closing the connection ends it. The loop runs as a spawned
+//! task so the blocking FFI call that started it returns immediately.
+
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Interval of a bare `\watch`, as in psql
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What a `\watch` line asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Re-run the last query at this interval
+    Every(Duration),
+    /// End the running watch
+    Stop,
+}
+
+/// Parse the argument of `\watch`: whole seconds, `stop`, or nothing for the
+/// default interval
+pub fn parse_action(arg: Option<&str>) -> Result<WatchAction> {
+    let Some(arg) = arg else {
+        return Ok(WatchAction::Every(DEFAULT_INTERVAL));
+    };
+    if arg.eq_ignore_ascii_case("stop") {
+        return Ok(WatchAction::Stop);
+    }
+    let seconds: u64 = arg
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .with_context(|| {
+            format!(
+                "Invalid \\watch interval '{}': expected seconds (e.g. \\watch 5) or stop",
+                arg
+            )
+        })?;
+    Ok(WatchAction::Every(Duration::from_secs(seconds)))
+}
+
+/// A running watch; dropping it stops the loop
+pub struct WatchLoop {
+    task: JoinHandle<()>,
+    pub interval: Duration,
+}
+
+impl WatchLoop {
+    /// Call `run` with iteration numbers 1, 2, ... with `interval` between the
+    /// end of one call and the start of the next, until `run` returns false
+    pub fn spawn<F, Fut>(interval: Duration, mut run: F) -> Self
+    where
+        F: FnMut(u64) -> Fut + Send + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        let task = tokio::spawn(async move {
+            let mut iteration = 1;
+            while run(iteration).await {
+                tokio::time::sleep(interval).await;
+                iteration += 1;
+            }
+        });
+        Self { task, interval }
+    }
+}
+
+impl Drop for WatchLoop {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(
+            parse_action(None).unwrap(),
+            WatchAction::Every(DEFAULT_INTERVAL)
+        );
+        assert_eq!(
+            parse_action(Some("5")).unwrap(),
+            WatchAction::Every(Duration::from_secs(5))
+        );
+        assert_eq!(parse_action(Some("stop")).unwrap(), WatchAction::Stop);
+        for invalid in ["0", "-1", "1.5", "soon"] {
+            let err = parse_action(Some(invalid)).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid \\watch interval"));
+        }
+    }
+
+    fn counting_loop(interval: Duration, last: u64) -> (WatchLoop, Arc<AtomicU64>) {
+        let count = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&count);
+        let watch = WatchLoop::spawn(interval, move |iteration| {
+            counter.store(iteration, Ordering::SeqCst);
+            async move { iteration < last }
+        });
+        (watch, count)
+    }
+
+    #[tokio::test]
+    async fn test_runs_until_run_returns_false() {
+        let (_watch, count) = counting_loop(Duration::from_millis(10), 3);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_the_loop() {
+        let (watch, count) = counting_loop(Duration::from_millis(50), u64::MAX);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        drop(watch);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,296 @@
+//! Workspace SQL file watcher for auto-execute on save
+//!
+//! Watches a connection's SQL file and runs a callback once changes have
+//! settled for a debounce interval. The parent directory is watched rather than
+//! the file itself so editors that save by renaming a temp file are handled.
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Quiet period after the last change event before the callback fires
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often a save made while a query runs checks whether it may execute
+const BUSY_POLL: Duration = Duration::from_millis(50);
+
+/// Active watcher on a SQL file; dropping it stops watching
+pub struct QueryWatcher {
+    _watcher: notify::RecommendedWatcher,
+    task: JoinHandle<()>,
+    ignore_next: Arc<AtomicBool>,
+}
+
+impl QueryWatcher {
+    /// Start watching `sql_file`, calling `on_change` after each debounced change
+    ///
+    /// The callback only fires when the file content differs from what it was
+    /// when the watcher started (or at the previous trigger), so creating the
+    /// file or touching it without edits does nothing. A save made while `busy`
+    /// is set waits for the running query to finish, then executes the file as
+    /// it is by then, so several saves during a query run it once.
+    pub fn spawn<F, Fut>(
+        sql_file: &Path,
+        debounce: Duration,
+        busy: Arc<AtomicBool>,
+        on_change: F,
+    ) -> Result<Self>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let sql_file = sql_file.to_path_buf();
+        let dir = sql_file
+            .parent()
+            .context("SQL file has no parent directory")?
+            .to_path_buf();
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let watched_file = sql_file.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    if event.paths.iter().any(|p| p == &watched_file) {
+                        let _ = tx.send(());
+                    }
+                }
+            })
+            .context("Failed to create file watcher")?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        let initial_content = std::fs::read_to_string(&sql_file).ok();
+        let ignore_next = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(debounce_loop(
+            rx,
+            sql_file.clone(),
+            initial_content,
+            debounce,
+            busy,
+            Arc::clone(&ignore_next),
+            on_change,
+        ));
+
+        log::info!("Watching {} for auto-execute", sql_file.display());
+
+        Ok(Self {
+            _watcher: watcher,
+            task,
+            ignore_next,
+        })
+    }
+
+    /// Don't execute for the next content change, e.g. one written by
+    /// helix-dadbod itself rather than saved by the user
+    pub fn ignore_next_change(&self) {
+        self.ignore_next.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for QueryWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn debounce_loop<F, Fut>(
+    mut rx: mpsc::UnboundedReceiver<()>,
+    sql_file: PathBuf,
+    mut last_content: Option<String>,
+    debounce: Duration,
+    busy: Arc<AtomicBool>,
+    ignore_next: Arc<AtomicBool>,
+    on_change: F,
+) where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    while rx.recv().await.is_some() {
+        // Wait until no further events arrive for the debounce interval
+        loop {
+            match tokio::time::timeout(debounce, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+
+        let content = tokio::fs::read_to_string(&sql_file).await.ok();
+        if content == last_content {
+            log::trace!("{} unchanged, not executing", sql_file.display());
+            continue;
+        }
+
+        if ignore_next.swap(false, Ordering::SeqCst) {
+            log::debug!("Ignoring own write to {}", sql_file.display());
+            last_content = content;
+            continue;
+        }
+
+        if busy.load(Ordering::SeqCst) {
+            log::info!(
+                "Query running; {} executes when it finishes",
+                sql_file.display()
+            );
+            while busy.load(Ordering::SeqCst) {
+                tokio::time::sleep(BUSY_POLL).await;
+            }
+        }
+
+        // Saves made while waiting are included; their events find it unchanged
+        let content = tokio::fs::read_to_string(&sql_file).await.ok();
+        if content == last_content {
+            continue;
+        }
+        last_content = content;
+        log::info!("{} changed, auto-executing", sql_file.display());
+        on_change().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::AtomicUsize;
+
+    const TEST_DEBOUNCE: Duration = Duration::from_millis(100);
+
+    /// Upper bound for an expected execution to arrive; tests don't sleep for it
+    const WAIT: Duration = Duration::from_secs(10);
+
+    /// SQL file in a directory of its own, unique to this test run
+    fn test_file(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "helix-dadbod-watch-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("conn.sql");
+        fs::write(&file, "").unwrap();
+        file
+    }
+
+    /// Watcher sending the file's content and the busy flag on each execution
+    fn recording_watcher(
+        file: &Path,
+        busy: Arc<AtomicBool>,
+    ) -> (QueryWatcher, mpsc::UnboundedReceiver<(String, bool)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (path, flag) = (file.to_path_buf(), Arc::clone(&busy));
+        let watcher = QueryWatcher::spawn(file, TEST_DEBOUNCE, busy, move || {
+            let content = fs::read_to_string(&path).unwrap();
+            let _ = tx.send((content, flag.load(Ordering::SeqCst)));
+            async {}
+        })
+        .unwrap();
+        (watcher, rx)
+    }
+
+    async fn next_execution(rx: &mut mpsc::UnboundedReceiver<(String, bool)>) -> String {
+        let (content, busy) = tokio::time::timeout(WAIT, rx.recv())
+            .await
+            .expect("no execution")
+            .expect("watcher stopped");
+        assert!(!busy, "executed while a query was running");
+        content
+    }
+
+    #[tokio::test]
+    async fn test_rapid_saves_are_debounced() {
+        let file = test_file("debounce");
+        let (_watcher, mut rx) = recording_watcher(&file, Arc::new(AtomicBool::new(false)));
+
+        for i in 0..5 {
+            fs::write(&file, format!("SELECT {};", i)).unwrap();
+        }
+        assert_eq!(next_execution(&mut rx).await, "SELECT 4;");
+
+        // The burst executed once: the next execution is the next save
+        fs::write(&file, "SELECT 5;").unwrap();
+        assert_eq!(next_execution(&mut rx).await, "SELECT 5;");
+        fs::remove_dir_all(file.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fire_without_changes() {
+        let file = test_file("initial");
+        fs::write(&file, "SELECT 1;").unwrap();
+        let (_watcher, mut rx) = recording_watcher(&file, Arc::new(AtomicBool::new(false)));
+
+        // Neither starting the watcher nor rewriting identical content triggers
+        fs::write(&file, "SELECT 1;").unwrap();
+        tokio::time::sleep(TEST_DEBOUNCE * 2).await;
+        fs::write(&file, "SELECT 2;").unwrap();
+        assert_eq!(next_execution(&mut rx).await, "SELECT 2;");
+        fs::remove_dir_all(file.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_saves_while_busy_wait_for_the_query() {
+        let file = test_file("busy");
+        let busy = Arc::new(AtomicBool::new(true));
+        let (_watcher, mut rx) = recording_watcher(&file, Arc::clone(&busy));
+
+        fs::write(&file, "SELECT 1;").unwrap();
+        fs::write(&file, "SELECT 2;").unwrap();
+        let query = Arc::clone(&busy);
+        tokio::spawn(async move {
+            tokio::time::sleep(TEST_DEBOUNCE * 3).await;
+            query.store(false, Ordering::SeqCst);
+        });
+
+        // Queued rather than dropped, and run once with the latest save
+        assert_eq!(next_execution(&mut rx).await, "SELECT 2;");
+        fs::write(&file, "SELECT 3;").unwrap();
+        assert_eq!(next_execution(&mut rx).await, "SELECT 3;");
+        fs::remove_dir_all(file.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_ignore_next_change() {
+        let file = test_file("ignore");
+        let (watcher, mut rx) = recording_watcher(&file, Arc::new(AtomicBool::new(false)));
+
+        watcher.ignore_next_change();
+        fs::write(&file, "SELECT 1;").unwrap();
+        tokio::time::sleep(TEST_DEBOUNCE * 2).await;
+        fs::write(&file, "SELECT 2;").unwrap();
+        assert_eq!(next_execution(&mut rx).await, "SELECT 2;");
+
+        fs::remove_dir_all(file.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_watching() {
+        let file = test_file("teardown");
+        let (watcher, mut rx) = recording_watcher(&file, Arc::new(AtomicBool::new(false)));
+
+        // The aborted task drops the callback, closing the channel
+        drop(watcher);
+        fs::write(&file, "SELECT 1;").unwrap();
+        assert_eq!(tokio::time::timeout(WAIT, rx.recv()).await, Ok(None));
+        fs::remove_dir_all(file.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_ignores_other_files_in_directory() {
+        let file = test_file("other");
+        let (_watcher, mut rx) = recording_watcher(&file, Arc::new(AtomicBool::new(false)));
+
+        fs::write(file.with_file_name("results.dbout"), "output").unwrap();
+        fs::write(&file, "SELECT 1;").unwrap();
+        assert_eq!(next_execution(&mut rx).await, "SELECT 1;");
+        fs::remove_dir_all(file.parent().unwrap()).ok();
+    }
+}
@@ -1,35 +1,185 @@
+use crate::config::Connection;
+use crate::run_report;
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Workspace for a database connection
 #[derive(Debug, Clone)]
 pub struct Workspace {
-    /// Root directory: /tmp/helix-dadbod
+    /// Root directory, `workspace_dir` (default: $XDG_RUNTIME_DIR/helix-dadbod)
     pub path: PathBuf,
-    /// Path to connection-specific SQL file: /tmp/helix-dadbod/{connection_name}.sql
+    /// Path to connection-specific SQL file: {path}/{connection_name}.sql, which
+    /// is also buffer 1
     pub sql_file: PathBuf,
-    /// Path to shared results file: /tmp/helix-dadbod/results.dbout
+    /// Path to shared results file: {path}/results.dbout
     pub dbout_file: PathBuf,
+    /// Where results go when results.dbout can't be written (read-only, disk full)
+    /// Default: ~/.local/state/helix-dadbod/results_fallback.dbout
+    pub fallback_file: Option<PathBuf>,
+    /// Names of the other configured connections: {name}.{n}.sql is the SQL
+    /// file of a connection named {name}.{n}, not buffer n of this one
+    pub other_connections: Vec<String>,
+}
+
+/// SQL file contents a new connection starts with, unless `query_template` is set
+pub const DEFAULT_QUERY_TEMPLATE: &str = "-- Connection: {connection}\n\
+                                          -- Database: {database}\n\
+                                          -- \\h lists meta-commands\n\n";
+
+/// `template` with `{connection}`, `{database}` and `{host}` filled in for `conn`
+pub fn render_template(template: &str, conn: &Connection) -> String {
+    template
+        .replace("{connection}", &conn.name)
+        .replace("{database}", &conn.database)
+        .replace("{host}", &conn.host)
+}
+
+/// Default location for results that couldn't be written to results.dbout
+pub fn default_fallback_file() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".local/state/helix-dadbod/results_fallback.dbout"))
+}
+
+/// Temporary file beside `path`, to be renamed over it once complete; unique
+/// per process and call so concurrent writers don't share one
+pub fn temp_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Write `content` to a temporary file beside `path` and rename it over
+/// `path`, so readers such as Helix's file watcher see the old or the new
+/// contents, never a half-written file
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let temp = temp_path(path);
+    let written = fs::write(&temp, content).and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Workspace directory: `configured` with `~` and `$VAR`/`${VAR}` expanded,
+/// else $XDG_RUNTIME_DIR/helix-dadbod, else /tmp/helix-dadbod
+pub fn resolve_dir<F>(configured: Option<&str>, env: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match configured {
+        Some(path) => {
+            let expanded = expand_env(path.trim(), &env)
+                .with_context(|| format!("Invalid workspace_dir '{}'", path))?;
+            Ok(crate::ssh_config::expand_tilde(&expanded))
+        }
+        None => {
+            let runtime_dir = env("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty());
+            Ok(runtime_dir
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/tmp"))
+                .join("helix-dadbod"))
+        }
+    }
+}
+
+/// `value` with `$VAR` and `${VAR}` replaced by their values; `$$` is a `$`
+fn expand_env<F>(value: &str, env: &F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remainder) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .with_context(|| format!("Unclosed ${{ in '{}'", value))?;
+            (&braced[..end], &braced[end + 1..])
+        } else if let Some(remainder) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = remainder;
+            continue;
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            let value = env(name).with_context(|| format!("${} is not set", name))?;
+            expanded.push_str(&value);
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Create `path` and its parents private to the user (0700), since query
+/// files may hold sensitive data
+///
+/// An existing directory is the user's to manage, e.g. a shared project
+/// directory set as `workspace_dir`: its mode is left alone, with a warning
+/// when other users can get into it.
+fn create_private_dir(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .permissions()
+                .mode();
+            if mode & 0o077 != 0 {
+                log::warn!(
+                    "Workspace directory {} is accessible to other users (mode {:o}); \
+                     query files may hold sensitive data, consider chmod 700",
+                    path.display(),
+                    mode & 0o777
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(path)
+        .with_context(|| format!("Failed to create workspace directory: {}", path.display()))
 }
 
 impl Workspace {
-    /// Create a new workspace for the connection
-    /// SQL file: /tmp/helix-dadbod/{connection_name}.sql
-    /// Results file: /tmp/helix-dadbod/results.dbout (shared)
-    pub fn create(connection_name: &str) -> Result<Self> {
-        let path = PathBuf::from("/tmp").join("helix-dadbod");
+    /// Create a new workspace for the connection in `base_dir`
+    /// SQL file: {base_dir}/{connection_name}.sql, starting as `initial_sql`
+    /// Results file: {base_dir}/results.dbout (shared)
+    pub fn create(connection_name: &str, base_dir: &Path, initial_sql: &str) -> Result<Self> {
+        let path = base_dir.to_path_buf();
 
         // Create the directory if it doesn't exist
-        fs::create_dir_all(&path)
-            .with_context(|| format!("Failed to create workspace directory: {}", path.display()))?;
+        create_private_dir(&path)?;
 
         let sql_file = path.join(format!("{}.sql", connection_name));
         let dbout_file = path.join("results.dbout");
 
-        // Create empty SQL file only if it doesn't exist (preserve user's queries)
+        // Create the SQL file only if it doesn't exist (preserve user's queries)
         if !sql_file.exists() {
-            fs::write(&sql_file, "")
+            fs::write(&sql_file, initial_sql)
                 .with_context(|| format!("Failed to create SQL file: {}", sql_file.display()))?;
             log::info!("Created new SQL file: {}", sql_file.display());
         } else {
@@ -58,25 +208,187 @@ impl Workspace {
             path,
             sql_file,
             dbout_file,
+            fallback_file: default_fallback_file(),
+            other_connections: Vec::new(),
         })
     }
 
+    /// SQL file of numbered buffer `index`: 1 is `sql_file`, further ones are
+    /// {path}/{connection_name}.{index}.sql
+    pub fn buffer_file(&self, index: usize) -> Result<PathBuf> {
+        match index {
+            0 => anyhow::bail!("Buffers are numbered from 1"),
+            1 => Ok(self.sql_file.clone()),
+            _ if self.is_other_connection(index) => anyhow::bail!(
+                "Buffer {} would be the SQL file of connection '{}.{}'",
+                index,
+                self.connection_name(),
+                index
+            ),
+            _ => Ok(self
+                .path
+                .join(format!("{}.{}.sql", self.connection_name(), index))),
+        }
+    }
+
+    /// Whether {connection}.{index} names another configured connection
+    fn is_other_connection(&self, index: usize) -> bool {
+        let name = format!("{}.{}", self.connection_name(), index);
+        self.other_connections.contains(&name)
+    }
+
+    /// SQL file of buffer `index`, created empty on first use
+    pub fn create_buffer(&self, index: usize) -> Result<PathBuf> {
+        let path = self.buffer_file(index)?;
+        if !path.exists() {
+            fs::write(&path, "")
+                .with_context(|| format!("Failed to create SQL file: {}", path.display()))?;
+            log::info!("Created buffer {}: {}", index, path.display());
+        }
+        Ok(path)
+    }
+
+    /// SQL files of the buffers that exist, by index, `sql_file` first
+    pub fn buffers(&self) -> Vec<PathBuf> {
+        let prefix = format!("{}.", self.connection_name());
+        let mut indexes: Vec<usize> = fs::read_dir(&self.path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let index = name
+                    .to_str()?
+                    .strip_prefix(&prefix)?
+                    .strip_suffix(".sql")?
+                    .parse::<usize>()
+                    .ok()?;
+                (index > 1 && !self.is_other_connection(index)).then_some(index)
+            })
+            .collect();
+        indexes.sort_unstable();
+
+        let first = self.sql_file.exists().then(|| self.sql_file.clone());
+        first
+            .into_iter()
+            .chain(indexes.into_iter().map(|index| {
+                self.path
+                    .join(format!("{}.{}.sql", self.connection_name(), index))
+            }))
+            .collect()
+    }
+
+    /// Connection name, as the SQL file is named after it
+    fn connection_name(&self) -> String {
+        self.sql_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
     /// Read the SQL query from query.sql
     pub fn read_query(&self) -> Result<String> {
         fs::read_to_string(&self.sql_file)
             .with_context(|| format!("Failed to read query from: {}", self.sql_file.display()))
     }
 
-    /// Write results to results.dbout
+    /// Write results to results.dbout, replacing it in one rename
+    /// If that fails the results are written to the fallback file instead, and
+    /// the returned error reports both outcomes so the failure is never silent
     pub fn write_results(&self, content: &str) -> Result<()> {
-        fs::write(&self.dbout_file, content)
+        let Err(e) = self.replace_dbout(content) else {
+            return Ok(());
+        };
+        log::error!("{:#}", e);
+
+        let fallback = match &self.fallback_file {
+            Some(path) => match write_fallback(path, content) {
+                Ok(()) => {
+                    log::warn!("Results written to fallback file {}", path.display());
+                    format!("results written to fallback {}", path.display())
+                }
+                Err(fallback_err) => {
+                    log::error!("{:#}", fallback_err);
+                    format!("fallback also failed: {:#}", fallback_err)
+                }
+            },
+            None => "no fallback file available".to_string(),
+        };
+
+        anyhow::bail!("{:#}; {}", e, fallback)
+    }
+
+    /// Replace results.dbout with `content`, recreating the workspace
+    /// directory if something removed it
+    fn replace_dbout(&self, content: &str) -> Result<()> {
+        let context = || format!("Failed to write results to {}", self.dbout_file.display());
+        if !self.path.is_dir() {
+            log::warn!(
+                "Workspace directory {} is gone, creating it again",
+                self.path.display()
+            );
+            create_private_dir(&self.path).with_context(context)?;
+        }
+        write_atomic(&self.dbout_file, content.as_bytes()).with_context(context)
+    }
+
+    /// Append to results.dbout
+    pub fn append_results(&self, content: &str) -> Result<()> {
+        use std::io::Write;
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&self.dbout_file)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .with_context(|| format!("Failed to append results to: {}", self.dbout_file.display()))
+    }
+
+    /// Put `line` above the current content of results.dbout
+    pub fn prepend_results(&self, line: &str) -> Result<()> {
+        let existing = fs::read_to_string(&self.dbout_file).with_context(|| {
+            format!("Failed to read results from: {}", self.dbout_file.display())
+        })?;
+        write_atomic(&self.dbout_file, format!("{}{}", line, existing).as_bytes())
             .with_context(|| format!("Failed to write results to: {}", self.dbout_file.display()))
     }
 
-    /// Clean up the workspace directory
+    /// `path` with `~` expanded and a relative path taken from the workspace
+    /// directory, for files named in meta-commands (\\i, \\o)
+    pub fn resolve_path(&self, path: &Path) -> PathBuf {
+        resolve_path(path, &self.path)
+    }
+
+    /// Append a block to the SQL file below its current content, a blank line
+    /// separating the two
+    ///
+    /// The `directive` line that asked for the block (e.g. `\insert-template`)
+    /// is commented out, so running the file again doesn't append it again;
+    /// the rest of the existing content is kept as it is.
+    pub fn append_query(&self, block: &str, directive: &str) -> Result<()> {
+        append_query(&self.sql_file, block, directive)
+    }
+
+    /// Remove this connection's SQL files (every buffer), and the workspace
+    /// directory once it is empty; other connections' files and the shared
+    /// results.dbout stay
     pub fn cleanup(&self) -> Result<()> {
-        if self.path.exists() {
-            fs::remove_dir_all(&self.path).with_context(|| {
+        for sql_file in self.buffers() {
+            match fs::remove_file(&sql_file) {
+                Ok(()) => log::info!("Removed SQL file: {}", sql_file.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to remove SQL file: {}", sql_file.display())
+                    })
+                }
+            }
+        }
+
+        let is_empty = fs::read_dir(&self.path)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(false);
+        if is_empty {
+            fs::remove_dir(&self.path).with_context(|| {
                 format!(
                     "Failed to remove workspace directory: {}",
                     self.path.display()
@@ -86,6 +398,75 @@ impl Workspace {
         }
         Ok(())
     }
+
+    /// Like `cleanup`, and also remove the files the connections share:
+    /// results.dbout, last_run.json and this process's leftover temporary
+    /// files, for when every connection closes
+    ///
+    /// Only files the plugin made go: history/, snippets/ and anything the
+    /// user put in the directory stay, and so does the directory with them.
+    pub fn cleanup_all(&self) -> Result<()> {
+        let report_file = self.path.join(run_report::REPORT_FILE);
+        let mut files = vec![self.dbout_file.clone(), report_file];
+        let temp_suffix = format!(".{}-", std::process::id());
+        if let Ok(entries) = fs::read_dir(&self.path) {
+            files.extend(entries.flatten().map(|entry| entry.path()).filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                name.starts_with('.') && name.ends_with(".tmp") && name.contains(&temp_suffix)
+            }));
+        }
+        for file in files {
+            match fs::remove_file(&file) {
+                Ok(()) => log::debug!("Removed {}", file.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to remove {}", file.display()))
+                }
+            }
+        }
+        self.cleanup()
+    }
+}
+
+/// `path` with `~` expanded and a relative path taken from `dir`
+pub fn resolve_path(path: &Path, dir: &Path) -> PathBuf {
+    let path = match path.to_str() {
+        Some(s) => crate::ssh_config::expand_tilde(s.trim()),
+        None => path.to_path_buf(),
+    };
+    if path.is_relative() {
+        dir.join(path)
+    } else {
+        path
+    }
+}
+
+/// `Workspace::append_query` for the SQL file at `sql_file`, e.g. a numbered
+/// buffer
+pub fn append_query(sql_file: &Path, block: &str, directive: &str) -> Result<()> {
+    let existing = fs::read_to_string(sql_file).unwrap_or_default();
+    let existing = comment_out_directive(&existing, directive);
+    let separator = match existing.as_str() {
+        "" => "",
+        s if s.ends_with("\n\n") => "",
+        s if s.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+
+    write_atomic(
+        sql_file,
+        format!("{}{}{}", existing, separator, block).as_bytes(),
+    )
+    .with_context(|| format!("Failed to append to: {}", sql_file.display()))
+}
+
+/// Write `content` to the fallback file, creating its directory if needed
+fn write_fallback(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
 }
 
 impl Drop for Workspace {
@@ -95,30 +476,55 @@ impl Drop for Workspace {
     }
 }
 
+/// `sql` with each line running `directive` commented out
+fn comment_out_directive(sql: &str, directive: &str) -> String {
+    sql.split_inclusive('\n')
+        .map(|line| {
+            let is_directive = line
+                .trim_start()
+                .strip_prefix(directive)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+            if is_directive {
+                format!("-- {}", line.trim_start())
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
 
-    // Note: These tests share the /tmp/helix-dadbod directory and results.dbout file
+    // Note: These tests share the test_dir() directory and results.dbout file
     // Run with --test-threads=1 to avoid race conditions:
     //   cargo test -- --test-threads=1
 
+    /// Workspace directory shared by the tests
+    fn test_dir() -> PathBuf {
+        std::env::temp_dir().join("helix-dadbod-tests")
+    }
+
     #[test]
     fn test_workspace_creation() {
         let test_name = "test_connection_create";
-        let workspace = Workspace::create(test_name).unwrap();
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
 
         // Verify paths are correct
-        assert_eq!(workspace.path, PathBuf::from("/tmp/helix-dadbod"));
+        assert_eq!(workspace.path, test_dir());
         assert_eq!(
             workspace.sql_file,
-            PathBuf::from(format!("/tmp/helix-dadbod/{}.sql", test_name))
-        );
-        assert_eq!(
-            workspace.dbout_file,
-            PathBuf::from("/tmp/helix-dadbod/results.dbout")
+            test_dir().join(format!("{}.sql", test_name))
         );
+        assert_eq!(workspace.dbout_file, test_dir().join("results.dbout"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&workspace.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
 
         // Verify files exist
         assert!(workspace.sql_file.exists());
@@ -137,17 +543,50 @@ mod tests {
         fs::remove_file(&workspace.sql_file).ok();
     }
 
+    #[test]
+    fn test_new_sql_file_starts_from_template() {
+        let config: crate::config::SqlConfig = toml::from_str(
+            r#"
+            [[connections]]
+            name = "prod"
+            type = "postgres"
+            host = "db.internal"
+            database = "app"
+            username = "app"
+        "#,
+        )
+        .unwrap();
+        let conn = &config.connections[0];
+        assert_eq!(
+            render_template(DEFAULT_QUERY_TEMPLATE, conn),
+            "-- Connection: prod\n-- Database: app\n-- \\h lists meta-commands\n\n"
+        );
+        let sql = render_template("-- {connection}@{host}/{database} {other}\n", conn);
+        assert_eq!(sql, "-- prod@db.internal/app {other}\n");
+
+        let dir = std::env::temp_dir().join("helix-dadbod-template-test");
+        let _ = fs::remove_dir_all(&dir);
+        let workspace = Workspace::create("prod", &dir, &sql).unwrap();
+        assert_eq!(fs::read_to_string(&workspace.sql_file).unwrap(), sql);
+        // Existing files are left alone, even empty ones
+        fs::write(&workspace.sql_file, "").unwrap();
+        Workspace::create("prod", &dir, &sql).unwrap();
+        assert_eq!(fs::read_to_string(&workspace.sql_file).unwrap(), "");
+
+        workspace.cleanup_all().unwrap();
+    }
+
     #[test]
     fn test_workspace_preserves_existing_sql() {
         let test_name = "test_connection_preserve";
-        let workspace = Workspace::create(test_name).unwrap();
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
 
         // Write some SQL
         let test_sql = "SELECT * FROM users;";
         fs::write(&workspace.sql_file, test_sql).unwrap();
 
         // Create workspace again - should preserve the SQL
-        let workspace2 = Workspace::create(test_name).unwrap();
+        let workspace2 = Workspace::create(test_name, &test_dir(), "").unwrap();
         let sql_content = fs::read_to_string(&workspace2.sql_file).unwrap();
         assert_eq!(sql_content, test_sql);
 
@@ -158,7 +597,7 @@ mod tests {
     #[test]
     fn test_read_write_query() {
         let test_name = "test_connection_rw";
-        let workspace = Workspace::create(test_name).unwrap();
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
 
         // Write a query to the SQL file
         let query = "SELECT version();";
@@ -180,19 +619,335 @@ mod tests {
         fs::remove_file(&workspace.sql_file).ok();
     }
 
+    #[test]
+    fn test_append_query_keeps_existing_content() {
+        let test_name = "test_connection_append";
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
+
+        fs::write(&workspace.sql_file, "SELECT 1;").unwrap();
+        workspace.append_query("-- block one\n", "\\x").unwrap();
+        workspace.append_query("-- block two\n", "\\x").unwrap();
+
+        let content = fs::read_to_string(&workspace.sql_file).unwrap();
+        assert_eq!(content, "SELECT 1;\n\n-- block one\n\n-- block two\n");
+
+        // Cleanup
+        fs::remove_file(&workspace.sql_file).ok();
+    }
+
+    #[test]
+    fn test_append_query_comments_out_the_directive() {
+        let test_name = "test_connection_append_directive";
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
+
+        fs::write(
+            &workspace.sql_file,
+            "-- Connection: prod\n  \\insert-template users\n\\insert-templates\n",
+        )
+        .unwrap();
+        workspace
+            .append_query("INSERT ...;\n", "\\insert-template")
+            .unwrap();
+
+        let content = fs::read_to_string(&workspace.sql_file).unwrap();
+        assert_eq!(
+            content,
+            "-- Connection: prod\n-- \\insert-template users\n\\insert-templates\n\nINSERT ...;\n"
+        );
+
+        // Cleanup
+        fs::remove_file(&workspace.sql_file).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_existing_directory_keeps_its_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join("helix-dadbod-test-shared-dir");
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Workspace::create("shared", &dir, "").unwrap();
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+
+        // Directories it creates are private
+        let nested = dir.join("nested/workspace");
+        Workspace::create("shared", &nested, "").unwrap();
+        let mode = fs::metadata(&nested).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_append_query_to_empty_file() {
+        let test_name = "test_connection_append_empty";
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
+
+        fs::write(&workspace.sql_file, "").unwrap();
+        workspace.append_query("-- block\n", "\\x").unwrap();
+
+        let content = fs::read_to_string(&workspace.sql_file).unwrap();
+        assert_eq!(content, "-- block\n");
+
+        // Cleanup
+        fs::remove_file(&workspace.sql_file).ok();
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let workspace = Workspace::create("test_connection_resolve", &test_dir(), "").unwrap();
+        assert_eq!(
+            workspace.resolve_path(Path::new("exports/orders.txt")),
+            test_dir().join("exports/orders.txt")
+        );
+        assert_eq!(
+            workspace.resolve_path(Path::new("/var/tmp/orders.txt")),
+            PathBuf::from("/var/tmp/orders.txt")
+        );
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(
+                workspace.resolve_path(Path::new("~/queries/slow.sql")),
+                PathBuf::from(home).join("queries/slow.sql")
+            );
+        }
+
+        // Cleanup
+        fs::remove_file(&workspace.sql_file).ok();
+    }
+
+    #[test]
+    fn test_resolve_dir() {
+        let env = |key: &str| match key {
+            "XDG_RUNTIME_DIR" => Some("/run/user/1000".to_string()),
+            "PROJECT" => Some("billing".to_string()),
+            _ => None,
+        };
+        let no_env = |_: &str| None;
+
+        assert_eq!(
+            resolve_dir(None, env).unwrap(),
+            PathBuf::from("/run/user/1000/helix-dadbod")
+        );
+        assert_eq!(
+            resolve_dir(None, no_env).unwrap(),
+            PathBuf::from("/tmp/helix-dadbod")
+        );
+        assert_eq!(
+            resolve_dir(Some("$XDG_RUNTIME_DIR/sql-${PROJECT}"), env).unwrap(),
+            PathBuf::from("/run/user/1000/sql-billing")
+        );
+        assert_eq!(
+            resolve_dir(Some("/srv/$$cash"), env).unwrap(),
+            PathBuf::from("/srv/$cash")
+        );
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(
+                resolve_dir(Some("~/.cache/dadbod-$PROJECT"), env).unwrap(),
+                PathBuf::from(home).join(".cache/dadbod-billing")
+            );
+        }
+
+        let err = resolve_dir(Some("$NOPE/sql"), env).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Invalid workspace_dir '$NOPE/sql': $NOPE is not set"
+        );
+        assert!(resolve_dir(Some("${PROJECT/sql"), env).is_err());
+    }
+
+    /// Workspace whose results.dbout can't be written
+    ///
+    /// Permission bits don't stop root, so the "read-only directory" is a
+    /// regular file: nothing can be created below it for any user.
+    fn unwritable_workspace(name: &str, fallback_file: Option<PathBuf>) -> Workspace {
+        let root = std::env::temp_dir().join(format!("helix-dadbod-fallback-{}", name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let read_only = root.join("read_only");
+        fs::write(&read_only, "").unwrap();
+
+        Workspace {
+            path: read_only.clone(),
+            sql_file: read_only.join("test.sql"),
+            dbout_file: read_only.join("results.dbout"),
+            fallback_file: fallback_file.map(|file| root.join(file)),
+            other_connections: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_write_results_falls_back() {
+        let workspace = unwritable_workspace("ok", Some(PathBuf::from("state/fallback.dbout")));
+        let fallback = workspace.fallback_file.clone().unwrap();
+
+        let err = workspace.write_results("rows").unwrap_err().to_string();
+        assert!(err.contains("Failed to write results to"));
+        assert!(err.contains(&format!(
+            "results written to fallback {}",
+            fallback.display()
+        )));
+        assert_eq!(fs::read_to_string(&fallback).unwrap(), "rows");
+
+        fs::remove_dir_all(workspace.path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_write_results_replaces_the_file() {
+        let dir = std::env::temp_dir().join("helix-dadbod-atomic-test");
+        let _ = fs::remove_dir_all(&dir);
+        let workspace = Workspace::create("prod", &dir, "").unwrap();
+
+        workspace.write_results("old rows").unwrap();
+        workspace.write_results("new rows").unwrap();
+        assert_eq!(
+            fs::read_to_string(&workspace.dbout_file).unwrap(),
+            "new rows"
+        );
+
+        // A stray cleanup removed the directory
+        fs::remove_dir_all(&dir).unwrap();
+        workspace.write_results("rows").unwrap();
+        assert_eq!(fs::read_to_string(&workspace.dbout_file).unwrap(), "rows");
+        workspace.prepend_results("-- note\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&workspace.dbout_file).unwrap(),
+            "-- note\nrows"
+        );
+
+        // No temporary files are left behind
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["results.dbout"]);
+
+        workspace.cleanup_all().unwrap();
+    }
+
+    #[test]
+    fn test_write_results_reports_failed_fallback() {
+        // The fallback sits below the same unwritable path
+        let workspace =
+            unwritable_workspace("fail", Some(PathBuf::from("read_only/fallback.dbout")));
+
+        let err = workspace.write_results("rows").unwrap_err().to_string();
+        assert!(err.contains("Failed to write results to"));
+        assert!(err.contains("fallback also failed"));
+        fs::remove_dir_all(workspace.path.parent().unwrap()).ok();
+
+        let workspace = unwritable_workspace("none", None);
+        let err = workspace.write_results("rows").unwrap_err().to_string();
+        assert!(err.contains("no fallback file available"));
+
+        fs::remove_dir_all(workspace.path.parent().unwrap()).ok();
+    }
+
     #[test]
     fn test_workspace_cleanup() {
         let test_name = "test_connection_cleanup";
-        let workspace = Workspace::create(test_name).unwrap();
+        let workspace = Workspace::create(test_name, &test_dir(), "").unwrap();
 
         assert!(workspace.path.exists());
         assert!(workspace.sql_file.exists());
 
-        // Note: We can't fully test cleanup() because other tests use the same directory
-        // Just verify that the workspace was created successfully
-        // In a real scenario, cleanup() removes the entire /tmp/helix-dadbod directory
+        workspace.cleanup().unwrap();
+        assert!(!workspace.sql_file.exists());
+        // Other tests' files keep the shared directory
+        assert!(workspace.path.exists());
+    }
 
-        // Cleanup just our test file
-        fs::remove_file(&workspace.sql_file).ok();
+    #[test]
+    fn test_numbered_buffers() {
+        let dir = std::env::temp_dir().join("helix-dadbod-buffers-test");
+        let _ = fs::remove_dir_all(&dir);
+        let workspace = Workspace::create("prod", &dir, "").unwrap();
+        // Another connection whose name starts the same
+        let other = Workspace::create("prod.eu", &dir, "").unwrap();
+        other.create_buffer(2).unwrap();
+
+        assert_eq!(workspace.buffer_file(1).unwrap(), workspace.sql_file);
+        assert!(workspace.buffer_file(0).is_err());
+        assert_eq!(workspace.buffers(), vec![workspace.sql_file.clone()]);
+
+        let third = workspace.create_buffer(3).unwrap();
+        assert_eq!(third, dir.join("prod.3.sql"));
+        fs::write(&third, "SELECT 3").unwrap();
+        // Creating it again keeps its contents
+        workspace.create_buffer(3).unwrap();
+        assert_eq!(fs::read_to_string(&third).unwrap(), "SELECT 3");
+        let tenth = workspace.create_buffer(10).unwrap();
+        assert_eq!(
+            workspace.buffers(),
+            vec![workspace.sql_file.clone(), third.clone(), tenth.clone()]
+        );
+
+        workspace.cleanup().unwrap();
+        assert!(!third.exists() && !tenth.exists());
+        assert_eq!(other.buffers().len(), 2);
+
+        // prod.2.sql belongs to a connection named prod.2
+        let prod_2 = Workspace::create("prod.2", &dir, "").unwrap();
+        let mut workspace = Workspace::create("prod", &dir, "").unwrap();
+        workspace.other_connections = vec!["prod.2".to_string(), "prod.eu".to_string()];
+        assert_eq!(workspace.buffers(), vec![workspace.sql_file.clone()]);
+        assert!(workspace.create_buffer(2).is_err());
+        workspace.cleanup().unwrap();
+        assert!(prod_2.sql_file.exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_cleanup_keeps_other_connections_files() {
+        let dir = std::env::temp_dir().join("helix-dadbod-cleanup-test");
+        let _ = fs::remove_dir_all(&dir);
+        let staging = Workspace::create("staging", &dir, "").unwrap();
+        let prod = Workspace::create("prod", &dir, "").unwrap();
+        fs::write(&prod.sql_file, "SELECT * FROM half_written").unwrap();
+
+        staging.cleanup().unwrap();
+        assert!(!staging.sql_file.exists());
+        assert_eq!(
+            fs::read_to_string(&prod.sql_file).unwrap(),
+            "SELECT * FROM half_written"
+        );
+        assert!(prod.dbout_file.exists(), "results.dbout is shared");
+
+        // Once empty, the directory goes too
+        prod.cleanup().unwrap();
+        fs::remove_file(&prod.dbout_file).unwrap();
+        staging.cleanup().unwrap();
+        assert!(!dir.exists());
+
+        let workspace = Workspace::create("staging", &dir, "").unwrap();
+        workspace.cleanup_all().unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_cleanup_all_keeps_the_users_files() {
+        let dir = std::env::temp_dir().join("helix-dadbod-cleanup-all-test");
+        let _ = fs::remove_dir_all(&dir);
+        let workspace = Workspace::create("prod", &dir, "").unwrap();
+        workspace.write_results("rows").unwrap();
+        fs::write(dir.join(run_report::REPORT_FILE), "{}").unwrap();
+        let temp = temp_path(&workspace.dbout_file);
+        fs::write(&temp, "").unwrap();
+        fs::create_dir_all(dir.join("history")).unwrap();
+        fs::write(dir.join("history/prod.sql"), "SELECT 1;\n").unwrap();
+        fs::write(dir.join("notes.md"), "keep me\n").unwrap();
+
+        workspace.cleanup_all().unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["history", "notes.md"]);
+        assert!(dir.join("history/prod.sql").exists());
+
+        fs::remove_dir_all(&dir).ok();
     }
 }